@@ -1,86 +1,163 @@
-use crate::db::DatabasePool;
-use axum::extract::State;
-use axum::http::StatusCode;
+use crate::config::Config;
+use crate::oauth::{self, Google, OAuthProvider};
+use crate::sessions::{SessionRegistry, CREATED_AT_KEY};
+use crate::store::{GuestStores, Store};
+use axum::extract::{Path, Request, State};
+use axum::http::header::USER_AGENT;
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
 use axum::{extract::Query, response::Redirect, Json};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::collections::BTreeSet;
+use std::sync::{Arc, OnceLock};
 use tower_sessions::Session;
-use url::Url;
+
+/// Settings read from the configuration at startup.
+struct AuthSettings {
+    frontend_url: String,
+    admin_emails: BTreeSet<String>,
+}
+
+static SETTINGS: OnceLock<AuthSettings> = OnceLock::new();
+
+/// Store the frontend URL and admin list from the configuration for all later requests, and the
+/// OAuth providers' credentials.
+pub fn init(config: &Config) {
+    let _ = SETTINGS.set(AuthSettings {
+        frontend_url: config.frontend_url.clone(),
+        admin_emails: config.admin_emails.clone(),
+    });
+    oauth::init(config);
+}
+
+/// Origin of the frontend to redirect to after login and logout.
+fn frontend_url() -> String {
+    SETTINGS
+        .get()
+        .map(|settings| settings.frontend_url.clone())
+        .unwrap_or_else(|| "http://localhost:5173".to_string())
+}
 
 /// Start the Google login flow by redirecting the user to the Google login page.
-pub async fn start_google_login() -> Redirect {
-    let client_id = env::var("GOOGLE_CLIENT_ID").expect("Missing GOOGLE_CLIENT_ID");
-    let redirect_uri = env::var("GOOGLE_REDIRECT_URI").expect("Missing GOOGLE_REDIRECT_URI");
+pub async fn start_google_login() -> Result<Redirect, StatusCode> {
+    redirect_to_provider(&Google).map_err(|e| {
+        tracing::error!("Error starting google login: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
 
-    let mut url = Url::parse("https://accounts.google.com/o/oauth2/v2/auth").unwrap();
-    url.query_pairs_mut()
-        .append_pair("client_id", &client_id)
-        .append_pair("redirect_uri", &redirect_uri)
-        .append_pair("response_type", "code")
-        .append_pair("scope", "openid email profile")
-        .append_pair("access_type", "offline");
+/// Start the login flow for the provider named in the path, e.g. `/login/github`.
+pub async fn start_login(Path(provider): Path<String>) -> Result<Redirect, StatusCode> {
+    let provider = oauth::provider(&provider).ok_or(StatusCode::NOT_FOUND)?;
+    redirect_to_provider(provider.as_ref()).map_err(|e| {
+        tracing::error!("Error starting {} login: {}", provider.name(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
 
-    Redirect::temporary(url.as_str())
+/// Redirect to a provider's login page.
+fn redirect_to_provider(provider: &dyn OAuthProvider) -> Result<Redirect, String> {
+    Ok(Redirect::temporary(provider.authorize_url()?.as_str()))
 }
 
-/// Handle the callback from Google after the user logs in.
+/// Handle the callback from Google after the user logs in. A login that can't be completed, e.g.
+/// because the code was rejected, responds 401.
 pub async fn handle_google_callback(
     session: Session,
-    State(pool): State<DatabasePool>,
-    Query(params): Query<GoogleCallbackQuery>,
-) -> Redirect {
-    let client = Client::new();
+    State(store): State<Arc<dyn Store>>,
+    State(config): State<Arc<Config>>,
+    State(sessions): State<SessionRegistry>,
+    headers: HeaderMap,
+    Query(params): Query<CallbackQuery>,
+) -> Result<Redirect, StatusCode> {
+    complete_login(
+        &Google,
+        session,
+        store,
+        config,
+        sessions,
+        user_agent(&headers),
+        params.code,
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error completing google login: {}", e);
+        StatusCode::UNAUTHORIZED
+    })
+}
 
-    let client_id = env::var("GOOGLE_CLIENT_ID").expect("Missing GOOGLE_CLIENT_ID");
-    let client_secret = env::var("GOOGLE_CLIENT_SECRET").expect("Missing GOOGLE_CLIENT_SECRET");
-    let redirect_uri = env::var("GOOGLE_REDIRECT_URI").expect("Missing GOOGLE_REDIRECT_URI");
+/// Handle the callback from the provider named in the path, e.g. `/callback/github`. As with
+/// Google, a login that can't be completed responds 401.
+pub async fn handle_oauth_callback(
+    Path(provider): Path<String>,
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(config): State<Arc<Config>>,
+    State(sessions): State<SessionRegistry>,
+    headers: HeaderMap,
+    Query(params): Query<CallbackQuery>,
+) -> Result<Redirect, StatusCode> {
+    let provider = oauth::provider(&provider).ok_or(StatusCode::NOT_FOUND)?;
+    complete_login(
+        provider.as_ref(),
+        session,
+        store,
+        config,
+        sessions,
+        user_agent(&headers),
+        params.code,
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error completing {} login: {}", provider.name(), e);
+        StatusCode::UNAUTHORIZED
+    })
+}
 
+/// The request's `User-Agent`, if present and valid.
+fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+/// Exchange the callback's code for the user's info, create their account on first login, and
+/// store the user in the session.
+async fn complete_login(
+    provider: &dyn OAuthProvider,
+    session: Session,
+    store: Arc<dyn Store>,
+    config: Arc<Config>,
+    sessions: SessionRegistry,
+    user_agent: Option<String>,
+    code: String,
+) -> Result<Redirect, String> {
     // Exchange authorization code for access token
-    let token_resp = client
-        .post("https://oauth2.googleapis.com/token")
-        .form(&[
-            ("code", params.code.as_str()),
-            ("client_id", client_id.as_str()),
-            ("client_secret", client_secret.as_str()),
-            ("redirect_uri", redirect_uri.as_str()),
-            ("grant_type", "authorization_code"),
-        ])
-        .send()
-        .await
-        .unwrap()
-        .json::<GoogleTokenResponse>()
-        .await
-        .unwrap();
+    let tokens = provider.exchange_code(&code).await?;
 
     // Use the access token to get user info
-    let user_info_resp = client
-        .get("https://www.googleapis.com/oauth2/v2/userinfo")
-        .bearer_auth(&token_resp.access_token)
-        .send()
-        .await
-        .unwrap()
-        .json::<GoogleUserInfo>()
-        .await
-        .unwrap();
+    let user_info_resp = provider.user_info(&tokens.access_token).await?;
 
-    let account = pool
+    let account = store
         .get_account(&user_info_resp.email.to_string())
         .await
         .unwrap_or_default()
         .unwrap_or_default();
 
-    if account.id == "" {
-        pool.add_account(crate::models::Account {
-            id: user_info_resp.email.to_string(),
-            cash: 100000_00,
-            value: 100000,
-            change: 0,
-        })
-        .await
-        .unwrap();
+    if account.id.is_empty() {
+        store
+            .add_account(crate::models::Account::open(
+                &user_info_resp.email,
+                config.starting_cash,
+                config.leaderboard_eligible_by_default,
+            ))
+            .await
+            .map_err(|e| e.to_string())?;
     }
 
+    let account_id = user_info_resp.email.clone();
     match session.insert("SESSION", user_info_resp).await {
         Ok(_) => {
             tracing::info!("Session inserted");
@@ -89,69 +166,354 @@ pub async fn handle_google_callback(
             tracing::error!("Error inserting session: {:?}", e);
         }
     };
-    let frontend_port =
-        env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
+    if let Err(e) = session.insert(PROVIDER_KEY, provider.name()).await {
+        tracing::error!("Error inserting login provider: {:?}", e);
+    }
+    if let Some(refresh_token) = tokens.refresh_token {
+        if let Err(e) = session.insert(REFRESH_TOKEN_KEY, refresh_token).await {
+            tracing::error!("Error inserting refresh token: {:?}", e);
+        }
+    }
+    if let Err(e) = session
+        .insert(VALIDATED_AT_KEY, chrono::Utc::now().timestamp())
+        .await
+    {
+        tracing::error!("Error inserting validation time: {:?}", e);
+    }
+
+    // Tag the session with its creation time and evict the account's oldest sessions over the limit
+    let created_at = chrono::Utc::now();
+    if let Err(e) = session.insert(CREATED_AT_KEY, created_at.timestamp()).await {
+        tracing::error!("Error inserting session creation time: {:?}", e);
+    }
+    match session.save().await {
+        Ok(_) => {
+            if let Some(id) = session.id() {
+                sessions
+                    .register(&account_id, id, created_at, user_agent)
+                    .await;
+            }
+        }
+        Err(e) => tracing::error!("Error saving session: {:?}", e),
+    }
+
+    let frontend_port = frontend_url();
     let redirect_url = format!("{}/home", frontend_port);
-    Redirect::to(&redirect_url)
+    Ok(Redirect::to(&redirect_url))
+}
+
+/// Session key holding the name of the provider the user logged in with.
+const PROVIDER_KEY: &str = "PROVIDER";
+/// Session key holding the provider's refresh token.
+const REFRESH_TOKEN_KEY: &str = "REFRESH_TOKEN";
+/// Session key holding when the user info was last fetched from the provider, as a Unix timestamp.
+const VALIDATED_AT_KEY: &str = "VALIDATED_AT";
+
+/// Middleware refreshing a session's user info from its provider once it is older than the
+/// configured revalidation interval. If the refresh fails the session is logged out.
+pub async fn revalidate_session(
+    State(config): State<Arc<Config>>,
+    session: Session,
+    req: Request,
+    next: Next,
+) -> Response {
+    revalidate(&config, &session).await;
+    next.run(req).await
 }
 
-/// Logout the user by removing the session.
-pub async fn logout(session: Session) -> Redirect {
-    session.remove::<GoogleUserInfo>("SESSION").await.unwrap();
+/// Refresh the session's user info if it's older than `SESSION_REVALIDATE_SECS`, logging the
+/// session out if the refresh fails.
+async fn revalidate(config: &Config, session: &Session) {
+    let Some(max_age) = config.session_revalidate_secs else {
+        return;
+    };
+    let logged_in = matches!(session.get::<SessionUser>("SESSION").await, Ok(Some(_)));
+    let guest = matches!(session.get::<bool>(GUEST_KEY).await, Ok(Some(true)));
+    let validated_at = session
+        .get::<i64>(VALIDATED_AT_KEY)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0);
+
+    if logged_in && !guest && chrono::Utc::now().timestamp() - validated_at > max_age {
+        if let Err(e) = refresh_session(session).await {
+            tracing::warn!("Session revalidation failed, logging out: {}", e);
+            let _ = session.flush().await;
+        }
+    }
+}
+
+/// Fetch fresh user info with the session's refresh token and store it in the session.
+async fn refresh_session(session: &Session) -> Result<(), String> {
+    let refresh_token: String = session
+        .get(REFRESH_TOKEN_KEY)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("No refresh token in session")?;
+    // Sessions from before other providers were added are all Google
+    let provider_name: String = session
+        .get(PROVIDER_KEY)
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_else(|| String::from("google"));
+    let provider = oauth::provider(&provider_name)
+        .ok_or_else(|| format!("Unknown login provider: {}", provider_name))?;
+
+    let access_token = provider.refresh(&refresh_token).await?;
+    let mut user_info = provider.user_info(&access_token).await?;
+    // The provider doesn't know about scopes, so keep the ones the session was granted
+    if let Some(previous) = session
+        .get::<SessionUser>("SESSION")
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        user_info.scopes = previous.scopes;
+    }
+
+    session
+        .insert("SESSION", user_info)
+        .await
+        .map_err(|e| e.to_string())?;
+    session
+        .insert(VALIDATED_AT_KEY, chrono::Utc::now().timestamp())
+        .await
+        .map_err(|e| e.to_string())?;
+    tracing::debug!("Session revalidated");
+    Ok(())
+}
+
+/// Session key marking a guest session, whose account lives in memory only.
+pub const GUEST_KEY: &str = "GUEST";
+
+/// Start a guest session backed by an ephemeral in-memory account. Only available when
+/// `ENABLE_GUEST_MODE` is set.
+pub async fn start_guest_session(
+    session: Session,
+    State(config): State<Arc<Config>>,
+) -> Result<Redirect, StatusCode> {
+    if !config.guest_mode {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let info = SessionUser {
+        email: format!("guest-{}", uuid::Uuid::new_v4()),
+        name: "Guest".to_string(),
+        picture: "".to_string(),
+        provider: "guest".to_string(),
+        scopes: Scope::all(),
+    };
+    if let Err(e) = session.insert("SESSION", info).await {
+        tracing::error!("Error inserting session: {:?}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = session.insert(GUEST_KEY, true).await {
+        tracing::error!("Error inserting session: {:?}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let frontend_port = frontend_url();
+    let redirect_url = format!("{}/home", frontend_port);
+    Ok(Redirect::to(&redirect_url))
+}
+
+/// Logout the user by removing the session. A guest's in-memory account is discarded.
+pub async fn logout(
+    session: Session,
+    State(guests): State<GuestStores>,
+    State(sessions): State<SessionRegistry>,
+) -> Redirect {
+    if let Ok(Some(info)) = session.get::<SessionUser>("SESSION").await {
+        if let Ok(Some(true)) = session.get::<bool>(GUEST_KEY).await {
+            guests.remove(&info.email);
+        }
+        if let Some(id) = session.id() {
+            sessions.unregister(&info.email, id);
+        }
+    }
+    session.remove::<SessionUser>("SESSION").await.unwrap();
     session.flush().await.unwrap();
-    let frontend_port =
-        env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
-    let redirect_url = format!("{}", frontend_port);
-    Redirect::to(&redirect_url)
+    let frontend_port = frontend_url();
+    Redirect::to(&frontend_port)
 }
 
 /// Get user data from the session.
 pub async fn get_user_data(
     session: Session,
-) -> Result<(StatusCode, Json<GoogleUserInfo>), StatusCode> {
+) -> Result<(StatusCode, Json<SessionUser>), StatusCode> {
     match validate_session(session).await {
         Ok(info) => Ok((StatusCode::OK, Json(info))),
         Err(status) => Err(status),
     }
 }
 
-/// Validate the session and return the user info if valid.
-pub async fn validate_session(session: Session) -> Result<GoogleUserInfo, StatusCode> {
-    let info: GoogleUserInfo = session.get("SESSION").await.unwrap().unwrap_or_default();
+/// Validate the session and return the user info if valid. The session must have the `Read`
+/// scope.
+pub async fn validate_session(session: Session) -> Result<SessionUser, StatusCode> {
+    validate_scope(session, Scope::Read).await
+}
+
+/// Validate the session and require it to have `scope`, e.g. `Trade` for handlers that place
+/// orders.
+pub async fn validate_scope(session: Session, scope: Scope) -> Result<SessionUser, StatusCode> {
+    let info: SessionUser = session.get("SESSION").await.unwrap().unwrap_or_default();
     if info.email.is_empty() {
         return Err(StatusCode::UNAUTHORIZED);
     }
+    if !info.scopes.contains(&scope) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(info)
+}
+
+/// Validate the session and require the user to be listed in `ADMIN_EMAILS`.
+pub async fn validate_admin(session: Session) -> Result<SessionUser, StatusCode> {
+    let info = validate_session(session).await?;
+    let is_admin = SETTINGS
+        .get()
+        .is_some_and(|settings| settings.admin_emails.contains(&info.email));
+    if !is_admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
     Ok(info)
 }
 
-/// Query parameters sent by Google during the callback.
+/// Query parameters sent by a provider during the callback.
 #[derive(Debug, Deserialize)]
-pub struct GoogleCallbackQuery {
+pub struct CallbackQuery {
     code: String,
 }
 
-/// Response from Google's token endpoint.
-#[derive(Debug, Deserialize)]
-pub struct GoogleTokenResponse {
-    access_token: String,
+/// What a session may do. Interactive logins get every scope; a read-only API token would only
+/// get `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// View the account, portfolio, and transactions.
+    Read,
+    /// Place, confirm, and liquidate orders.
+    Trade,
 }
 
-/// User info retrieved from Google's API.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GoogleUserInfo {
+impl Scope {
+    /// Every scope, as granted to interactive logins.
+    pub fn all() -> Vec<Scope> {
+        vec![Scope::Read, Scope::Trade]
+    }
+}
+
+/// The logged-in user stored in the session, whichever provider they logged in with. Sessions
+/// saved before `provider` existed deserialize as Google users, and sessions saved before
+/// `scopes` existed get every scope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionUser {
     pub(crate) email: String,
     pub(crate) name: String,
     pub(crate) picture: String,
+    #[serde(default = "default_provider")]
+    pub(crate) provider: String,
+    #[serde(default = "Scope::all")]
+    pub(crate) scopes: Vec<Scope>,
+}
+
+/// Provider of sessions saved before other providers were added.
+fn default_provider() -> String {
+    String::from("google")
 }
 
-/// Default implementation for GoogleUserInfo. All fields are empty strings.
-impl Default for GoogleUserInfo {
+/// Default implementation for SessionUser. All fields are empty strings and there are no scopes.
+impl Default for SessionUser {
     fn default() -> Self {
-        GoogleUserInfo {
+        SessionUser {
             email: "".to_string(),
             name: "".to_string(),
             picture: "".to_string(),
+            provider: "".to_string(),
+            scopes: Vec::new(),
         }
     }
 }
 
+/// An in-memory session logged in as `email` with `scopes`, for driving handlers in tests.
+#[cfg(test)]
+pub(crate) async fn test_session(email: &str, scopes: Vec<Scope>) -> Session {
+    let session = Session::new(None, Arc::new(tower_sessions::MemoryStore::default()), None);
+    let user = SessionUser {
+        email: email.to_string(),
+        scopes,
+        ..Default::default()
+    };
+    session.insert("SESSION", user).await.unwrap();
+    session
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn logged_in_session(validated_at: i64) -> Session {
+        let session = test_session("a@example.com", vec![Scope::Read]).await;
+        session
+            .insert(VALIDATED_AT_KEY, validated_at)
+            .await
+            .unwrap();
+        session
+    }
+
+    fn config() -> Config {
+        Config {
+            session_revalidate_secs: Some(60),
+            ..Config::for_tests()
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_sessions_are_refreshed_and_logged_out_when_that_fails() {
+        // Without a refresh token the refresh attempt fails
+        let session = logged_in_session(chrono::Utc::now().timestamp() - 120).await;
+        revalidate(&config(), &session).await;
+        let user: Option<SessionUser> = session.get("SESSION").await.unwrap();
+        assert!(user.is_none());
+    }
+
+    #[tokio::test]
+    async fn fresh_sessions_are_left_alone() {
+        let session = logged_in_session(chrono::Utc::now().timestamp()).await;
+        revalidate(&config(), &session).await;
+        let user: Option<SessionUser> = session.get("SESSION").await.unwrap();
+        assert_eq!(user.unwrap().scopes, vec![Scope::Read]);
+    }
+
+    #[test]
+    fn sessions_saved_before_other_providers_are_google_users() {
+        let old: SessionUser = serde_json::from_str(
+            r#"{"email":"a@example.com","name":"A","picture":"https://example.com/a.png"}"#,
+        )
+        .unwrap();
+        assert_eq!(old.email, "a@example.com");
+        assert_eq!(old.provider, "google");
+        assert_eq!(old.scopes, Scope::all());
+    }
+
+    #[tokio::test]
+    async fn a_failed_provider_login_is_unauthorized() {
+        // GitHub login isn't configured in tests, so the code exchange fails
+        let status = handle_oauth_callback(
+            Path(String::from("github")),
+            test_session("a@example.com", Scope::all()).await,
+            State(Arc::new(crate::store::MemoryStore::new()) as Arc<dyn Store>),
+            State(Arc::new(Config::for_tests())),
+            State(SessionRegistry::new(
+                None,
+                Arc::new(tower_sessions::MemoryStore::default()),
+            )),
+            HeaderMap::new(),
+            Query(CallbackQuery {
+                code: String::from("code"),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::config::Config;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Cash changes since startup that took an account further below zero without borrowing.
+static NEGATIVE_CASH_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of negative cash events since startup, for metrics.
+pub fn negative_cash_events() -> u64 {
+    NEGATIVE_CASH_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Check an account's cash after a change that isn't meant to borrow, such as a sale whose fee
+/// exceeds its proceeds. A change taking cash below both zero and its `previous` balance is
+/// logged and counted, and with `CLAMP_NEGATIVE_CASH` held at the lower of the two. Returns the
+/// cash to store. Accounts below zero have no buying power, so further buys are refused until
+/// cash is positive again.
+pub fn checked_cash(config: &Config, account_id: &str, previous: i64, cash: i64) -> i64 {
+    let floor = previous.min(0);
+    if cash >= floor {
+        return cash;
+    }
+    NEGATIVE_CASH_EVENTS.fetch_add(1, Ordering::Relaxed);
+    if config.clamp_negative_cash {
+        tracing::warn!(
+            "Cash for {} would go negative ({} to {}), clamping to {}",
+            account_id,
+            previous,
+            cash,
+            floor
+        );
+        floor
+    } else {
+        tracing::warn!(
+            "Cash for {} went negative ({} to {})",
+            account_id,
+            previous,
+            cash
+        );
+        cash
+    }
+}
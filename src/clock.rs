@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time. Handlers take it from the app state so time-dependent logic can
+/// run against a fixed time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time, or the simulated time in simulation mode.
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        crate::sim::now().unwrap_or_else(Utc::now)
+    }
+}
+
+/// A clock stopped at one instant.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
@@ -0,0 +1,59 @@
+use axum::{error_handling::HandleErrorLayer, http::StatusCode, BoxError, Router};
+use tower::{limit::GlobalConcurrencyLimitLayer, ServiceBuilder};
+
+/// Shed requests beyond `max` in flight with a 503 instead of queueing them. The limit is shared
+/// by every route, since `Router::layer` wraps each route separately.
+pub fn limit<S>(app: Router<S>, max: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    app.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(|_: BoxError| async {
+                StatusCode::SERVICE_UNAVAILABLE
+            }))
+            .load_shed()
+            .layer(GlobalConcurrencyLimitLayer::new(max)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, routing::get};
+    use std::sync::Arc;
+    use tokio::sync::{Notify, Semaphore};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn requests_beyond_the_limit_are_shed() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Semaphore::new(0));
+        let slow = {
+            let (entered, release) = (entered.clone(), release.clone());
+            move || async move {
+                entered.notify_one();
+                let _permit = release.acquire().await.unwrap();
+                StatusCode::OK
+            }
+        };
+        let app = limit(
+            Router::new()
+                .route("/slow", get(slow))
+                .route("/fast", get(|| async { StatusCode::OK })),
+            1,
+        );
+        let request = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let in_flight = tokio::spawn(app.clone().oneshot(request("/slow")));
+        entered.notified().await;
+        let shed = app.clone().oneshot(request("/fast")).await.unwrap();
+        release.add_permits(1);
+        let finished = in_flight.await.unwrap().unwrap();
+        let after = app.oneshot(request("/fast")).await.unwrap();
+
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(finished.status(), StatusCode::OK);
+        assert_eq!(after.status(), StatusCode::OK);
+    }
+}
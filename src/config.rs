@@ -0,0 +1,497 @@
+use crate::fees::FeeModel;
+use crate::finnhub::PriceSource;
+use crate::liquidity::LiquidityModel;
+use crate::market_hours::{AfterHoursPricing, ClosedMarketOrders};
+use crate::money_output::MoneyOutput;
+use crate::oauth::OAuthCredentials;
+use crate::sim::SimConfig;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// Environment variables the server can't start without.
+pub const REQUIRED_VARS: [&str; 4] = [
+    "MONGO_URI",
+    "GOOGLE_CLIENT_ID",
+    "GOOGLE_CLIENT_SECRET",
+    "GOOGLE_REDIRECT_URI",
+];
+
+/// `MAX_SHARES` when unset. Keeps an order's notional well inside the range of the account and
+/// holding fields at ordinary prices.
+pub const DEFAULT_MAX_SHARES: i32 = 1_000_000;
+
+/// Load variables from `ENV_FILE`, or from `.env` in the working directory when that isn't set,
+/// without overriding variables already in the environment. A missing `.env` is fine, since
+/// containers usually pass everything through the environment, but a missing `ENV_FILE` is an
+/// error. Returns the file loaded, if any.
+pub fn load_env_file() -> Result<Option<String>, String> {
+    match env::var("ENV_FILE") {
+        Ok(path) => match dotenv::from_path(&path) {
+            Ok(()) => Ok(Some(path)),
+            Err(e) => Err(format!("Failed to load ENV_FILE {}: {}", path, e)),
+        },
+        Err(_) => match dotenv::dotenv() {
+            Ok(path) => Ok(Some(path.display().to_string())),
+            Err(dotenv::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to load .env: {}", e)),
+        },
+    }
+}
+
+/// Required environment variables that were unset or blank at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingEnv(pub Vec<&'static str>);
+
+impl fmt::Display for MissingEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Missing required environment variables: {}. Set them in the environment or in a \
+             .env file.",
+            self.0.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for MissingEnv {}
+
+/// What happens to a sell placed while the account has a queued buy of the same symbol, whose
+/// reserved cash and eventual fill would interleave confusingly with the sale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingBuySells {
+    /// Place the sell as usual.
+    #[default]
+    Allow,
+    /// Place the sell, logging a warning.
+    Warn,
+    /// Refuse the sell until the buy fills or is cancelled.
+    Reject,
+}
+
+impl FromStr for PendingBuySells {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(PendingBuySells::Allow),
+            "warn" => Ok(PendingBuySells::Warn),
+            "reject" => Ok(PendingBuySells::Reject),
+            _ => Err(format!("Unknown pending buy sells mode: {}", s)),
+        }
+    }
+}
+
+/// Why `FINNHUB_API_KEY` can't be used as is, if it can't: it's unset, or it has whitespace or
+/// quotes that were likely pasted in with it and that Finnhub will reject.
+fn finnhub_key_problem(key: Option<&str>) -> Option<&'static str> {
+    match key {
+        None => Some("FINNHUB_API_KEY is not set, price data will be unavailable"),
+        Some(key)
+            if key
+                .chars()
+                .any(|c| c.is_whitespace() || c == '"' || c == '\'') =>
+        {
+            Some("FINNHUB_API_KEY contains whitespace or quotes, Finnhub will likely reject it")
+        }
+        Some(_) => None,
+    }
+}
+
+/// Read an environment variable, treating blank values as unset.
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Runtime settings read from the environment once at startup. Serialized as-is by
+/// `GET /admin/config`, so secret values must never be added without `#[serde(skip)]`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// Cash given to new accounts, in cents.
+    pub starting_cash: i64,
+    /// Distinct symbols a single request may fetch from Finnhub.
+    pub finnhub_request_budget: usize,
+    /// Transactions older than this are archived. Archival is off when unset.
+    pub transaction_retention_days: Option<i64>,
+    /// How often to reconcile account values. Reconciliation is off when unset.
+    pub reconcile_interval_secs: Option<u64>,
+    /// Largest allowed value drift before an account is flagged, in cents.
+    pub reconcile_drift_threshold: i64,
+    /// Overwrite drifted account values with the recomputed value.
+    pub reconcile_auto_correct: bool,
+    /// Allow ephemeral in-memory guest accounts.
+    pub guest_mode: bool,
+    /// Fees charged on every trade.
+    pub fee_model: FeeModel,
+    /// Fill large orders in tranches at worsening prices. Orders fill at the quote when unset.
+    pub liquidity: Option<LiquidityModel>,
+    /// Backtest against historical prices on a simulated clock when set.
+    pub sim: Option<SimConfig>,
+    /// Refresh a session's user info from Google once it is older than this. Off when unset.
+    pub session_revalidate_secs: Option<i64>,
+    /// Concurrent sessions allowed per account; the oldest is evicted at login. Unlimited when unset.
+    pub max_sessions_per_account: Option<usize>,
+    /// Wrap JSON responses in an envelope by default. Clients can override with `X-Envelope`.
+    pub response_envelope: bool,
+    /// Consecutive failed quotes after which a held symbol is treated as delisted.
+    pub delisted_after_failures: u32,
+    /// Whether new accounts start out eligible for the leaderboard.
+    pub leaderboard_eligible_by_default: bool,
+    /// Requests handled at once; further requests are shed with a 503. Unlimited when unset.
+    pub max_concurrent_requests: Option<usize>,
+    /// Prefix prepended to Finnhub logo URLs, e.g. an image proxy. Logos are passed through
+    /// unchanged when unset.
+    pub logo_cdn_prefix: Option<String>,
+    /// Fewest shares a single buy or sell may be for.
+    pub min_shares: i32,
+    /// Most shares a single buy or sell may be for, to catch fat-fingered orders. Defaults to
+    /// `DEFAULT_MAX_SHARES`.
+    pub max_shares: i32,
+    /// Buys above this notional, in cents, must be confirmed with `/buy/confirm` before they
+    /// execute. Buys execute immediately when unset.
+    pub confirm_notional_above: Option<i64>,
+    /// Profiles refreshed per minute ahead of expiry. Proactive refresh is off when unset.
+    pub profile_refresh_per_minute: Option<u32>,
+    /// Price used to value holdings outside regular market hours: `latest` or `close`.
+    pub after_hours_pricing: AfterHoursPricing,
+    /// Features only enabled for accounts granted them by an admin. Features not listed here are
+    /// on for every account.
+    pub gated_features: BTreeSet<String>,
+    /// Day trades allowed in a rolling five trading days before an account is flagged as a
+    /// pattern day trader.
+    pub day_trade_limit: usize,
+    /// Flagged accounts valued below this, in cents, may not make further day trades. Day
+    /// trades are never blocked when unset.
+    pub day_trade_min_equity: Option<i64>,
+    /// Highest buying power multiplier an account may choose. Leverage is off at the default of
+    /// 1.0.
+    pub max_buying_power_multiplier: f64,
+    /// Accounts borrowing on margin whose equity falls below this percent of their holdings'
+    /// value have positions sold, largest first, until it is met again. Margin calls are off
+    /// when unset.
+    pub maintenance_margin_percent: Option<f64>,
+    /// How often borrowing accounts are checked for margin calls, in seconds.
+    pub margin_check_secs: u64,
+    /// During the regular session, a quote not updated for this many seconds marks its symbol as
+    /// halted and trades on it are rejected. Quotes are cached for five minutes, so this should
+    /// be well above 300. Halt detection is off when unset.
+    pub halt_stale_after_secs: Option<i64>,
+    /// Buys and sells must be for a multiple of this many shares. Any whole number of shares may
+    /// be traded when unset.
+    pub round_lot: Option<i32>,
+    /// How long a computed leaderboard is reused. Every request recomputes it when unset.
+    pub leaderboard_cache_secs: Option<u64>,
+    /// How long an account's sector exposure is reused, unless the account trades. Every request
+    /// recomputes it when unset.
+    pub sectors_cache_secs: Option<u64>,
+    /// Stocks quoted under this many cents are priced to a tenth of a cent rather than whole
+    /// cents, in fills and stored holdings as well as valuation. Off when unset.
+    pub sub_cent_pricing_below: Option<i64>,
+    /// An account's first request after this many idle seconds quotes its holdings and recently
+    /// viewed symbols in the background. Warming is off when unset.
+    pub warm_after_idle_secs: Option<u64>,
+    /// How money is represented in responses: `cents`, `dollars`, or `string`.
+    pub money_output: MoneyOutput,
+    /// Most entries each Finnhub cache keeps, evicting the least recently fetched. Caches only
+    /// drop expired entries when unset.
+    pub finnhub_cache_max_entries: Option<usize>,
+    /// What happens to stock trades outside the regular session: `allow`, `reject`, or `queue`
+    /// them to fill at the next open.
+    pub closed_market_orders: ClosedMarketOrders,
+    /// Hold cash at zero, or at its earlier negative balance, when a fee or other change that
+    /// isn't borrowing would push it lower. Such changes are only logged when off.
+    pub clamp_negative_cash: bool,
+    /// How often to delete holdings left at zero or fewer shares by failed trades. The sweep is
+    /// off when unset; admins can still purge an account on demand.
+    pub purge_empty_holdings_secs: Option<u64>,
+    /// Where stocks are priced from for trading and valuation: `quote` or `last_candle`.
+    pub price_source: PriceSource,
+    /// Rate gains on shares held a year or less are taxed at in tax previews, in basis points.
+    pub short_term_tax_bps: i64,
+    /// Rate gains on shares held more than a year are taxed at in tax previews, in basis points.
+    pub long_term_tax_bps: i64,
+    /// Address the server listens on.
+    pub bind_addr: String,
+    /// Origin of the frontend, allowed by CORS and redirected to after login.
+    pub frontend_url: String,
+    /// Emails of the accounts allowed to use the admin endpoints. Personal data, so only their
+    /// count is shown by `GET /admin/config`.
+    #[serde(skip)]
+    pub admin_emails: BTreeSet<String>,
+    /// Base URL of the Finnhub API, without a trailing slash.
+    pub finnhub_base_url: String,
+    /// How long a stock's basic financials are cached. A day when unset.
+    pub financials_cache_secs: Option<u64>,
+    /// What happens to a sell while a queued buy of the same symbol is open: `allow`, `warn`, or
+    /// `reject` it.
+    pub pending_buy_sells: PendingBuySells,
+    /// Seconds after buying a symbol during which it can't be sold, for contests that forbid
+    /// quick flips. No lockup when unset.
+    pub sell_lockup_secs: Option<i64>,
+    /// Annual risk-free rate Sharpe ratios are measured against, in basis points.
+    pub risk_free_rate_bps: i64,
+    /// Price endpoints respond 503 when unset.
+    #[serde(skip)]
+    pub finnhub_api_key: Option<String>,
+    #[serde(skip)]
+    pub mongo_uri: String,
+    #[serde(skip)]
+    pub google_oauth: OAuthCredentials,
+    /// GitHub login is unavailable unless all three `GITHUB_*` settings are set.
+    #[serde(skip)]
+    pub github_oauth: Option<OAuthCredentials>,
+}
+
+impl Config {
+    /// Read the configuration from the environment once at startup, falling back to defaults for
+    /// unset or unparseable optional values. Every one of `REQUIRED_VARS` is checked so all the
+    /// missing ones are reported together.
+    pub fn from_env() -> Result<Self, MissingEnv> {
+        check_required(non_empty_var)?;
+
+        let finnhub_api_key = non_empty_var("FINNHUB_API_KEY");
+        if let Some(problem) = finnhub_key_problem(finnhub_api_key.as_deref()) {
+            tracing::warn!("{}", problem);
+        }
+
+        Ok(Config {
+            starting_cash: parse_var("STARTING_CASH").unwrap_or(10_000_000),
+            finnhub_request_budget: parse_var("FINNHUB_REQUEST_BUDGET")
+                .unwrap_or(crate::finnhub::DEFAULT_REQUEST_BUDGET),
+            transaction_retention_days: parse_var("TRANSACTION_RETENTION_DAYS"),
+            reconcile_interval_secs: parse_var("RECONCILE_INTERVAL_SECS"),
+            reconcile_drift_threshold: parse_var("RECONCILE_DRIFT_THRESHOLD").unwrap_or(100),
+            reconcile_auto_correct: parse_var("RECONCILE_AUTO_CORRECT").unwrap_or(false),
+            guest_mode: parse_var("ENABLE_GUEST_MODE").unwrap_or(false),
+            fee_model: FeeModel {
+                commission: parse_var("TRADE_COMMISSION").unwrap_or(0),
+                notional_bps: parse_var("TRADE_FEE_BPS").unwrap_or(0),
+                free_trades: parse_var("COMMISSION_FREE_TRADES").unwrap_or(0),
+            },
+            liquidity: parse_var("LIQUIDITY_TRANCHE_SIZE").map(|tranche_size| LiquidityModel {
+                tranche_size,
+                slippage_bps: parse_var("LIQUIDITY_SLIPPAGE_BPS").unwrap_or(10),
+            }),
+            sim: match parse_var("SIM_MODE").unwrap_or(false) {
+                true => parse_var("SIM_START_DATE").map(|start_date| SimConfig {
+                    start_date,
+                    speed: parse_var("SIM_SPEED").unwrap_or(1.0),
+                }),
+                false => None,
+            },
+            session_revalidate_secs: parse_var("SESSION_REVALIDATE_SECS"),
+            max_sessions_per_account: parse_var("MAX_SESSIONS_PER_ACCOUNT"),
+            response_envelope: parse_var("RESPONSE_ENVELOPE").unwrap_or(false),
+            delisted_after_failures: parse_var("DELISTED_AFTER_FAILURES").unwrap_or(3),
+            leaderboard_eligible_by_default: parse_var("LEADERBOARD_ELIGIBLE_BY_DEFAULT")
+                .unwrap_or(true),
+            max_concurrent_requests: parse_var("MAX_CONCURRENT_REQUESTS"),
+            logo_cdn_prefix: parse_var("LOGO_CDN_PREFIX"),
+            min_shares: parse_var("MIN_SHARES").unwrap_or(1),
+            max_shares: parse_var("MAX_SHARES").unwrap_or(DEFAULT_MAX_SHARES),
+            confirm_notional_above: parse_var("CONFIRM_NOTIONAL_ABOVE"),
+            profile_refresh_per_minute: parse_var("PROFILE_REFRESH_PER_MINUTE"),
+            after_hours_pricing: parse_var("AFTER_HOURS_PRICING").unwrap_or_default(),
+            gated_features: parse_list("GATED_FEATURES"),
+            day_trade_limit: parse_var("DAY_TRADE_LIMIT").unwrap_or(3),
+            day_trade_min_equity: parse_var("DAY_TRADE_MIN_EQUITY"),
+            max_buying_power_multiplier: parse_var("MAX_BUYING_POWER_MULTIPLIER").unwrap_or(1.0),
+            maintenance_margin_percent: parse_var("MAINTENANCE_MARGIN_PERCENT"),
+            margin_check_secs: parse_var("MARGIN_CHECK_SECS").unwrap_or(300),
+            halt_stale_after_secs: parse_var("HALT_STALE_AFTER_SECS"),
+            round_lot: match parse_var("ROUND_LOTS").unwrap_or(false) {
+                true => Some(parse_var("ROUND_LOT_SIZE").unwrap_or(100)).filter(|&size| size > 0),
+                false => None,
+            },
+            leaderboard_cache_secs: parse_var("LEADERBOARD_CACHE_SECS"),
+            sectors_cache_secs: parse_var("SECTORS_CACHE_SECS"),
+            sub_cent_pricing_below: parse_var("SUB_CENT_PRICING_BELOW"),
+            warm_after_idle_secs: parse_var("WARM_AFTER_IDLE_SECS"),
+            money_output: parse_var("MONEY_OUTPUT").unwrap_or_default(),
+            finnhub_cache_max_entries: parse_var("FINNHUB_CACHE_MAX_ENTRIES"),
+            closed_market_orders: parse_var("CLOSED_MARKET_ORDERS").unwrap_or_default(),
+            clamp_negative_cash: parse_var("CLAMP_NEGATIVE_CASH").unwrap_or(false),
+            purge_empty_holdings_secs: parse_var("PURGE_EMPTY_HOLDINGS_SECS"),
+            price_source: parse_var("PRICE_SOURCE").unwrap_or_default(),
+            short_term_tax_bps: parse_var("SHORT_TERM_TAX_BPS").unwrap_or(2400),
+            long_term_tax_bps: parse_var("LONG_TERM_TAX_BPS").unwrap_or(1500),
+            bind_addr: non_empty_var("BIND_ADDR").unwrap_or_else(|| "0.0.0.0:3000".to_string()),
+            frontend_url: non_empty_var("FRONTEND_URL")
+                .unwrap_or_else(|| "http://localhost:5173".to_string()),
+            admin_emails: parse_list("ADMIN_EMAILS"),
+            finnhub_base_url: non_empty_var("FINNHUB_BASE_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://finnhub.io/api/v1".to_string()),
+            financials_cache_secs: parse_var("FINANCIALS_CACHE_SECS"),
+            pending_buy_sells: parse_var("PENDING_BUY_SELLS").unwrap_or_default(),
+            sell_lockup_secs: parse_var("SELL_LOCKUP_SECS").filter(|secs| *secs > 0),
+            risk_free_rate_bps: parse_var("RISK_FREE_RATE_BPS").unwrap_or(0),
+            finnhub_api_key,
+            mongo_uri: non_empty_var("MONGO_URI").unwrap_or_default(),
+            google_oauth: OAuthCredentials {
+                client_id: non_empty_var("GOOGLE_CLIENT_ID").unwrap_or_default(),
+                client_secret: non_empty_var("GOOGLE_CLIENT_SECRET").unwrap_or_default(),
+                redirect_uri: non_empty_var("GOOGLE_REDIRECT_URI").unwrap_or_default(),
+            },
+            github_oauth: match (
+                non_empty_var("GITHUB_CLIENT_ID"),
+                non_empty_var("GITHUB_CLIENT_SECRET"),
+                non_empty_var("GITHUB_REDIRECT_URI"),
+            ) {
+                (Some(client_id), Some(client_secret), Some(redirect_uri)) => {
+                    Some(OAuthCredentials {
+                        client_id,
+                        client_secret,
+                        redirect_uri,
+                    })
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+/// Check that every one of `REQUIRED_VARS` has a value, as looked up by `var`, reporting all the
+/// missing ones together.
+fn check_required(var: impl Fn(&str) -> Option<String>) -> Result<(), MissingEnv> {
+    let missing: Vec<&'static str> = REQUIRED_VARS
+        .into_iter()
+        .filter(|name| var(name).is_none())
+        .collect();
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(MissingEnv(missing)),
+    }
+}
+
+/// Parse a comma-separated environment variable, ignoring blank entries.
+fn parse_list(name: &str) -> BTreeSet<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parse an environment variable, returning `None` if it is unset or invalid.
+fn parse_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            tracing::warn!("Ignoring invalid value for {}: {:?}", name, value);
+            None
+        }
+    }
+}
+
+/// Held while reading the environment into a `Config` in tests, and exclusively by tests that
+/// change it.
+#[cfg(test)]
+static ENV_LOCK: std::sync::RwLock<()> = std::sync::RwLock::new(());
+
+#[cfg(test)]
+impl Config {
+    /// The configuration a server started with only `REQUIRED_VARS` set would have.
+    pub(crate) fn for_tests() -> Config {
+        let _env = ENV_LOCK.read().unwrap_or_else(|e| e.into_inner());
+        for name in REQUIRED_VARS {
+            if non_empty_var(name).is_none() {
+                env::set_var(name, "test");
+            }
+        }
+        Config::from_env().expect("required variables are set")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_missing_and_malformed_finnhub_keys() {
+        assert!(finnhub_key_problem(None).is_some());
+        assert!(finnhub_key_problem(Some("\"abc123\"")).is_some());
+        assert!(finnhub_key_problem(Some("abc123 ")).is_some());
+        assert!(finnhub_key_problem(Some("abc123")).is_none());
+    }
+
+    #[test]
+    fn every_missing_required_var_is_reported_together() {
+        let set = |name: &str| match name {
+            "GOOGLE_CLIENT_ID" => Some(String::from("client")),
+            _ => None,
+        };
+
+        let missing = check_required(set).unwrap_err();
+
+        assert_eq!(
+            missing,
+            MissingEnv(vec![
+                "MONGO_URI",
+                "GOOGLE_CLIENT_SECRET",
+                "GOOGLE_REDIRECT_URI"
+            ])
+        );
+        assert_eq!(
+            missing.to_string(),
+            "Missing required environment variables: MONGO_URI, GOOGLE_CLIENT_SECRET, \
+             GOOGLE_REDIRECT_URI. Set them in the environment or in a .env file."
+        );
+        assert!(check_required(|_| Some(String::from("set"))).is_ok());
+    }
+
+    #[test]
+    fn config_is_parsed_from_a_populated_environment() {
+        let vars = [
+            ("STARTING_CASH", "2500000"),
+            ("ADMIN_EMAILS", "a@example.com, ,b@example.com"),
+            ("FINNHUB_BASE_URL", "http://localhost:8080/api/"),
+            ("FINNHUB_API_KEY", "abc123"),
+            ("BIND_ADDR", "127.0.0.1:4000"),
+            ("ROUND_LOTS", "true"),
+            ("MONEY_OUTPUT", "dollars"),
+            ("MAX_SHARES", "not a number"),
+            ("GITHUB_CLIENT_ID", "gh-id"),
+            ("GITHUB_CLIENT_SECRET", "gh-secret"),
+            (
+                "GITHUB_REDIRECT_URI",
+                "http://localhost:3000/auth/github/callback",
+            ),
+        ];
+        let config = {
+            let _env = ENV_LOCK.write().unwrap_or_else(|e| e.into_inner());
+            for name in REQUIRED_VARS {
+                env::set_var(name, "test");
+            }
+            for (name, value) in vars {
+                env::set_var(name, value);
+            }
+            let config = Config::from_env();
+            for (name, _) in vars {
+                env::remove_var(name);
+            }
+            config.unwrap()
+        };
+
+        assert_eq!(config.starting_cash, 2_500_000);
+        assert_eq!(
+            config.admin_emails,
+            BTreeSet::from([String::from("a@example.com"), String::from("b@example.com")])
+        );
+        assert_eq!(config.finnhub_base_url, "http://localhost:8080/api");
+        assert_eq!(config.finnhub_api_key.as_deref(), Some("abc123"));
+        assert_eq!(config.bind_addr, "127.0.0.1:4000");
+        assert_eq!(config.round_lot, Some(100));
+        assert_eq!(config.money_output, MoneyOutput::Dollars);
+        // Unparseable values fall back to the default
+        assert_eq!(config.max_shares, DEFAULT_MAX_SHARES);
+        assert_eq!(config.mongo_uri, "test");
+        assert_eq!(config.github_oauth.unwrap().client_id, "gh-id");
+    }
+}
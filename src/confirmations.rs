@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a large order waits for confirmation before its token expires.
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(60);
+
+/// A buy held back until the user confirms it.
+#[derive(Debug, Clone)]
+pub struct PendingOrder {
+    pub account_id: String,
+    pub stock_symbol: String,
+    pub quantity: i32,
+    pub note: Option<String>,
+}
+
+/// Buys above `CONFIRM_NOTIONAL_ABOVE` awaiting confirmation, keyed by confirmation token.
+/// Tokens live in memory, so pending orders are lost on restart.
+#[derive(Clone, Default)]
+pub struct PendingOrders {
+    orders: Arc<Mutex<HashMap<String, (PendingOrder, Instant)>>>,
+}
+
+impl PendingOrders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold an order and return the token that confirms it.
+    pub fn hold(&self, order: PendingOrder) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut orders = self.orders.lock().unwrap();
+        // Drop expired orders so abandoned confirmations don't accumulate
+        orders.retain(|_, (_, held_at)| held_at.elapsed() < CONFIRMATION_TTL);
+        orders.insert(token.clone(), (order, Instant::now()));
+        token
+    }
+
+    /// Remove and return the order for `token` if it belongs to `account_id` and has not
+    /// expired. Each token confirms at most one order.
+    pub fn take(&self, token: &str, account_id: &str) -> Option<PendingOrder> {
+        let mut orders = self.orders.lock().unwrap();
+        match orders.get(token) {
+            Some((order, _)) if order.account_id != account_id => return None,
+            None => return None,
+            Some(_) => {}
+        }
+        let (order, held_at) = orders.remove(token)?;
+        (held_at.elapsed() < CONFIRMATION_TTL).then_some(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(account_id: &str) -> PendingOrder {
+        PendingOrder {
+            account_id: account_id.to_string(),
+            stock_symbol: String::from("AAPL"),
+            quantity: 1_000,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn a_token_confirms_its_order_once_for_its_owner() {
+        let pending = PendingOrders::new();
+        let token = pending.hold(order("a@example.com"));
+
+        assert!(pending.take("not-a-token", "a@example.com").is_none());
+        assert!(pending.take(&token, "b@example.com").is_none());
+        let confirmed = pending.take(&token, "a@example.com").unwrap();
+        assert_eq!(confirmed.quantity, 1_000);
+        assert!(pending.take(&token, "a@example.com").is_none());
+    }
+
+    #[test]
+    fn expired_tokens_confirm_nothing() {
+        let pending = PendingOrders::new();
+        let held_at = Instant::now() - CONFIRMATION_TTL;
+        pending
+            .orders
+            .lock()
+            .unwrap()
+            .insert(String::from("stale"), (order("a@example.com"), held_at));
+
+        assert!(pending.take("stale", "a@example.com").is_none());
+    }
+}
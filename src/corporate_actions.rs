@@ -0,0 +1,179 @@
+use crate::models::{CorporateAction, CorporateActionRecord, CorporateActionRequest, Transaction};
+use crate::money::notional;
+use crate::store::{Store, StoreError};
+use tokio::sync::Mutex;
+
+/// Serializes corporate actions so two requests with the same id can't both apply it.
+static APPLYING: Mutex<()> = Mutex::const_new(());
+
+/// Apply a corporate action across all accounts and record it in the audit log. An action whose
+/// id was already applied changes nothing. Returns the audit entry and whether it was applied now.
+pub async fn apply(
+    store: &dyn Store,
+    request: CorporateActionRequest,
+    applied_by: &str,
+) -> Result<(CorporateActionRecord, bool), StoreError> {
+    let _guard = APPLYING.lock().await;
+    if let Some(record) = store.get_corporate_action(&request.action_id).await? {
+        tracing::info!("Corporate action {} already applied", request.action_id);
+        return Ok((record, false));
+    }
+
+    // Every write goes through the transaction, so a failure changes nothing
+    let txn = store.start_transaction().await?;
+    let result = async {
+        let (holdings_changed, transactions_changed) =
+            apply_action(txn.store(), &request.action).await?;
+        let record = CorporateActionRecord {
+            action_id: request.action_id,
+            action: request.action,
+            applied_at: crate::timestamps::now(),
+            applied_by: applied_by.to_string(),
+            holdings_changed,
+            transactions_changed,
+        };
+        txn.store().add_corporate_action(record.clone()).await?;
+        Ok::<_, StoreError>(record)
+    }
+    .await;
+    match result {
+        Ok(record) => {
+            txn.commit().await?;
+            tracing::info!("Applied corporate action {}", record.action_id);
+            Ok((record, true))
+        }
+        Err(e) => {
+            txn.abort().await?;
+            Err(e)
+        }
+    }
+}
+
+/// Rewrite holdings and transactions for an action. Returns the holdings and transactions changed.
+async fn apply_action(
+    store: &dyn Store,
+    action: &CorporateAction,
+) -> Result<(i64, i64), StoreError> {
+    match action {
+        CorporateAction::TickerChange { from, to } => {
+            let (holdings, transactions) = store.rename_symbol(from, to).await?;
+            Ok((holdings as i64, transactions as i64))
+        }
+        CorporateAction::CashMerger {
+            stock_symbol,
+            cash_per_share,
+        } => {
+            let holdings = store.get_holdings_by_symbol(stock_symbol).await?;
+            for holding in &holdings {
+                let Some(account) = store.get_account(&holding.account_id).await? else {
+                    continue;
+                };
+                let proceeds = notional(*cash_per_share, holding.quantity);
+                store
+                    .update_account(
+                        &account.id,
+                        account.value as i64,
+                        account.cash as i64 + proceeds,
+                    )
+                    .await?;
+                store.delete_holding(&account.id, stock_symbol).await?;
+                store
+                    .add_transaction(Transaction {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        account_id: account.id.clone(),
+                        stock_symbol: stock_symbol.clone(),
+                        transaction_type: String::from("SELL"),
+                        quantity: holding.quantity,
+                        price: *cash_per_share,
+                        price_tenths: None,
+                        timestamp: crate::timestamps::now(),
+                        fee: 0,
+                        realized_pnl_cents: Some(
+                            (proceeds
+                                - holding
+                                    .purchase_share_price()
+                                    .value(holding.quantity as i64))
+                                as i32,
+                        ),
+                        note: None,
+                    })
+                    .await?;
+                store.increment_account_version(&account.id).await?;
+            }
+            Ok((holdings.len() as i64, holdings.len() as i64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Holding;
+    use crate::store::MemoryStore;
+
+    fn holding(account_id: &str, symbol: &str, quantity: i32, purchase_price: i32) -> Holding {
+        Holding {
+            account_id: account_id.to_string(),
+            stock_symbol: symbol.to_string(),
+            stock_name: symbol.to_string(),
+            quantity,
+            purchase_price,
+            ..Default::default()
+        }
+    }
+
+    fn rename() -> CorporateActionRequest {
+        CorporateActionRequest {
+            action_id: String::from("rename-fb"),
+            action: CorporateAction::TickerChange {
+                from: String::from("FB"),
+                to: String::from("META"),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn a_repeated_ticker_change_applies_once() {
+        let store = MemoryStore::new();
+        store
+            .add_holding(holding("a", "FB", 10, 1_000))
+            .await
+            .unwrap();
+        store
+            .add_holding(holding("b", "FB", 5, 1_000))
+            .await
+            .unwrap();
+        store
+            .add_holding(holding("b", "META", 5, 2_000))
+            .await
+            .unwrap();
+        store
+            .add_transaction(Transaction {
+                account_id: String::from("a"),
+                stock_symbol: String::from("FB"),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let (record, applied) = apply(&store, rename(), "admin@example.com").await.unwrap();
+        assert!(applied);
+        assert_eq!(record.holdings_changed, 2);
+        assert_eq!(record.transactions_changed, 1);
+
+        let (again, applied) = apply(&store, rename(), "admin@example.com").await.unwrap();
+        assert!(!applied);
+        assert_eq!(again.applied_at, record.applied_at);
+
+        assert!(store.get_holdings_by_symbol("FB").await.unwrap().is_empty());
+        let a = store.get_holding("a", "META").await.unwrap().unwrap();
+        assert_eq!((a.quantity, a.purchase_price), (10, 1_000));
+        let b = store.get_holdings("b").await.unwrap();
+        assert_eq!(b.len(), 1);
+        assert_eq!((b[0].quantity, b[0].purchase_price), (10, 1_500));
+        assert_eq!(
+            store.get_transactions("a").await.unwrap()[0].stock_symbol,
+            "META"
+        );
+    }
+}
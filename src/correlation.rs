@@ -0,0 +1,104 @@
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+/// Fewest daily returns two series must share for their correlation to be reported.
+pub const MIN_SHARED_RETURNS: usize = 20;
+
+/// Pearson correlation of two equally long samples, or `None` if either has no variance or
+/// there are fewer than two samples.
+pub fn pearson(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return None;
+    }
+    let (a, b) = (&a[..n], &b[..n]);
+    let mean_a = a.iter().sum::<f64>() / n as f64;
+    let mean_b = b.iter().sum::<f64>() / n as f64;
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return None;
+    }
+    Some((covariance / (variance_a * variance_b).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Daily returns of two close series over the days both have a close, so a stock and a crypto
+/// pair that also trades on weekends line up.
+fn shared_returns(
+    a: &BTreeMap<NaiveDate, f64>,
+    b: &BTreeMap<NaiveDate, f64>,
+) -> (Vec<f64>, Vec<f64>) {
+    let closes: Vec<(f64, f64)> = a
+        .iter()
+        .filter_map(|(date, close)| Some((*close, *b.get(date)?)))
+        .filter(|(x, y)| *x > 0.0 && *y > 0.0)
+        .collect();
+    closes
+        .windows(2)
+        .map(|pair| (pair[1].0 / pair[0].0 - 1.0, pair[1].1 / pair[0].1 - 1.0))
+        .unzip()
+}
+
+/// Pairwise correlations of the daily returns of each series of closes. The diagonal is 1.0, and
+/// pairs sharing fewer than `MIN_SHARED_RETURNS` returns, or without any movement, are `None`.
+pub fn correlation_matrix(series: &[BTreeMap<NaiveDate, f64>]) -> Vec<Vec<Option<f64>>> {
+    let mut matrix = vec![vec![None; series.len()]; series.len()];
+    for i in 0..series.len() {
+        matrix[i][i] = Some(1.0);
+        for j in i + 1..series.len() {
+            let (a, b) = shared_returns(&series[i], &series[j]);
+            let correlation = match a.len() {
+                n if n >= MIN_SHARED_RETURNS => pearson(&a, &b),
+                _ => None,
+            };
+            matrix[i][j] = correlation;
+            matrix[j][i] = correlation;
+        }
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Closes on consecutive days from March 1, 2024.
+    fn series(closes: impl Iterator<Item = f64>) -> BTreeMap<NaiveDate, f64> {
+        let start = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        closes
+            .enumerate()
+            .map(|(i, close)| (start + chrono::Duration::days(i as i64), close))
+            .collect()
+    }
+
+    #[test]
+    fn series_moving_together_correlate_at_one() {
+        let wobble = |scale: f64| (0..30).map(move |i| scale * (100.0 + (i * 7 % 11) as f64));
+        let matrix = correlation_matrix(&[
+            series(wobble(1.0)),
+            series(wobble(3.0)),
+            series(wobble(1.0).take(5)),
+        ]);
+
+        assert!((matrix[0][1].unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(matrix[0][1], matrix[1][0]);
+        assert_eq!(matrix[2][2], Some(1.0));
+        // Too little shared history to report
+        assert_eq!(matrix[0][2], None);
+    }
+
+    #[test]
+    fn opposite_returns_correlate_at_minus_one() {
+        assert_eq!(
+            pearson(&[0.01, -0.02, 0.03], &[-0.01, 0.02, -0.03]),
+            Some(-1.0)
+        );
+        assert_eq!(pearson(&[0.01, 0.01], &[0.02, -0.02]), None);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::models::Transaction;
+use crate::pnl::parse_timestamp;
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use std::collections::HashMap;
+
+/// Trading days in the rolling window day trades are counted over.
+pub const WINDOW_TRADING_DAYS: usize = 5;
+
+/// First day of the window of `WINDOW_TRADING_DAYS` weekdays ending on `today`.
+pub fn window_start(today: NaiveDate) -> NaiveDate {
+    let mut start = today;
+    let mut counted = 0;
+    loop {
+        if !matches!(start.weekday(), Weekday::Sat | Weekday::Sun) {
+            counted += 1;
+            if counted == WINDOW_TRADING_DAYS {
+                return start;
+            }
+        }
+        start -= chrono::Duration::days(1);
+    }
+}
+
+/// Count day trades made from `from` through `until`, by UTC date. A day trade is a sell that
+/// closes shares of a symbol bought earlier the same day; selling shares held overnight is not
+/// one, and a buy followed by several partial sells counts once per sell.
+pub fn count_day_trades(transactions: &[Transaction], from: NaiveDate, until: NaiveDate) -> usize {
+    let mut ordered: Vec<(NaiveDate, &Transaction)> = transactions
+        .iter()
+        .filter_map(|t| Some((parse_timestamp(t)?.with_timezone(&Utc).date_naive(), t)))
+        .filter(|(date, _)| *date >= from && *date <= until)
+        .collect();
+    ordered.sort_by_key(|(_, t)| parse_timestamp(t));
+
+    // Shares bought and not yet sold, per symbol and day
+    let mut opened: HashMap<(&str, NaiveDate), i32> = HashMap::new();
+    let mut count = 0;
+    for (date, transaction) in ordered {
+        let open = opened
+            .entry((transaction.stock_symbol.as_str(), date))
+            .or_default();
+        match transaction.transaction_type.as_str() {
+            "BUY" => *open += transaction.quantity,
+            "SELL" if *open > 0 => {
+                *open -= transaction.quantity.min(*open);
+                count += 1;
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+/// Whether selling `symbol` on `today` would be a day trade, i.e. shares bought today are still
+/// held.
+pub fn is_day_trade(transactions: &[Transaction], symbol: &str, today: NaiveDate) -> bool {
+    let mut open = 0;
+    let mut ordered: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| t.stock_symbol == symbol)
+        .filter(|t| {
+            parse_timestamp(t).is_some_and(|ts| ts.with_timezone(&Utc).date_naive() == today)
+        })
+        .collect();
+    ordered.sort_by_key(|t| parse_timestamp(t));
+    for transaction in ordered {
+        match transaction.transaction_type.as_str() {
+            "BUY" => open += transaction.quantity,
+            "SELL" => open -= transaction.quantity.min(open),
+            _ => {}
+        }
+    }
+    open > 0
+}
@@ -1,17 +1,49 @@
-use crate::models::{Account, Holding, Transaction};
+use crate::models::{
+    Account, AccountSettings, CorporateActionRecord, Holding, MarginCallRecord, QueuedOrder,
+    SnapshotRestoreRecord, Transaction, TransactionSummary, UpdateSettings, ValueDrift,
+    ValueSnapshot,
+};
+use crate::money::SharePrice;
 use futures_util::TryStreamExt;
 use mongodb::{
-    bson::doc,
-    options::{ClientOptions, ServerApi, ServerApiVersion},
-    Client, Collection,
+    action::Find,
+    bson::{doc, Bson, Document},
+    options::{ClientOptions, ServerApi, ServerApiVersion, UpdateOptions},
+    Client, ClientSession, Collection,
 };
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Run a MongoDB action, in the pool's transaction when it has one.
+macro_rules! exec {
+    ($pool:expr, $action:expr) => {
+        match &$pool.session {
+            Some(session) => $action.session(&mut *session.lock().await).await,
+            None => $action.await,
+        }
+    };
+}
 
 #[derive(Clone)]
 pub struct DatabasePool {
     pub accounts: Collection<Account>,
     pub holdings: Collection<Holding>,
     pub transactions: Collection<Transaction>,
+    pub archived_transactions: Collection<Transaction>,
+    pub transaction_summaries: Collection<TransactionSummary>,
+    pub settings: Collection<AccountSettings>,
+    pub value_drifts: Collection<ValueDrift>,
+    pub corporate_actions: Collection<CorporateActionRecord>,
+    pub value_snapshots: Collection<ValueSnapshot>,
+    pub queued_orders: Collection<QueuedOrder>,
+    pub snapshot_restores: Collection<SnapshotRestoreRecord>,
+    pub margin_calls: Collection<MarginCallRecord>,
     pub client: Client,
+    /// Session of the transaction this pool's operations run in, for pools returned by
+    /// `begin_transaction`.
+    session: Option<Arc<Mutex<ClientSession>>>,
 }
 
 impl DatabasePool {
@@ -28,16 +60,79 @@ impl DatabasePool {
         db.run_command(doc! { "ping": 1 }).await?;
         tracing::info!("Connected to MongoDB");
 
-        Ok(Self {
+        Ok(Self::with_client(client))
+    }
+
+    /// A pool over the `user_data` database of `client`.
+    fn with_client(client: Client) -> Self {
+        let db = client.database("user_data");
+        Self {
             accounts: db.collection::<Account>("accounts"),
             holdings: db.collection::<Holding>("holdings"),
             transactions: db.collection::<Transaction>("transactions"),
+            archived_transactions: db.collection::<Transaction>("archived_transactions"),
+            transaction_summaries: db.collection::<TransactionSummary>("transaction_summaries"),
+            settings: db.collection::<AccountSettings>("settings"),
+            value_drifts: db.collection::<ValueDrift>("value_drifts"),
+            corporate_actions: db.collection::<CorporateActionRecord>("corporate_actions"),
+            value_snapshots: db.collection::<ValueSnapshot>("value_snapshots"),
+            queued_orders: db.collection::<QueuedOrder>("queued_orders"),
+            snapshot_restores: db.collection::<SnapshotRestoreRecord>("snapshot_restores"),
+            margin_calls: db.collection::<MarginCallRecord>("margin_calls"),
             client,
+            session: None,
+        }
+    }
+
+    /// Start a transaction. Operations on the returned pool run in it until it is committed or
+    /// aborted; operations on `self` stay outside it.
+    pub async fn begin_transaction(&self) -> Result<DatabasePool, mongodb::error::Error> {
+        let mut session = self.client.start_session().await?;
+        session.start_transaction().await?;
+        Ok(DatabasePool {
+            session: Some(Arc::new(Mutex::new(session))),
+            ..self.clone()
         })
     }
 
-    pub async fn add_account(&self, account: Account) -> Result<(), mongodb::error::Error> {
-        self.accounts.insert_one(account).await?;
+    /// Commit the transaction started by `begin_transaction`. Does nothing outside one.
+    pub async fn commit_transaction(&self) -> Result<(), mongodb::error::Error> {
+        match &self.session {
+            Some(session) => session.lock().await.commit_transaction().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Abort the transaction started by `begin_transaction`, discarding its writes. Does nothing
+    /// outside one.
+    pub async fn abort_transaction(&self) -> Result<(), mongodb::error::Error> {
+        match &self.session {
+            Some(session) => session.lock().await.abort_transaction().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Collect every document a find returns, in the pool's transaction when it has one.
+    async fn collect<T>(&self, find: Find<'_, T>) -> Result<Vec<T>, mongodb::error::Error>
+    where
+        T: DeserializeOwned + Send + Sync + Unpin,
+    {
+        match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = find.session(&mut *session).await?;
+                cursor.stream(&mut session).try_collect().await
+            }
+            None => find.await?.try_collect().await,
+        }
+    }
+
+    /// Insert an account, stamping its creation and update times.
+    pub async fn add_account(&self, mut account: Account) -> Result<(), mongodb::error::Error> {
+        let now = crate::timestamps::now();
+        account.created_at = Some(now.clone());
+        account.updated_at = Some(now);
+        exec!(self, self.accounts.insert_one(account))?;
         Ok(())
     }
 
@@ -46,10 +141,13 @@ impl DatabasePool {
         account_id: &str,
     ) -> Result<Option<Account>, mongodb::error::Error> {
         let filter = doc! { "id": account_id };
-        let accounts = &self.accounts;
-        let account = accounts.find_one(filter).await?;
+        let account = exec!(self, self.accounts.find_one(filter))?;
         Ok(account)
     }
+    pub async fn get_accounts(&self) -> Result<Vec<Account>, mongodb::error::Error> {
+        let accounts: Vec<Account> = self.collect(self.accounts.find(doc! {})).await?;
+        Ok(accounts)
+    }
     pub async fn update_account(
         &self,
         account_id: &str,
@@ -60,22 +158,76 @@ impl DatabasePool {
         let update = doc! {
             "$set": {
                 "value": new_value,
-                "cash": new_cash
+                "cash": new_cash,
+                "updated_at": crate::timestamps::now()
+            }
+        };
+        exec!(self, self.accounts.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn increment_account_version(
+        &self,
+        account_id: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "id": account_id };
+        let update = doc! {
+            "$inc": { "version": 1 },
+            "$set": { "updated_at": crate::timestamps::now() }
+        };
+        exec!(self, self.accounts.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn increment_trades_count(
+        &self,
+        account_id: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "id": account_id };
+        let update = doc! { "$inc": { "trades_count": 1 } };
+        exec!(self, self.accounts.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn set_leaderboard_eligibility(
+        &self,
+        account_id: &str,
+        eligible: bool,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "id": account_id };
+        let update = doc! {
+            "$set": {
+                "eligible_for_leaderboard": eligible,
+                "eligibility_locked": true,
+                "updated_at": crate::timestamps::now()
+            }
+        };
+        exec!(self, self.accounts.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn flag_pattern_day_trader(
+        &self,
+        account_id: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "id": account_id };
+        let update = doc! {
+            "$set": {
+                "pattern_day_trader": true,
+                "updated_at": crate::timestamps::now()
             }
         };
-        let accounts = &self.accounts;
-        accounts.update_one(filter, update).await?;
+        exec!(self, self.accounts.update_one(filter, update))?;
         Ok(())
     }
     pub async fn _delete_account(&self, account_id: &str) -> Result<(), mongodb::error::Error> {
         let filter = doc! { "id": account_id };
-        let accounts = &self.accounts;
-        accounts.delete_one(filter).await?;
+        exec!(self, self.accounts.delete_one(filter))?;
         Ok(())
     }
 
-    pub async fn add_holding(&self, holding: Holding) -> Result<(), mongodb::error::Error> {
-        self.holdings.insert_one(holding).await?;
+    /// Insert a holding, stamping when the position was opened and last updated.
+    pub async fn add_holding(&self, mut holding: Holding) -> Result<(), mongodb::error::Error> {
+        let now = crate::timestamps::now();
+        holding.created_at = Some(now.clone());
+        holding.updated_at = Some(now);
+        exec!(self, self.holdings.insert_one(holding))?;
         Ok(())
     }
     pub async fn get_holding(
@@ -84,8 +236,7 @@ impl DatabasePool {
         stock_symbol: &str,
     ) -> Result<Option<Holding>, mongodb::error::Error> {
         let filter = doc! { "account_id": account_id, "stock_symbol": stock_symbol };
-        let holdings = &self.holdings;
-        let holding = holdings.find_one(filter).await?;
+        let holding = exec!(self, self.holdings.find_one(filter))?;
         Ok(holding)
     }
 
@@ -94,9 +245,7 @@ impl DatabasePool {
         account_id: &str,
     ) -> Result<Vec<Holding>, mongodb::error::Error> {
         let filter = doc! { "account_id": account_id };
-        let x = &self.holdings;
-        let cursor = x.find(filter).await?;
-        let holdings: Vec<Holding> = cursor.try_collect().await?;
+        let holdings: Vec<Holding> = self.collect(self.holdings.find(filter)).await?;
         Ok(holdings)
     }
     pub async fn update_holding(
@@ -104,17 +253,99 @@ impl DatabasePool {
         account_id: &str,
         stock_symbol: &str,
         quantity: i64,
-        purchase_price: i64,
+        purchase_price: SharePrice,
     ) -> Result<(), mongodb::error::Error> {
         let filter = doc! { "account_id": account_id, "stock_symbol": stock_symbol };
         let update = doc! {
             "$set": {
-                "quantity": quantity,
-                "purchase_price": purchase_price
+                "quantity": quantity as i32,
+                "purchase_price": purchase_price.cents(),
+                "purchase_price_tenths": purchase_price.sub_cent(),
+                "updated_at": crate::timestamps::now()
             }
         };
-        let holdings = &self.holdings;
-        holdings.update_one(filter, update).await?;
+        exec!(self, self.holdings.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn update_holding_name(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        stock_name: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id, "stock_symbol": stock_symbol };
+        let update = doc! {
+            "$set": { "stock_name": stock_name, "updated_at": crate::timestamps::now() }
+        };
+        exec!(self, self.holdings.update_one(filter, update))?;
+        Ok(())
+    }
+    pub async fn mark_holding_delisted(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        last_price: i64,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id, "stock_symbol": stock_symbol };
+        let update = doc! {
+            "$set": {
+                "delisted": true,
+                "current_price": last_price as i32,
+                "updated_at": crate::timestamps::now()
+            }
+        };
+        exec!(self, self.holdings.update_one(filter, update))?;
+        Ok(())
+    }
+    /// Store each `(symbol, price)` as the holding's current price and update its total value to
+    /// match its quantity, in a single update. Prices are written as 32-bit integers like the rest
+    /// of the holding, with the tenths of a cent alongside when the price has them.
+    pub async fn update_holdings_prices(
+        &self,
+        account_id: &str,
+        prices: &[(String, SharePrice)],
+    ) -> Result<(), mongodb::error::Error> {
+        if prices.is_empty() {
+            return Ok(());
+        }
+        let symbols: Vec<&str> = prices.iter().map(|(symbol, _)| symbol.as_str()).collect();
+        let branches = |price_of: fn(SharePrice) -> Bson| -> Vec<Document> {
+            prices
+                .iter()
+                .map(|(symbol, price)| {
+                    doc! { "case": { "$eq": ["$stock_symbol", symbol] }, "then": price_of(*price) }
+                })
+                .collect()
+        };
+        let cents = branches(|price| Bson::from(price.cents()));
+        let tenths = branches(|price| Bson::from(price.sub_cent()));
+        let filter = doc! { "account_id": account_id, "stock_symbol": { "$in": symbols } };
+        // A pipeline update so each total can be computed from the stored quantity
+        let update = vec![
+            doc! {
+                "$set": {
+                    "current_price": {
+                        "$switch": { "branches": cents, "default": "$current_price" }
+                    },
+                    "current_price_tenths": {
+                        "$switch": { "branches": tenths, "default": "$current_price_tenths" }
+                    },
+                    "updated_at": crate::timestamps::now()
+                }
+            },
+            doc! {
+                "$set": {
+                    "total_value": { "$toInt": { "$trunc": { "$divide": [
+                        { "$multiply": ["$quantity", { "$ifNull": [
+                            "$current_price_tenths",
+                            { "$multiply": ["$current_price", 10] }
+                        ] }] },
+                        10
+                    ] } } }
+                }
+            },
+        ];
+        exec!(self, self.holdings.update_many(filter, update))?;
         Ok(())
     }
     pub async fn delete_holding(
@@ -123,14 +354,89 @@ impl DatabasePool {
         stock_symbol: &str,
     ) -> Result<(), mongodb::error::Error> {
         let filter = doc! { "account_id": account_id, "stock_symbol": stock_symbol };
-        self.holdings.delete_one(filter).await?;
+        exec!(self, self.holdings.delete_one(filter))?;
         Ok(())
     }
+    /// Delete an account's holdings left at zero or fewer shares by a failed or partial trade,
+    /// bumping the account's version if any were removed. Returns the removed holdings.
+    pub async fn purge_empty_holdings(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<Holding>, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id, "quantity": { "$lte": 0 } };
+        let empty: Vec<Holding> = self.collect(self.holdings.find(filter.clone())).await?;
+        if empty.is_empty() {
+            return Ok(empty);
+        }
+        exec!(self, self.holdings.delete_many(filter))?;
+        self.increment_account_version(account_id).await?;
+        for holding in &empty {
+            tracing::info!(
+                "Purged empty {} holding for {}",
+                holding.stock_symbol,
+                account_id
+            );
+        }
+        Ok(empty)
+    }
+    /// Merge an account's holdings that share a symbol, left over from before buys were
+    /// serialized, into a single holding per symbol. Returns the merged holdings. The merge runs
+    /// in a transaction, so a failure partway leaves the duplicates as they were.
+    pub async fn dedupe_holdings(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<Holding>, mongodb::error::Error> {
+        let txn = self.begin_transaction().await?;
+        let merged = match txn.merge_duplicate_holdings(account_id).await {
+            Ok(merged) => merged,
+            Err(e) => {
+                txn.abort_transaction().await?;
+                return Err(e);
+            }
+        };
+        txn.commit_transaction().await?;
+        for holding in &merged {
+            tracing::info!(
+                "Merged duplicate {} holdings for {}",
+                holding.stock_symbol,
+                account_id
+            );
+        }
+        Ok(merged)
+    }
+    /// Replace each symbol's duplicate holdings with their merge. Returns the merged holdings.
+    async fn merge_duplicate_holdings(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<Holding>, mongodb::error::Error> {
+        let mut by_symbol: BTreeMap<String, Vec<Holding>> = BTreeMap::new();
+        for holding in self.get_holdings(account_id).await? {
+            by_symbol
+                .entry(holding.stock_symbol.clone())
+                .or_default()
+                .push(holding);
+        }
+
+        let mut merged = Vec::new();
+        for (stock_symbol, duplicates) in by_symbol {
+            if duplicates.len() < 2 {
+                continue;
+            }
+            let Some(holding) = Holding::merge(duplicates) else {
+                continue;
+            };
+            let filter = doc! { "account_id": account_id, "stock_symbol": &stock_symbol };
+            exec!(self, self.holdings.delete_many(filter))?;
+            exec!(self, self.holdings.insert_one(holding.clone()))?;
+            merged.push(holding);
+        }
+        Ok(merged)
+    }
     pub async fn add_transaction(
         &self,
         transaction: Transaction,
     ) -> Result<(), mongodb::error::Error> {
-        self.transactions.insert_one(transaction).await?;
+        exec!(self, self.transactions.insert_one(transaction))?;
         Ok(())
     }
     pub async fn get_transactions(
@@ -138,9 +444,360 @@ impl DatabasePool {
         account_id: &str,
     ) -> Result<Vec<Transaction>, mongodb::error::Error> {
         let filter = doc! { "account_id": account_id };
-        let transactions = &self.transactions;
-        let cursor = transactions.find(filter).await?;
-        let transactions: Vec<Transaction> = cursor.try_collect().await?;
+        let transactions: Vec<Transaction> = self.collect(self.transactions.find(filter)).await?;
         Ok(transactions)
     }
+    pub async fn delete_transactions(&self, ids: &[String]) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "id": { "$in": ids } };
+        exec!(self, self.transactions.delete_many(filter))?;
+        Ok(())
+    }
+    pub async fn archive_transactions(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<(), mongodb::error::Error> {
+        exec!(self, self.archived_transactions.insert_many(transactions))?;
+        Ok(())
+    }
+    pub async fn get_transaction_summaries(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<TransactionSummary>, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        let summaries: Vec<TransactionSummary> = self
+            .collect(self.transaction_summaries.find(filter))
+            .await?;
+        Ok(summaries)
+    }
+    /// Record an account's value for a day, replacing any earlier snapshot from the same day.
+    pub async fn record_snapshot(
+        &self,
+        snapshot: ValueSnapshot,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter = doc! {
+            "account_id": &snapshot.account_id,
+            "date": snapshot.date.to_string()
+        };
+        exec!(
+            self,
+            self.value_snapshots
+                .replace_one(filter, snapshot)
+                .upsert(true)
+        )?;
+        Ok(())
+    }
+    /// Get an account's snapshots, oldest first.
+    pub async fn get_snapshots(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<ValueSnapshot>, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        self.collect(self.value_snapshots.find(filter).sort(doc! { "date": 1 }))
+            .await
+    }
+    /// Get an account's snapshot from a day.
+    pub async fn get_snapshot(
+        &self,
+        account_id: &str,
+        date: chrono::NaiveDate,
+    ) -> Result<Option<ValueSnapshot>, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id, "date": date.to_string() };
+        exec!(self, self.value_snapshots.find_one(filter))
+    }
+    /// Replace all of an account's holdings. The delete and insert are separate writes, so call this
+    /// on a pool from `begin_transaction` to make the swap atomic.
+    pub async fn replace_holdings(
+        &self,
+        account_id: &str,
+        holdings: Vec<Holding>,
+    ) -> Result<(), mongodb::error::Error> {
+        exec!(
+            self,
+            self.holdings.delete_many(doc! { "account_id": account_id })
+        )?;
+        if !holdings.is_empty() {
+            exec!(self, self.holdings.insert_many(holdings))?;
+        }
+        Ok(())
+    }
+    pub async fn add_snapshot_restore(
+        &self,
+        record: SnapshotRestoreRecord,
+    ) -> Result<(), mongodb::error::Error> {
+        exec!(self, self.snapshot_restores.insert_one(record))?;
+        Ok(())
+    }
+    pub async fn upsert_transaction_summary(
+        &self,
+        summary: TransactionSummary,
+    ) -> Result<(), mongodb::error::Error> {
+        let filter =
+            doc! { "account_id": &summary.account_id, "stock_symbol": &summary.stock_symbol };
+        let update = doc! {
+            "$set": {
+                "quantity": summary.quantity,
+                "cost_basis": summary.cost_basis,
+                "realized_pnl": summary.realized_pnl,
+                "archived_count": summary.archived_count
+            }
+        };
+        exec!(
+            self,
+            self.transaction_summaries
+                .update_one(filter, update)
+                .with_options(UpdateOptions::builder().upsert(true).build())
+        )?;
+        Ok(())
+    }
+
+    /// Get an account's settings, or the defaults if none have been saved.
+    pub async fn get_settings(
+        &self,
+        account_id: &str,
+    ) -> Result<AccountSettings, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        let settings = exec!(self, self.settings.find_one(filter))?;
+        Ok(settings.unwrap_or_else(|| AccountSettings {
+            account_id: account_id.to_string(),
+            ..Default::default()
+        }))
+    }
+    /// Merge the provided fields into an account's settings and return the result.
+    pub async fn update_settings(
+        &self,
+        account_id: &str,
+        update: &UpdateSettings,
+    ) -> Result<AccountSettings, mongodb::error::Error> {
+        let set = mongodb::bson::to_document(update)?;
+        if !set.is_empty() {
+            let filter = doc! { "account_id": account_id };
+            exec!(
+                self,
+                self.settings
+                    .update_one(filter, doc! { "$set": set })
+                    .with_options(UpdateOptions::builder().upsert(true).build())
+            )?;
+        }
+        self.get_settings(account_id).await
+    }
+
+    /// Grant or revoke a gated feature for an account and return its updated settings.
+    pub async fn set_feature(
+        &self,
+        account_id: &str,
+        feature: &str,
+        enabled: bool,
+    ) -> Result<AccountSettings, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        let update = match enabled {
+            true => doc! { "$addToSet": { "features": feature } },
+            false => doc! { "$pull": { "features": feature } },
+        };
+        exec!(
+            self,
+            self.settings
+                .update_one(filter, update)
+                .with_options(UpdateOptions::builder().upsert(true).build())
+        )?;
+        self.get_settings(account_id).await
+    }
+
+    pub async fn add_queued_order(&self, order: QueuedOrder) -> Result<(), mongodb::error::Error> {
+        exec!(self, self.queued_orders.insert_one(order))?;
+        Ok(())
+    }
+    /// Get queued orders, oldest first, for one account or every account.
+    pub async fn get_queued_orders(
+        &self,
+        account_id: Option<&str>,
+    ) -> Result<Vec<QueuedOrder>, mongodb::error::Error> {
+        let filter = match account_id {
+            Some(account_id) => doc! { "account_id": account_id },
+            None => doc! {},
+        };
+        self.collect(
+            self.queued_orders
+                .find(filter)
+                .sort(doc! { "placed_at": 1 }),
+        )
+        .await
+    }
+    /// Remove a queued order. Returns false if the account has no such order.
+    pub async fn delete_queued_order(
+        &self,
+        account_id: &str,
+        id: &str,
+    ) -> Result<bool, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id, "id": id };
+        let result = exec!(self, self.queued_orders.delete_one(filter))?;
+        Ok(result.deleted_count > 0)
+    }
+    /// Cash set aside for an account's queued buys, in cents.
+    pub async fn reserved_cash(&self, account_id: &str) -> Result<i64, mongodb::error::Error> {
+        let orders = self.get_queued_orders(Some(account_id)).await?;
+        Ok(orders.iter().map(|order| order.reserved).sum())
+    }
+
+    /// Record the latest drift for an account, replacing any earlier record.
+    pub async fn flag_value_drift(&self, drift: ValueDrift) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "account_id": &drift.account_id };
+        exec!(
+            self,
+            self.value_drifts.replace_one(filter, drift).upsert(true)
+        )?;
+        Ok(())
+    }
+    pub async fn clear_value_drift(&self, account_id: &str) -> Result<(), mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        exec!(self, self.value_drifts.delete_one(filter))?;
+        Ok(())
+    }
+    pub async fn get_value_drifts(&self) -> Result<Vec<ValueDrift>, mongodb::error::Error> {
+        let drifts: Vec<ValueDrift> = self.collect(self.value_drifts.find(doc! {})).await?;
+        Ok(drifts)
+    }
+
+    /// Get every account's holding of a symbol.
+    pub async fn get_holdings_by_symbol(
+        &self,
+        stock_symbol: &str,
+    ) -> Result<Vec<Holding>, mongodb::error::Error> {
+        let filter = doc! { "stock_symbol": stock_symbol };
+        let holdings: Vec<Holding> = self.collect(self.holdings.find(filter)).await?;
+        Ok(holdings)
+    }
+    /// Move every holding, transaction, and archived record from one symbol to another. An account
+    /// that already holds `to` has the renamed holding and summary merged into its existing ones,
+    /// so it never ends up with two of either. Returns the number of holdings and transactions
+    /// changed. The writes are separate, so call this on a pool from `begin_transaction`.
+    pub async fn rename_symbol(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(u64, u64), mongodb::error::Error> {
+        let update = doc! { "$set": { "stock_symbol": to } };
+
+        let mut holdings = 0;
+        for holding in self.get_holdings_by_symbol(from).await? {
+            let account_id = holding.account_id.clone();
+            match self.get_holding(&account_id, to).await? {
+                Some(existing) => {
+                    let stock_name = existing.stock_name.clone();
+                    let renamed = Holding {
+                        stock_symbol: to.to_string(),
+                        ..holding
+                    };
+                    let Some(mut merged) = Holding::merge(vec![existing, renamed]) else {
+                        continue;
+                    };
+                    merged.stock_name = stock_name;
+                    let filter =
+                        doc! { "account_id": &account_id, "stock_symbol": { "$in": [from, to] } };
+                    exec!(self, self.holdings.delete_many(filter))?;
+                    exec!(self, self.holdings.insert_one(merged))?;
+                }
+                None => {
+                    let filter = doc! { "account_id": &account_id, "stock_symbol": from };
+                    exec!(self, self.holdings.update_one(filter, update.clone()))?;
+                }
+            }
+            holdings += 1;
+        }
+
+        let filter = doc! { "stock_symbol": from };
+        let summaries: Vec<TransactionSummary> = self
+            .collect(self.transaction_summaries.find(filter.clone()))
+            .await?;
+        for summary in summaries {
+            let existing = exec!(
+                self,
+                self.transaction_summaries
+                    .find_one(doc! { "account_id": &summary.account_id, "stock_symbol": to })
+            )?;
+            let filter = doc! { "account_id": &summary.account_id, "stock_symbol": from };
+            match existing {
+                Some(existing) => {
+                    exec!(self, self.transaction_summaries.delete_one(filter))?;
+                    self.upsert_transaction_summary(TransactionSummary {
+                        quantity: existing.quantity + summary.quantity,
+                        cost_basis: existing.cost_basis + summary.cost_basis,
+                        realized_pnl: existing.realized_pnl + summary.realized_pnl,
+                        archived_count: existing.archived_count + summary.archived_count,
+                        ..existing
+                    })
+                    .await?;
+                }
+                None => {
+                    exec!(
+                        self,
+                        self.transaction_summaries
+                            .update_one(filter, update.clone())
+                    )?;
+                }
+            }
+        }
+
+        let transactions = exec!(
+            self,
+            self.transactions
+                .update_many(filter.clone(), update.clone())
+        )?;
+        let archived = exec!(self, self.archived_transactions.update_many(filter, update))?;
+        Ok((
+            holdings,
+            transactions.modified_count + archived.modified_count,
+        ))
+    }
+    pub async fn get_corporate_action(
+        &self,
+        action_id: &str,
+    ) -> Result<Option<CorporateActionRecord>, mongodb::error::Error> {
+        let filter = doc! { "action_id": action_id };
+        exec!(self, self.corporate_actions.find_one(filter))
+    }
+    pub async fn add_corporate_action(
+        &self,
+        record: CorporateActionRecord,
+    ) -> Result<(), mongodb::error::Error> {
+        exec!(self, self.corporate_actions.insert_one(record))?;
+        Ok(())
+    }
+    pub async fn get_margin_calls(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<MarginCallRecord>, mongodb::error::Error> {
+        let filter = doc! { "account_id": account_id };
+        self.collect(self.margin_calls.find(filter)).await
+    }
+    pub async fn add_margin_call(
+        &self,
+        record: MarginCallRecord,
+    ) -> Result<(), mongodb::error::Error> {
+        exec!(self, self.margin_calls.insert_one(record))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl DatabasePool {
+    /// A pool pointed at a server that isn't there, for tests of handlers that take one but
+    /// don't use it on the path being tested. Operations fail after a short timeout.
+    pub(crate) async fn unreachable() -> Self {
+        let options = ClientOptions::parse("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=100")
+            .await
+            .unwrap();
+        Self::with_client(Client::with_options(options).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_updates_only_set_the_provided_fields() {
+        let update: UpdateSettings = serde_json::from_str(r#"{"email_opt_in": true}"#).unwrap();
+        let set = mongodb::bson::to_document(&update).unwrap();
+        assert_eq!(set, doc! { "email_opt_in": true });
+    }
 }
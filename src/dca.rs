@@ -0,0 +1,130 @@
+use crate::pnl::Granularity;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// Result of one investing strategy. Amounts are in cents.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StrategyOutcome {
+    pub invested: i64,
+    /// Fractional shares bought.
+    pub shares: f64,
+    /// Value of the shares at the last close in the range.
+    pub final_value: i64,
+    /// `final_value` minus `invested`.
+    pub gain: i64,
+}
+
+/// Dollar-cost averaging compared with investing everything up front.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DcaComparison {
+    /// First day with a close, which may be after the requested start.
+    pub start: NaiveDate,
+    /// Last day with a close, which may be before the requested end.
+    pub end: NaiveDate,
+    /// Purchases made by dollar-cost averaging, one per period.
+    pub contributions: usize,
+    pub dca: StrategyOutcome,
+    pub lump_sum: StrategyOutcome,
+}
+
+/// Compare investing `amount` cents at the first close against splitting it evenly across
+/// periods of `frequency`, buying at the first close of each period. `closes` are daily closes in
+/// dollars, oldest first; days without a close are skipped, so the range is trimmed to the data
+/// available. Returns `None` without any positive closes.
+pub fn compare(
+    closes: &[(NaiveDate, f64)],
+    amount: i64,
+    frequency: Granularity,
+) -> Option<DcaComparison> {
+    let closes: Vec<(NaiveDate, f64)> = closes.iter().copied().filter(|(_, c)| *c > 0.0).collect();
+    let (start, first) = *closes.first()?;
+    let (end, last) = *closes.last()?;
+
+    // The first close of each period is when that period's contribution is invested
+    let mut buys: Vec<f64> = Vec::new();
+    let mut period = None;
+    for (date, close) in &closes {
+        let current = frequency.period_start(*date);
+        if period != Some(current) {
+            period = Some(current);
+            buys.push(*close);
+        }
+    }
+
+    // Split the amount evenly, with the remainder going into the first contribution
+    let contributions = buys.len() as i64;
+    let each = amount / contributions;
+    let mut dca_shares = 0.0;
+    for (i, close) in buys.iter().enumerate() {
+        let contribution = match i {
+            0 => each + amount % contributions,
+            _ => each,
+        };
+        dca_shares += contribution as f64 / 100.0 / close;
+    }
+    let lump_shares = amount as f64 / 100.0 / first;
+
+    Some(DcaComparison {
+        start,
+        end,
+        contributions: buys.len(),
+        dca: outcome(amount, dca_shares, last),
+        lump_sum: outcome(amount, lump_shares, last),
+    })
+}
+
+/// Value `shares` bought for `invested` cents at a final close in dollars.
+fn outcome(invested: i64, shares: f64, last: f64) -> StrategyOutcome {
+    let final_value = (shares * last * 100.0).round() as i64;
+    StrategyOutcome {
+        invested,
+        shares,
+        final_value,
+        gain: final_value - invested,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn dca_buys_more_shares_through_a_dip() {
+        let closes = [
+            (day("2023-12-29"), 0.0),
+            (day("2024-01-02"), 10.0),
+            (day("2024-01-15"), 12.0),
+            (day("2024-02-01"), 5.0),
+            (day("2024-03-01"), 10.0),
+        ];
+
+        let comparison = compare(&closes, 30_000, Granularity::Month).unwrap();
+
+        // The missing close trims the range to start at the first real one
+        assert_eq!(comparison.start, day("2024-01-02"));
+        assert_eq!(comparison.end, day("2024-03-01"));
+        assert_eq!(comparison.contributions, 3);
+        assert_eq!(comparison.dca.shares, 40.0);
+        assert_eq!(
+            (comparison.dca.final_value, comparison.dca.gain),
+            (40_000, 10_000)
+        );
+        assert_eq!(comparison.lump_sum.shares, 30.0);
+        assert_eq!(
+            (comparison.lump_sum.final_value, comparison.lump_sum.gain),
+            (30_000, 0)
+        );
+    }
+
+    #[test]
+    fn no_closes_is_no_comparison() {
+        assert_eq!(
+            compare(&[(day("2024-01-02"), 0.0)], 30_000, Granularity::Week),
+            None
+        );
+    }
+}
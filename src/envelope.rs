@@ -0,0 +1,114 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderName,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// Request header choosing whether the response is wrapped, overriding the server default.
+pub const X_ENVELOPE: HeaderName = HeaderName::from_static("x-envelope");
+
+/// Wrapper around a successful JSON response body.
+#[derive(Serialize)]
+struct Envelope {
+    data: serde_json::Value,
+    /// Real time on the server, for clients correcting clock skew.
+    server_time: String,
+    version: &'static str,
+}
+
+/// Whether the request asks for an envelope, falling back to the server default when the
+/// `X-Envelope` header is missing or not a boolean.
+fn wants_envelope(req: &Request, default: bool) -> bool {
+    match req.headers().get(X_ENVELOPE).and_then(|v| v.to_str().ok()) {
+        Some("true" | "1") => true,
+        Some("false" | "0") => false,
+        _ => default,
+    }
+}
+
+/// Middleware wrapping successful JSON responses in `{ "data", "server_time", "version" }`
+/// when enabled by config or the `X-Envelope` header. Downloads and other responses pass through.
+pub async fn wrap_responses(State(default): State<bool>, req: Request, next: Next) -> Response {
+    let enabled = wants_envelope(&req, default);
+    let response = next.run(req).await;
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !enabled
+        || !response.status().is_success()
+        || !is_json
+        || response.headers().contains_key(CONTENT_DISPOSITION)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let data = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(data) => data,
+            Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+        },
+        Err(e) => {
+            tracing::error!("Error reading response body for envelope: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let envelope = Envelope {
+        data,
+        server_time: crate::timestamps::format_utc(chrono::Utc::now()),
+        version: env!("CARGO_PKG_VERSION"),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    let body = axum::Json(envelope).into_response().into_body();
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::get, Json, Router};
+    use tower::ServiceExt;
+
+    async fn portfolio(default: bool, envelope: Option<&str>) -> serde_json::Value {
+        let app = Router::new()
+            .route(
+                "/portfolio",
+                get(|| async { Json(serde_json::json!({ "holdings": [] })) }),
+            )
+            .layer(middleware::from_fn_with_state(default, wrap_responses));
+        let mut request = Request::get("/portfolio");
+        if let Some(envelope) = envelope {
+            request = request.header(X_ENVELOPE, envelope);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn wraps_the_portfolio_when_requested() {
+        let wrapped = portfolio(false, Some("true")).await;
+        assert_eq!(wrapped["data"], serde_json::json!({ "holdings": [] }));
+        assert_eq!(wrapped["version"], env!("CARGO_PKG_VERSION"));
+        assert!(wrapped["server_time"].is_string());
+    }
+
+    #[tokio::test]
+    async fn leaves_the_portfolio_bare_by_default_or_when_declined() {
+        let bare = serde_json::json!({ "holdings": [] });
+        assert_eq!(portfolio(false, None).await, bare);
+        assert_eq!(portfolio(true, Some("false")).await, bare);
+    }
+}
@@ -0,0 +1,26 @@
+use crate::finnhub::QUOTE_TTL;
+use crate::models::Account;
+use axum::http::{header::IF_NONE_MATCH, HeaderMap};
+
+/// ETag for responses derived from an account and its priced holdings. It changes whenever the
+/// account's version is bumped by a trade or cached quotes become due for a reprice.
+pub fn account_etag(account: &Account) -> String {
+    let reprice_epoch = chrono::Utc::now().timestamp() as u64 / QUOTE_TTL.as_secs();
+    format!("\"{}-{}\"", account.version, reprice_epoch)
+}
+
+/// ETag for a variant of a response, such as one rounded for display.
+pub fn variant_etag(etag: &str, variant: &str) -> String {
+    format!("\"{}-{}\"", etag.trim_matches('"'), variant)
+}
+
+/// Whether the request's `If-None-Match` header matches `etag`.
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
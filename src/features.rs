@@ -0,0 +1,84 @@
+use crate::config::Config;
+use crate::models::AccountSettings;
+use crate::store::{Store, StoreError};
+use axum::{http::StatusCode, Json};
+
+/// Buying crypto pairs. Selling stays open so accounts can always exit positions.
+pub const CRYPTO: &str = "crypto";
+
+/// Whether an account may use a feature. Features not listed in `GATED_FEATURES` are on for
+/// every account; gated ones only for accounts granted them in their settings.
+pub async fn account_has_feature(
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    feature: &str,
+) -> Result<bool, StoreError> {
+    if !config.gated_features.contains(feature) {
+        return Ok(true);
+    }
+    let settings = store.get_settings(account_id).await?;
+    Ok(settings_allow(config, &settings, feature))
+}
+
+/// Whether an account with `settings` may use a feature.
+fn settings_allow(config: &Config, settings: &AccountSettings, feature: &str) -> bool {
+    !config.gated_features.contains(feature) || settings.features.contains(feature)
+}
+
+/// The 403 for an account without a gated feature.
+fn feature_disabled(feature: &str) -> (StatusCode, Json<String>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(format!(
+            "The {} feature is not enabled for this account.",
+            feature
+        )),
+    )
+}
+
+/// Reject the request with a 403 unless the account has the feature.
+pub async fn require_feature(
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    feature: &str,
+) -> Result<(), (StatusCode, Json<String>)> {
+    match account_has_feature(store, config, account_id, feature).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(feature_disabled(feature)),
+        Err(e) => {
+            tracing::error!("Error fetching settings: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(String::from("Error checking account features")),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gated_features_need_a_grant() {
+        let config = Config {
+            gated_features: [String::from(CRYPTO)].into(),
+            ..Config::for_tests()
+        };
+        let mut settings = AccountSettings::default();
+
+        assert!(!settings_allow(&config, &settings, CRYPTO));
+        assert!(settings_allow(&config, &settings, "shorting"));
+        settings.features.insert(String::from(CRYPTO));
+        assert!(settings_allow(&config, &settings, CRYPTO));
+
+        let (status, Json(message)) = feature_disabled(CRYPTO);
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            message,
+            "The crypto feature is not enabled for this account."
+        );
+    }
+}
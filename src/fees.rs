@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+/// Commission charged on trades. All amounts are in cents.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FeeModel {
+    /// Flat commission per trade.
+    pub commission: i64,
+    /// Fee in basis points of the trade's notional value.
+    pub notional_bps: i64,
+    /// Number of trades per account that are commission-free.
+    pub free_trades: i64,
+}
+
+/// Itemized fees for a single trade, in cents.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    pub commission: i64,
+    pub notional_fee: i64,
+    /// Amount waived by the commission-free promotion.
+    pub promotion_discount: i64,
+    pub total: i64,
+}
+
+impl FeeModel {
+    /// Itemize the fees for a trade of `notional` cents by an account that has already made
+    /// `trades_count` trades. The account's first `free_trades` trades are waived.
+    pub fn breakdown(&self, trades_count: i64, notional: i64) -> FeeBreakdown {
+        let commission = self.commission;
+        let notional_fee = notional * self.notional_bps / 10_000;
+        let gross = commission + notional_fee;
+        let promotion_discount = if trades_count < self.free_trades {
+            gross
+        } else {
+            0
+        };
+        FeeBreakdown {
+            commission,
+            notional_fee,
+            promotion_discount,
+            total: gross - promotion_discount,
+        }
+    }
+
+    /// Total fee for a trade. See `breakdown`.
+    pub fn fee(&self, trades_count: i64, notional: i64) -> i64 {
+        self.breakdown(trades_count, notional).total
+    }
+}
@@ -1,10 +1,102 @@
-use reqwest;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::env;
+use crate::config::Config;
+use crate::models::AssetType;
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use chrono::NaiveDate;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// Errors returned by the Finnhub fetchers.
+#[derive(Debug, Clone)]
+pub enum FinnhubError {
+    /// No API key was configured at startup.
+    MissingApiKey,
+    /// The request failed or Finnhub returned a non-success status.
+    Request(String),
+    /// Finnhub returned a quote without a usable price.
+    InvalidPrice,
+    /// Finnhub has no quote at all for the symbol.
+    UnknownSymbol,
+    /// Finnhub rejected the request for exceeding the rate limit. Holds the seconds to wait.
+    RateLimited(u64),
+    /// Finnhub rejected the API key as invalid or expired.
+    Unauthorized,
+    /// Finnhub answered with a body that is not the expected JSON, such as an HTML error page.
+    Decode(String),
+}
+
+impl fmt::Display for FinnhubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FinnhubError::MissingApiKey => write!(f, "Finnhub API key is not configured"),
+            FinnhubError::Request(e) => write!(f, "{}", e),
+            FinnhubError::InvalidPrice => write!(f, "Invalid stock price returned"),
+            FinnhubError::UnknownSymbol => write!(f, "Unknown symbol"),
+            FinnhubError::RateLimited(secs) => {
+                write!(f, "Finnhub rate limit exceeded, retry in {}s", secs)
+            }
+            FinnhubError::Unauthorized => write!(f, "Finnhub rejected the API key"),
+            FinnhubError::Decode(e) => write!(f, "Unreadable Finnhub response: {}", e),
+        }
+    }
+}
+
+impl FinnhubError {
+    /// Whether the price provider itself is unavailable, as opposed to a single lookup failing.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            FinnhubError::MissingApiKey | FinnhubError::RateLimited(_) | FinnhubError::Unauthorized
+        )
+    }
+}
+
+impl From<FinnhubError> for (StatusCode, Json<String>) {
+    fn from(e: FinnhubError) -> Self {
+        match e {
+            FinnhubError::MissingApiKey => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(String::from("Price data is currently unavailable")),
+            ),
+            FinnhubError::UnknownSymbol => {
+                (StatusCode::NOT_FOUND, Json(String::from("Unknown symbol")))
+            }
+            // `add_retry_after` sets the Retry-After header on the response
+            FinnhubError::RateLimited(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(String::from(
+                    "Price data is temporarily rate limited, try again shortly",
+                )),
+            ),
+            FinnhubError::Unauthorized => (
+                StatusCode::BAD_GATEWAY,
+                Json(String::from("Price provider misconfigured")),
+            ),
+            FinnhubError::Decode(_) => (
+                StatusCode::BAD_GATEWAY,
+                Json(String::from(
+                    "Price provider returned an unreadable response",
+                )),
+            ),
+            e => (
+                StatusCode::BAD_GATEWAY,
+                Json(format!("Failed to fetch stock price: {}", e)),
+            ),
+        }
+    }
+}
+
 /// Response structure for Finnhub API
 #[derive(Deserialize, Clone)]
 pub struct FinnhubQuote {
@@ -12,94 +104,1460 @@ pub struct FinnhubQuote {
     pub d: f64,  // Day change
     pub dp: f64, // Day change percentage
     pub pc: f64, // Previous close
+    #[serde(default)]
+    pub t: i64, // Time of the last update as a Unix timestamp, or 0 when unknown
 }
 
-/// Response structure for Finnhub API
-#[derive(Deserialize, Clone)]
+/// Quote as sent by Finnhub, which leaves fields null for some symbols.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct RawQuote {
+    pub c: Option<f64>,
+    pub d: Option<f64>,
+    pub dp: Option<f64>,
+    pub pc: Option<f64>,
+    pub t: Option<i64>,
+}
+
+impl TryFrom<RawQuote> for FinnhubQuote {
+    type Error = FinnhubError;
+
+    /// Fill in missing fields from the ones present. Finnhub answers unknown symbols with a
+    /// quote of nulls and zeros, which becomes `UnknownSymbol`.
+    fn try_from(raw: RawQuote) -> Result<Self, Self::Error> {
+        let c = raw.c.unwrap_or(0.0);
+        let pc = raw.pc.unwrap_or(0.0);
+        if c <= 0.0 && pc <= 0.0 {
+            return Err(FinnhubError::UnknownSymbol);
+        }
+        if c <= 0.0 {
+            return Err(FinnhubError::InvalidPrice);
+        }
+
+        let pc = if pc > 0.0 { pc } else { c };
+        let d = raw.d.unwrap_or(c - pc);
+        let dp = raw.dp.unwrap_or(d / pc * 100.0);
+        Ok(FinnhubQuote {
+            c,
+            d,
+            dp,
+            pc,
+            t: raw.t.unwrap_or(0),
+        })
+    }
+}
+
+/// Response structure for Finnhub API. Finnhub returns an empty object for symbols it has no
+/// profile for, so every field defaults to empty.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct FinnhubProfile {
+    pub ticker: String,
     pub name: String,
     pub logo: String,
     #[serde(rename = "finnhubIndustry")]
     pub finnhub_industry: String,
 }
 
+/// A dividend paid on a stock, in dollars per share.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FinnhubDividend {
+    /// Ex-dividend date.
+    pub date: String,
+    pub amount: f64,
+}
+
+/// A scheduled earnings release. Estimates are missing until analysts publish them.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FinnhubEarnings {
+    pub date: String,
+    /// `bmo` before market open, `amc` after market close, `dmh` during market hours, or empty
+    /// when unannounced.
+    pub hour: String,
+    #[serde(rename = "epsEstimate")]
+    pub eps_estimate: Option<f64>,
+    #[serde(rename = "revenueEstimate")]
+    pub revenue_estimate: Option<f64>,
+    pub quarter: u32,
+    pub year: i32,
+}
+
+/// Body of Finnhub's earnings calendar.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FinnhubEarningsCalendar {
+    #[serde(rename = "earningsCalendar")]
+    earnings_calendar: Vec<FinnhubEarnings>,
+}
+
+/// Key metrics of a stock from Finnhub's basic financials. Finnhub omits or nulls metrics it
+/// doesn't have, so every one is optional. Prices are in dollars.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FinnhubMetrics {
+    #[serde(rename = "peTTM")]
+    pub pe_ttm: Option<f64>,
+    #[serde(rename = "52WeekHigh")]
+    pub week_52_high: Option<f64>,
+    #[serde(rename = "52WeekLow")]
+    pub week_52_low: Option<f64>,
+    /// In millions of dollars.
+    #[serde(rename = "marketCapitalization")]
+    pub market_capitalization: Option<f64>,
+    pub beta: Option<f64>,
+    #[serde(rename = "dividendYieldIndicatedAnnual")]
+    pub dividend_yield_indicated_annual: Option<f64>,
+    #[serde(rename = "epsTTM")]
+    pub eps_ttm: Option<f64>,
+}
+
+/// Body of Finnhub's basic financials. Symbols Finnhub has no financials for get an empty
+/// `metric` object.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FinnhubBasicFinancials {
+    metric: FinnhubMetrics,
+}
+
+impl FinnhubProfile {
+    /// Classify the security from its profile. Common stock carries an industry, while funds
+    /// have none but usually say so in their name.
+    pub fn asset_type(&self) -> AssetType {
+        let name = self.name.to_uppercase();
+        if is_crypto_symbol(&self.ticker) {
+            AssetType::Crypto
+        } else if !self.finnhub_industry.is_empty() {
+            AssetType::Stock
+        } else if name.contains("ETF") || name.contains("FUND") || name.contains("TRUST") {
+            AssetType::Etf
+        } else {
+            AssetType::Unknown
+        }
+    }
+
+    /// The logo URL to hand to clients, routed through `cdn_prefix` when one is configured.
+    /// Empty logos stay empty.
+    pub fn logo_url(&self, cdn_prefix: Option<&str>) -> String {
+        match cdn_prefix {
+            Some(prefix) if !prefix.is_empty() && !self.logo.is_empty() => {
+                format!("{}{}", prefix, self.logo)
+            }
+            _ => self.logo.clone(),
+        }
+    }
+
+    /// The name to show for `symbol`. Finnhub sometimes answers with a blank name, in which case
+    /// the symbol itself is used.
+    pub fn display_name(&self, symbol: &str) -> String {
+        match self.name.trim() {
+            "" => symbol.to_string(),
+            name => name.to_string(),
+        }
+    }
+
+    /// The new name for a holding of `symbol` currently named `current`, if it should change. A
+    /// blank profile only replaces a blank name, so it never erases a name stored earlier.
+    pub fn rename(&self, symbol: &str, current: &str) -> Option<String> {
+        if self.name.trim().is_empty() && !current.trim().is_empty() {
+            return None;
+        }
+        let name = self.display_name(symbol);
+        (name != current).then_some(name)
+    }
+}
+
+/// Longest part of an unreadable body that is logged.
+const DECODE_SNIPPET_CHARS: usize = 200;
+
+/// Read a successful response's body as JSON. Bodies that don't decode, such as HTML error pages,
+/// are logged in part at debug level and become `FinnhubError::Decode`.
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, FinnhubError> {
+    let body = response
+        .text()
+        .await
+        .map_err(|e| FinnhubError::Request(e.to_string()))?;
+    serde_json::from_str(&body).map_err(|e| {
+        let snippet: String = body.chars().take(DECODE_SNIPPET_CHARS).collect();
+        tracing::debug!("Unreadable Finnhub response ({}): {:?}", e, snippet);
+        FinnhubError::Decode(e.to_string())
+    })
+}
+
+/// Finnhub API key, read once at startup by `init`.
+static API_KEY: OnceLock<String> = OnceLock::new();
+static BASE_URL: OnceLock<String> = OnceLock::new();
+static FINANCIALS_TTL: OnceLock<Duration> = OnceLock::new();
+
+/// Store the Finnhub API key, base URL, and configured cache lifetimes from the configuration for
+/// all later requests. Without a key, fetches fail with `FinnhubError::MissingApiKey` instead of
+/// panicking.
+pub fn init(config: &Config) {
+    let _ = BASE_URL.set(config.finnhub_base_url.clone());
+    if let Some(secs) = config.financials_cache_secs {
+        let _ = FINANCIALS_TTL.set(Duration::from_secs(secs));
+    }
+    // A missing or malformed key is warned about when the configuration is loaded
+    if let Some(key) = &config.finnhub_api_key {
+        let _ = API_KEY.set(key.clone());
+    }
+}
+
+/// Base URL Finnhub requests are made against.
+fn base_url() -> &'static str {
+    BASE_URL
+        .get()
+        .map(String::as_str)
+        .unwrap_or("https://finnhub.io/api/v1")
+}
+
+/// How long basic financials are cached.
+fn financials_ttl() -> Duration {
+    FINANCIALS_TTL
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_FINANCIALS_TTL)
+}
+
+fn api_key() -> Result<&'static str, FinnhubError> {
+    API_KEY
+        .get()
+        .map(String::as_str)
+        .ok_or(FinnhubError::MissingApiKey)
+}
+
+/// How long fetched quotes are cached.
+pub const QUOTE_TTL: Duration = Duration::from_secs(300);
+/// How long fetched company profiles are cached.
+pub const PROFILE_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// How long fetched peer lists are cached.
+pub const PEERS_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// How long trailing dividends are cached.
+pub const DIVIDENDS_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// How long a stock's upcoming earnings dates are cached.
+pub const EARNINGS_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// How long basic financials are cached unless `FINANCIALS_CACHE_SECS` is set.
+pub const DEFAULT_FINANCIALS_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// Days ahead of today searched for scheduled earnings.
+pub const EARNINGS_LOOKAHEAD_DAYS: i64 = 90;
+/// How long crypto prices, and stock prices taken from candles, are cached.
+pub const CRYPTO_QUOTE_TTL: Duration = Duration::from_secs(60);
+
+/// Default number of distinct symbols a single request may fetch from Finnhub.
+pub const DEFAULT_REQUEST_BUDGET: usize = 50;
+
+/// Per-request budget limiting how many distinct symbols a handler may fetch from Finnhub.
+/// Attached to every request as an extension by `attach_budget`.
+#[derive(Clone)]
+pub struct FinnhubBudget {
+    limit: usize,
+    symbols: Arc<std::sync::Mutex<HashSet<String>>>,
+}
+
+impl FinnhubBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            symbols: Arc::new(std::sync::Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Reserve a fetch for `symbol`. Symbols already reserved are free; returns false once
+    /// the budget is spent.
+    pub fn try_spend(&self, symbol: &str) -> bool {
+        let mut symbols = self.symbols.lock().unwrap();
+        if symbols.contains(symbol) {
+            return true;
+        }
+        if symbols.len() >= self.limit {
+            return false;
+        }
+        symbols.insert(symbol.to_string());
+        true
+    }
+}
+
+/// Middleware giving each request a fresh `FinnhubBudget` of `limit` symbols.
+pub async fn attach_budget(State(limit): State<usize>, mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(FinnhubBudget::new(limit));
+    next.run(req).await
+}
+
 // Make the client and cache static and reusable
 lazy_static::lazy_static! {
     static ref CLIENT: reqwest::Client = reqwest::Client::new();
     static ref CACHE: Mutex<HashMap<String, (FinnhubQuote, Instant)>> = Mutex::new(HashMap::new());
     static ref PROFILE_CACHE: Mutex<HashMap<String, (FinnhubProfile, Instant)>> = Mutex::new(HashMap::new());
+    static ref CANDLE_PRICE_CACHE: Mutex<HashMap<String, (FinnhubQuote, Instant)>> = Mutex::new(HashMap::new());
+    static ref HISTORICAL_CACHE: Mutex<HashMap<(String, NaiveDate), (FinnhubQuote, Instant)>> = Mutex::new(HashMap::new());
+    static ref PEERS_CACHE: Mutex<HashMap<String, (Vec<String>, Instant)>> = Mutex::new(HashMap::new());
+    static ref DIVIDENDS_CACHE: Mutex<HashMap<String, (Vec<FinnhubDividend>, Instant)>> = Mutex::new(HashMap::new());
+    static ref EARNINGS_CACHE: Mutex<HashMap<String, (Vec<FinnhubEarnings>, Instant)>> = Mutex::new(HashMap::new());
+    static ref FINANCIALS_CACHE: Mutex<HashMap<String, (FinnhubMetrics, Instant)>> = Mutex::new(HashMap::new());
+    static ref IN_FLIGHT: Mutex<HashMap<String, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
 }
 
-/// Fetch stock profile from Finnhub API. A stock profile includes the name and logo of the company.
-pub async fn fetch_stock_profile(symbol: &str) -> Result<FinnhubProfile, String> {
-    let api_key = env::var("FINNHUB_API_KEY").expect("Missing FINNHUB_API_KEY");
+/// Finnhub's free tier allows this many calls per minute.
+pub const FREE_TIER_CALLS_PER_MINUTE: usize = 60;
+
+/// Upstream Finnhub calls made since startup.
+static TOTAL_CALLS: AtomicU64 = AtomicU64::new(0);
+/// When each call in the last minute was made, oldest first.
+static RECENT_CALLS: std::sync::Mutex<VecDeque<Instant>> = std::sync::Mutex::new(VecDeque::new());
+
+/// Finnhub API usage, for watching how close the server is to the rate limit.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FinnhubUsage {
+    pub total_calls: u64,
+    /// Calls in the rolling minute ending now.
+    pub calls_last_minute: usize,
+    pub calls_per_minute_limit: usize,
+}
+
+/// Count an upstream Finnhub call.
+fn record_call() {
+    TOTAL_CALLS.fetch_add(1, Ordering::Relaxed);
     let now = Instant::now();
+    let mut recent = RECENT_CALLS.lock().unwrap();
+    recent.push_back(now);
+    prune_recent(&mut recent, now);
+}
+
+/// Drop calls older than a minute from the rolling window.
+fn prune_recent(recent: &mut VecDeque<Instant>, now: Instant) {
+    while recent
+        .front()
+        .is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(60))
+    {
+        recent.pop_front();
+    }
+}
+
+/// Current Finnhub usage counters.
+pub fn usage() -> FinnhubUsage {
+    let mut recent = RECENT_CALLS.lock().unwrap();
+    prune_recent(&mut recent, Instant::now());
+    FinnhubUsage {
+        total_calls: TOTAL_CALLS.load(Ordering::Relaxed),
+        calls_last_minute: recent.len(),
+        calls_per_minute_limit: FREE_TIER_CALLS_PER_MINUTE,
+    }
+}
+
+/// Consecutive failed quotes per symbol and when the latest failed, reset by a successful quote.
+static QUOTE_FAILURES: std::sync::Mutex<Option<HashMap<String, (u32, Instant)>>> =
+    std::sync::Mutex::new(None);
+
+/// Count a failed quote for `symbol` and return how many have failed in a row.
+pub fn record_quote_failure(symbol: &str) -> u32 {
+    let mut failures = QUOTE_FAILURES.lock().unwrap();
+    let (count, failed_at) = failures
+        .get_or_insert_with(HashMap::new)
+        .entry(normalize_symbol(symbol))
+        .or_insert((0, Instant::now()));
+    *count += 1;
+    *failed_at = Instant::now();
+    *count
+}
+
+/// Forget failed quotes for `symbol` after it quotes successfully.
+fn clear_quote_failures(symbol: &str) {
+    if let Some(failures) = QUOTE_FAILURES.lock().unwrap().as_mut() {
+        failures.remove(symbol);
+    }
+}
+
+/// The last price quoted for a stock, even if the cached quote has expired.
+pub async fn last_known_price(symbol: &str) -> Option<f64> {
+    CACHE
+        .lock()
+        .await
+        .get(&normalize_symbol(symbol))
+        .map(|(quote, _)| quote.c)
+}
+
+/// Expired quotes are kept this long for `last_known_price` before being swept.
+pub const STALE_QUOTE_RETENTION: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Remove expired entries from the quote, profile, peers, dividends, earnings, and financials
+/// caches, then evict the least recently fetched entries from any cache still holding more than
+/// `max_entries`. Entries in use are refetched once they expire, so the least recently fetched
+/// approximate the least recently used. Historical prices never expire but are capped the same
+/// way. Quote failure counts are forgotten once a symbol hasn't failed for
+/// `STALE_QUOTE_RETENTION`, and fetch locks nobody is waiting on are dropped. Returns the number
+/// of entries removed.
+pub async fn sweep_caches(max_entries: Option<usize>) -> usize {
+    let failures = match QUOTE_FAILURES.lock().unwrap().as_mut() {
+        Some(failures) => sweep(failures, STALE_QUOTE_RETENTION, None),
+        None => 0,
+    };
+    let in_flight = {
+        let mut in_flight = IN_FLIGHT.lock().await;
+        let before = in_flight.len();
+        // Fetches clone the lock while holding the map, so a lone reference is unused
+        in_flight.retain(|_, lock| Arc::strong_count(lock) > 1);
+        before - in_flight.len()
+    };
+
+    sweep(&mut *CACHE.lock().await, STALE_QUOTE_RETENTION, max_entries)
+        + sweep(
+            &mut *CANDLE_PRICE_CACHE.lock().await,
+            CRYPTO_QUOTE_TTL,
+            max_entries,
+        )
+        + sweep(
+            &mut *HISTORICAL_CACHE.lock().await,
+            Duration::MAX,
+            max_entries,
+        )
+        + sweep(&mut *PROFILE_CACHE.lock().await, PROFILE_TTL, max_entries)
+        + sweep(&mut *PEERS_CACHE.lock().await, PEERS_TTL, max_entries)
+        + sweep(
+            &mut *DIVIDENDS_CACHE.lock().await,
+            DIVIDENDS_TTL,
+            max_entries,
+        )
+        + sweep(&mut *EARNINGS_CACHE.lock().await, EARNINGS_TTL, max_entries)
+        + sweep(
+            &mut *FINANCIALS_CACHE.lock().await,
+            financials_ttl(),
+            max_entries,
+        )
+        + failures
+        + in_flight
+}
+
+/// Drop entries older than `ttl`, then the oldest entries beyond `max_entries`.
+fn sweep<K: Clone + Eq + Hash, V>(
+    cache: &mut HashMap<K, (V, Instant)>,
+    ttl: Duration,
+    max_entries: Option<usize>,
+) -> usize {
+    let before = cache.len();
+    cache.retain(|_, (_, fetched_at)| fetched_at.elapsed() < ttl);
+    if let Some(max) = max_entries {
+        if cache.len() > max {
+            let mut by_age: Vec<(K, Instant)> = cache
+                .iter()
+                .map(|(key, (_, fetched_at))| (key.clone(), *fetched_at))
+                .collect();
+            by_age.sort_by_key(|(_, fetched_at)| *fetched_at);
+            let excess = cache.len() - max;
+            for (key, _) in by_age.into_iter().take(excess) {
+                cache.remove(&key);
+            }
+        }
+    }
+    before - cache.len()
+}
+
+/// Wait applied when Finnhub rate limits a request without a usable `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Until when Finnhub is rate limiting us. No requests are sent before then.
+static RATE_LIMITED_UNTIL: std::sync::Mutex<Option<Instant>> = std::sync::Mutex::new(None);
+
+/// How long until Finnhub accepts requests again, if currently rate limited.
+pub fn rate_limited_for() -> Option<Duration> {
+    let until = (*RATE_LIMITED_UNTIL.lock().unwrap())?;
+    until
+        .checked_duration_since(Instant::now())
+        .filter(|wait| !wait.is_zero())
+}
+
+/// Fail fast while rate limited instead of spending another request on a certain 429.
+fn check_rate_limit() -> Result<(), FinnhubError> {
+    match rate_limited_for() {
+        Some(wait) => Err(FinnhubError::RateLimited(wait.as_secs().max(1))),
+        None => Ok(()),
+    }
+}
+
+/// Record a 429 from Finnhub, honoring its `Retry-After` header.
+fn rate_limited(response: &reqwest::Response) -> FinnhubError {
+    let wait = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_AFTER);
+    tracing::warn!("Finnhub rate limit hit, pausing requests for {:?}", wait);
+    *RATE_LIMITED_UNTIL.lock().unwrap() = Some(Instant::now() + wait);
+    FinnhubError::RateLimited(wait.as_secs().max(1))
+}
+
+/// Record a 403 from Finnhub, which means `FINNHUB_API_KEY` is wrong or has expired. Logged
+/// as an error on every occurrence since no price data can be fetched until it is fixed.
+fn unauthorized() -> FinnhubError {
+    tracing::error!("Finnhub rejected the API key (HTTP 403); check FINNHUB_API_KEY");
+    FinnhubError::Unauthorized
+}
+
+/// Middleware adding `Retry-After` to 503 responses while Finnhub is rate limiting us.
+pub async fn add_retry_after(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+        if let Some(wait) = rate_limited_for() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(wait.as_secs().max(1)));
+        }
+    }
+    response
+}
+
+/// Send a request to Finnhub, counting it toward usage. Fails fast while rate limited, records
+/// a 429's `Retry-After`, and turns a 403 or any other unsuccessful status into an error.
+async fn send_finnhub(request: reqwest::RequestBuilder) -> Result<reqwest::Response, FinnhubError> {
+    check_rate_limit()?;
+    record_call();
+    let response = request
+        .send()
+        .await
+        .map_err(|e| FinnhubError::Request(e.to_string()))?;
+    match response.status().as_u16() {
+        429 => Err(rate_limited(&response)),
+        403 => Err(unauthorized()),
+        _ if !response.status().is_success() => Err(FinnhubError::Request(format!(
+            "Finnhub responded with HTTP {}",
+            response.status()
+        ))),
+        _ => Ok(response),
+    }
+}
+
+/// Lock serializing fetches of one resource, e.g. `quote:AAPL`. Concurrent cache misses wait on
+/// the first fetch and then find its result cached, instead of each calling Finnhub.
+async fn in_flight(key: String) -> Arc<Mutex<()>> {
+    IN_FLIGHT.lock().await.entry(key).or_default().clone()
+}
+
+/// Response structure for Finnhub candle endpoints. `s` is `"no_data"` when there are no candles.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FinnhubCandles {
+    pub c: Vec<f64>, // Close prices
+    pub o: Vec<f64>, // Open prices
+    pub t: Vec<i64>, // Unix timestamps
+    pub s: String,   // Status
+}
 
-    let mut cache = PROFILE_CACHE.lock().await;
-    if let Some((profile, timestamp)) = cache.get(symbol) {
-        // Check if the profile is still valid (less than 24 hours)
-        if now.duration_since(*timestamp) < Duration::from_secs(60 * 60 * 24) {
-            tracing::debug!("Returning cached profile for {}", symbol);
-            return Ok(profile.clone());
+/// Whether a symbol is an exchange-prefixed crypto pair such as `BINANCE:BTCUSDT`.
+pub fn is_crypto_symbol(symbol: &str) -> bool {
+    symbol.contains(':')
+}
+
+/// Normalize a stock symbol for cache keys and requests, so `aapl` and ` AAPL ` share an entry.
+pub fn normalize_symbol(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+/// Where the current price of a stock comes from. Crypto pairs are always priced from candles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// Finnhub's `/quote` endpoint.
+    #[default]
+    Quote,
+    /// The close of the latest daily candle, which for some symbols is fresher than the quote.
+    LastCandle,
+}
+
+impl std::str::FromStr for PriceSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "quote" => Ok(PriceSource::Quote),
+            "last_candle" => Ok(PriceSource::LastCandle),
+            _ => Err(format!("Unknown price source: {}", s)),
         }
     }
+}
+
+static PRICE_SOURCE: OnceLock<PriceSource> = OnceLock::new();
+
+/// Choose where `fetch_price` prices stocks from for all later requests. Defaults to
+/// `PriceSource::Quote` when never set.
+pub fn set_price_source(source: PriceSource) {
+    let _ = PRICE_SOURCE.set(source);
+    tracing::info!("Pricing stocks from {:?}", source);
+}
+
+/// The configured source of stock prices.
+pub fn price_source() -> PriceSource {
+    PRICE_SOURCE.get().copied().unwrap_or_default()
+}
+
+/// Fetch the current price of a stock or crypto pair, from the configured `PriceSource` for
+/// stocks and from candles for crypto pairs. Trading and valuation both price through this. In
+/// simulation mode this is the historical close for the simulated date.
+pub async fn fetch_price(symbol: &str) -> Result<FinnhubQuote, FinnhubError> {
+    if let Some(now) = crate::sim::now() {
+        fetch_historical_price(symbol, now.date_naive()).await
+    } else {
+        fetch_price_from(price_source(), symbol).await
+    }
+}
+
+/// Fetch the current price of a stock from `source`, or of a crypto pair from candles.
+pub async fn fetch_price_from(
+    source: PriceSource,
+    symbol: &str,
+) -> Result<FinnhubQuote, FinnhubError> {
+    if is_crypto_symbol(symbol) {
+        return fetch_candle_price(symbol).await;
+    }
+    match source {
+        PriceSource::Quote => fetch_stock_price(symbol).await,
+        PriceSource::LastCandle => fetch_candle_price(&normalize_symbol(symbol)).await,
+    }
+}
+
+/// Fetch the profile of a stock or crypto pair. Finnhub has no crypto profiles, so crypto pairs
+/// get a profile named after the symbol.
+pub async fn fetch_profile(symbol: &str) -> Result<FinnhubProfile, FinnhubError> {
+    if is_crypto_symbol(symbol) {
+        api_key()?;
+        Ok(FinnhubProfile {
+            ticker: symbol.to_string(),
+            name: symbol.to_string(),
+            ..Default::default()
+        })
+    } else {
+        fetch_stock_profile(symbol).await
+    }
+}
+
+/// Fetch candles between two Unix timestamps from the stock or crypto candle endpoint.
+pub async fn fetch_candles(
+    symbol: &str,
+    resolution: &str,
+    from: i64,
+    to: i64,
+) -> Result<FinnhubCandles, FinnhubError> {
+    let api_key = api_key()?;
+    let kind = if is_crypto_symbol(symbol) {
+        "crypto"
+    } else {
+        "stock"
+    };
 
     let url = format!(
-        "https://finnhub.io/api/v1/stock/profile2?symbol={}&token={}",
-        symbol, api_key
+        "{}/{}/candle?symbol={}&resolution={}&from={}&to={}&token={}",
+        base_url(),
+        kind,
+        symbol,
+        resolution,
+        from,
+        to,
+        api_key
     );
-    let response = CLIENT.get(&url).send().await.map_err(|e| e.to_string())?;
-    if !response.status().is_success() {
-        return Err(format!(
-            "Failed to fetch stock name: HTTP {}",
-            response.status()
-        ));
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched {} candles for {}", resolution, symbol);
+
+    decode(response).await
+}
+
+/// Fetch the closing price on `date` from daily candles, or the last close before it if the market
+/// was shut that day. Historical prices never change, so they are cached indefinitely.
+pub async fn fetch_historical_price(
+    symbol: &str,
+    date: NaiveDate,
+) -> Result<FinnhubQuote, FinnhubError> {
+    let key = (symbol.to_string(), date);
+    if let Some((quote, _)) = HISTORICAL_CACHE.lock().await.get(&key) {
+        return Ok(quote.clone());
+    }
+
+    // Look back a week so weekends and holidays still find a close
+    let end = date.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp();
+    let candles = fetch_candles(symbol, "D", end - 60 * 60 * 24 * 7, end).await?;
+    if candles.s != "ok" {
+        return Err(FinnhubError::InvalidPrice);
+    }
+    let closes: Vec<f64> = candles
+        .t
+        .iter()
+        .zip(&candles.c)
+        .filter(|(t, _)| **t <= end)
+        .map(|(_, c)| *c)
+        .collect();
+    let current = match closes.last() {
+        Some(&c) if c > 0.0 => c,
+        _ => return Err(FinnhubError::InvalidPrice),
+    };
+    let previous = match closes.len() {
+        n if n >= 2 => closes[n - 2],
+        _ => current,
+    };
+    let quote = FinnhubQuote {
+        c: current,
+        d: current - previous,
+        dp: if previous > 0.0 {
+            (current - previous) / previous * 100.0
+        } else {
+            0.0
+        },
+        pc: previous,
+        t: 0,
+    };
+
+    HISTORICAL_CACHE
+        .lock()
+        .await
+        .insert(key, (quote.clone(), Instant::now()));
+    Ok(quote)
+}
+
+/// Fetch a crypto pair's or stock's price from its recent daily candles. The latest close is the
+/// current price and the close before it is the previous close.
+pub async fn fetch_candle_price(symbol: &str) -> Result<FinnhubQuote, FinnhubError> {
+    api_key()?;
+    let now = Instant::now();
+
+    if let Some((quote, timestamp)) = CANDLE_PRICE_CACHE.lock().await.get(symbol) {
+        if now.duration_since(*timestamp) < CRYPTO_QUOTE_TTL {
+            tracing::debug!("Returning cached candle price for {}", symbol);
+            return Ok(quote.clone());
+        }
     }
+
+    let to = chrono::Utc::now().timestamp();
+    // Look back a week so weekends and holidays still find a close
+    let candles = fetch_candles(symbol, "D", to - 60 * 60 * 24 * 7, to).await?;
+    let current = match candles.c.last() {
+        Some(&c) if candles.s == "ok" && c > 0.0 => c,
+        _ => return Err(FinnhubError::InvalidPrice),
+    };
+    let previous = match candles.c.len() {
+        n if n >= 2 => candles.c[n - 2],
+        _ => candles.o.last().copied().unwrap_or(current),
+    };
+    let quote = FinnhubQuote {
+        c: current,
+        d: current - previous,
+        dp: if previous > 0.0 {
+            (current - previous) / previous * 100.0
+        } else {
+            0.0
+        },
+        pc: previous,
+        t: 0,
+    };
+
+    clear_quote_failures(symbol);
+    CANDLE_PRICE_CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (quote.clone(), now));
+
+    Ok(quote)
+}
+
+/// Fetch stock profile from Finnhub API. A stock profile includes the name and logo of the company.
+pub async fn fetch_stock_profile(symbol: &str) -> Result<FinnhubProfile, FinnhubError> {
+    api_key()?;
+    let symbol = &normalize_symbol(symbol);
+    let now = Instant::now();
+
+    if let Some(profile) = cached_profile(symbol, now).await {
+        return Ok(profile);
+    }
+
+    let flight = in_flight(format!("profile:{}", symbol)).await;
+    let _guard = flight.lock().await;
+    // Another request may have fetched the profile while this one waited
+    if let Some(profile) = cached_profile(symbol, Instant::now()).await {
+        return Ok(profile);
+    }
+
+    refresh_stock_profile(symbol).await
+}
+
+/// Get a profile from the cache if it is still valid (less than 24 hours old).
+async fn cached_profile(symbol: &str, now: Instant) -> Option<FinnhubProfile> {
+    let cache = PROFILE_CACHE.lock().await;
+    let (profile, timestamp) = cache.get(symbol)?;
+    if now.duration_since(*timestamp) < PROFILE_TTL {
+        tracing::debug!("Returning cached profile for {}", symbol);
+        return Some(profile.clone());
+    }
+    None
+}
+
+/// Get a still valid profile from the cache without calling Finnhub.
+pub async fn peek_profile(symbol: &str) -> Option<FinnhubProfile> {
+    cached_profile(symbol, Instant::now()).await
+}
+
+/// Cached profiles that expire within `within` of `now`, soonest first. Already expired profiles
+/// are left for the next request to refetch.
+pub async fn expiring_profiles(within: Duration, now: Instant) -> Vec<String> {
+    let cache = PROFILE_CACHE.lock().await;
+    let cutoff = PROFILE_TTL.saturating_sub(within);
+    let mut expiring: Vec<(&String, Duration)> = cache
+        .iter()
+        .map(|(symbol, (_, fetched_at))| (symbol, now.saturating_duration_since(*fetched_at)))
+        .filter(|(_, age)| *age >= cutoff && *age < PROFILE_TTL)
+        .collect();
+    expiring.sort_by_key(|(_, age)| std::cmp::Reverse(*age));
+    expiring
+        .into_iter()
+        .map(|(symbol, _)| symbol.clone())
+        .collect()
+}
+
+/// Fetch stock profile from Finnhub API, bypassing the cache. The fresh profile replaces any cached one.
+pub async fn refresh_stock_profile(symbol: &str) -> Result<FinnhubProfile, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
+
+    let url = format!(
+        "{}/stock/profile2?symbol={}&token={}",
+        base_url(),
+        symbol,
+        api_key
+    );
+    let response = send_finnhub(CLIENT.get(&url)).await?;
     tracing::debug!("Fetched stock profile for {}", symbol);
-    let profile: FinnhubProfile = response.json().await.map_err(|e| e.to_string())?;
+    let profile: FinnhubProfile = decode(response).await?;
 
-    cache.insert(symbol.to_string(), (profile.clone(), now));
+    PROFILE_CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (profile.clone(), Instant::now()));
 
     Ok(profile)
 }
 
-pub async fn fetch_stock_price(symbol: &str) -> Result<FinnhubQuote, String> {
-    let api_key = env::var("FINNHUB_API_KEY").expect("Missing FINNHUB_API_KEY");
+pub async fn fetch_stock_price(symbol: &str) -> Result<FinnhubQuote, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
     let now = Instant::now();
 
-    // Lock the cache using `tokio::sync::Mutex`
-    let mut cache = CACHE.lock().await;
+    if let Some(quote) = cached_quote(symbol, now).await {
+        return Ok(quote);
+    }
 
-    // Check if the symbol is in the cache and still valid
-    if let Some((quote, timestamp)) = cache.get(symbol) {
-        if now.duration_since(*timestamp) < Duration::from_secs(300) {
-            tracing::debug!("Returning cached price for {}", symbol);
-            return Ok(quote.clone());
-        }
+    let flight = in_flight(format!("quote:{}", symbol)).await;
+    let _guard = flight.lock().await;
+    // Another request may have fetched the quote while this one waited
+    let now = Instant::now();
+    if let Some(quote) = cached_quote(symbol, now).await {
+        return Ok(quote);
     }
 
     // Fetch from API if not in cache or expired
+    let url = format!("{}/quote?symbol={}&token={}", base_url(), symbol, api_key);
+
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched stock price for {}", symbol);
+
+    let quote: FinnhubQuote = decode::<RawQuote>(response).await?.try_into()?;
+
+    clear_quote_failures(symbol);
+
+    // Update the cache
+    CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (quote.clone(), now));
+
+    Ok(quote)
+}
+
+/// Get a quote from the cache if it is still valid.
+async fn cached_quote(symbol: &str, now: Instant) -> Option<FinnhubQuote> {
+    let cache = CACHE.lock().await;
+    let (quote, timestamp) = cache.get(symbol)?;
+    if now.duration_since(*timestamp) < QUOTE_TTL {
+        tracing::debug!("Returning cached price for {}", symbol);
+        return Some(quote.clone());
+    }
+    None
+}
+
+/// Fetch the symbols Finnhub considers peers of a stock, excluding the stock itself. Symbols
+/// without peers, including crypto pairs, get an empty list.
+pub async fn fetch_peers(symbol: &str) -> Result<Vec<String>, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
+    if is_crypto_symbol(symbol) {
+        return Ok(Vec::new());
+    }
+
+    if let Some((peers, timestamp)) = PEERS_CACHE.lock().await.get(symbol) {
+        if Instant::now().duration_since(*timestamp) < PEERS_TTL {
+            tracing::debug!("Returning cached peers for {}", symbol);
+            return Ok(peers.clone());
+        }
+    }
+
     let url = format!(
-        "https://finnhub.io/api/v1/quote?symbol={}&token={}",
-        symbol, api_key
+        "{}/stock/peers?symbol={}&token={}",
+        base_url(),
+        symbol,
+        api_key
     );
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched peers for {}", symbol);
+    let peers: Vec<String> = decode(response).await?;
+    let peers: Vec<String> = peers.into_iter().filter(|peer| peer != symbol).collect();
 
-    let response = CLIENT.get(&url).send().await.map_err(|e| e.to_string())?;
-    if !response.status().is_success() {
-        return Err(format!(
-            "Failed to fetch stock price: HTTP {}",
-            response.status()
+    PEERS_CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (peers.clone(), Instant::now()));
+
+    Ok(peers)
+}
+
+/// Fetch the dividends a stock went ex-dividend for in the year up to `today`. Symbols that pay
+/// no dividends, including crypto pairs, get an empty list.
+pub async fn fetch_trailing_dividends(
+    symbol: &str,
+    today: NaiveDate,
+) -> Result<Vec<FinnhubDividend>, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
+    if is_crypto_symbol(symbol) {
+        return Ok(Vec::new());
+    }
+
+    if let Some((dividends, timestamp)) = DIVIDENDS_CACHE.lock().await.get(symbol) {
+        if Instant::now().duration_since(*timestamp) < DIVIDENDS_TTL {
+            tracing::debug!("Returning cached dividends for {}", symbol);
+            return Ok(dividends.clone());
+        }
+    }
+
+    let from = today - chrono::Duration::days(365);
+    let url = format!(
+        "{}/stock/dividend?symbol={}&from={}&to={}&token={}",
+        base_url(),
+        symbol,
+        from,
+        today,
+        api_key
+    );
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched dividends for {}", symbol);
+    let dividends: Vec<FinnhubDividend> = decode(response).await?;
+
+    DIVIDENDS_CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (dividends.clone(), Instant::now()));
+
+    Ok(dividends)
+}
+
+/// Fetch a stock's earnings releases scheduled from `today` through `EARNINGS_LOOKAHEAD_DAYS`
+/// ahead, earliest first. Symbols with nothing scheduled, including crypto pairs, get an empty
+/// list.
+pub async fn fetch_earnings(
+    symbol: &str,
+    today: NaiveDate,
+) -> Result<Vec<FinnhubEarnings>, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
+    if is_crypto_symbol(symbol) {
+        return Ok(Vec::new());
+    }
+
+    if let Some((earnings, timestamp)) = EARNINGS_CACHE.lock().await.get(symbol) {
+        if Instant::now().duration_since(*timestamp) < EARNINGS_TTL {
+            tracing::debug!("Returning cached earnings for {}", symbol);
+            return Ok(earnings.clone());
+        }
+    }
+
+    let to = today + chrono::Duration::days(EARNINGS_LOOKAHEAD_DAYS);
+    let url = format!(
+        "{}/calendar/earnings?symbol={}&from={}&to={}&token={}",
+        base_url(),
+        symbol,
+        today,
+        to,
+        api_key
+    );
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched earnings for {}", symbol);
+    let calendar: FinnhubEarningsCalendar = decode(response).await?;
+    let mut earnings = calendar.earnings_calendar;
+    earnings.sort_by(|a, b| a.date.cmp(&b.date));
+
+    EARNINGS_CACHE
+        .lock()
+        .await
+        .insert(symbol.to_string(), (earnings.clone(), Instant::now()));
+
+    Ok(earnings)
+}
+
+/// Fetch a stock's basic financials, such as its P/E ratio and 52-week range. Crypto pairs have
+/// none, so they get empty metrics.
+pub async fn fetch_basic_financials(symbol: &str) -> Result<FinnhubMetrics, FinnhubError> {
+    let api_key = api_key()?;
+    let symbol = &normalize_symbol(symbol);
+    if is_crypto_symbol(symbol) {
+        return Ok(FinnhubMetrics::default());
+    }
+
+    if let Some((metrics, timestamp)) = FINANCIALS_CACHE.lock().await.get(symbol) {
+        if Instant::now().duration_since(*timestamp) < financials_ttl() {
+            tracing::debug!("Returning cached financials for {}", symbol);
+            return Ok(metrics.clone());
+        }
+    }
+
+    let url = format!(
+        "{}/stock/metric?symbol={}&metric=all&token={}",
+        base_url(),
+        symbol,
+        api_key
+    );
+    let response = send_finnhub(CLIENT.get(&url)).await?;
+    tracing::debug!("Fetched financials for {}", symbol);
+    let financials: FinnhubBasicFinancials = decode(response).await?;
+
+    FINANCIALS_CACHE.lock().await.insert(
+        symbol.to_string(),
+        (financials.metric.clone(), Instant::now()),
+    );
+
+    Ok(financials.metric)
+}
+
+/// A local stand-in for the Finnhub API. Tests register the body to answer each endpoint and
+/// symbol with, and the fetchers are pointed at it the first time it is started. Caches are
+/// shared by every test, so each test should use symbols of its own.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use axum::{extract::Query, http::Uri, response::IntoResponse, Router};
+    use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    /// An endpoint path, e.g. `/quote`, and the symbol requested from it.
+    type Endpoint = (String, String);
+
+    /// Canned responses as status and body.
+    static RESPONSES: std::sync::Mutex<Option<HashMap<Endpoint, (u16, String)>>> =
+        std::sync::Mutex::new(None);
+    /// Requests answered so far.
+    static CALLS: std::sync::Mutex<Option<HashMap<Endpoint, usize>>> = std::sync::Mutex::new(None);
+    static STARTED: OnceLock<()> = OnceLock::new();
+
+    /// Held shared by tests calling Finnhub, and exclusively by tests that need it to themselves,
+    /// such as ones counting calls or leaving it rate limited.
+    static IN_USE: RwLock<()> = RwLock::const_new(());
+
+    /// Start the mock server if it isn't running and point the fetchers at it. The returned
+    /// guard should be held for as long as the test calls Finnhub.
+    pub(crate) async fn start() -> RwLockReadGuard<'static, ()> {
+        launch();
+        IN_USE.read().await
+    }
+
+    /// Like `start`, but waits for other tests calling Finnhub to finish and keeps new ones
+    /// waiting until the guard is dropped.
+    pub(crate) async fn exclusive() -> RwLockWriteGuard<'static, ()> {
+        launch();
+        IN_USE.write().await
+    }
+
+    fn launch() {
+        STARTED.get_or_init(|| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let addr = listener.local_addr().unwrap();
+            // Each test runs on its own runtime, so the server gets a thread that outlives them
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async move {
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                    let app = Router::new().fallback(answer);
+                    axum::serve(listener, app).await.unwrap();
+                });
+            });
+            init(&Config {
+                finnhub_base_url: format!("http://{}", addr),
+                finnhub_api_key: Some(String::from("test")),
+                ..Config::for_tests()
+            });
+        });
+    }
+
+    async fn answer(uri: Uri, Query(query): Query<HashMap<String, String>>) -> Response {
+        let key = (
+            uri.path().to_string(),
+            query.get("symbol").cloned().unwrap_or_default(),
+        );
+        *CALLS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .entry(key.clone())
+            .or_default() += 1;
+        let response = RESPONSES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .get(&key)
+            .cloned();
+        match response {
+            Some((status, body)) => (StatusCode::from_u16(status).unwrap(), body).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// Answer requests to `path`, e.g. `/quote`, for `symbol` with `body`.
+    pub(crate) fn respond(path: &str, symbol: &str, body: &str) {
+        respond_with_status(path, symbol, 200, body);
+    }
+
+    /// Answer requests to `path` for `symbol` with `status` and `body`.
+    pub(crate) fn respond_with_status(path: &str, symbol: &str, status: u16, body: &str) {
+        RESPONSES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(
+                (path.to_string(), symbol.to_string()),
+                (status, body.to_string()),
+            );
+    }
+
+    /// Answer `/quote` for `symbol` with a current price of `price` dollars, and its profile
+    /// with `name` in the technology industry.
+    pub(crate) fn stock(symbol: &str, name: &str, price: f64) {
+        respond(
+            "/quote",
+            symbol,
+            &format!(
+                r#"{{"c":{price},"d":1.0,"dp":1.0,"pc":{},"t":0}}"#,
+                price - 1.0
+            ),
+        );
+        respond(
+            "/stock/profile2",
+            symbol,
+            &format!(
+                r#"{{"ticker":"{symbol}","name":"{name}","logo":"https://logos.example/{symbol}.png","finnhubIndustry":"Technology"}}"#
+            ),
+        );
+    }
+
+    /// How many requests to `path` for `symbol` the server has answered.
+    pub(crate) fn calls(path: &str, symbol: &str) -> usize {
+        CALLS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .get(&(path.to_string(), symbol.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_key_makes_prices_unavailable() {
+        let error = FinnhubError::MissingApiKey;
+        assert!(error.is_unavailable());
+        let (status, Json(message)) = error.into();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(message, "Price data is currently unavailable");
+    }
+
+    #[test]
+    fn a_cdn_prefix_rewrites_logos() {
+        let profile = FinnhubProfile {
+            logo: String::from("https://static.finnhub.io/logo/aapl.png"),
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.logo_url(Some("https://cdn.example/")),
+            "https://cdn.example/https://static.finnhub.io/logo/aapl.png"
+        );
+        assert_eq!(profile.logo_url(Some("")), profile.logo);
+        assert_eq!(profile.logo_url(None), profile.logo);
+
+        let no_logo = FinnhubProfile::default();
+        assert_eq!(no_logo.logo_url(Some("https://cdn.example/")), "");
+    }
+
+    #[test]
+    fn sweep_drops_expired_entries_then_the_oldest_beyond_the_cap() {
+        let now = Instant::now();
+        let mut cache: HashMap<(String, NaiveDate), (u32, Instant)> = HashMap::new();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        cache.insert(
+            (String::from("OLD"), date),
+            (1, now - Duration::from_secs(120)),
+        );
+        cache.insert(
+            (String::from("A"), date),
+            (2, now - Duration::from_secs(30)),
+        );
+        cache.insert(
+            (String::from("B"), date),
+            (3, now - Duration::from_secs(20)),
+        );
+        cache.insert(
+            (String::from("C"), date),
+            (4, now - Duration::from_secs(10)),
+        );
+
+        let removed = sweep(&mut cache, Duration::from_secs(60), Some(2));
+
+        assert_eq!(removed, 2);
+        let mut kept: Vec<&str> = cache.keys().map(|(symbol, _)| symbol.as_str()).collect();
+        kept.sort();
+        assert_eq!(kept, ["B", "C"]);
+    }
+
+    #[tokio::test]
+    async fn a_403_is_reported_as_unauthorized() {
+        use axum::{routing::get, Router};
+        let _finnhub = mock::start().await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/quote", get(|| async { StatusCode::FORBIDDEN }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("http://{}/quote?symbol=AAPL&token=bad", addr);
+        let error = send_finnhub(CLIENT.get(&url)).await.unwrap_err();
+        assert!(matches!(error, FinnhubError::Unauthorized));
+        let (status, Json(message)) = error.into();
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(message, "Price provider misconfigured");
+    }
+
+    #[tokio::test]
+    async fn a_429_becomes_a_503_with_retry_after() {
+        use axum::{body::Body, middleware, routing::get, Router};
+        use tower::ServiceExt;
+        let _finnhub = mock::exclusive().await;
+        mock::respond_with_status("/quote", "RATELIMITED", 429, "");
+
+        let app = Router::new()
+            .route(
+                "/price",
+                get(|| async {
+                    fetch_stock_price("RATELIMITED")
+                        .await
+                        .map(|_| StatusCode::OK)
+                        .map_err(<(StatusCode, Json<String>)>::from)
+                }),
+            )
+            .layer(middleware::from_fn(add_retry_after));
+        let request = Request::builder()
+            .uri("/price")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        // Later tests must not find Finnhub paused
+        *RATE_LIMITED_UNTIL.lock().unwrap() = None;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let retry_after: u64 = response.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=DEFAULT_RETRY_AFTER.as_secs()).contains(&retry_after));
+    }
+
+    #[tokio::test]
+    async fn an_html_body_is_a_decode_error() {
+        let _finnhub = mock::start().await;
+        mock::respond(
+            "/stock/profile2",
+            "HTMLPAGE",
+            "<html><body>Service Unavailable</body></html>",
+        );
+
+        let Err(error) = fetch_stock_profile("HTMLPAGE").await else {
+            panic!("an HTML profile decoded");
+        };
+
+        assert!(matches!(error, FinnhubError::Decode(_)));
+        let (status, Json(message)) = error.into();
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(message, "Price provider returned an unreadable response");
+    }
+
+    #[tokio::test]
+    async fn sweep_caches_drops_unused_fetch_locks() {
+        let held = in_flight(String::from("quote:SWEEP_HELD")).await;
+        drop(in_flight(String::from("quote:SWEEP_UNUSED")).await);
+
+        sweep_caches(None).await;
+
+        let in_flight = IN_FLIGHT.lock().await;
+        assert!(in_flight.contains_key("quote:SWEEP_HELD"));
+        assert!(!in_flight.contains_key("quote:SWEEP_UNUSED"));
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn the_last_candle_source_prices_from_candles() {
+        let _finnhub = mock::start().await;
+        mock::stock("SRCQ", "Source", 10.0);
+        mock::respond(
+            "/stock/candle",
+            "SRCQ",
+            r#"{"c":[11.0,12.5],"o":[10.5,11.0],"t":[1709586000,1709672400],"s":"ok"}"#,
+        );
+
+        let quote = fetch_price_from(PriceSource::Quote, "SRCQ").await.unwrap();
+        assert_eq!(quote.c, 10.0);
+        assert_eq!(mock::calls("/stock/candle", "SRCQ"), 0);
+
+        let candle = fetch_price_from(PriceSource::LastCandle, "srcq")
+            .await
+            .unwrap();
+        assert_eq!((candle.c, candle.pc), (12.5, 11.0));
+        assert_eq!(mock::calls("/stock/candle", "SRCQ"), 1);
+    }
+
+    #[test]
+    fn basic_financials_deserialize_with_missing_metrics_unset() {
+        let body = r#"{
+            "metric": {
+                "peTTM": 28.4,
+                "52WeekHigh": 199.62,
+                "52WeekLow": 164.08,
+                "marketCapitalization": 2650000.5,
+                "beta": null,
+                "epsTTM": 6.43,
+                "10DayAverageTradingVolume": 55.1
+            },
+            "metricType": "all",
+            "symbol": "AAPL"
+        }"#;
+
+        let metrics = serde_json::from_str::<FinnhubBasicFinancials>(body)
+            .unwrap()
+            .metric;
+
+        assert_eq!(metrics.pe_ttm, Some(28.4));
+        assert_eq!(metrics.week_52_high, Some(199.62));
+        assert_eq!(metrics.week_52_low, Some(164.08));
+        assert_eq!(metrics.market_capitalization, Some(2650000.5));
+        assert_eq!(metrics.eps_ttm, Some(6.43));
+        assert_eq!(metrics.beta, None);
+        assert_eq!(metrics.dividend_yield_indicated_annual, None);
+
+        // Symbols without financials get an empty metric object
+        let empty = serde_json::from_str::<FinnhubBasicFinancials>(r#"{"metric":{}}"#).unwrap();
+        assert_eq!(empty.metric.pe_ttm, None);
+    }
+
+    #[tokio::test]
+    async fn historical_prices_are_the_close_on_the_date() {
+        let _finnhub = mock::start().await;
+        let day = |d: u32| {
+            NaiveDate::from_ymd_opt(2024, 3, d)
+                .unwrap()
+                .and_hms_opt(21, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp()
+        };
+        mock::respond(
+            "/stock/candle",
+            "HISTA",
+            &format!(
+                r#"{{"c":[10.0,12.0,15.0],"o":[9.0,10.0,12.0],"t":[{},{},{}],"s":"ok"}}"#,
+                day(4),
+                day(5),
+                day(6)
+            ),
+        );
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        let quote = fetch_historical_price("HISTA", date).await.unwrap();
+        assert_eq!(quote.c, 12.0);
+        assert_eq!(quote.pc, 10.0);
+
+        // Historical prices never change, so the second lookup is cached
+        fetch_historical_price("HISTA", date).await.unwrap();
+        assert_eq!(mock::calls("/stock/candle", "HISTA"), 1);
+    }
+
+    #[test]
+    fn quotes_with_null_changes_still_have_a_price() {
+        let raw: RawQuote =
+            serde_json::from_str(r#"{"c":150.0,"d":null,"dp":null,"pc":120.0,"t":null}"#).unwrap();
+        let quote = FinnhubQuote::try_from(raw).unwrap();
+        assert_eq!(quote.c, 150.0);
+        assert_eq!(quote.d, 30.0);
+        assert_eq!(quote.dp, 25.0);
+        assert_eq!(quote.t, 0);
+    }
+
+    #[test]
+    fn all_null_quotes_are_unknown_symbols() {
+        let raw: RawQuote = serde_json::from_str(r#"{"c":0,"d":null,"dp":null,"pc":0}"#).unwrap();
+        assert!(matches!(
+            FinnhubQuote::try_from(raw),
+            Err(FinnhubError::UnknownSymbol)
         ));
     }
-    tracing::debug!("Fetched stock price for {}", symbol);
 
-    let quote: FinnhubQuote = response.json().await.map_err(|e| e.to_string())?;
-    if quote.c <= 0.0 {
-        return Err("Invalid stock price returned".to_string());
+    #[tokio::test]
+    async fn symbols_share_a_cache_entry_whatever_their_case() {
+        let _finnhub = mock::start().await;
+        mock::stock("CASEA", "Case A", 10.0);
+
+        fetch_stock_price("casea").await.unwrap();
+        fetch_stock_price(" CASEA ").await.unwrap();
+        fetch_stock_price("CaseA").await.unwrap();
+
+        assert_eq!(mock::calls("/quote", "CASEA"), 1);
     }
 
-    // Update the cache
-    cache.insert(symbol.to_string(), (quote.clone(), now));
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_cold_fetches_call_finnhub_once() {
+        let _finnhub = mock::start().await;
+        mock::stock("FLIGHTA", "Flight A", 10.0);
 
-    Ok(quote)
+        let fetches = (0..10).map(|_| tokio::spawn(fetch_stock_price("FLIGHTA")));
+        for fetch in futures_util::future::join_all(fetches).await {
+            assert_eq!(fetch.unwrap().unwrap().c, 10.0);
+        }
+
+        assert_eq!(mock::calls("/quote", "FLIGHTA"), 1);
+    }
+
+    #[tokio::test]
+    async fn each_upstream_call_is_counted() {
+        let _finnhub = mock::exclusive().await;
+        for symbol in ["COUNTA", "COUNTB", "COUNTC"] {
+            mock::stock(symbol, symbol, 10.0);
+        }
+        let before = usage();
+
+        for symbol in ["COUNTA", "COUNTB", "COUNTC"] {
+            fetch_stock_price(symbol).await.unwrap();
+        }
+        // Cached quotes don't reach Finnhub
+        fetch_stock_price("COUNTA").await.unwrap();
+
+        let after = usage();
+        assert_eq!(after.total_calls, before.total_calls + 3);
+        assert!(after.calls_last_minute >= 3);
+    }
 }
@@ -1,25 +1,49 @@
 use crate::auth::validate_session;
 use crate::db::DatabasePool;
-use crate::finnhub::fetch_stock_price;
-use crate::models::Account;
-use axum::{extract::State, http::StatusCode, Json};
+use crate::etag::{account_etag, if_none_match, variant_etag};
+use crate::finnhub::fetch_price;
+use crate::models::{
+    Account, AccountExtremes, AccountSettings, CompositionHistory, Holding, Transaction,
+};
+use crate::money::{round_dollars, Rounding, RoundingQuery};
+use crate::snapshots::{composition, extremes};
+use crate::state::AppState;
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, ETAG},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use std::convert::Infallible;
+use std::sync::Arc;
 use tower_sessions::Session;
 
-#[axum::debug_handler]
-/// Gets an account by ID.
+#[axum::debug_handler(state = AppState)]
+/// Gets an account by ID. Responds 304 when `If-None-Match` matches the account's current ETag.
+/// With `round=dollars`, money values are rounded to whole dollars for display.
 pub async fn get_account(
-    State(pool): State<DatabasePool>,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
     session: Session,
-) -> Result<(StatusCode, Json<Account>), (StatusCode, Json<String>)> {
+    headers: HeaderMap,
+    Query(rounding): Query<RoundingQuery>,
+) -> Result<Response, (StatusCode, Json<String>)> {
     // Validate the session
-    let info = match validate_session(session).await {
+    let info = match validate_session(session.clone()).await {
         Ok(info) => info,
         Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
     };
     let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
 
     // Fetch the account details using `get_account` method
-    let account = match pool.get_account(&account_id).await {
+    let account = match store.get_account(&account_id).await {
         Ok(account) => account,
         Err(e) => {
             return Err((
@@ -27,10 +51,19 @@ pub async fn get_account(
                 Json(format!("Failed to fetch account details: {}", e)),
             ));
         }
-    };
+    }
+    .unwrap();
+
+    let mut etag = account_etag(&account);
+    if rounding.round == Some(Rounding::Dollars) {
+        etag = variant_etag(&etag, "dollars");
+    }
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
 
     // Fetch holdings using `get_holdings` method
-    let holdings = match pool.get_holdings(&account_id).await {
+    let holdings = match store.get_holdings(&account_id).await {
         Ok(holdings) => holdings,
         Err(e) => {
             return Err((
@@ -43,12 +76,13 @@ pub async fn get_account(
     // Calculate changes based on stock prices
     let mut sum_changes = 0;
     for holding in holdings {
-        match fetch_stock_price(&holding.stock_symbol).await {
+        match fetch_price(&holding.stock_symbol).await {
             Ok(quote) => {
                 let current_value = (quote.c * 100.0) as i32 * holding.quantity;
                 let yesterday_value = (quote.pc * 100.0) as i32 * holding.quantity;
                 sum_changes += current_value - yesterday_value;
             }
+            Err(e) if e.is_unavailable() => return Err(e.into()),
             Err(e) => {
                 return Err((
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -58,11 +92,221 @@ pub async fn get_account(
         }
     }
 
-    let mut a = account.unwrap();
+    let mut a = account;
 
     // Update the `change` field of the account
     a.change = sum_changes;
 
+    if rounding.round == Some(Rounding::Dollars) {
+        a.value = round_dollars(a.value);
+        a.cash = round_dollars(a.cash);
+        a.change = round_dollars(a.change);
+    }
+
     // Return the updated account
-    Ok((StatusCode::OK, Json(a)))
+    Ok((StatusCode::OK, [(ETAG, etag)], Json(a)).into_response())
+}
+
+/// Download everything stored for the current account as a single JSON attachment: the
+/// account, its holdings, settings, and full transaction history. Transactions are serialized
+/// one at a time as the body streams, so large histories are never rendered into one buffer.
+pub async fn export_account(
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(pool): State<DatabasePool>,
+    session: Session,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let account = match store.get_account(&account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account details: {}", e)),
+            ));
+        }
+    };
+    let holdings = store.get_holdings(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch holdings: {}", e)),
+        )
+    })?;
+    let settings = pool.get_settings(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch settings: {}", e)),
+        )
+    })?;
+    let transactions = store.get_transactions(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch transactions: {}", e)),
+        )
+    })?;
+
+    let filename = format!(
+        "attachment; filename=\"stocksim-export-{}.json\"",
+        account_id
+    );
+    Ok((
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, String::from("application/json")),
+            (CONTENT_DISPOSITION, filename),
+        ],
+        export_body(&account, &holdings, &settings, transactions),
+    )
+        .into_response())
+}
+
+/// The JSON document `export_account` downloads, with transactions serialized as it streams.
+fn export_body(
+    account: &Account,
+    holdings: &[Holding],
+    settings: &AccountSettings,
+    transactions: Vec<Transaction>,
+) -> Body {
+    // These serialize infallibly: plain structs with string keys
+    let head = format!(
+        "{{\"account\":{},\"holdings\":{},\"settings\":{},\"transactions\":[",
+        serde_json::to_string(account).unwrap(),
+        serde_json::to_string(holdings).unwrap(),
+        serde_json::to_string(settings).unwrap(),
+    );
+    let body = stream::once(async move { head })
+        .chain(
+            stream::iter(transactions.into_iter().enumerate()).map(|(i, transaction)| {
+                let json = serde_json::to_string(&transaction).unwrap();
+                match i {
+                    0 => json,
+                    _ => format!(",{}", json),
+                }
+            }),
+        )
+        .chain(stream::once(async { String::from("]}") }))
+        .map(Ok::<_, Infallible>);
+
+    Body::from_stream(body)
+}
+
+/// Get the highest and lowest recorded values of the current account and how far its latest
+/// recorded value is below the high. Values are recorded when the portfolio is recomputed and
+/// by the reconciliation job, at most once a day.
+pub async fn get_account_extremes(
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    session: Session,
+) -> Result<(StatusCode, Json<AccountExtremes>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let snapshots = store.get_snapshots(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch value snapshots: {}", e)),
+        )
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(AccountExtremes {
+            snapshots: snapshots.len(),
+            extremes: extremes(&snapshots),
+        }),
+    ))
+}
+
+/// Get the current account's value split between cash and holdings on each day it was recorded,
+/// oldest first, for charting. Values are recorded when the portfolio is recomputed and by the
+/// reconciliation job, at most once a day.
+pub async fn get_composition_history(
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    session: Session,
+) -> Result<(StatusCode, Json<CompositionHistory>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let snapshots = store.get_snapshots(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch value snapshots: {}", e)),
+        )
+    })?;
+    let points = composition(&snapshots);
+
+    Ok((
+        StatusCode::OK,
+        Json(CompositionHistory {
+            skipped: snapshots.len() - points.len(),
+            points,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn export(transactions: usize) -> serde_json::Value {
+        let account = Account::open("a@example.com", 10_000, true);
+        let holdings = ["AAPL", "MSFT"].map(|symbol| Holding {
+            account_id: account.id.clone(),
+            stock_symbol: symbol.to_string(),
+            ..Default::default()
+        });
+        let transactions = (0..transactions)
+            .map(|i| Transaction {
+                id: i.to_string(),
+                account_id: account.id.clone(),
+                ..Default::default()
+            })
+            .collect();
+        let body = export_body(
+            &account,
+            &holdings,
+            &AccountSettings::default(),
+            transactions,
+        );
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn exports_hold_the_account_and_all_its_records() {
+        let export = export(3).await;
+        assert_eq!(export["account"]["id"], "a@example.com");
+        assert_eq!(export["holdings"].as_array().unwrap().len(), 2);
+        assert_eq!(export["transactions"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn exports_without_transactions_are_valid_json() {
+        let export = export(0).await;
+        assert!(export["transactions"].as_array().unwrap().is_empty());
+    }
 }
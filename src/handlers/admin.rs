@@ -0,0 +1,431 @@
+use crate::auth::validate_admin;
+use crate::config::Config;
+use crate::corporate_actions;
+use crate::db::DatabasePool;
+use crate::finnhub::{self, FinnhubUsage, CRYPTO_QUOTE_TTL, PROFILE_TTL, QUOTE_TTL};
+use crate::locks::AccountLocks;
+use crate::models::{
+    Account, AccountSettings, BulkCreateAccounts, BulkCreateSummary, CorporateActionRecord,
+    CorporateActionRequest, Holding, RestoreSnapshot, SnapshotRestoreRecord, UpdateFeature,
+    ValueDrift,
+};
+use crate::store::{Store, StoreError};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// The resolved, non-secret runtime configuration.
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    #[serde(flatten)]
+    pub config: Config,
+    pub quote_cache_ttl_secs: u64,
+    pub profile_cache_ttl_secs: u64,
+    pub crypto_quote_cache_ttl_secs: u64,
+    /// Number of `ADMIN_EMAILS`, which are left out.
+    pub admin_count: usize,
+}
+
+/// Get the effective runtime configuration. Secrets such as API keys are never included.
+pub async fn get_config(
+    session: Session,
+    State(config): State<Arc<Config>>,
+) -> Result<(StatusCode, Json<EffectiveConfig>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    Ok((StatusCode::OK, Json(EffectiveConfig::new(&config))))
+}
+
+impl EffectiveConfig {
+    /// The configuration as shown by `GET /admin/config`.
+    pub fn new(config: &Config) -> Self {
+        EffectiveConfig {
+            config: config.clone(),
+            quote_cache_ttl_secs: QUOTE_TTL.as_secs(),
+            profile_cache_ttl_secs: PROFILE_TTL.as_secs(),
+            crypto_quote_cache_ttl_secs: CRYPTO_QUOTE_TTL.as_secs(),
+            admin_count: config.admin_emails.len(),
+        }
+    }
+}
+
+/// List accounts flagged by the reconciliation job for value drift.
+pub async fn get_value_drifts(
+    session: Session,
+    State(pool): State<DatabasePool>,
+) -> Result<(StatusCode, Json<Vec<ValueDrift>>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    match pool.get_value_drifts().await {
+        Ok(drifts) => Ok((StatusCode::OK, Json(drifts))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch flagged accounts: {}", e)),
+        )),
+    }
+}
+
+/// Get Finnhub API usage: total calls since startup and calls in the last minute.
+pub async fn get_finnhub_usage(
+    session: Session,
+) -> Result<(StatusCode, Json<FinnhubUsage>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    Ok((StatusCode::OK, Json(finnhub::usage())))
+}
+
+/// Apply a corporate action, such as a ticker change or cash merger, to every affected account.
+/// Responds 201 when applied and 200 with the original audit entry if the action id was seen before.
+pub async fn apply_corporate_action(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    Json(request): Json<CorporateActionRequest>,
+) -> Result<(StatusCode, Json<CorporateActionRecord>), (StatusCode, Json<String>)> {
+    let admin = match validate_admin(session).await {
+        Ok(admin) => admin,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    match corporate_actions::apply(store.as_ref(), request, &admin.email).await {
+        Ok((record, true)) => Ok((StatusCode::CREATED, Json(record))),
+        Ok((record, false)) => Ok((StatusCode::OK, Json(record))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to apply corporate action: {}", e)),
+        )),
+    }
+}
+
+/// Grant or revoke a gated feature for an account and return the account's settings.
+pub async fn set_account_feature(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    Path(account_id): Path<String>,
+    Json(update): Json<UpdateFeature>,
+) -> Result<(StatusCode, Json<AccountSettings>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    let feature = update.feature.trim();
+    if feature.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("A feature name is required.")),
+        ));
+    }
+
+    match pool.set_feature(&account_id, feature, update.enabled).await {
+        Ok(settings) => Ok((StatusCode::OK, Json(settings))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to update features: {}", e)),
+        )),
+    }
+}
+
+/// Merge an account's duplicate holdings of the same symbol and return the merged holdings.
+pub async fn dedupe_account_holdings(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    State(locks): State<AccountLocks>,
+    Path(account_id): Path<String>,
+) -> Result<(StatusCode, Json<Vec<Holding>>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+    let _lock = locks.lock(&account_id).await;
+
+    match pool.dedupe_holdings(&account_id).await {
+        Ok(merged) => Ok((StatusCode::OK, Json(merged))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to merge holdings: {}", e)),
+        )),
+    }
+}
+
+/// Delete an account's holdings left at zero or fewer shares and return them.
+pub async fn purge_account_empty_holdings(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    State(locks): State<AccountLocks>,
+    Path(account_id): Path<String>,
+) -> Result<(StatusCode, Json<Vec<Holding>>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+    let _lock = locks.lock(&account_id).await;
+
+    match pool.purge_empty_holdings(&account_id).await {
+        Ok(purged) => Ok((StatusCode::OK, Json(purged))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to purge holdings: {}", e)),
+        )),
+    }
+}
+
+/// Most accounts a single bulk request may create.
+pub const MAX_BULK_ACCOUNTS: usize = 500;
+
+/// Create an account for each listed email with the configured starting cash, as if each user had
+/// signed in. Emails that already have an account are skipped, so the request can be retried.
+pub async fn bulk_create_accounts(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    State(config): State<Arc<Config>>,
+    Json(request): Json<BulkCreateAccounts>,
+) -> Result<(StatusCode, Json<BulkCreateSummary>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+    if request.emails.len() > MAX_BULK_ACCOUNTS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "At most {} accounts can be created at once.",
+                MAX_BULK_ACCOUNTS
+            )),
+        ));
+    }
+
+    let summary = create_accounts(&pool, &config, request.emails).await?;
+    tracing::info!(
+        "Bulk created {} accounts, skipped {}",
+        summary.created.len(),
+        summary.skipped.len()
+    );
+
+    Ok((StatusCode::OK, Json(summary)))
+}
+
+/// Open an account with the starting cash for each new email, skipping existing accounts and
+/// repeats and listing malformed emails as invalid.
+async fn create_accounts(
+    store: &dyn Store,
+    config: &Config,
+    emails: Vec<String>,
+) -> Result<BulkCreateSummary, (StatusCode, Json<String>)> {
+    let mut summary = BulkCreateSummary::default();
+    for email in emails {
+        let email = email.trim().to_string();
+        if !email.contains('@') || email.contains(char::is_whitespace) {
+            summary.invalid.push(email);
+            continue;
+        }
+        if summary.created.contains(&email) || summary.skipped.contains(&email) {
+            summary.skipped.push(email);
+            continue;
+        }
+        let existing = store.get_account(&email).await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account: {}", e)),
+            )
+        })?;
+        if existing.is_some() {
+            summary.skipped.push(email);
+            continue;
+        }
+        let account = Account::open(
+            &email,
+            config.starting_cash,
+            config.leaderboard_eligible_by_default,
+        );
+        store.add_account(account).await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to create account {}: {}", email, e)),
+            )
+        })?;
+        summary.created.push(email);
+    }
+    Ok(summary)
+}
+
+/// Restore an account's cash, value, and holdings to the snapshot recorded on a day, and record
+/// who did it. Transactions are left alone, so history-based views will disagree with the
+/// restored holdings.
+pub async fn restore_account_snapshot(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    State(locks): State<AccountLocks>,
+    Path(account_id): Path<String>,
+    Json(request): Json<RestoreSnapshot>,
+) -> Result<(StatusCode, Json<SnapshotRestoreRecord>), (StatusCode, Json<String>)> {
+    let admin = match validate_admin(session).await {
+        Ok(admin) => admin,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let _lock = locks.lock(&account_id).await;
+
+    let snapshot = match pool.get_snapshot(&account_id, request.date).await {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("No snapshot was recorded on that day.")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch snapshot: {}", e)),
+            ))
+        }
+    };
+    let Some(cash) = snapshot.cash else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(String::from(
+                "That snapshot predates holdings being recorded and can't be restored.",
+            )),
+        ));
+    };
+    let account = match Store::get_account(&pool, &account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account details: {}", e)),
+            ))
+        }
+    };
+
+    let now = crate::timestamps::now();
+    let record = SnapshotRestoreRecord {
+        account_id: account_id.clone(),
+        date: snapshot.date,
+        restored_at: now.clone(),
+        restored_by: admin.email,
+        previous_value: account.value as i64,
+        previous_cash: account.cash as i64,
+        value: snapshot.value,
+        cash,
+        holdings: snapshot.holdings.len(),
+    };
+    let holdings = snapshot
+        .holdings
+        .into_iter()
+        .map(|holding| holding.into_holding(&account_id, &now))
+        .collect();
+
+    // Every write goes through the transaction's session, so a failure leaves the account as it was
+    let restore = async {
+        let txn = pool.begin_transaction().await?;
+        let result = async {
+            Store::update_account(&txn, &account_id, record.value, record.cash).await?;
+            txn.replace_holdings(&account_id, holdings).await?;
+            Store::increment_account_version(&txn, &account_id).await?;
+            txn.add_snapshot_restore(record.clone()).await?;
+            Ok::<(), StoreError>(())
+        }
+        .await;
+        match result {
+            Ok(()) => txn.commit_transaction().await.map_err(StoreError::from),
+            Err(e) => {
+                txn.abort_transaction().await?;
+                Err(e)
+            }
+        }
+    };
+    match restore.await {
+        Ok(()) => {
+            tracing::info!("Restored {} to its {} snapshot", account_id, record.date);
+            Ok((StatusCode::OK, Json(record)))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to restore snapshot: {}", e)),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_config_leaves_out_secrets_and_admin_emails() {
+        let config = Config {
+            admin_emails: ["admin@example.com".to_string()].into(),
+            finnhub_api_key: Some(String::from("secret")),
+            ..Config::for_tests()
+        };
+
+        let json = serde_json::to_value(EffectiveConfig::new(&config)).unwrap();
+        let keys = json.as_object().unwrap();
+        for included in [
+            "starting_cash",
+            "max_shares",
+            "quote_cache_ttl_secs",
+            "admin_count",
+        ] {
+            assert!(keys.contains_key(included), "{} is missing", included);
+        }
+        for excluded in [
+            "admin_emails",
+            "finnhub_api_key",
+            "mongo_uri",
+            "google_oauth",
+            "github_oauth",
+        ] {
+            assert!(!keys.contains_key(excluded), "{} is exposed", excluded);
+        }
+        assert_eq!(json["admin_count"], 1);
+        assert!(!json.to_string().contains("admin@example.com"));
+    }
+
+    #[tokio::test]
+    async fn bulk_creation_opens_new_accounts_and_skips_existing_ones() {
+        let store = crate::store::MemoryStore::new();
+        store
+            .add_account(Account::open("existing@example.com", 5_000, true))
+            .await
+            .unwrap();
+        let config = Config {
+            starting_cash: 1_000_000,
+            ..Config::for_tests()
+        };
+        let emails = [
+            "new@example.com",
+            " existing@example.com",
+            "not an email",
+            "new@example.com",
+        ];
+
+        let summary = create_accounts(&store, &config, emails.map(String::from).to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.created, ["new@example.com"]);
+        assert_eq!(summary.skipped, ["existing@example.com", "new@example.com"]);
+        assert_eq!(summary.invalid, ["not an email"]);
+        let created = store.get_account("new@example.com").await.unwrap().unwrap();
+        assert_eq!(created.cash, 1_000_000);
+        let existing = store
+            .get_account("existing@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(existing.cash, 5_000);
+    }
+}
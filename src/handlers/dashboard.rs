@@ -0,0 +1,182 @@
+use crate::auth::validate_session;
+use crate::finnhub::{fetch_price, fetch_profile, FinnhubBudget};
+use crate::handlers::portfolio::{load_account, price_holdings};
+use crate::models::{Dashboard, Holding};
+use crate::pnl::sort_chronologically;
+use crate::state::AppState;
+use crate::store::resolve_store;
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use futures_util::future::join_all;
+use tower_sessions::Session;
+
+/// Holdings listed as movers.
+const MOVERS: usize = 3;
+/// Transactions listed as recent activity.
+const RECENT_TRANSACTIONS: usize = 10;
+
+/// Get the account, priced portfolio, today's biggest movers, and recent transactions in one
+/// response. Holdings are quoted concurrently up front, so pricing reads from the cache and each
+/// symbol is fetched from Finnhub at most once.
+pub async fn get_dashboard(
+    session: Session,
+    State(state): State<AppState>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<Dashboard>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        config,
+        guests,
+        clock,
+        ..
+    } = state;
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (mut account, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let mut transactions = match store.get_transactions(&account_id).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch transactions: {}", e)),
+            ));
+        }
+    };
+
+    prefetch(&holdings, &budget).await;
+    let priced = price_holdings(
+        &account_id,
+        holdings,
+        &budget,
+        &config,
+        clock.as_ref(),
+        false,
+    )
+    .await?;
+
+    account.change = priced
+        .holdings
+        .iter()
+        .filter(|h| !h.delisted)
+        .map(|h| h.day_change.unwrap_or(0) * h.quantity)
+        .sum();
+
+    let mut movers: Vec<_> = priced
+        .holdings
+        .iter()
+        .filter(|h| h.day_change_percent.is_some_and(|p| p != 0))
+        .cloned()
+        .collect();
+    movers.sort_by_key(|h| std::cmp::Reverse(h.day_change_percent.unwrap_or(0).abs()));
+    movers.truncate(MOVERS);
+
+    sort_chronologically(&mut transactions);
+    transactions.reverse();
+    transactions.truncate(RECENT_TRANSACTIONS);
+
+    Ok((
+        StatusCode::OK,
+        Json(Dashboard {
+            account,
+            holdings: priced.holdings,
+            movers,
+            recent_transactions: transactions,
+            truncated: priced.truncated,
+        }),
+    ))
+}
+
+/// Quote and profile the holdings the budget allows concurrently, warming the caches that
+/// `price_holdings` reads from. Failures are left for `price_holdings` to handle.
+async fn prefetch(holdings: &[Holding], budget: &FinnhubBudget) {
+    let symbols: Vec<&str> = holdings
+        .iter()
+        .filter(|h| !h.delisted)
+        .map(|h| h.stock_symbol.as_str())
+        .take_while(|symbol| budget.try_spend(symbol))
+        .collect();
+    join_all(symbols.into_iter().map(|symbol| async move {
+        if fetch_price(symbol).await.is_ok() {
+            let _ = fetch_profile(symbol).await;
+        }
+    }))
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::finnhub::mock;
+    use crate::handlers::portfolio::recompute_portfolio;
+    use crate::models::{Account, HoldingResponse, PartialQuery};
+    use crate::store::{MemoryStore, Store};
+    use axum::extract::Query;
+    use std::sync::Arc;
+
+    const ACCOUNT: &str = "a@example.com";
+
+    fn holding(symbol: &str, quantity: i32) -> Holding {
+        Holding {
+            account_id: String::from(ACCOUNT),
+            stock_symbol: symbol.to_string(),
+            quantity,
+            purchase_price: 1_000,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn dashboard_totals_match_the_portfolio() {
+        let _finnhub = mock::start().await;
+        mock::stock("DASHA", "Dash A", 10.0);
+        mock::stock("DASHB", "Dash B", 20.0);
+        let store = Arc::new(MemoryStore::new());
+        store
+            .add_account(Account::open(ACCOUNT, 50_000, true))
+            .await
+            .unwrap();
+        store.add_holding(holding("DASHA", 3)).await.unwrap();
+        store.add_holding(holding("DASHB", 2)).await.unwrap();
+        let state = AppState::for_tests(store, Config::for_tests()).await;
+        let session = || crate::auth::test_session(ACCOUNT, crate::auth::Scope::all());
+
+        let (_, Json(dashboard)) = get_dashboard(
+            session().await,
+            State(state.clone()),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+        let (_, Json(portfolio)) = recompute_portfolio(
+            session().await,
+            Query(PartialQuery { partial: false }),
+            State(state),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+
+        let values = |holdings: &[HoldingResponse]| -> Vec<(String, i32)> {
+            holdings
+                .iter()
+                .map(|h| (h.stock_symbol.clone(), h.total_value))
+                .collect()
+        };
+        assert_eq!(values(&dashboard.holdings), values(&portfolio.holdings));
+        assert_eq!(
+            values(&dashboard.holdings),
+            [
+                (String::from("DASHA"), 3_000),
+                (String::from("DASHB"), 4_000)
+            ]
+        );
+        // Each stock is up a dollar on the day
+        assert_eq!(dashboard.account.change, 500);
+    }
+}
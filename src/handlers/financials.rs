@@ -0,0 +1,52 @@
+use crate::auth::validate_session;
+use crate::finnhub::{fetch_basic_financials, normalize_symbol};
+use crate::models::Financials;
+use crate::recent::RecentSymbols;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tower_sessions::Session;
+
+/// Get a stock's key metrics, such as its P/E ratio, 52-week range, and market cap. Metrics the
+/// price provider doesn't have are `null`.
+pub async fn get_financials(
+    session: Session,
+    State(recent): State<RecentSymbols>,
+    Path(symbol): Path<String>,
+) -> Result<(StatusCode, Json<Financials>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    recent.view(&info.email, &symbol);
+
+    let metrics = match fetch_basic_financials(&symbol).await {
+        Ok(metrics) => metrics,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e) => {
+            tracing::error!("Error fetching financials: {}", e);
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(String::from("Failed to fetch financials")),
+            ));
+        }
+    };
+
+    let cents = |dollars: f64| (dollars * 100.0).round() as i64;
+    Ok((
+        StatusCode::OK,
+        Json(Financials {
+            stock_symbol: normalize_symbol(&symbol),
+            pe_ratio: metrics.pe_ttm,
+            week_52_high_cents: metrics.week_52_high.map(cents),
+            week_52_low_cents: metrics.week_52_low.map(cents),
+            market_cap_millions: metrics.market_capitalization,
+            beta: metrics.beta,
+            dividend_yield_percent: metrics.dividend_yield_indicated_annual,
+            eps_cents: metrics.eps_ttm.map(cents),
+        }),
+    ))
+}
@@ -0,0 +1,179 @@
+use crate::auth::{validate_scope, validate_session, Scope};
+use crate::finnhub::refresh_stock_profile;
+use crate::handlers::trading::{apply_sell, begin_transaction, finish_transaction, TradeContext};
+use crate::models::{Holding, Transaction};
+use crate::state::AppState;
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Re-fetch a held stock's profile, bypassing the cache, and store its current name on the holding.
+pub async fn refresh_holding_profile(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    Path(symbol): Path<String>,
+) -> Result<(StatusCode, Json<Holding>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let mut holding = match store.get_holding(&account_id, &symbol).await {
+        Ok(Some(holding)) => holding,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("You do not own this stock.")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch holding: {}", e)),
+            ));
+        }
+    };
+
+    let profile = match refresh_stock_profile(&symbol).await {
+        Ok(profile) => profile,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e) => {
+            tracing::error!("Error fetching stock profile: {}", e);
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(String::from("Failed to refresh stock profile")),
+            ));
+        }
+    };
+
+    if let Some(name) = profile.rename(&holding.stock_symbol, &holding.stock_name) {
+        store
+            .update_holding_name(&account_id, &symbol, &name)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(format!("Failed to update holding: {}", e)),
+                )
+            })?;
+        holding.stock_name = name;
+    }
+
+    Ok((StatusCode::OK, Json(holding)))
+}
+
+/// Sell all shares of a delisted holding at its last known price.
+pub async fn liquidate_delisted_holding(
+    session: Session,
+    State(state): State<AppState>,
+    Path(symbol): Path<String>,
+) -> Result<(StatusCode, Json<Transaction>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_scope(session.clone(), Scope::Trade).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        config,
+        guests,
+        ids,
+        clock,
+        locks,
+        ..
+    } = state;
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+    let _lock = locks.lock(&account_id).await;
+
+    let holding = match store.get_holding(&account_id, &symbol).await {
+        Ok(Some(holding)) => holding,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("You do not own this stock.")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch holding: {}", e)),
+            ));
+        }
+    };
+    if !holding.delisted {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(String::from("Only delisted holdings can be liquidated.")),
+        ));
+    }
+
+    let txn = begin_transaction(store.as_ref(), "Error completing trade").await?;
+    let ctx = TradeContext {
+        store: txn.store(),
+        config: &config,
+        ids: ids.as_ref(),
+        clock: clock.as_ref(),
+        buying_power_multiplier: 1.0,
+        reserved_cash: 0,
+    };
+    let result = apply_sell(
+        &ctx,
+        &account_id,
+        &symbol,
+        holding.quantity,
+        holding.current_share_price(),
+        None,
+    )
+    .await;
+    let transaction = finish_transaction(txn, result, "Error completing trade").await?;
+    Ok((StatusCode::CREATED, Json(transaction)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{test_session, Scope};
+    use crate::finnhub::mock;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn refreshing_stores_a_changed_company_name() {
+        let _finnhub = mock::start().await;
+        mock::stock("RENM", "Renamed Corp", 10.0);
+        let store = Arc::new(MemoryStore::new());
+        store
+            .add_holding(Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("RENM"),
+                stock_name: String::from("Original Corp"),
+                quantity: 1,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let (status, Json(holding)) = refresh_holding_profile(
+            test_session("a@example.com", Scope::all()).await,
+            State(store.clone() as Arc<dyn Store>),
+            State(GuestStores::new(0)),
+            Path(String::from("RENM")),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(holding.stock_name, "Renamed Corp");
+        let stored = store.get_holding("a@example.com", "RENM").await.unwrap();
+        assert_eq!(stored.unwrap().stock_name, "Renamed Corp");
+    }
+}
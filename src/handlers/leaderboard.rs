@@ -0,0 +1,233 @@
+use crate::auth::{validate_admin, validate_session, GUEST_KEY};
+use crate::models::{Account, LeaderboardEntry, UpdateEligibility};
+use crate::response_cache::{CachedRoute, ResponseCache};
+use crate::store::Store;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Number of accounts shown on the leaderboard.
+const LEADERBOARD_SIZE: usize = 50;
+
+/// Mask an email's local part for public display, e.g. `j***@example.com`.
+fn mask_account_id(account_id: &str) -> String {
+    match account_id.split_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{}***@{}", first, domain)
+        }
+        None => String::from("***"),
+    }
+}
+
+/// Rank leaderboard-eligible accounts by value, highest first. Ties go to the lower account id.
+pub fn rank_accounts(mut accounts: Vec<Account>, limit: usize) -> Vec<LeaderboardEntry> {
+    accounts.retain(|a| a.eligible_for_leaderboard);
+    accounts.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.id.cmp(&b.id)));
+    accounts
+        .into_iter()
+        .take(limit)
+        .enumerate()
+        .map(|(i, account)| LeaderboardEntry {
+            rank: i + 1,
+            account: mask_account_id(&account.id),
+            value: account.value,
+        })
+        .collect()
+}
+
+/// Get the top accounts by value. Practice accounts are left out. With `LEADERBOARD_CACHE_SECS`
+/// set, the ranking is computed at most once per period.
+pub async fn get_leaderboard(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(responses): State<ResponseCache>,
+) -> Result<(StatusCode, Json<Vec<LeaderboardEntry>>), (StatusCode, Json<String>)> {
+    // Validate the session
+    if let Err(status) = validate_session(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    if let Some(entries) = responses.get(CachedRoute::Leaderboard, "") {
+        return Ok((StatusCode::OK, Json(entries)));
+    }
+
+    match store.get_accounts().await {
+        Ok(accounts) => {
+            let entries = rank_accounts(accounts, LEADERBOARD_SIZE);
+            responses.put(CachedRoute::Leaderboard, "", entries.clone());
+            Ok((StatusCode::OK, Json(entries)))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch accounts: {}", e)),
+        )),
+    }
+}
+
+/// Choose whether the current account competes on the leaderboard. Users can choose once;
+/// later changes need an admin.
+pub async fn set_my_eligibility(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(responses): State<ResponseCache>,
+    Json(update): Json<UpdateEligibility>,
+) -> Result<(StatusCode, Json<Account>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    if let Ok(Some(true)) = session.get::<bool>(GUEST_KEY).await {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(String::from("Guest accounts can't join the leaderboard.")),
+        ));
+    }
+
+    let account = match store.get_account(&info.email).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account details: {}", e)),
+            ));
+        }
+    };
+    if account.eligibility_locked {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(String::from(
+                "Leaderboard eligibility has already been chosen.",
+            )),
+        ));
+    }
+
+    update_eligibility(store.as_ref(), &responses, account, update.eligible).await
+}
+
+/// Set any account's leaderboard eligibility.
+pub async fn set_account_eligibility(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(responses): State<ResponseCache>,
+    Path(account_id): Path<String>,
+    Json(update): Json<UpdateEligibility>,
+) -> Result<(StatusCode, Json<Account>), (StatusCode, Json<String>)> {
+    if let Err(status) = validate_admin(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    let account = match store.get_account(&account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account details: {}", e)),
+            ));
+        }
+    };
+
+    update_eligibility(store.as_ref(), &responses, account, update.eligible).await
+}
+
+/// Store an account's eligibility and return the updated account. The cached leaderboard is
+/// dropped so the change shows up immediately.
+async fn update_eligibility(
+    store: &dyn Store,
+    responses: &ResponseCache,
+    mut account: Account,
+    eligible: bool,
+) -> Result<(StatusCode, Json<Account>), (StatusCode, Json<String>)> {
+    store
+        .set_leaderboard_eligibility(&account.id, eligible)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to update account: {}", e)),
+            )
+        })?;
+    responses.invalidate_route(CachedRoute::Leaderboard);
+    account.eligible_for_leaderboard = eligible;
+    account.eligibility_locked = true;
+    Ok((StatusCode::OK, Json(account)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn practice_accounts_are_left_off_the_leaderboard() {
+        let store = Arc::new(MemoryStore::new());
+        for account in [
+            Account::open("ranked@example.com", 1_000_000, true),
+            Account::open("practice@example.com", 5_000_000, false),
+        ] {
+            store.add_account(account).await.unwrap();
+        }
+        let session =
+            crate::auth::test_session("ranked@example.com", crate::auth::Scope::all()).await;
+
+        let (_, Json(entries)) =
+            get_leaderboard(session, State(store), State(ResponseCache::default()))
+                .await
+                .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].account, "r***@example.com");
+        assert_eq!(entries[0].value, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn rapid_leaderboard_calls_rank_the_accounts_once() {
+        let store = Arc::new(MemoryStore::new());
+        store
+            .add_account(Account::open("first@example.com", 1_000_000, true))
+            .await
+            .unwrap();
+        let responses =
+            ResponseCache::new([(CachedRoute::Leaderboard, std::time::Duration::from_secs(60))]);
+        let leaderboard = || async {
+            let session =
+                crate::auth::test_session("first@example.com", crate::auth::Scope::all()).await;
+            let (_, Json(entries)) = get_leaderboard(
+                session,
+                State(store.clone() as Arc<dyn Store>),
+                State(responses.clone()),
+            )
+            .await
+            .unwrap();
+            entries
+        };
+
+        assert_eq!(leaderboard().await.len(), 1);
+        store
+            .add_account(Account::open("second@example.com", 2_000_000, true))
+            .await
+            .unwrap();
+        // Served from the cache, so the new account isn't ranked yet
+        assert_eq!(leaderboard().await.len(), 1);
+
+        responses.invalidate_route(CachedRoute::Leaderboard);
+        assert_eq!(leaderboard().await.len(), 2);
+    }
+}
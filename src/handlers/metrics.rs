@@ -0,0 +1,31 @@
+use crate::cash;
+use crate::finnhub;
+use axum::http::{header::CONTENT_TYPE, HeaderName, StatusCode};
+
+/// Expose operational counters in the Prometheus text format.
+pub async fn get_metrics() -> (StatusCode, [(HeaderName, &'static str); 1], String) {
+    let usage = finnhub::usage();
+    let body = format!(
+        "# HELP finnhub_calls_total Finnhub API calls since startup.\n\
+         # TYPE finnhub_calls_total counter\n\
+         finnhub_calls_total {}\n\
+         # HELP finnhub_calls_last_minute Finnhub API calls in the last 60 seconds.\n\
+         # TYPE finnhub_calls_last_minute gauge\n\
+         finnhub_calls_last_minute {}\n\
+         # HELP finnhub_calls_per_minute_limit Finnhub API calls allowed per minute.\n\
+         # TYPE finnhub_calls_per_minute_limit gauge\n\
+         finnhub_calls_per_minute_limit {}\n\
+         # HELP negative_cash_events_total Cash changes that took an account below zero without borrowing.\n\
+         # TYPE negative_cash_events_total counter\n\
+         negative_cash_events_total {}\n",
+        usage.total_calls,
+        usage.calls_last_minute,
+        usage.calls_per_minute_limit,
+        cash::negative_cash_events()
+    );
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        body,
+    )
+}
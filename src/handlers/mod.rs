@@ -1,3 +1,19 @@
 pub mod accounts;
+pub mod admin;
+pub mod dashboard;
+pub mod financials;
+pub mod holdings;
+pub mod leaderboard;
+pub mod metrics;
+pub mod orders;
+pub mod peers;
+pub mod pnl;
 pub mod portfolio;
+pub mod recent;
+pub mod sessions;
+pub mod settings;
+pub mod simulate;
+pub mod stats;
+pub mod suggestions;
+pub mod tax;
 pub mod trading;
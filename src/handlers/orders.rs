@@ -0,0 +1,51 @@
+use crate::auth::{validate_scope, validate_session, Scope};
+use crate::db::DatabasePool;
+use crate::models::QueuedOrder;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tower_sessions::Session;
+
+/// List the user's orders waiting for the market to open, oldest first.
+pub async fn list_queued_orders(
+    session: Session,
+    State(pool): State<DatabasePool>,
+) -> Result<(StatusCode, Json<Vec<QueuedOrder>>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    match pool.get_queued_orders(Some(&info.email)).await {
+        Ok(orders) => Ok((StatusCode::OK, Json(orders))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch queued orders: {}", e)),
+        )),
+    }
+}
+
+/// Cancel one of the user's queued orders, releasing any cash it reserved.
+pub async fn cancel_queued_order(
+    session: Session,
+    State(pool): State<DatabasePool>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_scope(session, Scope::Trade).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    match pool.delete_queued_order(&info.email, &id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, Json(String::from("Order not found")))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to cancel order: {}", e)),
+        )),
+    }
+}
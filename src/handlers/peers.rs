@@ -0,0 +1,96 @@
+use crate::auth::validate_session;
+use crate::finnhub::{fetch_peers, fetch_price, FinnhubBudget};
+use crate::models::Peer;
+use crate::recent::RecentSymbols;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use futures_util::future::join_all;
+use tower_sessions::Session;
+
+/// Get the companies related to a stock with their current prices. Peers are quoted
+/// concurrently; those beyond the request's Finnhub budget or without a quote have no price.
+pub async fn get_peers(
+    session: Session,
+    State(recent): State<RecentSymbols>,
+    Path(symbol): Path<String>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<Vec<Peer>>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    recent.view(&info.email, &symbol);
+
+    let symbols = match fetch_peers(&symbol).await {
+        Ok(symbols) => symbols,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e) => {
+            tracing::error!("Error fetching peers: {}", e);
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(String::from("Failed to fetch peers")),
+            ));
+        }
+    };
+
+    let peers = join_all(symbols.into_iter().map(|symbol| async {
+        let quote = match budget.try_spend(&symbol) {
+            true => fetch_price(&symbol).await.ok(),
+            false => None,
+        };
+        Peer {
+            current_price: quote.as_ref().map(|q| (q.c * 100.0) as i32),
+            day_change_percent: quote.as_ref().map(|q| (q.dp * 100.0) as i32),
+            stock_symbol: symbol,
+        }
+    }))
+    .await;
+
+    Ok((StatusCode::OK, Json(peers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finnhub::mock;
+
+    async fn peers_of(symbol: &str) -> Vec<Peer> {
+        let session = crate::auth::test_session("a@example.com", crate::auth::Scope::all()).await;
+        let (_, Json(peers)) = get_peers(
+            session,
+            State(RecentSymbols::new()),
+            Path(symbol.to_string()),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+        peers
+    }
+
+    #[tokio::test]
+    async fn peers_come_with_their_prices() {
+        let _finnhub = mock::start().await;
+        mock::respond("/stock/peers", "PEERA", r#"["PEERA","PEERB","PEERC"]"#);
+        mock::stock("PEERB", "Peer B", 20.0);
+
+        let peers = peers_of("PEERA").await;
+
+        let symbols: Vec<&str> = peers.iter().map(|p| p.stock_symbol.as_str()).collect();
+        assert_eq!(symbols, ["PEERB", "PEERC"]);
+        assert_eq!(peers[0].current_price, Some(2_000));
+        assert_eq!(peers[0].day_change_percent, Some(100));
+        assert_eq!(peers[1].current_price, None);
+    }
+
+    #[tokio::test]
+    async fn a_symbol_without_peers_gets_an_empty_list() {
+        let _finnhub = mock::start().await;
+        mock::respond("/stock/peers", "LONER", "[]");
+
+        assert!(peers_of("LONER").await.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::auth::validate_session;
+use crate::clock::Clock;
+use crate::finnhub::{fetch_historical_price, fetch_price, FinnhubBudget};
+use crate::models::{PnlPeriod, PnlPeriods};
+use crate::pnl::{pnl_by_period, Granularity};
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Most periods a single request may cover.
+pub const MAX_PERIODS: usize = 400;
+
+/// Query for P&L by period.
+#[derive(Deserialize, Debug)]
+pub struct PnlPeriodsQuery {
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+/// Get realized and unrealized P&L per day, week, month, or year, from the first transaction
+/// through today. Open positions are valued at each period's last close, and the current period
+/// at the current quote. Once the request's Finnhub budget runs out, remaining periods have no
+/// unrealized P&L and the response is marked as truncated.
+pub async fn get_pnl_periods(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(budget): Extension<FinnhubBudget>,
+    Query(query): Query<PnlPeriodsQuery>,
+) -> Result<(StatusCode, Json<PnlPeriods>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let transactions = store.get_transactions(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch transactions: {}", e)),
+        )
+    })?;
+    let summaries = store
+        .get_transaction_summaries(&account_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch transaction summaries: {}", e)),
+            )
+        })?;
+
+    let today = clock.now().date_naive();
+    let buckets = pnl_by_period(&summaries, &transactions, query.granularity, today);
+    if buckets.len() > MAX_PERIODS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from(
+                "Too many periods, choose a coarser granularity.",
+            )),
+        ));
+    }
+
+    let mut truncated = false;
+    let mut periods = Vec::new();
+    for bucket in buckets {
+        let mut unrealized = Some(0);
+        for (symbol, position) in &bucket.open {
+            // Each symbol and day is a separate lookup, so budget them separately
+            if truncated || !budget.try_spend(&format!("{}@{}", symbol, bucket.end)) {
+                truncated = true;
+                unrealized = None;
+                break;
+            }
+            let quote = match bucket.end >= today {
+                true => fetch_price(symbol).await,
+                false => fetch_historical_price(symbol, bucket.end).await,
+            };
+            match quote {
+                Ok(quote) => {
+                    let value = (quote.c * 100.0) as i64 * position.quantity;
+                    unrealized = unrealized.map(|u| u + value - position.cost_basis);
+                }
+                Err(e) if e.is_unavailable() => return Err(e.into()),
+                Err(e) => {
+                    tracing::warn!("Failed to price {} on {}: {}", symbol, bucket.end, e);
+                    unrealized = None;
+                    break;
+                }
+            }
+        }
+        periods.push(PnlPeriod {
+            start: bucket.start,
+            end: bucket.end,
+            realized: bucket.realized_pnl,
+            unrealized,
+        });
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(PnlPeriods {
+            granularity: query.granularity,
+            periods,
+            truncated,
+        }),
+    ))
+}
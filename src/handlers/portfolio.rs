@@ -1,32 +1,82 @@
-use crate::auth::validate_session;
+use crate::auth::{validate_scope, validate_session, Scope};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::correlation::{correlation_matrix, MIN_SHARED_RETURNS};
 use crate::db::DatabasePool;
-use crate::finnhub::{fetch_stock_price, fetch_stock_profile};
-use crate::models::{HoldingResponse, Portfolio, Transaction};
-use axum::{extract::State, http::StatusCode, Json};
+use crate::etag::{account_etag, if_none_match, variant_etag};
+use crate::finnhub::{
+    fetch_candles, fetch_earnings, fetch_historical_price, fetch_price, fetch_profile,
+    fetch_trailing_dividends, last_known_price, normalize_symbol, peek_profile,
+    record_quote_failure, FinnhubBudget, FinnhubError, FinnhubQuote,
+};
+use crate::handlers::trading::{
+    apply_buy, apply_sell, begin_transaction, check_trade, fill_price, finish_transaction,
+    quote_cents, quote_price, TradeContext,
+};
+use crate::market_hours::valuation_price;
+use crate::models::{
+    Account, AssetType, CommitMode, CorrelationMatrix, DividendEstimate, DividendIncome,
+    EarningsDate, HistoricalHolding, HistoricalPortfolio, Holding, HoldingResponse, HoldingSort,
+    HoldingSortQuery, PartialQuery, Portfolio, PortfolioEarnings, PositionHistory, PositionPoint,
+    PricingError, RebalanceRequest, RebalanceResponse, SectorBreakdown, SharpeRatio,
+    SnapshotHolding, TradeOutcome, TradeSide, Transaction, ValueSnapshot,
+};
+use crate::money::{change_percent, round_dollars, Rounding, RoundingQuery, SharePrice};
+use crate::pnl::{holdings_as_of, position_timeline, quantity_on};
+use crate::rebalance::{plan, validate_targets, PricedPosition, RebalanceTrade};
+use crate::response_cache::{CachedRoute, ResponseCache};
+use crate::sectors::{aggregate, OTHER_THRESHOLD_PERCENT};
+use crate::snapshots::{daily_returns, sharpe_ratio};
+use crate::state::AppState;
+use crate::store::{resolve_store, GuestStores, Store};
+use crate::timestamps::{localize, parse_tz};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::ETAG, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use futures_util::future::join_all;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use tower_sessions::Session;
 
-pub async fn get_portfolio(
-    session: Session,
-    State(pool): State<DatabasePool>,
-) -> Result<(StatusCode, Json<Portfolio>), (StatusCode, Json<String>)> {
-    // Validate the session
-    let info = match validate_session(session).await {
-        Ok(info) => info,
-        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
-    };
-    let account_id = info.email;
-
-    // Use the `get_holdings` method
-    let holdings = match pool.get_holdings(&account_id).await {
-        Ok(holdings) => holdings,
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(format!("Failed to fetch holdings: {}", e)),
-            ));
-        }
-    };
+/// Holdings priced at current quotes, along with changes found while pricing that
+/// `persist_pricing` writes back to the store.
+pub(crate) struct PricedHoldings {
+    pub holdings: Vec<HoldingResponse>,
+    /// Total value of the priced holdings, in cents.
+    pub total_value: i32,
+    /// Set when the request's Finnhub budget ran out before every holding was priced.
+    pub truncated: bool,
+    /// Holdings whose company name changed, as `(symbol, new name)`.
+    pub renamed: Vec<(String, String)>,
+    /// Holdings that failed to quote often enough to count as delisted, as `(symbol, last price)`.
+    pub delisted: Vec<(String, i32)>,
+    /// Holdings that failed to quote and were valued at their last known price.
+    pub errors: Vec<PricingError>,
+}
 
+/// Price holdings at current quotes without writing anything. If the Finnhub budget runs out,
+/// the remaining holdings are left out and the result is marked as truncated. Holdings that
+/// fail to quote are valued at their last known price. Outside market hours, holdings are valued
+/// at the previous close instead of the latest price when `AFTER_HOURS_PRICING` is `close`.
+/// The price provider being unavailable fails the whole pricing unless `partial` is set, in which
+/// case the holdings it couldn't quote are valued at their last known price too.
+pub(crate) async fn price_holdings(
+    account_id: &str,
+    holdings: Vec<Holding>,
+    budget: &FinnhubBudget,
+    config: &Config,
+    clock: &dyn Clock,
+    partial: bool,
+) -> Result<PricedHoldings, (StatusCode, Json<String>)> {
+    // Mongo doesn't return holdings in a stable order, so sort them to keep responses, and which
+    // holdings a spent budget truncates, the same from call to call
+    let mut holdings = holdings;
+    holdings.sort_by(|a, b| a.stock_symbol.cmp(&b.stock_symbol));
     let mut h: Vec<HoldingResponse> = Vec::new();
     for holding in holdings {
         h.push(HoldingResponse {
@@ -35,96 +85,746 @@ pub async fn get_portfolio(
             quantity: holding.quantity,
             current_price: holding.current_price,
             total_value: holding.total_value,
-            day_change: 0,
-            day_change_percent: 0,
+            day_change: None,
+            day_change_percent: None,
             purchase_price: holding.purchase_price,
+            current_price_tenths: holding.current_price_tenths,
+            purchase_price_tenths: holding.purchase_price_tenths,
             stock_logo_url: String::from(""),
             overall_change: 0,
             category: String::from(""),
+            asset_type: holding.asset_type,
+            delisted: holding.delisted,
+            opened_at: holding.created_at,
+            priced: false,
         });
     }
 
-    let mut updated_holdings = Vec::new();
-    let mut total_portfolio_value = 0;
+    let mut priced = PricedHoldings {
+        holdings: Vec::new(),
+        total_value: 0,
+        truncated: false,
+        renamed: Vec::new(),
+        delisted: Vec::new(),
+        errors: Vec::new(),
+    };
 
     for mut holding in h {
+        // Delisted holdings no longer quote, so they keep their last known price
+        if holding.delisted {
+            holding.set_current_price(holding.current_share_price());
+            priced.total_value += holding.total_value;
+            priced.holdings.push(holding);
+            continue;
+        }
+
+        if !budget.try_spend(&holding.stock_symbol) {
+            tracing::warn!(
+                "Finnhub budget exhausted, truncating portfolio for {}",
+                account_id
+            );
+            priced.truncated = true;
+            break;
+        }
+
         // Fetch stock price and update holding
-        match fetch_stock_price(&holding.stock_symbol).await {
+        match fetch_price(&holding.stock_symbol).await {
             Ok(quote) => {
-                let current_price = (quote.c * 100.0) as i32;
-                let total_value = current_price * holding.quantity;
-                holding.current_price = current_price;
-                holding.total_value = total_value;
-                holding.overall_change = total_value - (holding.purchase_price * holding.quantity);
-                holding.day_change = (quote.d * 100.0) as i32;
-                holding.day_change_percent = (quote.dp * 100.0) as i32;
-
-                total_portfolio_value += total_value;
+                let price = valuation_price(&quote, config.after_hours_pricing, clock.now());
+                holding.set_current_price(SharePrice::from_quote(
+                    price,
+                    config.sub_cent_pricing_below,
+                ));
+                holding.day_change = Some((quote.d * 100.0) as i32);
+                holding.day_change_percent = Some((quote.dp * 100.0) as i32);
+                holding.priced = true;
+
+                priced.total_value += holding.total_value;
             }
+            Err(e) if e.is_unavailable() && !partial => return Err(e.into()),
             Err(e) => {
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(format!("Failed to fetch stock price: {}", e)),
-                ));
+                // Value the holding at its last known price rather than failing the portfolio
+                tracing::warn!("Failed to price {}: {}", holding.stock_symbol, e);
+                priced.errors.push(PricingError {
+                    stock_symbol: holding.stock_symbol.clone(),
+                    message: e.to_string(),
+                });
+                let price = match last_known_price(&holding.stock_symbol).await {
+                    Some(price) => SharePrice::from_quote(price, config.sub_cent_pricing_below),
+                    None => holding.current_share_price(),
+                };
+                holding.set_current_price(price);
+                priced.total_value += holding.total_value;
+
+                // Only missing quotes count toward delisting, not network errors
+                let missing = matches!(e, FinnhubError::UnknownSymbol | FinnhubError::InvalidPrice);
+                if missing
+                    && record_quote_failure(&holding.stock_symbol) >= config.delisted_after_failures
+                {
+                    priced
+                        .delisted
+                        .push((holding.stock_symbol.clone(), holding.current_price));
+                }
+                priced.holdings.push(holding);
+                continue;
             }
         }
 
         // Fetch stock profile for logo and category
-        if let Ok(profile) = fetch_stock_profile(&holding.stock_symbol).await {
-            holding.stock_logo_url = profile.logo;
+        if let Ok(profile) = fetch_profile(&holding.stock_symbol).await {
+            if holding.asset_type == AssetType::Unknown {
+                holding.asset_type = profile.asset_type();
+            }
+            holding.stock_logo_url = profile.logo_url(config.logo_cdn_prefix.as_deref());
+            // Profiles are refetched once the cache expires, so pick up renamed companies and
+            // backfill blank names here
+            if let Some(name) = profile.rename(&holding.stock_symbol, &holding.stock_name) {
+                priced
+                    .renamed
+                    .push((holding.stock_symbol.clone(), name.clone()));
+                holding.stock_name = name;
+            }
             holding.category = profile.finnhub_industry;
         }
 
-        updated_holdings.push(holding);
+        priced.holdings.push(holding);
+    }
+
+    Ok(priced)
+}
+
+/// Write the results of pricing back to the store: renamed and delisted holdings, each priced
+/// holding's current price, and unless pricing was truncated, the account value and a snapshot of
+/// it for `today`.
+pub(crate) async fn persist_pricing(
+    store: &dyn Store,
+    account: &Account,
+    priced: &mut PricedHoldings,
+    today: NaiveDate,
+) -> Result<(), (StatusCode, Json<String>)> {
+    for (symbol, name) in &priced.renamed {
+        if let Err(e) = store.update_holding_name(&account.id, symbol, name).await {
+            tracing::error!("Error updating holding name: {}", e);
+        }
+    }
+    for (symbol, last_price) in &priced.delisted {
+        tracing::warn!("Marking {} as delisted", symbol);
+        match store
+            .mark_holding_delisted(&account.id, symbol, *last_price as i64)
+            .await
+        {
+            Ok(_) => {
+                if let Some(holding) = priced
+                    .holdings
+                    .iter_mut()
+                    .find(|h| &h.stock_symbol == symbol)
+                {
+                    holding.delisted = true;
+                }
+            }
+            Err(e) => tracing::error!("Error marking holding delisted: {}", e),
+        }
+    }
+
+    let prices: Vec<(String, SharePrice)> = priced
+        .holdings
+        .iter()
+        .filter(|h| !h.delisted)
+        .map(|h| (h.stock_symbol.clone(), h.current_share_price()))
+        .collect();
+    if let Err(e) = store.update_holdings_prices(&account.id, &prices).await {
+        tracing::error!("Error updating holding prices: {}", e);
     }
 
-    let account = match pool.get_account(&account_id).await {
-        Ok(account) => account,
+    // A truncated portfolio undervalues the account, so only persist complete totals
+    if priced.truncated {
+        return Ok(());
+    }
+
+    let value = (account.cash + priced.total_value) as i64;
+    store
+        .update_account(&account.id, value, account.cash as i64)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to update account: {}", e)),
+            )
+        })?;
+
+    let snapshot = ValueSnapshot {
+        account_id: account.id.clone(),
+        date: today,
+        value,
+        cash: Some(account.cash as i64),
+        invested: Some(priced.total_value as i64),
+        holdings: priced.holdings.iter().map(SnapshotHolding::from).collect(),
+    };
+    if let Err(e) = store.record_snapshot(snapshot).await {
+        tracing::error!("Error recording value snapshot: {}", e);
+    }
+    Ok(())
+}
+
+/// Fetch an account and its holdings for pricing.
+pub(crate) async fn load_account(
+    store: &dyn Store,
+    account_id: &str,
+) -> Result<(Account, Vec<Holding>), (StatusCode, Json<String>)> {
+    let account = match store.get_account(account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
         Err(e) => {
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(format!("Failed to fetch account details: {}", e)),
             ));
         }
+    };
+
+    // Use the `get_holdings` method
+    let holdings = match store.get_holdings(account_id).await {
+        Ok(holdings) => holdings,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch holdings: {}", e)),
+            ));
+        }
+    };
+    Ok((account, holdings))
+}
+
+/// Get the user's holdings at the prices and values stored by the last repricing, without
+/// calling Finnhub or writing anything: use `POST /portfolio/recompute` to reprice them. Logos and
+/// categories come from cached profiles when there are any. Responds 304 when `If-None-Match`
+/// matches the account's current ETag. With `round=dollars`, money values are rounded to whole
+/// dollars for display. Holdings are ordered by symbol unless `sort` asks for `value`, `gain` or
+/// `day_change`. Since nothing is quoted, holdings carry no `day_change` and pricing can't fail,
+/// so `partial=true` is taken by `POST /portfolio/recompute` instead.
+pub async fn get_portfolio(
+    session: Session,
+    headers: HeaderMap,
+    Query(rounding): Query<RoundingQuery>,
+    Query(order): Query<HoldingSortQuery>,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        config,
+        guests,
+        ..
+    } = state;
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (account, holdings) = load_account(store.as_ref(), &account_id).await?;
+
+    // Skip building the response if the client already has the current portfolio
+    let mut etag = account_etag(&account);
+    if rounding.round == Some(Rounding::Dollars) {
+        etag = variant_etag(&etag, "dollars");
+    }
+    if order.sort != HoldingSort::Symbol {
+        etag = variant_etag(&etag, &format!("{:?}", order.sort).to_lowercase());
+    }
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+
+    let mut holdings = stored_holdings(holdings, &config).await;
+    order.sort.apply(&mut holdings);
+    if rounding.round == Some(Rounding::Dollars) {
+        holdings.iter_mut().for_each(round_holding);
+    }
+
+    Ok((
+        StatusCode::OK,
+        [(ETAG, etag)],
+        Json(Portfolio {
+            holdings,
+            truncated: false,
+            errors: Vec::new(),
+        }),
+    )
+        .into_response())
+}
+
+/// Holdings as stored, valued at their stored price, ordered by symbol.
+async fn stored_holdings(mut holdings: Vec<Holding>, config: &Config) -> Vec<HoldingResponse> {
+    holdings.sort_by(|a, b| a.stock_symbol.cmp(&b.stock_symbol));
+    let mut responses = Vec::new();
+    for holding in holdings {
+        let profile = peek_profile(&holding.stock_symbol).await;
+        let cost = holding
+            .purchase_share_price()
+            .value(holding.quantity as i64);
+        responses.push(HoldingResponse {
+            overall_change: (holding.total_value as i64 - cost) as i32,
+            stock_logo_url: profile
+                .as_ref()
+                .map(|p| p.logo_url(config.logo_cdn_prefix.as_deref()))
+                .unwrap_or_default(),
+            category: profile.map(|p| p.finnhub_industry).unwrap_or_default(),
+            stock_symbol: holding.stock_symbol,
+            stock_name: holding.stock_name,
+            quantity: holding.quantity,
+            current_price: holding.current_price,
+            total_value: holding.total_value,
+            day_change: None,
+            day_change_percent: None,
+            purchase_price: holding.purchase_price,
+            current_price_tenths: holding.current_price_tenths,
+            purchase_price_tenths: holding.purchase_price_tenths,
+            asset_type: holding.asset_type,
+            delisted: holding.delisted,
+            opened_at: holding.created_at,
+            priced: false,
+        });
     }
-    .unwrap();
-    // todo: Update the account value in the database
+    responses
+}
 
-    pool.update_account(
+/// Reprice the user's holdings and store the results: the account value, renamed holdings, and
+/// holdings now considered delisted. Returns the repriced portfolio. The price provider being
+/// unavailable fails the request unless `partial` is set, in which case the holdings it couldn't
+/// quote keep their last known price.
+pub async fn recompute_portfolio(
+    session: Session,
+    Query(mode): Query<PartialQuery>,
+    State(state): State<AppState>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<Portfolio>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        config,
+        guests,
+        clock,
+        locks,
+        ..
+    } = state;
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+    let _lock = locks.lock(&account_id).await;
+
+    let (account, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let mut priced = price_holdings(
         &account_id,
-        (account.cash + total_portfolio_value) as i64,
-        account.cash as i64,
+        holdings,
+        &budget,
+        &config,
+        clock.as_ref(),
+        mode.partial,
+    )
+    .await?;
+    persist_pricing(
+        store.as_ref(),
+        &account,
+        &mut priced,
+        clock.now().date_naive(),
     )
-    .await
-    .map_err(|e| {
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(Portfolio {
+            holdings: priced.holdings,
+            truncated: priced.truncated,
+            errors: priced.errors,
+        }),
+    ))
+}
+
+/// Get the value of the user's holdings per industry, largest first. Holdings are priced and
+/// profiled concurrently, reusing cached quotes and profiles. Industries under 2% of the total
+/// are grouped as "Other", and holdings without an industry as "Unknown". With
+/// `SECTORS_CACHE_SECS` set, complete breakdowns are reused until they expire or the account
+/// trades.
+pub async fn get_sector_exposure(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(responses): State<ResponseCache>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<SectorBreakdown>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    if let Some(breakdown) = responses.get(CachedRoute::Sectors, &account_id) {
+        return Ok((StatusCode::OK, Json(breakdown)));
+    }
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (_, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let (holdings, skipped): (Vec<Holding>, Vec<Holding>) = holdings
+        .into_iter()
+        .partition(|h| h.delisted || budget.try_spend(&h.stock_symbol));
+
+    let results = join_all(holdings.iter().map(|holding| async move {
+        // Delisted holdings no longer quote, so they keep their last known price
+        let price = match holding.delisted {
+            true => Ok(holding.current_price),
+            false => fetch_price(&holding.stock_symbol)
+                .await
+                .map(|quote| (quote.c * 100.0) as i32),
+        };
+        let sector = match holding.asset_type {
+            AssetType::Crypto => String::from("Crypto"),
+            _ => fetch_profile(&holding.stock_symbol)
+                .await
+                .map(|profile| profile.finnhub_industry)
+                .unwrap_or_default(),
+        };
+        (holding, price, sector)
+    }))
+    .await;
+
+    let mut values = Vec::new();
+    for (holding, price, sector) in results {
+        let price = match price {
+            Ok(price) => price,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!("Failed to price {}: {}", holding.stock_symbol, e);
+                holding.current_price
+            }
+        };
+        values.push((sector, price as i64 * holding.quantity as i64));
+    }
+
+    let breakdown = SectorBreakdown {
+        sectors: aggregate(values, OTHER_THRESHOLD_PERCENT),
+        truncated: !skipped.is_empty(),
+    };
+    // A truncated breakdown is missing holdings, so it isn't worth reusing
+    if !breakdown.truncated {
+        responses.put(CachedRoute::Sectors, &account_id, breakdown.clone());
+    }
+    Ok((StatusCode::OK, Json(breakdown)))
+}
+
+/// Estimate the portfolio's dividend income over the next year from each holding's dividends over
+/// the trailing year and its current share count. Delisted holdings and holdings that paid no
+/// dividends are left out.
+pub async fn get_dividend_estimate(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<DividendIncome>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (_, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let (holdings, skipped): (Vec<Holding>, Vec<Holding>) = holdings
+        .into_iter()
+        .filter(|h| !h.delisted)
+        .partition(|h| budget.try_spend(&h.stock_symbol));
+
+    let today = clock.now().date_naive();
+    let results = join_all(holdings.iter().map(|holding| async move {
+        let dividends = fetch_trailing_dividends(&holding.stock_symbol, today).await;
+        (holding, dividends)
+    }))
+    .await;
+
+    let mut estimates = Vec::new();
+    let mut truncated = !skipped.is_empty();
+    for (holding, dividends) in results {
+        let dividends = match dividends {
+            Ok(dividends) => dividends,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch dividends for {}: {}",
+                    holding.stock_symbol,
+                    e
+                );
+                truncated = true;
+                continue;
+            }
+        };
+        let per_share = (dividends.iter().map(|d| d.amount).sum::<f64>() * 100.0).round() as i64;
+        if per_share <= 0 {
+            continue;
+        }
+        estimates.push(DividendEstimate {
+            stock_symbol: holding.stock_symbol.clone(),
+            quantity: holding.quantity,
+            annual_dividend_per_share: per_share,
+            annual_income: per_share * holding.quantity as i64,
+        });
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(DividendIncome {
+            total_annual_income: estimates.iter().map(|e| e.annual_income).sum(),
+            holdings: estimates,
+            truncated,
+        }),
+    ))
+}
+
+/// Get the account's annualized Sharpe ratio from the returns between its recorded values,
+/// measured against `RISK_FREE_RATE_BPS`. Accounts without enough varied history get a null
+/// ratio and the reason.
+pub async fn get_sharpe_ratio(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+) -> Result<(StatusCode, Json<SharpeRatio>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let snapshots = store.get_snapshots(&account_id).await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(format!("Failed to update account: {}", e)),
+            Json(format!("Failed to fetch value snapshots: {}", e)),
         )
     })?;
+    let returns = daily_returns(&snapshots);
+    let ratio = sharpe_ratio(&returns, config.risk_free_rate_bps as f64 / 10_000.0);
 
-    // Return the portfolio
     Ok((
         StatusCode::OK,
-        Json(Portfolio {
-            holdings: updated_holdings,
+        Json(SharpeRatio {
+            sharpe_ratio: ratio.ok(),
+            reason: ratio.err().map(|e| e.to_string()),
+            returns: returns.len(),
+            risk_free_rate_bps: config.risk_free_rate_bps,
         }),
     ))
 }
 
+/// List the earnings releases scheduled for the user's holdings over the next
+/// `EARNINGS_LOOKAHEAD_DAYS` days, earliest first. Delisted holdings and holdings with nothing
+/// scheduled are left out.
+pub async fn get_earnings(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<PortfolioEarnings>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (_, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let (holdings, skipped): (Vec<Holding>, Vec<Holding>) = holdings
+        .into_iter()
+        .filter(|h| !h.delisted)
+        .partition(|h| budget.try_spend(&h.stock_symbol));
+
+    let today = clock.now().date_naive();
+    let results = join_all(holdings.iter().map(|holding| async move {
+        let earnings = fetch_earnings(&holding.stock_symbol, today).await;
+        (holding, earnings)
+    }))
+    .await;
+
+    let mut earnings = Vec::new();
+    let mut truncated = !skipped.is_empty();
+    for (holding, releases) in results {
+        let releases = match releases {
+            Ok(releases) => releases,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch earnings for {}: {}",
+                    holding.stock_symbol,
+                    e
+                );
+                truncated = true;
+                continue;
+            }
+        };
+        for release in releases {
+            let Ok(date) = NaiveDate::parse_from_str(&release.date, "%Y-%m-%d") else {
+                continue;
+            };
+            if date < today {
+                continue;
+            }
+            earnings.push(EarningsDate {
+                stock_symbol: holding.stock_symbol.clone(),
+                date,
+                hour: release.hour,
+                eps_estimate: release.eps_estimate,
+                revenue_estimate: release.revenue_estimate,
+                quarter: release.quarter,
+                year: release.year,
+            });
+        }
+    }
+    earnings.sort_by(|a, b| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| a.stock_symbol.cmp(&b.stock_symbol))
+    });
+
+    Ok((
+        StatusCode::OK,
+        Json(PortfolioEarnings {
+            earnings,
+            truncated,
+        }),
+    ))
+}
+
+/// Days of daily closes correlations are computed over.
+pub const CORRELATION_LOOKBACK_DAYS: i64 = 180;
+
+/// Get the pairwise correlation of the daily returns of the user's holdings over the last
+/// `CORRELATION_LOOKBACK_DAYS` days. Candles are fetched concurrently. Holdings without enough
+/// history, including delisted ones, are reported as excluded.
+pub async fn get_correlation(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<CorrelationMatrix>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let (_, holdings) = load_account(store.as_ref(), &account_id).await?;
+    let (delisted, holdings): (Vec<Holding>, Vec<Holding>) =
+        holdings.into_iter().partition(|h| h.delisted);
+    let (holdings, skipped): (Vec<Holding>, Vec<Holding>) = holdings
+        .into_iter()
+        .partition(|h| budget.try_spend(&h.stock_symbol));
+
+    let to = clock.now().timestamp();
+    let from = to - 60 * 60 * 24 * CORRELATION_LOOKBACK_DAYS;
+    let results = join_all(holdings.iter().map(|holding| async move {
+        let candles = fetch_candles(&holding.stock_symbol, "D", from, to).await;
+        (holding, candles)
+    }))
+    .await;
+
+    let mut symbols = Vec::new();
+    let mut series = Vec::new();
+    let mut excluded: Vec<String> = delisted.into_iter().map(|h| h.stock_symbol).collect();
+    for (holding, candles) in results {
+        let closes: BTreeMap<NaiveDate, f64> = match candles {
+            Ok(candles) => candles
+                .t
+                .iter()
+                .zip(&candles.c)
+                .filter_map(|(t, c)| Some((DateTime::from_timestamp(*t, 0)?.date_naive(), *c)))
+                .collect(),
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch candles for {}: {}",
+                    holding.stock_symbol,
+                    e
+                );
+                BTreeMap::new()
+            }
+        };
+        if closes.len() <= MIN_SHARED_RETURNS {
+            excluded.push(holding.stock_symbol.clone());
+            continue;
+        }
+        symbols.push(holding.stock_symbol.clone());
+        series.push(closes);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(CorrelationMatrix {
+            symbols,
+            matrix: correlation_matrix(&series),
+            excluded,
+            truncated: !skipped.is_empty(),
+        }),
+    ))
+}
+
+/// Round a holding's money values to whole dollars and recompute its day change percentage
+/// from the rounded values.
+fn round_holding(holding: &mut HoldingResponse) {
+    holding.current_price = round_dollars(holding.current_price);
+    holding.purchase_price = round_dollars(holding.purchase_price);
+    holding.total_value = round_dollars(holding.total_value);
+    holding.overall_change = round_dollars(holding.overall_change);
+    holding.day_change = holding.day_change.map(round_dollars);
+    holding.day_change_percent = holding
+        .day_change
+        .map(|change| change_percent(change, holding.current_price));
+}
+
+/// Query parameters for the transaction history.
+#[derive(Debug, Deserialize)]
+pub struct TransactionHistoryQuery {
+    /// IANA timezone to express timestamps in. Timestamps are UTC when omitted.
+    pub tz: Option<String>,
+}
+
+/// Get the user's transactions, with timestamps in UTC or the requested timezone.
 pub async fn get_transaction_history(
     session: Session,
-    State(pool): State<DatabasePool>,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    Query(query): Query<TransactionHistoryQuery>,
 ) -> Result<(StatusCode, Json<Vec<Transaction>>), (StatusCode, Json<String>)> {
     // Validate the session
-    let info = match validate_session(session).await {
+    let info = match validate_session(session.clone()).await {
         Ok(info) => info,
         Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
     };
     let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let tz = match query.tz.as_deref().map(parse_tz).transpose() {
+        Ok(tz) => tz,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, Json(e))),
+    };
 
     // Use the `get_transactions` method
-    let transactions = match pool.get_transactions(&account_id).await {
+    let mut transactions = match store.get_transactions(&account_id).await {
         Ok(transactions) => transactions,
         Err(e) => {
             return Err((
@@ -134,5 +834,1000 @@ pub async fn get_transaction_history(
         }
     };
 
+    for transaction in &mut transactions {
+        transaction.timestamp = localize(&transaction.timestamp, tz);
+    }
+
     Ok((StatusCode::OK, Json(transactions)))
 }
+
+/// Query parameters for valuing the portfolio on a past day.
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    pub date: NaiveDate,
+}
+
+/// Value the holdings the user had at the end of a past day at that day's closing prices. Holdings
+/// are rebuilt from the transaction history, so lots already archived are not included.
+pub async fn get_portfolio_as_of(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Query(query): Query<AsOfQuery>,
+) -> Result<(StatusCode, Json<HistoricalPortfolio>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    if query.date > clock.now().date_naive() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("The date must not be in the future.")),
+        ));
+    }
+
+    let transactions = match store.get_transactions(&account_id).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch transactions: {}", e)),
+            ));
+        }
+    };
+
+    let reconstructed = holdings_as_of(&transactions, query.date, config.starting_cash);
+    let mut holdings = Vec::new();
+    let mut value = reconstructed.cash;
+    for (symbol, quantity) in reconstructed.quantities {
+        let price = match fetch_historical_price(&symbol, query.date).await {
+            Ok(quote) => (quote.c * 100.0) as i64,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    Json(format!(
+                        "Failed to fetch closing price for {}: {}",
+                        symbol, e
+                    )),
+                ));
+            }
+        };
+        value += price * quantity;
+        holdings.push(HistoricalHolding {
+            stock_symbol: symbol,
+            quantity,
+            price,
+            total_value: price * quantity,
+        });
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(HistoricalPortfolio {
+            date: query.date,
+            cash: reconstructed.cash,
+            holdings,
+            value,
+        }),
+    ))
+}
+
+/// Get the size and value of the user's position in a symbol at each daily close since they
+/// first traded it. Share counts are rebuilt from the transaction history, so lots already
+/// archived are not included.
+pub async fn get_position_history(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(clock): State<Arc<dyn Clock>>,
+    Path(symbol): Path<String>,
+) -> Result<(StatusCode, Json<PositionHistory>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+    let symbol = normalize_symbol(&symbol);
+
+    let transactions = match store.get_transactions(&account_id).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch transactions: {}", e)),
+            ));
+        }
+    };
+    let changes = position_timeline(&transactions, &symbol);
+    let Some(first) = changes.first() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(format!("No transactions found for {}", symbol)),
+        ));
+    };
+
+    let from = first.date.and_time(NaiveTime::MIN).and_utc().timestamp();
+    let to = clock.now().timestamp();
+    let candles = match fetch_candles(&symbol, "D", from, to).await {
+        Ok(candles) => candles,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(format!(
+                    "Failed to fetch closing prices for {}: {}",
+                    symbol, e
+                )),
+            ));
+        }
+    };
+    let points = candles
+        .t
+        .iter()
+        .zip(&candles.c)
+        .filter_map(|(t, c)| {
+            let date = DateTime::from_timestamp(*t, 0)?.date_naive();
+            let quantity = quantity_on(&changes, date);
+            let price = (c * 100.0) as i64;
+            Some(PositionPoint {
+                date,
+                quantity,
+                price,
+                value: price * quantity,
+            })
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(PositionHistory {
+            stock_symbol: symbol,
+            changes,
+            points,
+        }),
+    ))
+}
+
+/// Propose whole-share trades moving the user's holdings toward target weights, taken from the
+/// request body or the account's saved `target_allocations`. With `execute` set, the trades are
+/// placed in a single store transaction, sells first, each refused if it fails a check `/buy` or
+/// `/sell` would make.
+#[axum::debug_handler(state = AppState)]
+pub async fn rebalance_portfolio(
+    session: Session,
+    State(state): State<AppState>,
+    Json(request): Json<RebalanceRequest>,
+) -> Result<(StatusCode, Json<RebalanceResponse>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_scope(session.clone(), Scope::Trade).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        pool,
+        config,
+        guests,
+        ids,
+        clock,
+        locks,
+        ..
+    } = state;
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+    let _lock = locks.lock(&account_id).await;
+
+    let targets = match request.targets {
+        Some(targets) => targets,
+        None => match pool.get_settings(&account_id).await {
+            Ok(settings) => settings.target_allocations,
+            Err(e) => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(format!("Failed to fetch settings: {}", e)),
+                ));
+            }
+        },
+    };
+    if targets.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("No target allocations to rebalance toward.")),
+        ));
+    }
+    if let Err(e) = validate_targets(&targets) {
+        return Err((StatusCode::BAD_REQUEST, Json(e)));
+    }
+
+    let account = match store.get_account(&account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account details: {}", e)),
+            ));
+        }
+    };
+    let holdings = match store.get_holdings(&account_id).await {
+        Ok(holdings) => holdings,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch holdings: {}", e)),
+            ));
+        }
+    };
+
+    // Price every held symbol plus any target symbol not held yet
+    let mut quantities: HashMap<String, i32> = holdings
+        .iter()
+        .map(|h| (h.stock_symbol.clone(), h.quantity))
+        .collect();
+    for symbol in targets.keys() {
+        quantities.entry(symbol.clone()).or_insert(0);
+    }
+    let mut positions = Vec::new();
+    let mut quotes = HashMap::new();
+    for (symbol, quantity) in quantities {
+        let price = match fetch_price(&symbol).await {
+            Ok(quote) => {
+                let price = quote_cents(&symbol, &quote)?;
+                quotes.insert(symbol.clone(), quote);
+                price
+            }
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    Json(format!("Failed to fetch stock price for {}: {}", symbol, e)),
+                ));
+            }
+        };
+        positions.push(PricedPosition {
+            stock_symbol: symbol,
+            quantity,
+            price,
+        });
+    }
+
+    let trades = plan(&positions, account.cash as i64, &targets);
+    if !request.execute || trades.is_empty() {
+        return Ok((
+            StatusCode::OK,
+            Json(RebalanceResponse {
+                trades,
+                executed: false,
+                transactions: Vec::new(),
+                outcomes: Vec::new(),
+            }),
+        ));
+    }
+
+    // Queued buys keep their cash
+    let reserved_cash = pool.reserved_cash(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch queued orders: {}", e)),
+        )
+    })?;
+
+    let ctx = TradeContext {
+        store: store.as_ref(),
+        config: &config,
+        ids: ids.as_ref(),
+        clock: clock.as_ref(),
+        buying_power_multiplier: 1.0,
+        reserved_cash,
+    };
+
+    // In best-effort mode each trade commits on its own and failures are reported per trade
+    if request.mode == CommitMode::BestEffort {
+        let outcomes = rebalance_best_effort(&pool, &ctx, &account_id, &trades, &quotes).await?;
+        return Ok((
+            StatusCode::CREATED,
+            Json(RebalanceResponse {
+                trades,
+                executed: true,
+                transactions: outcomes
+                    .iter()
+                    .filter_map(|o| o.transaction.clone())
+                    .collect(),
+                outcomes,
+            }),
+        ));
+    }
+
+    // Otherwise every trade is written in one transaction, so a failure undoes the trades before it
+    let txn = begin_transaction(store.as_ref(), "Error completing rebalance").await?;
+    let trade_ctx = TradeContext {
+        store: txn.store(),
+        ..ctx
+    };
+    let result = async {
+        let mut transactions = Vec::new();
+        for trade in &trades {
+            transactions
+                .push(place_rebalance_trade(&pool, &trade_ctx, &account_id, trade, &quotes).await?);
+        }
+        Ok(transactions)
+    }
+    .await;
+    let transactions = finish_transaction(txn, result, "Error completing rebalance").await?;
+
+    let outcomes = trades
+        .iter()
+        .zip(&transactions)
+        .map(|(trade, transaction)| TradeOutcome {
+            stock_symbol: trade.stock_symbol.clone(),
+            side: trade.side,
+            quantity: trade.quantity,
+            transaction: Some(transaction.clone()),
+            error: None,
+        })
+        .collect();
+    Ok((
+        StatusCode::CREATED,
+        Json(RebalanceResponse {
+            trades,
+            executed: true,
+            transactions,
+            outcomes,
+        }),
+    ))
+}
+
+/// Place each rebalance trade in its own store transaction, so a failed trade leaves the ones
+/// before and after it in place, and report how each went.
+async fn rebalance_best_effort(
+    pool: &DatabasePool,
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    trades: &[RebalanceTrade],
+    quotes: &HashMap<String, FinnhubQuote>,
+) -> Result<Vec<TradeOutcome>, (StatusCode, Json<String>)> {
+    let mut outcomes = Vec::new();
+    for trade in trades {
+        let txn = begin_transaction(ctx.store, "Error completing rebalance").await?;
+        let trade_ctx = TradeContext {
+            store: txn.store(),
+            ..*ctx
+        };
+        let result = place_rebalance_trade(pool, &trade_ctx, account_id, trade, quotes).await;
+        let result = finish_transaction(txn, result, "Error completing rebalance").await;
+        let (transaction, error) = match result {
+            Ok(transaction) => (Some(transaction), None),
+            Err((_, Json(message))) => {
+                tracing::warn!(
+                    "Rebalance {:?} of {} failed for {}: {}",
+                    trade.side,
+                    trade.stock_symbol,
+                    account_id,
+                    message
+                );
+                (None, Some(message))
+            }
+        };
+        outcomes.push(TradeOutcome {
+            stock_symbol: trade.stock_symbol.clone(),
+            side: trade.side,
+            quantity: trade.quantity,
+            transaction,
+            error,
+        });
+    }
+    Ok(outcomes)
+}
+
+/// Place one rebalance trade at the price the trade endpoints would fill it at, after the checks
+/// they make. The caller owns the store transaction.
+async fn place_rebalance_trade(
+    pool: &DatabasePool,
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    trade: &RebalanceTrade,
+    quotes: &HashMap<String, FinnhubQuote>,
+) -> Result<Transaction, (StatusCode, Json<String>)> {
+    let Some(quote) = quotes.get(&trade.stock_symbol) else {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            Json(format!("No quote is available for {}.", trade.stock_symbol)),
+        ));
+    };
+    let day_trades = check_trade(
+        pool,
+        ctx,
+        account_id,
+        trade.side,
+        &trade.stock_symbol,
+        trade.quantity,
+        quote,
+    )
+    .await?;
+    let price = fill_price(
+        ctx.config,
+        trade.side,
+        &trade.stock_symbol,
+        trade.quantity,
+        quote_price(ctx.config, &trade.stock_symbol, quote)?,
+    );
+    let transaction = match trade.side {
+        TradeSide::Sell => {
+            apply_sell(
+                ctx,
+                account_id,
+                &trade.stock_symbol,
+                trade.quantity,
+                price,
+                None,
+            )
+            .await
+        }
+        TradeSide::Buy => {
+            let profile = fetch_profile(&trade.stock_symbol).await.map_err(|e| {
+                tracing::error!("Error fetching stock profile: {}", e);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(String::from("Error completing rebalance")),
+                )
+            })?;
+            apply_buy(
+                ctx,
+                account_id,
+                &trade.stock_symbol,
+                trade.quantity,
+                price,
+                &profile,
+                None,
+            )
+            .await
+        }
+    }?;
+
+    if day_trades.is_some_and(|count| count > ctx.config.day_trade_limit) {
+        tracing::info!("Flagging {} as a pattern day trader", account_id);
+        ctx.store
+            .flag_pattern_day_trader(account_id)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error flagging pattern day trader: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(String::from("Error completing rebalance")),
+                )
+            })?;
+    }
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::finnhub::mock;
+
+    const ACCOUNT: &str = "a@example.com";
+
+    fn holding(symbol: &str, quantity: i32, purchase_price: i32) -> Holding {
+        Holding {
+            account_id: String::from(ACCOUNT),
+            stock_symbol: symbol.to_string(),
+            stock_name: symbol.to_string(),
+            quantity,
+            purchase_price,
+            current_price: purchase_price,
+            total_value: purchase_price * quantity,
+            ..Default::default()
+        }
+    }
+
+    /// A store holding an account with `cash` cents and `holdings`, and app state over it.
+    async fn state_with(
+        cash: i64,
+        holdings: Vec<Holding>,
+    ) -> (Arc<crate::store::MemoryStore>, AppState) {
+        let store = Arc::new(crate::store::MemoryStore::new());
+        store
+            .add_account(Account::open(ACCOUNT, cash, true))
+            .await
+            .unwrap();
+        for holding in holdings {
+            store.add_holding(holding).await.unwrap();
+        }
+        let state = AppState::for_tests(store.clone(), Config::for_tests()).await;
+        (store, state)
+    }
+
+    async fn session() -> Session {
+        crate::auth::test_session(ACCOUNT, crate::auth::Scope::all()).await
+    }
+
+    async fn portfolio(
+        state: &AppState,
+        headers: HeaderMap,
+        round: Option<Rounding>,
+        sort: HoldingSort,
+    ) -> Response {
+        get_portfolio(
+            session().await,
+            headers,
+            Query(RoundingQuery { round }),
+            Query(HoldingSortQuery { sort }),
+            State(state.clone()),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn clock() -> FixedClock {
+        FixedClock(
+            DateTime::parse_from_rfc3339("2024-03-05T15:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        )
+    }
+
+    #[tokio::test]
+    async fn a_spent_budget_truncates_the_portfolio() {
+        let _finnhub = mock::start().await;
+        mock::stock("BUDGA", "Budget A", 10.0);
+        mock::stock("BUDGB", "Budget B", 20.0);
+
+        let priced = price_holdings(
+            ACCOUNT,
+            vec![holding("BUDGB", 1, 2_000), holding("BUDGA", 1, 1_000)],
+            &FinnhubBudget::new(1),
+            &Config::for_tests(),
+            &clock(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert!(priced.truncated);
+        assert_eq!(priced.holdings.len(), 1);
+        assert_eq!(priced.holdings[0].stock_symbol, "BUDGA");
+        assert!(priced.holdings[0].priced);
+        assert_eq!(mock::calls("/quote", "BUDGB"), 0);
+    }
+
+    #[tokio::test]
+    async fn stored_holdings_use_the_stored_valuation() {
+        let holdings = vec![
+            Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("MSFT"),
+                quantity: 2,
+                current_price: 40_000,
+                total_value: 80_000,
+                purchase_price: 30_000,
+                ..Default::default()
+            },
+            Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("AAPL"),
+                quantity: 10,
+                current_price: 15_000,
+                total_value: 150_000,
+                purchase_price: 20_000,
+                ..Default::default()
+            },
+        ];
+
+        let responses = stored_holdings(holdings, &Config::for_tests()).await;
+        let symbols: Vec<&str> = responses.iter().map(|h| h.stock_symbol.as_str()).collect();
+        assert_eq!(symbols, ["AAPL", "MSFT"]);
+        assert_eq!(responses[0].total_value, 150_000);
+        assert_eq!(responses[0].overall_change, -50_000);
+        assert_eq!(responses[1].overall_change, 20_000);
+        assert!(responses.iter().all(|h| !h.priced));
+    }
+
+    #[tokio::test]
+    async fn a_matching_etag_answers_not_modified() {
+        let (_, state) = state_with(10_000, vec![holding("AAPL", 2, 15_000)]).await;
+
+        let first = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers()[ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::IF_NONE_MATCH, etag.clone());
+        let repeat = portfolio(&state, headers, None, HoldingSort::Symbol).await;
+        assert_eq!(repeat.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(repeat.headers()[ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn read_only_sessions_see_the_portfolio_but_may_not_trade() {
+        let (store, state) = state_with(10_000, vec![holding("AAPL", 2, 15_000)]).await;
+        let read_only = || crate::auth::test_session(ACCOUNT, vec![Scope::Read]);
+
+        let response = get_portfolio(
+            read_only().await,
+            HeaderMap::new(),
+            Query(RoundingQuery { round: None }),
+            Query(HoldingSortQuery {
+                sort: HoldingSort::Symbol,
+            }),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (status, _) = crate::handlers::trading::buy_stock(
+            State(state),
+            read_only().await,
+            Json(crate::models::TradeRequest {
+                stock_symbol: String::from("AAPL"),
+                quantity: 1,
+                notional: None,
+                note: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            store.get_account(ACCOUNT).await.unwrap().unwrap().cash,
+            10_000
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failing_symbol_is_valued_at_its_stored_price_and_marked_delisted() {
+        let _finnhub = mock::start().await;
+        mock::stock("LISTA", "Listed A", 10.0);
+        mock::respond(
+            "/quote",
+            "GONEA",
+            r#"{"c":0,"d":null,"dp":null,"pc":0,"t":0}"#,
+        );
+        let config = Config {
+            delisted_after_failures: 1,
+            ..Config::for_tests()
+        };
+
+        let priced = price_holdings(
+            ACCOUNT,
+            vec![holding("LISTA", 2, 900), holding("GONEA", 3, 500)],
+            &FinnhubBudget::new(10),
+            &config,
+            &clock(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let symbols: Vec<&str> = priced
+            .holdings
+            .iter()
+            .map(|h| h.stock_symbol.as_str())
+            .collect();
+        assert_eq!(symbols, ["GONEA", "LISTA"]);
+        assert!(!priced.holdings[0].priced);
+        assert_eq!(priced.holdings[0].total_value, 1_500);
+        assert!(priced.holdings[1].priced);
+        assert_eq!(priced.total_value, 1_500 + 2_000);
+        assert_eq!(priced.errors.len(), 1);
+        assert_eq!(priced.delisted, [(String::from("GONEA"), 500)]);
+    }
+
+    async fn holdings_of(response: Response) -> Vec<serde_json::Value> {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let portfolio: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        portfolio["holdings"].as_array().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn rounding_changes_only_the_displayed_values() {
+        let mut aapl = holding("AAPL", 3, 14_999);
+        aapl.current_price = 15_049;
+        aapl.total_value = 45_147;
+        let (_, state) = state_with(10_000, vec![aapl]).await;
+
+        let exact = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        let rounded = portfolio(
+            &state,
+            HeaderMap::new(),
+            Some(Rounding::Dollars),
+            HoldingSort::Symbol,
+        )
+        .await;
+        assert_ne!(exact.headers()[ETAG], rounded.headers()[ETAG]);
+
+        let exact = &holdings_of(exact).await[0];
+        let rounded = &holdings_of(rounded).await[0];
+        assert_eq!(exact["current_price"], 15_049);
+        assert_eq!(rounded["current_price"], 15_000);
+        assert_eq!(exact["total_value"], 45_147);
+        assert_eq!(rounded["total_value"], 45_100);
+        assert_eq!(exact["quantity"], rounded["quantity"]);
+    }
+
+    #[tokio::test]
+    async fn consecutive_portfolios_list_holdings_in_the_same_order() {
+        let (_, state) = state_with(
+            10_000,
+            vec![
+                holding("TSLA", 1, 20_000),
+                holding("AAPL", 1, 15_000),
+                holding("MSFT", 2, 40_000),
+            ],
+        )
+        .await;
+        let symbols = |holdings: Vec<serde_json::Value>| -> Vec<String> {
+            holdings
+                .iter()
+                .map(|h| h["stock_symbol"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let first = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        let second = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        let first = symbols(holdings_of(first).await);
+        assert_eq!(first, ["AAPL", "MSFT", "TSLA"]);
+        assert_eq!(first, symbols(holdings_of(second).await));
+
+        let by_value = portfolio(&state, HeaderMap::new(), None, HoldingSort::Value).await;
+        assert_eq!(
+            symbols(holdings_of(by_value).await),
+            ["MSFT", "TSLA", "AAPL"]
+        );
+    }
+
+    #[tokio::test]
+    async fn best_effort_rebalances_keep_the_trades_that_succeed() {
+        let (store, _) = state_with(
+            10_000,
+            vec![holding("BESTA", 5, 1_000), holding("BESTB", 1, 1_000)],
+        )
+        .await;
+        let config = Config::for_tests();
+        let clock = clock();
+        let ctx = TradeContext {
+            store: store.as_ref(),
+            config: &config,
+            ids: &crate::ids::SequentialIds::new("txn"),
+            clock: &clock,
+            buying_power_multiplier: 1.0,
+            reserved_cash: 0,
+        };
+        let sell = |symbol: &str, quantity| RebalanceTrade {
+            stock_symbol: symbol.to_string(),
+            side: TradeSide::Sell,
+            quantity,
+            price: 1_000,
+        };
+        let quote = FinnhubQuote {
+            c: 10.0,
+            d: 0.0,
+            dp: 0.0,
+            pc: 10.0,
+            t: 0,
+        };
+        let quotes = HashMap::from([
+            (String::from("BESTA"), quote.clone()),
+            (String::from("BESTB"), quote),
+        ]);
+
+        // BESTB only has one share, so its sale fails between the two BESTA sales
+        let outcomes = rebalance_best_effort(
+            &DatabasePool::unreachable().await,
+            &ctx,
+            ACCOUNT,
+            &[sell("BESTA", 2), sell("BESTB", 3), sell("BESTA", 1)],
+            &quotes,
+        )
+        .await
+        .unwrap();
+
+        let committed: Vec<bool> = outcomes.iter().map(|o| o.transaction.is_some()).collect();
+        assert_eq!(committed, [true, false, true]);
+        assert!(outcomes[1].error.is_some());
+        let holdings: HashMap<String, i32> = store
+            .get_holdings(ACCOUNT)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.stock_symbol, h.quantity))
+            .collect();
+        assert_eq!(holdings["BESTA"], 2);
+        assert_eq!(holdings["BESTB"], 1);
+        assert_eq!(
+            store.get_account(ACCOUNT).await.unwrap().unwrap().cash,
+            13_000
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_serve_stored_values_and_recompute_writes() {
+        let _finnhub = mock::start().await;
+        mock::stock("RECOMP", "Recompute Inc", 15.0);
+        let (store, state) = state_with(10_000, vec![holding("RECOMP", 2, 1_000)]).await;
+
+        let read = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        assert_eq!(holdings_of(read).await[0]["current_price"], 1_000);
+        assert_eq!(mock::calls("/quote", "RECOMP"), 0);
+        let stored = store.get_holding(ACCOUNT, "RECOMP").await.unwrap().unwrap();
+        assert_eq!(stored.current_price, 1_000);
+
+        let (_, Json(recomputed)) = recompute_portfolio(
+            session().await,
+            Query(PartialQuery { partial: false }),
+            State(state),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(recomputed.holdings[0].current_price, 1_500);
+        let stored = store.get_holding(ACCOUNT, "RECOMP").await.unwrap().unwrap();
+        assert_eq!(stored.current_price, 1_500);
+        let account = store.get_account(ACCOUNT).await.unwrap().unwrap();
+        assert_eq!(account.value, 10_000 + 3_000);
+    }
+
+    #[tokio::test]
+    async fn dividend_income_is_trailing_dividends_times_shares() {
+        let _finnhub = mock::start().await;
+        mock::respond(
+            "/stock/dividend",
+            "DIVQ",
+            r#"[{"date":"2023-05-10","amount":0.24},{"date":"2023-08-10","amount":0.24},
+                {"date":"2023-11-10","amount":0.24},{"date":"2024-02-09","amount":0.24}]"#,
+        );
+        mock::respond("/stock/dividend", "DIVNONE", "[]");
+        let (store, _) = state_with(
+            10_000,
+            vec![holding("DIVQ", 40, 10_000), holding("DIVNONE", 5, 10_000)],
+        )
+        .await;
+
+        let (_, Json(income)) = get_dividend_estimate(
+            session().await,
+            State(store as Arc<dyn Store>),
+            State(GuestStores::new(0)),
+            State(Arc::new(clock()) as Arc<dyn Clock>),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(income.holdings.len(), 1);
+        assert_eq!(income.holdings[0].stock_symbol, "DIVQ");
+        assert_eq!(income.holdings[0].annual_dividend_per_share, 96);
+        assert_eq!(income.holdings[0].annual_income, 3_840);
+        assert_eq!(income.total_annual_income, 3_840);
+        assert!(!income.truncated);
+    }
+
+    #[tokio::test]
+    async fn holdings_map_to_their_upcoming_earnings() {
+        let _finnhub = mock::start().await;
+        mock::respond(
+            "/calendar/earnings",
+            "EARNA",
+            r#"{"earningsCalendar":[
+                {"date":"2024-04-25","hour":"amc","epsEstimate":1.5,"quarter":1,"year":2024},
+                {"date":"2024-02-01","hour":"amc","quarter":4,"year":2023}]}"#,
+        );
+        mock::respond(
+            "/calendar/earnings",
+            "EARNB",
+            r#"{"earningsCalendar":[{"date":"2024-03-20","hour":"bmo","quarter":1,"year":2024}]}"#,
+        );
+        mock::respond(
+            "/calendar/earnings",
+            "EARNNONE",
+            r#"{"earningsCalendar":[]}"#,
+        );
+        let (store, _) = state_with(
+            10_000,
+            vec![
+                holding("EARNA", 1, 10_000),
+                holding("EARNB", 1, 10_000),
+                holding("EARNNONE", 1, 10_000),
+            ],
+        )
+        .await;
+
+        let (_, Json(calendar)) = get_earnings(
+            session().await,
+            State(store as Arc<dyn Store>),
+            State(GuestStores::new(0)),
+            State(Arc::new(clock()) as Arc<dyn Clock>),
+            Extension(FinnhubBudget::new(10)),
+        )
+        .await
+        .unwrap();
+
+        let dates: Vec<(&str, String)> = calendar
+            .earnings
+            .iter()
+            .map(|e| (e.stock_symbol.as_str(), e.date.to_string()))
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                ("EARNB", String::from("2024-03-20")),
+                ("EARNA", String::from("2024-04-25"))
+            ]
+        );
+        assert_eq!(calendar.earnings[1].eps_estimate, Some(1.5));
+        assert!(!calendar.truncated);
+    }
+
+    #[tokio::test]
+    async fn a_partial_pricing_keeps_the_holdings_that_priced() {
+        let _finnhub = mock::start().await;
+        mock::stock("PARTA", "Partial A", 10.0);
+        mock::respond_with_status("/quote", "PARTB", 403, "");
+        let holdings = || vec![holding("PARTA", 2, 900), holding("PARTB", 3, 500)];
+        let price = |partial| {
+            let budget = FinnhubBudget::new(10);
+            let config = Config::for_tests();
+            let holdings = holdings();
+            async move { price_holdings(ACCOUNT, holdings, &budget, &config, &clock(), partial).await }
+        };
+
+        let Err((status, _)) = price(false).await else {
+            panic!("strict pricing succeeded without a quote");
+        };
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+        let Ok(priced) = price(true).await else {
+            panic!("partial pricing failed");
+        };
+        let flags: Vec<(&str, bool)> = priced
+            .holdings
+            .iter()
+            .map(|h| (h.stock_symbol.as_str(), h.priced))
+            .collect();
+        assert_eq!(flags, [("PARTA", true), ("PARTB", false)]);
+        assert_eq!(priced.errors.len(), 1);
+        assert_eq!(priced.errors[0].stock_symbol, "PARTB");
+        assert_eq!(priced.total_value, 2_000 + 1_500);
+    }
+
+    #[tokio::test]
+    async fn only_quoted_holdings_report_a_day_change() {
+        let _finnhub = mock::start().await;
+        mock::stock("DAYCA", "Day A", 10.0);
+        let (_, state) = state_with(10_000, vec![holding("DAYCA", 2, 900)]).await;
+
+        let stored = portfolio(&state, HeaderMap::new(), None, HoldingSort::Symbol).await;
+        let stored = &holdings_of(stored).await[0];
+        assert!(stored.get("day_change").is_none());
+        assert!(stored.get("day_change_percent").is_none());
+
+        let priced = price_holdings(
+            ACCOUNT,
+            vec![holding("DAYCA", 2, 900)],
+            &FinnhubBudget::new(10),
+            &Config::for_tests(),
+            &clock(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(priced.holdings[0].day_change, Some(100));
+        assert_eq!(priced.holdings[0].day_change_percent, Some(100));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::auth::validate_session;
+use crate::finnhub::{fetch_price, FinnhubBudget};
+use crate::models::Peer;
+use crate::recent::RecentSymbols;
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use futures_util::future::join_all;
+use tower_sessions::Session;
+
+/// Get the symbols the user recently looked up, most recent first, with their current prices.
+/// Symbols beyond the request's Finnhub budget or without a quote have no price.
+pub async fn get_recent(
+    session: Session,
+    State(recent): State<RecentSymbols>,
+    Extension(budget): Extension<FinnhubBudget>,
+) -> Result<(StatusCode, Json<Vec<Peer>>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    let symbols = recent.list(&info.email);
+    let viewed = join_all(symbols.into_iter().map(|symbol| async {
+        let quote = match budget.try_spend(&symbol) {
+            true => fetch_price(&symbol).await.ok(),
+            false => None,
+        };
+        Peer {
+            current_price: quote.as_ref().map(|q| (q.c * 100.0) as i32),
+            day_change_percent: quote.as_ref().map(|q| (q.dp * 100.0) as i32),
+            stock_symbol: symbol,
+        }
+    }))
+    .await;
+
+    Ok((StatusCode::OK, Json(viewed)))
+}
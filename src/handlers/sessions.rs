@@ -0,0 +1,47 @@
+use crate::auth::validate_session;
+use crate::sessions::{ActiveSession, SessionRegistry};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tower_sessions::Session;
+
+/// List the user's logged-in sessions, oldest first. Only sessions created since the server
+/// started are known.
+pub async fn list_sessions(
+    session: Session,
+    State(sessions): State<SessionRegistry>,
+) -> Result<(StatusCode, Json<Vec<ActiveSession>>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(sessions.list(&info.email, session.id())),
+    ))
+}
+
+/// Log out one of the user's sessions by the id from `GET /sessions`.
+pub async fn revoke_session(
+    session: Session,
+    State(sessions): State<SessionRegistry>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    match sessions.revoke(&info.email, &id).await {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((
+            StatusCode::NOT_FOUND,
+            Json(String::from("Session not found")),
+        )),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::auth::validate_session;
+use crate::config::Config;
+use crate::models::{AccountSettings, UpdateSettings};
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Get the current user's settings. Guests' settings live in their in-memory store.
+pub async fn get_settings(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+) -> Result<(StatusCode, Json<AccountSettings>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let store = resolve_store(&session, &info.email, &store, &guests).await;
+
+    match store.get_settings(&info.email).await {
+        Ok(settings) => Ok((StatusCode::OK, Json(settings))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch settings: {}", e)),
+        )),
+    }
+}
+
+/// Update the current user's settings. Fields left out of the request body are unchanged.
+/// Guests' settings live in their in-memory store.
+pub async fn update_settings(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    Json(update): Json<UpdateSettings>,
+) -> Result<(StatusCode, Json<AccountSettings>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+
+    if update
+        .min_cash_reserve_cents
+        .is_some_and(|reserve| reserve < 0)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("The cash reserve cannot be negative.")),
+        ));
+    }
+
+    if let Some(multiplier) = update.buying_power_multiplier {
+        if !(1.0..=config.max_buying_power_multiplier).contains(&multiplier) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(format!(
+                    "The buying power multiplier must be between 1 and {}.",
+                    config.max_buying_power_multiplier
+                )),
+            ));
+        }
+    }
+
+    let store = resolve_store(&session, &info.email, &store, &guests).await;
+    match store.update_settings(&info.email, &update).await {
+        Ok(settings) => Ok((StatusCode::OK, Json(settings))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to update settings: {}", e)),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{test_session, Scope, GUEST_KEY};
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn guest_settings_stay_in_the_guest_store() {
+        let shared: Arc<dyn Store> = Arc::new(MemoryStore::new());
+        let guests = GuestStores::new(100_000);
+        let session = test_session("guest-2", Scope::all()).await;
+        session.insert(GUEST_KEY, true).await.unwrap();
+        let update = UpdateSettings {
+            min_cash_reserve_cents: Some(5_000),
+            ..Default::default()
+        };
+
+        let (_, Json(updated)) = update_settings(
+            session.clone(),
+            State(shared.clone()),
+            State(guests.clone()),
+            State(Arc::new(Config::for_tests())),
+            Json(update),
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.min_cash_reserve_cents, 5_000);
+
+        let (_, Json(read)) = get_settings(session, State(shared.clone()), State(guests.clone()))
+            .await
+            .unwrap();
+        assert_eq!(read.min_cash_reserve_cents, 5_000);
+        let guest = guests.get_or_create("guest-2").await;
+        assert_eq!(
+            guest
+                .get_settings("guest-2")
+                .await
+                .unwrap()
+                .min_cash_reserve_cents,
+            5_000
+        );
+        assert_eq!(
+            shared
+                .get_settings("guest-2")
+                .await
+                .unwrap()
+                .min_cash_reserve_cents,
+            0
+        );
+    }
+}
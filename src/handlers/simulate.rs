@@ -0,0 +1,76 @@
+use crate::auth::validate_session;
+use crate::clock::Clock;
+use crate::dca::{compare, DcaComparison};
+use crate::finnhub::fetch_candles;
+use crate::models::DcaRequest;
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, NaiveDate};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Longest range a simulation may cover, in days.
+pub const MAX_RANGE_DAYS: i64 = 366 * 10;
+
+/// Compare what dollar-cost averaging into a symbol over a past date range would have returned
+/// against investing the same amount at the start, using daily closes. The range is trimmed to
+/// the days Finnhub has closes for.
+pub async fn simulate_dca(
+    session: Session,
+    State(clock): State<Arc<dyn Clock>>,
+    Json(request): Json<DcaRequest>,
+) -> Result<(StatusCode, Json<DcaComparison>), (StatusCode, Json<String>)> {
+    // Validate the session
+    if let Err(status) = validate_session(session).await {
+        return Err((status, Json("Unauthorized access".to_string())));
+    }
+
+    if request.amount <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("The amount must be positive.")),
+        ));
+    }
+    let today = clock.now().date_naive();
+    let end = request.end.min(today);
+    if request.start >= end {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("The start date must be before the end date.")),
+        ));
+    }
+    if (end - request.start).num_days() > MAX_RANGE_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("The date range is too long.")),
+        ));
+    }
+
+    let from = request.start.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let to = end.and_hms_opt(23, 59, 59).unwrap().and_utc();
+    let candles =
+        match fetch_candles(&request.stock_symbol, "D", from.timestamp(), to.timestamp()).await {
+            Ok(candles) => candles,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::error!("Error fetching candles: {}", e);
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    Json(String::from("Failed to fetch historical prices")),
+                ));
+            }
+        };
+
+    let closes: Vec<(NaiveDate, f64)> = candles
+        .t
+        .iter()
+        .zip(&candles.c)
+        .filter_map(|(t, c)| Some((DateTime::from_timestamp(*t, 0)?.date_naive(), *c)))
+        .collect();
+    match compare(&closes, request.amount, request.frequency) {
+        Some(comparison) => Ok((StatusCode::OK, Json(comparison))),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(String::from("No historical prices for this range")),
+        )),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::auth::validate_session;
+use crate::stats::{trade_stats, TradeStats};
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Get the current user's trading statistics, computed from their transaction history.
+pub async fn get_my_stats(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+) -> Result<(StatusCode, Json<TradeStats>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let transactions = match store.get_transactions(&account_id).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch transactions: {}", e)),
+            ));
+        }
+    };
+
+    Ok((StatusCode::OK, Json(trade_stats(&transactions))))
+}
@@ -0,0 +1,169 @@
+use crate::auth::validate_session;
+use crate::config::Config;
+use crate::finnhub::{fetch_price, FinnhubBudget};
+use crate::handlers::portfolio::load_account;
+use crate::models::{Suggestion, Suggestions, SuggestionsQuery};
+use crate::money::change_percent;
+use crate::store::{resolve_store, GuestStores, Store};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use futures_util::future::join_all;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Most symbols one request may ask about.
+pub const MAX_SYMBOLS: usize = 50;
+/// Symbols listed as movers.
+const MOVERS: usize = 3;
+const DISCLAIMER: &str =
+    "Quantities are cash divided by the current quote. This is not financial advice.";
+
+/// Get how many shares of each requested symbol the account's cash covers at the current quote,
+/// and which of them moved the most today. Symbols are quoted concurrently.
+pub async fn get_suggestions(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    Extension(budget): Extension<FinnhubBudget>,
+    Query(query): Query<SuggestionsQuery>,
+) -> Result<(StatusCode, Json<Suggestions>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let mut seen = HashSet::new();
+    let symbols: Vec<String> = query
+        .symbols
+        .split(',')
+        .map(|symbol| symbol.trim().to_uppercase())
+        .filter(|symbol| !symbol.is_empty() && seen.insert(symbol.clone()))
+        .collect();
+    if symbols.is_empty() || symbols.len() > MAX_SYMBOLS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "Between 1 and {} symbols are required.",
+                MAX_SYMBOLS
+            )),
+        ));
+    }
+
+    let (account, _) = load_account(store.as_ref(), &account_id).await?;
+    let cash = (account.cash as i64).max(0);
+
+    let (symbols, skipped): (Vec<String>, Vec<String>) = symbols
+        .into_iter()
+        .partition(|symbol| budget.try_spend(symbol));
+    let quotes = join_all(symbols.iter().map(|symbol| fetch_price(symbol))).await;
+
+    let mut suggestions = Vec::new();
+    let mut unpriced = Vec::new();
+    for (symbol, quote) in symbols.into_iter().zip(quotes) {
+        let quote = match quote {
+            Ok(quote) => quote,
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!("Failed to price {}: {}", symbol, e);
+                unpriced.push(symbol);
+                continue;
+            }
+        };
+        let price = (quote.c * 100.0) as i32;
+        if price <= 0 {
+            unpriced.push(symbol);
+            continue;
+        }
+        let day_change = ((quote.c - quote.pc) * 100.0) as i32;
+        suggestions.push(Suggestion {
+            stock_symbol: symbol,
+            price,
+            affordable_quantity: affordable_quantity(&config, cash, price),
+            day_change,
+            day_change_percent: change_percent(day_change, price),
+        });
+    }
+
+    let mut movers: Vec<&Suggestion> = suggestions
+        .iter()
+        .filter(|s| s.day_change_percent != 0)
+        .collect();
+    movers.sort_by_key(|s| std::cmp::Reverse(s.day_change_percent.abs()));
+    let movers = movers
+        .into_iter()
+        .take(MOVERS)
+        .map(|s| s.stock_symbol.clone())
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(Suggestions {
+            disclaimer: DISCLAIMER,
+            cash,
+            suggestions,
+            movers,
+            unpriced,
+            truncated: !skipped.is_empty(),
+        }),
+    ))
+}
+
+/// Whole shares `cash` covers at `price`, rounded down to a whole lot when round lots are on.
+fn affordable_quantity(config: &Config, cash: i64, price: i32) -> i64 {
+    let quantity = cash / price as i64;
+    match config.round_lot {
+        Some(lot) => quantity - quantity % lot as i64,
+        None => quantity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finnhub::mock;
+    use crate::models::Account;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn affordable_quantities_are_cash_divided_by_price() {
+        let _finnhub = mock::start().await;
+        mock::stock("SUGGA", "Suggest A", 30.0);
+        mock::stock("SUGGB", "Suggest B", 7.0);
+        let store = Arc::new(MemoryStore::new());
+        store
+            .add_account(Account::open("a@example.com", 100_000, true))
+            .await
+            .unwrap();
+
+        let (_, Json(suggestions)) = get_suggestions(
+            crate::auth::test_session("a@example.com", crate::auth::Scope::all()).await,
+            State(store),
+            State(GuestStores::new(0)),
+            State(Arc::new(Config::for_tests())),
+            Extension(FinnhubBudget::new(10)),
+            Query(SuggestionsQuery {
+                symbols: String::from("sugga, SUGGB,SUGGA"),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(suggestions.cash, 100_000);
+        let quantities: Vec<(&str, i64)> = suggestions
+            .suggestions
+            .iter()
+            .map(|s| (s.stock_symbol.as_str(), s.affordable_quantity))
+            .collect();
+        assert_eq!(quantities, [("SUGGA", 33), ("SUGGB", 142)]);
+        // SUGGB's $1 move is the larger percentage
+        assert_eq!(suggestions.movers, ["SUGGB", "SUGGA"]);
+    }
+}
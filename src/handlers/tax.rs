@@ -0,0 +1,118 @@
+use crate::auth::validate_session;
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::handlers::trading::price_hypothetical;
+use crate::models::{TaxPreview, TaxPreviewQuery, TermTax, TradeCostQuery, TradeSide};
+use crate::store::{resolve_store, GuestStores, Store};
+use crate::tax::{estimated_tax, open_lots, split_sale, TermGain};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+const DISCLAIMER: &str =
+    "Estimated at flat short- and long-term rates with the oldest shares sold first. This is not tax advice.";
+
+/// Estimate the short- and long-term gains, and the tax on them, of selling shares at the current
+/// quote. Lots are rebuilt from the transaction history, oldest sold first, and shares held more
+/// than a year are long-term. Rates come from `SHORT_TERM_TAX_BPS` and `LONG_TERM_TAX_BPS`.
+pub async fn get_tax_preview(
+    session: Session,
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Query(query): Query<TaxPreviewQuery>,
+) -> Result<(StatusCode, Json<TaxPreview>), (StatusCode, Json<String>)> {
+    // Validate the session
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let account_id = info.email;
+    let store = resolve_store(&session, &account_id, &store, &guests).await;
+
+    let price = price_hypothetical(
+        &config,
+        &TradeCostQuery {
+            symbol: query.symbol.clone(),
+            quantity: query.quantity,
+            side: TradeSide::Sell,
+        },
+    )
+    .await?;
+
+    let account = match store.get_account(&account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch account: {}", e)),
+            ))
+        }
+    };
+    let transactions = store.get_transactions(&account_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to fetch transactions: {}", e)),
+        )
+    })?;
+    let summaries = store
+        .get_transaction_summaries(&account_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to fetch archived transactions: {}", e)),
+            )
+        })?;
+    let summary = summaries
+        .iter()
+        .find(|s| s.stock_symbol.eq_ignore_ascii_case(&query.symbol));
+
+    let lots = open_lots(summary, &transactions, &query.symbol);
+    let quantity = query.quantity as i64;
+    if lots.iter().map(|lot| lot.quantity).sum::<i64>() < quantity {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("You cannot sell more shares than you own.")),
+        ));
+    }
+
+    let notional = price.value(quantity);
+    let fee = config.fee_model.fee(account.trades_count, notional);
+    let proceeds = notional - fee;
+    let split = split_sale(&lots, quantity, proceeds, clock.now().date_naive());
+    let taxed = |gain: TermGain, rate_bps: i64| TermTax {
+        gain,
+        rate_bps,
+        estimated_tax: estimated_tax(gain.gain, rate_bps),
+    };
+    let short_term = taxed(split.short_term, config.short_term_tax_bps);
+    let long_term = taxed(split.long_term, config.long_term_tax_bps);
+
+    Ok((
+        StatusCode::OK,
+        Json(TaxPreview {
+            disclaimer: DISCLAIMER,
+            stock_symbol: query.symbol,
+            quantity: query.quantity,
+            price: price.cents(),
+            fee,
+            proceeds,
+            estimated_tax: short_term.estimated_tax + long_term.estimated_tax,
+            short_term,
+            long_term,
+            unknown_term: split.unknown_term,
+        }),
+    ))
+}
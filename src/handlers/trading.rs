@@ -1,25 +1,409 @@
-use crate::auth::validate_session;
+use crate::auth::{validate_scope, validate_session, Scope, GUEST_KEY};
+use crate::cash::checked_cash;
+use crate::clock::Clock;
+use crate::config::{Config, PendingBuySells};
+use crate::confirmations::{PendingOrder, CONFIRMATION_TTL};
+use crate::day_trades::{count_day_trades, is_day_trade, window_start};
 use crate::db::DatabasePool;
-use crate::finnhub::{fetch_stock_price, fetch_stock_profile};
-use crate::models::{TradeRequest, Transaction};
-use axum::{extract::State, http::StatusCode, Json};
+use crate::features::{self, require_feature};
+use crate::finnhub::{
+    fetch_price, fetch_profile, is_crypto_symbol, normalize_symbol, FinnhubError, FinnhubProfile,
+    FinnhubQuote,
+};
+use crate::ids::IdGenerator;
+use crate::market_hours::{is_halted, is_regular_session, ClosedMarketOrders};
+use crate::models::{
+    Account, AccountSettings, ConfirmTrade, FeeQuote, QueuedOrder, TradeConfirmation, TradeCost,
+    TradeCostQuery, TradeRequest, TradeSide, TradeValidation, Transaction, ValidateTrade,
+    ValidationCode, ValidationError,
+};
+use crate::money::SharePrice;
+use crate::pnl::parse_timestamp;
+use crate::recent::RecentSymbols;
+use crate::state::AppState;
+use crate::store::{resolve_store, GuestStores, Store, StoreError, StoreTransaction};
+use crate::timestamps::format_utc;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::sync::Arc;
 use tower_sessions::Session;
 
-/// Buy a stock with a given account ID. The request body should contain the stock symbol and the quantity to buy.
-#[axum::debug_handler]
+/// Buy a stock with a given account ID. The request body should contain the stock symbol and either
+/// the quantity to buy or a `notional` dollar amount to spend on whole shares. Buys above
+/// `CONFIRM_NOTIONAL_ABOVE` are not executed; they respond 202 with a confirmation token for
+/// `/buy/confirm` instead. Outside the regular session, `CLOSED_MARKET_ORDERS` may refuse stock
+/// buys or queue them, responding 202 with the queued order.
+#[axum::debug_handler(state = AppState)]
 pub async fn buy_stock(
-    State(pool): State<DatabasePool>,
+    State(state): State<AppState>,
     session: Session,
     Json(trade): Json<TradeRequest>,
-) -> Result<(StatusCode, Json<Transaction>), (StatusCode, Json<String>)> {
-    let info = match validate_session(session).await {
+) -> Result<Response, (StatusCode, Json<String>)> {
+    let info = match validate_scope(session.clone(), Scope::Trade).await {
         Ok(info) => info,
         Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
     };
+    let AppState {
+        store,
+        pool,
+        config,
+        guests,
+        confirmations,
+        ids,
+        clock,
+        locks,
+        ..
+    } = state;
     let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+    let _lock = locks.lock(&s).await;
 
-    let stock_price = match fetch_stock_price(&trade.stock_symbol).await {
-        Ok(price) => (price.c * 100.0) as i32,
+    if is_crypto_symbol(&trade.stock_symbol) {
+        require_feature(store.as_ref(), &config, &s, features::CRYPTO).await?;
+    }
+
+    let (stock_price, profile) = fetch_buy_quote(&config, clock.now(), &trade.stock_symbol).await?;
+
+    // A notional order buys as many whole shares, or whole lots, as the amount covers
+    let quantity = match trade.notional {
+        Some(notional) => notional_shares(&config, notional, stock_price)?,
+        None => trade.quantity,
+    };
+    if quantity <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("You must buy at least one share.")),
+        ));
+    }
+    check_order_size(&config, quantity)?;
+    check_note(&trade.note)?;
+
+    // Large orders may fill in tranches at a worse blended price
+    let stock_price = fill_price(
+        &config,
+        TradeSide::Buy,
+        &trade.stock_symbol,
+        quantity,
+        stock_price,
+    );
+
+    let notional = stock_price.value(quantity as i64);
+    let settings = load_settings(store.as_ref(), &s).await?;
+    let multiplier = buying_power_multiplier(&config, &settings);
+    let reserved_cash = load_reserved_cash(store.as_ref(), &s).await?;
+    check_cash_reserve(&settings, store.as_ref(), &config, &s, notional).await?;
+
+    let ctx = TradeContext {
+        store: store.as_ref(),
+        config: &config,
+        ids: ids.as_ref(),
+        clock: clock.as_ref(),
+        buying_power_multiplier: multiplier,
+        reserved_cash,
+    };
+
+    // Outside the session the order may have to wait for the open
+    if must_queue(&config, clock.now(), &trade.stock_symbol)? {
+        let order = QueuedOrder {
+            id: ids.next_id(),
+            account_id: s,
+            stock_symbol: trade.stock_symbol,
+            side: TradeSide::Buy,
+            quantity,
+            reserved: notional,
+            note: trade.note,
+            placed_at: format_utc(clock.now()),
+        };
+        return queue_buy(&pool, &session, &ctx, order).await;
+    }
+
+    // Hold large orders until the user confirms them
+    if config
+        .confirm_notional_above
+        .is_some_and(|limit| notional > limit)
+    {
+        let account = match store.get_account(&s).await {
+            Ok(Some(account)) => account,
+            Ok(None) => {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    Json(String::from("Account not found")),
+                ))
+            }
+            Err(e) => {
+                tracing::error!("Error fetching account: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(String::from("Error completing trade")),
+                ));
+            }
+        };
+        let fee = config.fee_model.fee(account.trades_count, notional);
+        let confirmation_token = confirmations.hold(PendingOrder {
+            account_id: s,
+            stock_symbol: trade.stock_symbol.clone(),
+            quantity,
+            note: trade.note.clone(),
+        });
+        let confirmation = TradeConfirmation {
+            confirmation_token,
+            expires_in_secs: CONFIRMATION_TTL.as_secs(),
+            summary: TradeCost {
+                stock_symbol: trade.stock_symbol,
+                side: TradeSide::Buy,
+                quantity,
+                price: stock_price.cents(),
+                notional,
+                fee,
+                total: notional + fee,
+                sufficient: account.buying_power(multiplier) - reserved_cash >= notional + fee,
+            },
+        };
+        return Ok((StatusCode::ACCEPTED, Json(confirmation)).into_response());
+    }
+
+    let transaction = execute_buy(
+        &ctx,
+        &s,
+        &trade.stock_symbol,
+        quantity,
+        stock_price,
+        &profile,
+        trade.note,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(transaction)).into_response())
+}
+
+/// Execute a buy held by `/buy` for confirmation, at the current price. Tokens are single use
+/// and expire after `CONFIRMATION_TTL`. The order goes through `/buy`'s checks again, since the
+/// market may have closed or the symbol halted since it was held; a confirmation after the close
+/// may be queued, responding 202 with the queued order.
+pub async fn confirm_buy(
+    State(state): State<AppState>,
+    session: Session,
+    Json(request): Json<ConfirmTrade>,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    let info = match validate_scope(session.clone(), Scope::Trade).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        pool,
+        config,
+        guests,
+        confirmations,
+        ids,
+        clock,
+        locks,
+        ..
+    } = state;
+    let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+    let _lock = locks.lock(&s).await;
+
+    let Some(order) = confirmations.take(&request.confirmation_token, &s) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from(
+                "Confirmation token is invalid or has expired.",
+            )),
+        ));
+    };
+
+    // The feature may have been revoked since the order was held
+    if is_crypto_symbol(&order.stock_symbol) {
+        require_feature(store.as_ref(), &config, &s, features::CRYPTO).await?;
+    }
+
+    // Limits may have changed since the order was held too
+    check_order_size(&config, order.quantity)?;
+    let (stock_price, profile) = fetch_buy_quote(&config, clock.now(), &order.stock_symbol).await?;
+    let stock_price = fill_price(
+        &config,
+        TradeSide::Buy,
+        &order.stock_symbol,
+        order.quantity,
+        stock_price,
+    );
+    let notional = stock_price.value(order.quantity as i64);
+    let settings = load_settings(store.as_ref(), &s).await?;
+    let multiplier = buying_power_multiplier(&config, &settings);
+    let reserved_cash = load_reserved_cash(store.as_ref(), &s).await?;
+    check_cash_reserve(&settings, store.as_ref(), &config, &s, notional).await?;
+
+    let ctx = TradeContext {
+        store: store.as_ref(),
+        config: &config,
+        ids: ids.as_ref(),
+        clock: clock.as_ref(),
+        buying_power_multiplier: multiplier,
+        reserved_cash,
+    };
+    if must_queue(&config, clock.now(), &order.stock_symbol)? {
+        let queued = QueuedOrder {
+            id: ids.next_id(),
+            account_id: s,
+            stock_symbol: order.stock_symbol,
+            side: TradeSide::Buy,
+            quantity: order.quantity,
+            reserved: notional,
+            note: order.note,
+            placed_at: format_utc(clock.now()),
+        };
+        return queue_buy(&pool, &session, &ctx, queued).await;
+    }
+
+    let transaction = execute_buy(
+        &ctx,
+        &s,
+        &order.stock_symbol,
+        order.quantity,
+        stock_price,
+        &profile,
+        order.note,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(transaction)).into_response())
+}
+
+/// Fetch an account's settings for a trade.
+async fn load_settings(
+    store: &dyn Store,
+    account_id: &str,
+) -> Result<AccountSettings, (StatusCode, Json<String>)> {
+    store.get_settings(account_id).await.map_err(|e| {
+        tracing::error!("Error fetching settings: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    })
+}
+
+/// The account's buying power multiplier, capped by `MAX_BUYING_POWER_MULTIPLIER` in case the
+/// cap was lowered after the setting was saved.
+fn buying_power_multiplier(config: &Config, settings: &AccountSettings) -> f64 {
+    settings
+        .buying_power_multiplier
+        .min(config.max_buying_power_multiplier)
+        .max(1.0)
+}
+
+/// Reject a buy of `notional` cents that, with fees, would leave less cash than the account's
+/// `min_cash_reserve_cents` setting.
+async fn check_cash_reserve(
+    settings: &AccountSettings,
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    notional: i64,
+) -> Result<(), (StatusCode, Json<String>)> {
+    let reserve = settings.min_cash_reserve_cents;
+    if reserve <= 0 {
+        return Ok(());
+    }
+
+    let account = match store.get_account(account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Error fetching account: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(String::from("Error completing trade")),
+            ));
+        }
+    };
+    let fee = config.fee_model.fee(account.trades_count, notional);
+    if account.cash as i64 - notional - fee < reserve {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "This trade would take your cash below your reserve of ${:.2}.",
+                reserve as f64 / 100.0
+            )),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject trades on a symbol whose quote looks halted, if halt detection is configured.
+fn check_halt(
+    config: &Config,
+    now: DateTime<Utc>,
+    symbol: &str,
+    quote: &FinnhubQuote,
+) -> Result<(), (StatusCode, Json<String>)> {
+    let Some(secs) = config.halt_stale_after_secs else {
+        return Ok(());
+    };
+    if is_halted(quote, now, chrono::Duration::seconds(secs)) {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(format!(
+                "Trading in {} appears to be halted, try again later.",
+                symbol
+            )),
+        ));
+    }
+    Ok(())
+}
+
+/// The last price of `quote` in cents. A zero or sub-cent quote would fill at nothing, so it's
+/// refused as no quote at all.
+pub(crate) fn quote_cents(
+    symbol: &str,
+    quote: &FinnhubQuote,
+) -> Result<i32, (StatusCode, Json<String>)> {
+    if quote.c * 100.0 < 1.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!("No quote is available for {}.", symbol)),
+        ));
+    }
+    Ok((quote.c * 100.0) as i32)
+}
+
+/// The last price of `quote` per share, kept to a tenth of a cent under
+/// `SUB_CENT_PRICING_BELOW`. Refuses the same quotes as `quote_cents`.
+pub(crate) fn quote_price(
+    config: &Config,
+    symbol: &str,
+    quote: &FinnhubQuote,
+) -> Result<SharePrice, (StatusCode, Json<String>)> {
+    quote_cents(symbol, quote)?;
+    Ok(SharePrice::from_quote(
+        quote.c,
+        config.sub_cent_pricing_below,
+    ))
+}
+
+/// Fetch the quote and profile needed to buy a stock.
+async fn fetch_buy_quote(
+    config: &Config,
+    now: DateTime<Utc>,
+    symbol: &str,
+) -> Result<(SharePrice, FinnhubProfile), (StatusCode, Json<String>)> {
+    let stock_price = match fetch_price(symbol).await {
+        Ok(quote) => {
+            let price = quote_price(config, symbol, &quote)?;
+            check_halt(config, now, symbol, &quote)?;
+            price
+        }
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e @ FinnhubError::Decode(_)) => return Err(e.into()),
         Err(_) => {
             return Err((
                 StatusCode::BAD_REQUEST,
@@ -28,8 +412,10 @@ pub async fn buy_stock(
         }
     };
 
-    let stock_name = match fetch_stock_profile(&trade.stock_symbol).await {
-        Ok(stock) => stock.name,
+    let profile = match fetch_profile(symbol).await {
+        Ok(profile) => profile,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e @ FinnhubError::Decode(_)) => return Err(e.into()),
         Err(e) => {
             tracing::error!("Error fetching stock profile: {}", e);
             return Err((
@@ -39,271 +425,2155 @@ pub async fn buy_stock(
         }
     };
 
-    let total_cost = stock_price * trade.quantity;
+    Ok((stock_price, profile))
+}
+
+/// Whole shares, or whole lots, that `notional` cents buys at `price` a share. Rejects a
+/// non-positive amount, and a price of zero rather than dividing by it.
+fn notional_shares(
+    config: &Config,
+    notional: i64,
+    price: SharePrice,
+) -> Result<i32, (StatusCode, Json<String>)> {
+    if notional <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("Notional amounts must be positive.")),
+        ));
+    }
+    if price.tenths() <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("No quote is available to size this order.")),
+        ));
+    }
+    let shares = (notional * 10 / price.tenths()).min(i32::MAX as i64) as i32;
+    Ok(match config.round_lot {
+        Some(lot) => shares / lot * lot,
+        None => shares,
+    })
+}
 
-    let mut session = pool.client.start_session().await.unwrap();
+/// Run `apply_buy` in its own store transaction.
+async fn execute_buy(
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    symbol: &str,
+    quantity: i32,
+    price: SharePrice,
+    profile: &FinnhubProfile,
+    note: Option<String>,
+) -> Result<Transaction, (StatusCode, Json<String>)> {
+    let txn = begin_transaction(ctx.store, "Error completing trade").await?;
+    let ctx = TradeContext {
+        store: txn.store(),
+        ..*ctx
+    };
+    let result = apply_buy(&ctx, account_id, symbol, quantity, price, profile, note).await;
+    finish_transaction(txn, result, "Error completing trade").await
+}
 
-    session.start_transaction().await.map_err(|e| {
+/// Start a store transaction, failing with a 500 carrying `message` if it can't be started.
+pub(crate) async fn begin_transaction<'a>(
+    store: &'a dyn Store,
+    message: &str,
+) -> Result<StoreTransaction<'a>, (StatusCode, Json<String>)> {
+    store.start_transaction().await.map_err(|e| {
         tracing::error!("Error starting transaction: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(String::from("Error completing trade")),
+            Json(String::from(message)),
         )
-    })?;
+    })
+}
 
-    let result = async {
-        // Check if account has enough cash
-        // Update account cash
-        // Update or insert holding
-        // Record transaction
-        // Commit transaction
-        // Return transaction
+/// Commit `txn` if `result` succeeded and abort it otherwise. A failed commit becomes a 500
+/// carrying `message`. A failed abort is only logged, since an uncommitted transaction's writes
+/// never take effect, and the error that caused it is returned.
+pub(crate) async fn finish_transaction<T>(
+    txn: StoreTransaction<'_>,
+    result: Result<T, (StatusCode, Json<String>)>,
+    message: &str,
+) -> Result<T, (StatusCode, Json<String>)> {
+    match result {
+        Ok(value) => match txn.commit().await {
+            Ok(()) => Ok(value),
+            Err(e) => {
+                tracing::error!("Error committing transaction: {}", e);
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(String::from(message)),
+                ))
+            }
+        },
+        Err(e) => {
+            if let Err(abort) = txn.abort().await {
+                tracing::error!("Error aborting transaction: {}", abort);
+            }
+            Err(e)
+        }
+    }
+}
 
-        let mut account = pool
-            .get_account(&s)
-            .await
-            .map_err(|e| {
-                tracing::error!("Error fetching account: {}", e);
-                return Err::<Transaction, (StatusCode, Json<String>)>((
-                    StatusCode::NOT_FOUND,
+/// Sell a stock with a given account ID. The request body should contain the stock symbol and the quantity to sell.
+/// The returned transaction carries the realized P&L of the sale against the holding's average cost.
+/// Outside the regular session, sells are refused or queued like buys.
+pub async fn sell_stock(
+    State(state): State<AppState>,
+    session: Session,
+    Json(trade): Json<TradeRequest>,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    let info = match validate_scope(session.clone(), Scope::Trade).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        pool,
+        config,
+        guests,
+        ids,
+        clock,
+        locks,
+        ..
+    } = state;
+    let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+    let _lock = locks.lock(&s).await;
+    check_order_size(&config, trade.quantity)?;
+    check_note(&trade.note)?;
+    check_pending_buy(store.as_ref(), &config, &s, &trade.stock_symbol).await?;
+    check_lockup(
+        store.as_ref(),
+        &config,
+        &s,
+        &trade.stock_symbol,
+        clock.now(),
+    )
+    .await?;
+
+    // Outside the session the order may have to wait for the open
+    if must_queue(&config, clock.now(), &trade.stock_symbol)? {
+        let held = match store.get_holding(&s, &trade.stock_symbol).await {
+            Ok(holding) => holding.map(|h| h.quantity).unwrap_or(0),
+            Err(e) => {
+                tracing::error!("Error fetching holding: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
                     Json(String::from("Error completing trade")),
                 ));
-            })
-            .unwrap()
-            .unwrap();
-
-        if account.cash < total_cost {
+            }
+        };
+        if held < trade.quantity {
             return Err((
                 StatusCode::BAD_REQUEST,
-                Json(String::from(
-                    "You don't have enough cash to complete this trade.",
-                )),
+                Json(String::from("You cannot sell more shares than you own.")),
             ));
         }
-
-        account.cash -= total_cost;
-
-        pool.update_account(&s, account.value as i64, account.cash as i64)
-            .await
-            .map_err(|e| {
-                tracing::error!("Error updating account cash: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(String::from("Error completing trade")),
-                )
-            })?;
-        // update holdings
-        let holding = pool.get_holding(&s, &trade.stock_symbol).await.unwrap();
-        let holding = holding.unwrap_or_default();
-        if holding.quantity > 0 {
-            let new_quantity = holding.quantity + trade.quantity;
-            let new_price = ((holding.purchase_price * holding.quantity)
-                + (stock_price * trade.quantity))
-                / (holding.quantity + trade.quantity);
-
-            pool.update_holding(
-                &s,
-                &trade.stock_symbol,
-                new_quantity as i64,
-                new_price as i64,
-            )
-            .await
-            .map_err(|e| {
-                tracing::error!("Error updating holding: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(String::from("Error completing trade")),
-                )
-            })?;
-        } else {
-            // insert holding
-            pool.add_holding(crate::models::Holding {
-                account_id: s.clone(),
-                stock_symbol: trade.stock_symbol.clone(),
-                stock_name: stock_name.clone(),
-                quantity: trade.quantity,
-                purchase_price: stock_price,
-                total_value: stock_price * trade.quantity,
-                current_price: stock_price,
-            })
-            .await
-            .unwrap();
-        }
-
-        // Record transaction
-        let transaction_id = uuid::Uuid::new_v4().to_string();
-        pool.add_transaction(crate::models::Transaction {
-            id: transaction_id.clone(),
-            account_id: s.clone(),
-            stock_symbol: trade.stock_symbol.clone(),
-            transaction_type: String::from("BUY"),
-            quantity: trade.quantity,
-            price: stock_price,
-            timestamp: chrono::Local::now().to_rfc3339(),
-        })
-        .await
-        .unwrap();
-
-        Ok(Transaction {
-            id: transaction_id,
+        let order = QueuedOrder {
+            id: ids.next_id(),
             account_id: s,
             stock_symbol: trade.stock_symbol,
-            transaction_type: String::from("BUY"),
+            side: TradeSide::Sell,
             quantity: trade.quantity,
-            price: stock_price,
-            timestamp: chrono::Local::now().to_rfc3339(),
-        })
+            reserved: 0,
+            note: trade.note,
+            placed_at: format_utc(clock.now()),
+        };
+        return queue_order(&pool, &session, order).await;
     }
+    let day_trades = check_day_trade(
+        store.as_ref(),
+        &config,
+        &s,
+        &trade.stock_symbol,
+        clock.now().date_naive(),
+    )
+    .await?;
+
+    // Fetch stock price from Finnhub API
+    let quote = fetch_price(&trade.stock_symbol).await.map_err(|e| {
+        tracing::error!("Error fetching stock price: {}", e);
+        if e.is_unavailable() || matches!(e, FinnhubError::Decode(_)) {
+            return e.into();
+        }
+        (
+            StatusCode::BAD_REQUEST,
+            Json(String::from("Error completing trade")),
+        )
+    })?;
+    let stock_price = quote_price(&config, &trade.stock_symbol, &quote)?;
+    check_halt(&config, clock.now(), &trade.stock_symbol, &quote)?;
+
+    let stock_price = fill_price(
+        &config,
+        TradeSide::Sell,
+        &trade.stock_symbol,
+        trade.quantity,
+        stock_price,
+    );
+
+    let txn = begin_transaction(store.as_ref(), "Error completing trade").await?;
+    let ctx = TradeContext {
+        store: txn.store(),
+        config: &config,
+        ids: ids.as_ref(),
+        clock: clock.as_ref(),
+        buying_power_multiplier: 1.0,
+        reserved_cash: 0,
+    };
+    let result = apply_sell(
+        &ctx,
+        &s,
+        &trade.stock_symbol,
+        trade.quantity,
+        stock_price,
+        trade.note,
+    )
     .await;
+    let transaction = finish_transaction(txn, result, "Error completing trade").await?;
 
-    match result {
-        Ok(transaction) => {
-            session.commit_transaction().await.unwrap();
-            Ok((StatusCode::CREATED, Json(transaction)))
+    if day_trades.is_some_and(|count| count > config.day_trade_limit) {
+        tracing::info!("Flagging {} as a pattern day trader", s);
+        if let Err(e) = store.flag_pattern_day_trader(&s).await {
+            tracing::error!("Error flagging pattern day trader: {}", e);
         }
-        Err(e) => {
-            session.abort_transaction().await.unwrap();
-            Err(e)
+    }
+    Ok((StatusCode::CREATED, Json(transaction)).into_response())
+}
+
+/// Whether a trade in `symbol` placed now must be queued for the open, per
+/// `CLOSED_MARKET_ORDERS`. Refuses it outright in reject mode.
+fn must_queue(
+    config: &Config,
+    now: DateTime<Utc>,
+    symbol: &str,
+) -> Result<bool, (StatusCode, Json<String>)> {
+    if is_crypto_symbol(symbol) || is_regular_session(now) {
+        return Ok(false);
+    }
+    match config.closed_market_orders {
+        ClosedMarketOrders::Allow => Ok(false),
+        ClosedMarketOrders::Reject => Err((
+            StatusCode::CONFLICT,
+            Json(String::from(
+                "The market is closed. Try again during regular trading hours.",
+            )),
+        )),
+        ClosedMarketOrders::Queue => Ok(true),
+    }
+}
+
+/// Check a sell of `symbol` against the account's queued buys of it, per `PENDING_BUY_SELLS`.
+/// Refuses the sell in reject mode while such a buy is open.
+async fn check_pending_buy(
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    symbol: &str,
+) -> Result<(), (StatusCode, Json<String>)> {
+    if config.pending_buy_sells == PendingBuySells::Allow {
+        return Ok(());
+    }
+    let orders = store.get_queued_orders(account_id).await.map_err(|e| {
+        tracing::error!("Error fetching queued orders: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    })?;
+    pending_buy_conflict(config, &orders, account_id, symbol)
+}
+
+/// Decide a sell of `symbol` against the account's queued `orders`, per `PENDING_BUY_SELLS`.
+fn pending_buy_conflict(
+    config: &Config,
+    orders: &[QueuedOrder],
+    account_id: &str,
+    symbol: &str,
+) -> Result<(), (StatusCode, Json<String>)> {
+    let pending = orders.iter().any(|order| {
+        order.side == TradeSide::Buy && order.stock_symbol.eq_ignore_ascii_case(symbol.trim())
+    });
+    match (pending, config.pending_buy_sells) {
+        (true, PendingBuySells::Reject) => Err((
+            StatusCode::CONFLICT,
+            Json(format!(
+                "You have a pending buy of {}. Cancel it or wait for it to fill before selling.",
+                normalize_symbol(symbol)
+            )),
+        )),
+        (true, _) => {
+            tracing::warn!(
+                "{} is selling {} with a buy of it still queued",
+                account_id,
+                normalize_symbol(symbol)
+            );
+            Ok(())
         }
+        (false, _) => Ok(()),
     }
 }
 
-/// Sell a stock with a given account ID. The request body should contain the stock symbol and the quantity to sell.
-pub async fn sell_stock(
-    State(pool): State<DatabasePool>,
-    session: Session,
-    Json(trade): Json<TradeRequest>,
-) -> Result<(StatusCode, Json<Transaction>), (StatusCode, Json<String>)> {
-    let info = match validate_session(session).await {
-        Ok(info) => info,
-        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+/// Refuse a sell of `symbol` within `SELL_LOCKUP_SECS` of the account's latest buy of it, saying
+/// how long remains.
+async fn check_lockup(
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    symbol: &str,
+    now: DateTime<Utc>,
+) -> Result<(), (StatusCode, Json<String>)> {
+    let Some(lockup) = config.sell_lockup_secs else {
+        return Ok(());
+    };
+    let transactions = store.get_transactions(account_id).await.map_err(|e| {
+        tracing::error!("Error fetching transactions: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    })?;
+    let last_buy = transactions
+        .iter()
+        .filter(|t| t.transaction_type == "BUY")
+        .filter(|t| t.stock_symbol.eq_ignore_ascii_case(symbol.trim()))
+        .filter_map(parse_timestamp)
+        .max();
+    let Some(last_buy) = last_buy else {
+        return Ok(());
     };
-    let s = info.email;
 
-    // Fetch stock price from Finnhub API
-    let stock_price = (fetch_stock_price(&trade.stock_symbol)
-        .await
-        .map_err(|e| {
-            tracing::error!("Error fetching stock price: {}", e);
-            (
-                StatusCode::BAD_REQUEST,
-                Json(String::from("Error completing trade")),
-            )
-        })?
-        .c
-        * 100.0) as i32;
+    let unlocks = last_buy.with_timezone(&Utc) + chrono::Duration::seconds(lockup);
+    if now >= unlocks {
+        return Ok(());
+    }
+    let remaining = (unlocks - now).num_seconds().max(1);
+    Err((
+        StatusCode::CONFLICT,
+        Json(format!(
+            "Shares of {} can't be sold within {}s of buying them. Try again in {}s.",
+            normalize_symbol(symbol),
+            lockup,
+            remaining
+        )),
+    ))
+}
 
-    let total_value = stock_price * trade.quantity;
+/// Store a market-on-open order and respond 202 with it. Guest accounts live in memory and
+/// aren't seen by the order job, so they can't queue orders.
+async fn queue_order(
+    pool: &DatabasePool,
+    session: &Session,
+    order: QueuedOrder,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    if let Ok(Some(true)) = session.get::<bool>(GUEST_KEY).await {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(String::from(
+                "The market is closed. Guest accounts can only trade during regular hours.",
+            )),
+        ));
+    }
+    pool.add_queued_order(order.clone()).await.map_err(|e| {
+        tracing::error!("Error queueing order: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    })?;
+    tracing::info!("Queued {:?} order {} for the open", order.side, order.id);
+    Ok((StatusCode::ACCEPTED, Json(order)).into_response())
+}
 
-    let mut session = pool.client.start_session().await.unwrap();
+/// Queue a buy for the open, reserving its notional, which `order.reserved` holds on the way in,
+/// plus fees. Refused if the account can't cover it.
+async fn queue_buy(
+    pool: &DatabasePool,
+    session: &Session,
+    ctx: &TradeContext<'_>,
+    mut order: QueuedOrder,
+) -> Result<Response, (StatusCode, Json<String>)> {
+    let account = load_trade_account(ctx.store, &order.account_id).await?;
+    let notional = order.reserved;
+    order.reserved = notional + ctx.config.fee_model.fee(account.trades_count, notional);
+    if account.buying_power(ctx.buying_power_multiplier) - ctx.reserved_cash < order.reserved {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from(
+                "You don't have enough cash to complete this trade.",
+            )),
+        ));
+    }
+    queue_order(pool, session, order).await
+}
 
-    session.start_transaction().await.map_err(|e| {
-        tracing::error!("Error starting transaction: {}", e);
+/// Cash set aside for an account's queued buys.
+async fn load_reserved_cash(
+    store: &dyn Store,
+    account_id: &str,
+) -> Result<i64, (StatusCode, Json<String>)> {
+    let orders = store.get_queued_orders(account_id).await.map_err(|e| {
+        tracing::error!("Error fetching queued orders: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(String::from("Error completing trade")),
         )
     })?;
+    Ok(orders.iter().map(|order| order.reserved).sum())
+}
+
+/// Fetch the account placing a trade.
+async fn load_trade_account(
+    store: &dyn Store,
+    account_id: &str,
+) -> Result<Account, (StatusCode, Json<String>)> {
+    match store.get_account(account_id).await {
+        Ok(Some(account)) => Ok(account),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(String::from("Account not found")),
+        )),
+        Err(e) => {
+            tracing::error!("Error fetching account: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(String::from("Error completing trade")),
+            ))
+        }
+    }
+}
 
-    let result = async {
-        // Check if account has enough shares
-        // Update account cash
-        // Update holdings
-        // Record transaction
-        // Commit transaction
-        // Return transaction
+/// Fill a queued order at the current quote, as `/buy` or `/sell` would have. The caller removes
+/// the order first, releasing its reserved cash, and holds the account's lock.
+pub(crate) async fn execute_queued_order(
+    pool: &DatabasePool,
+    config: &Config,
+    ids: &dyn IdGenerator,
+    clock: &dyn Clock,
+    order: QueuedOrder,
+) -> Result<Transaction, (StatusCode, Json<String>)> {
+    let QueuedOrder {
+        account_id,
+        stock_symbol,
+        side,
+        quantity,
+        note,
+        ..
+    } = order;
+    let settings = load_settings(pool, &account_id).await?;
+    let mut ctx = TradeContext {
+        store: pool,
+        config,
+        ids,
+        clock,
+        buying_power_multiplier: 1.0,
+        reserved_cash: load_reserved_cash(pool, &account_id).await?,
+    };
 
-        let mut account = pool
-            .get_account(&s)
+    match side {
+        TradeSide::Buy => {
+            let (quote, profile) = fetch_buy_quote(config, clock.now(), &stock_symbol).await?;
+            let price = fill_price(config, side, &stock_symbol, quantity, quote);
+            let notional = price.value(quantity as i64);
+            check_cash_reserve(&settings, pool, config, &account_id, notional).await?;
+            ctx.buying_power_multiplier = buying_power_multiplier(config, &settings);
+            execute_buy(
+                &ctx,
+                &account_id,
+                &stock_symbol,
+                quantity,
+                price,
+                &profile,
+                note,
+            )
             .await
-            .map_err(|e| {
-                tracing::error!("Error fetching account: {}", e);
-                return Err::<Transaction, (StatusCode, Json<String>)>((
-                    StatusCode::NOT_FOUND,
+        }
+        TradeSide::Sell => {
+            let quote = fetch_price(&stock_symbol).await.map_err(|e| {
+                tracing::error!("Error fetching stock price: {}", e);
+                (
+                    StatusCode::BAD_GATEWAY,
                     Json(String::from("Error completing trade")),
-                ));
-            })
-            .unwrap()
-            .unwrap();
+                )
+            })?;
+            let price = quote_price(config, &stock_symbol, &quote)?;
+            check_halt(config, clock.now(), &stock_symbol, &quote)?;
+            let price = fill_price(config, side, &stock_symbol, quantity, price);
 
-        let current_quantity = pool
-            .get_holding(&s, &trade.stock_symbol)
-            .await
-            .map_err(|e| {
-                tracing::error!("Error fetching holding: {}", e);
-                return Err::<Transaction, (StatusCode, Json<String>)>((
+            let txn = begin_transaction(pool, "Error completing trade").await?;
+            let ctx = TradeContext {
+                store: txn.store(),
+                ..ctx
+            };
+            let result = apply_sell(&ctx, &account_id, &stock_symbol, quantity, price, note).await;
+            finish_transaction(txn, result, "Error completing trade").await
+        }
+    }
+}
+
+/// If selling `symbol` today would be a day trade, return how many day trades the account will
+/// have made in the rolling window including it. Flagged accounts valued below
+/// `DAY_TRADE_MIN_EQUITY` are refused further day trades.
+async fn check_day_trade(
+    store: &dyn Store,
+    config: &Config,
+    account_id: &str,
+    symbol: &str,
+    today: NaiveDate,
+) -> Result<Option<usize>, (StatusCode, Json<String>)> {
+    let transactions = store.get_transactions(account_id).await.map_err(|e| {
+        tracing::error!("Error fetching transactions: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    })?;
+    if !is_day_trade(&transactions, symbol, today) {
+        return Ok(None);
+    }
+    let count = count_day_trades(&transactions, window_start(today), today) + 1;
+
+    if let Some(min_equity) = config.day_trade_min_equity {
+        let account = match store.get_account(account_id).await {
+            Ok(Some(account)) => account,
+            Ok(None) => {
+                return Err((
                     StatusCode::NOT_FOUND,
-                    Json(String::from("You cannot sell a stock you do not own.")),
+                    Json(String::from("Account not found")),
+                ))
+            }
+            Err(e) => {
+                tracing::error!("Error fetching account: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(String::from("Error completing trade")),
                 ));
-            })
-            .unwrap()
-            .unwrap()
-            .quantity;
+            }
+        };
+        let flagged = account.pattern_day_trader || count > config.day_trade_limit;
+        if flagged && (account.value as i64) < min_equity {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(format!(
+                    "Pattern day traders need an account value of at least ${:.2} to day trade.",
+                    min_equity as f64 / 100.0
+                )),
+            ));
+        }
+    }
+    Ok(Some(count))
+}
 
-        if current_quantity < trade.quantity {
+/// Reject orders for fewer than `MIN_SHARES` or more than `MAX_SHARES` shares, or for odd lots
+/// when `ROUND_LOTS` is on.
+fn check_order_size(config: &Config, quantity: i32) -> Result<(), (StatusCode, Json<String>)> {
+    if let Some(lot) = config.round_lot {
+        if quantity % lot != 0 {
             return Err((
                 StatusCode::BAD_REQUEST,
-                Json(String::from("You cannot sell more shares than you own.")),
+                Json(format!("Orders must be for a multiple of {} shares.", lot)),
             ));
         }
+    }
+    if quantity < config.min_shares {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "Orders must be for at least {} shares.",
+                config.min_shares
+            )),
+        ));
+    }
+    if quantity > config.max_shares {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "Orders must be for at most {} shares.",
+                config.max_shares
+            )),
+        ));
+    }
+    Ok(())
+}
 
-        account.cash += total_value;
-        pool.update_account(&s, account.value as i64, account.cash as i64)
-            .await
-            .unwrap();
+/// Longest note a trade may carry, in characters.
+pub const MAX_NOTE_LENGTH: usize = 500;
 
-        let new_quantity = current_quantity - trade.quantity;
-        if new_quantity == 0 {
-            pool.delete_holding(&s, &trade.stock_symbol).await.unwrap();
-        } else {
-            let holding = pool
-                .get_holding(&s, &trade.stock_symbol)
-                .await
-                .unwrap()
-                .unwrap();
-            pool.update_holding(
-                &s,
-                &trade.stock_symbol,
-                new_quantity as i64,
-                holding.purchase_price as i64,
-            )
-            .await
-            .unwrap();
-        }
+/// Reject trade notes longer than `MAX_NOTE_LENGTH`.
+fn check_note(note: &Option<String>) -> Result<(), (StatusCode, Json<String>)> {
+    match note {
+        Some(note) if note.chars().count() > MAX_NOTE_LENGTH => Err((
+            StatusCode::BAD_REQUEST,
+            Json(format!(
+                "Notes must be at most {} characters.",
+                MAX_NOTE_LENGTH
+            )),
+        )),
+        _ => Ok(()),
+    }
+}
 
-        let transaction_id = uuid::Uuid::new_v4().to_string();
-        pool.add_transaction(crate::models::Transaction {
-            id: transaction_id.clone(),
-            account_id: s.clone(),
-            stock_symbol: trade.stock_symbol.clone(),
-            transaction_type: String::from("SELL"),
-            quantity: trade.quantity,
-            price: stock_price,
-            timestamp: chrono::Local::now().to_rfc3339(),
-        })
+/// Price each share of an order fills at, given the quote. Without a liquidity model orders
+/// fill at the quote. Sub-cent quotes are filled in tenths of a cent so the blend keeps them.
+pub(crate) fn fill_price(
+    config: &Config,
+    side: TradeSide,
+    symbol: &str,
+    quantity: i32,
+    quote: SharePrice,
+) -> SharePrice {
+    let Some(liquidity) = &config.liquidity else {
+        return quote;
+    };
+    match quote.sub_cent() {
+        Some(tenths) => {
+            SharePrice::from_tenths(liquidity.fill(side, symbol, quantity, tenths as i32) as i64)
+        }
+        None => SharePrice::from_cents(liquidity.fill(side, symbol, quantity, quote.cents())),
+    }
+}
+
+/// Price a trade at the current quote without placing it, using the same fill price and fees
+/// as `/buy` and `/sell`, and report whether the account could cover it.
+pub async fn get_trade_cost(
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    State(recent): State<RecentSymbols>,
+    session: Session,
+    Query(query): Query<TradeCostQuery>,
+) -> Result<(StatusCode, Json<TradeCost>), (StatusCode, Json<String>)> {
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+    recent.view(&s, &query.symbol);
+
+    let price = price_hypothetical(&config, &query).await?;
+
+    let account = match store.get_account(&s).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Account not found")),
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Error fetching account: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(String::from("Error pricing trade")),
+            ));
+        }
+    };
+
+    let notional = price.value(query.quantity as i64);
+    let fee = config.fee_model.fee(account.trades_count, notional);
+    let (total, sufficient) = match query.side {
+        TradeSide::Buy => (notional + fee, account.cash as i64 >= notional + fee),
+        TradeSide::Sell => {
+            let held = match store.get_holding(&s, &query.symbol).await {
+                Ok(holding) => holding.map(|h| h.quantity).unwrap_or(0),
+                Err(e) => {
+                    tracing::error!("Error fetching holding: {}", e);
+                    return Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(String::from("Error pricing trade")),
+                    ));
+                }
+            };
+            (notional - fee, held >= query.quantity)
+        }
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(TradeCost {
+            stock_symbol: query.symbol,
+            side: query.side,
+            quantity: query.quantity,
+            price: price.cents(),
+            notional,
+            fee,
+            total,
+            sufficient,
+        }),
+    ))
+}
+
+/// Itemize the fees a trade would be charged at the current quote, including any
+/// commission-free promotion the account still qualifies for. The items sum to the fee an
+/// actual `/buy` or `/sell` would charge right now.
+pub async fn get_fee_quote(
+    State(store): State<Arc<dyn Store>>,
+    State(guests): State<GuestStores>,
+    State(config): State<Arc<Config>>,
+    session: Session,
+    Query(query): Query<TradeCostQuery>,
+) -> Result<(StatusCode, Json<FeeQuote>), (StatusCode, Json<String>)> {
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+
+    let price = price_hypothetical(&config, &query).await?;
+    let account = load_trade_account(store.as_ref(), &s).await?;
+    let notional = price.value(query.quantity as i64);
+
+    Ok((
+        StatusCode::OK,
+        Json(FeeQuote {
+            stock_symbol: query.symbol,
+            side: query.side,
+            quantity: query.quantity,
+            price: price.cents(),
+            notional,
+            fees: config.fee_model.breakdown(account.trades_count, notional),
+        }),
+    ))
+}
+
+/// Run an order through the same checks `/buy` and `/sell` make against the account's current
+/// state, without placing it, queueing it or holding it for confirmation. Every failed check is
+/// reported rather than just the first. Errors the checks themselves hit, such as Finnhub being
+/// unavailable, fail the request.
+pub async fn validate_trade(
+    State(state): State<AppState>,
+    session: Session,
+    Json(request): Json<ValidateTrade>,
+) -> Result<(StatusCode, Json<TradeValidation>), (StatusCode, Json<String>)> {
+    let info = match validate_session(session.clone()).await {
+        Ok(info) => info,
+        Err(status) => return Err((status, Json("Unauthorized access".to_string()))),
+    };
+    let AppState {
+        store,
+        config,
+        guests,
+        clock,
+        ..
+    } = state;
+    let s = info.email;
+    let store = resolve_store(&session, &s, &store, &guests).await;
+    let symbol = request.stock_symbol.as_str();
+    let now = clock.now();
+    let mut errors = Vec::new();
+
+    check_note(&request.note).or_else(|e| failed(&mut errors, ValidationCode::InvalidNote, e))?;
+    if is_crypto_symbol(symbol) {
+        require_feature(store.as_ref(), &config, &s, features::CRYPTO)
+            .await
+            .or_else(|e| failed(&mut errors, ValidationCode::FeatureDisabled, e))?;
+    }
+
+    let quote = if normalize_symbol(symbol).is_empty() {
+        errors.push(ValidationError {
+            code: ValidationCode::UnknownSymbol,
+            message: String::from("Enter a stock symbol."),
+        });
+        None
+    } else {
+        match fetch_price(symbol).await {
+            Ok(quote) => match quote_price(&config, symbol, &quote) {
+                Ok(price) => {
+                    check_halt(&config, now, symbol, &quote)
+                        .or_else(|e| failed(&mut errors, ValidationCode::Halted, e))?;
+                    Some(price)
+                }
+                Err(e) => {
+                    failed(&mut errors, ValidationCode::UnknownSymbol, e)?;
+                    None
+                }
+            },
+            Err(e) if e.is_unavailable() => return Err(e.into()),
+            Err(_) => {
+                errors.push(ValidationError {
+                    code: ValidationCode::UnknownSymbol,
+                    message: format!("No quote is available for {}.", symbol),
+                });
+                None
+            }
+        }
+    };
+
+    // Buys for a dollar amount become whole shares, or whole lots, at the quote
+    let mut sized = true;
+    let quantity = match (request.side, request.notional, quote) {
+        (TradeSide::Buy, Some(notional), Some(price)) => {
+            match notional_shares(&config, notional, price) {
+                Ok(shares) => shares,
+                Err(e) => {
+                    failed(&mut errors, ValidationCode::InvalidQuantity, e)?;
+                    sized = false;
+                    0
+                }
+            }
+        }
+        _ => request.quantity,
+    };
+    if sized && quantity <= 0 && (request.notional.is_none() || quote.is_some()) {
+        errors.push(ValidationError {
+            code: ValidationCode::InvalidQuantity,
+            message: String::from("Orders must be for at least one share."),
+        });
+    } else if quantity > 0 {
+        check_order_size(&config, quantity)
+            .or_else(|e| failed(&mut errors, ValidationCode::InvalidQuantity, e))?;
+    }
+
+    let queued = must_queue(&config, now, symbol)
+        .or_else(|e| failed(&mut errors, ValidationCode::MarketClosed, e).map(|_| false))?;
+
+    let account = load_trade_account(store.as_ref(), &s).await?;
+    match (request.side, quote) {
+        (TradeSide::Buy, Some(price)) if quantity > 0 => {
+            let price = fill_price(&config, TradeSide::Buy, symbol, quantity, price);
+            let notional = price.value(quantity as i64);
+            let total = notional + config.fee_model.fee(account.trades_count, notional);
+            let settings = load_settings(store.as_ref(), &s).await?;
+            let multiplier = buying_power_multiplier(&config, &settings);
+            let reserved_cash = load_reserved_cash(store.as_ref(), &s).await?;
+            errors.extend(check_funds(&account, multiplier, reserved_cash, total));
+            check_cash_reserve(&settings, store.as_ref(), &config, &s, notional)
+                .await
+                .or_else(|e| failed(&mut errors, ValidationCode::BelowCashReserve, e))?;
+        }
+        (TradeSide::Sell, _) => {
+            let held = match store.get_holding(&s, symbol).await {
+                Ok(holding) => holding.map(|h| h.quantity).unwrap_or(0),
+                Err(e) => {
+                    tracing::error!("Error fetching holding: {}", e);
+                    return Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(String::from("Error validating trade")),
+                    ));
+                }
+            };
+            if held < quantity {
+                errors.push(ValidationError {
+                    code: ValidationCode::InsufficientShares,
+                    message: String::from("You cannot sell more shares than you own."),
+                });
+            }
+            check_pending_buy(store.as_ref(), &config, &s, symbol)
+                .await
+                .or_else(|e| failed(&mut errors, ValidationCode::PendingBuy, e))?;
+            check_lockup(store.as_ref(), &config, &s, symbol, now)
+                .await
+                .or_else(|e| failed(&mut errors, ValidationCode::LockedUp, e))?;
+            if !queued {
+                check_day_trade(store.as_ref(), &config, &s, symbol, now.date_naive())
+                    .await
+                    .map(|_| ())
+                    .or_else(|e| failed(&mut errors, ValidationCode::DayTradeRestricted, e))?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(TradeValidation {
+            ok: errors.is_empty(),
+            stock_symbol: normalize_symbol(symbol),
+            side: request.side,
+            quantity,
+            queued,
+            errors,
+        }),
+    ))
+}
+
+/// The validation error for a buy costing `total` cents, fees included, that the account's
+/// buying power less `reserved_cash` doesn't cover.
+fn check_funds(
+    account: &Account,
+    multiplier: f64,
+    reserved_cash: i64,
+    total: i64,
+) -> Option<ValidationError> {
+    if account.buying_power(multiplier) - reserved_cash >= total {
+        return None;
+    }
+    Some(ValidationError {
+        code: ValidationCode::InsufficientFunds,
+        message: String::from("You don't have enough cash to complete this trade."),
+    })
+}
+
+/// Run a trade placed on the account's behalf, such as a rebalance trade, through the checks
+/// `/buy` and `/sell` make. These trades are placed immediately, so one that would have to wait
+/// for the open is refused. Returns the account's day trade count if the trade is a day trade.
+pub(crate) async fn check_trade(
+    pool: &DatabasePool,
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    side: TradeSide,
+    symbol: &str,
+    quantity: i32,
+    quote: &FinnhubQuote,
+) -> Result<Option<usize>, (StatusCode, Json<String>)> {
+    let now = ctx.clock.now();
+    if is_crypto_symbol(symbol) {
+        require_feature(pool, ctx.config, account_id, features::CRYPTO).await?;
+    }
+    let price = quote_price(ctx.config, symbol, quote)?;
+    check_halt(ctx.config, now, symbol, quote)?;
+    check_order_size(ctx.config, quantity)?;
+    if must_queue(ctx.config, now, symbol)? {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(String::from(
+                "The market is closed. Try again during regular trading hours.",
+            )),
+        ));
+    }
+
+    match side {
+        TradeSide::Buy => {
+            let price = fill_price(ctx.config, side, symbol, quantity, price);
+            let settings = load_settings(pool, account_id).await?;
+            let notional = price.value(quantity as i64);
+            check_cash_reserve(&settings, ctx.store, ctx.config, account_id, notional).await?;
+            Ok(None)
+        }
+        TradeSide::Sell => {
+            check_pending_buy(pool, ctx.config, account_id, symbol).await?;
+            check_lockup(ctx.store, ctx.config, account_id, symbol, now).await?;
+            check_day_trade(ctx.store, ctx.config, account_id, symbol, now.date_naive()).await
+        }
+    }
+}
+
+/// Record a refused check as a validation error. Server errors aren't a verdict on the order, so
+/// they're passed through to fail the request.
+fn failed(
+    errors: &mut Vec<ValidationError>,
+    code: ValidationCode,
+    (status, Json(message)): (StatusCode, Json<String>),
+) -> Result<(), (StatusCode, Json<String>)> {
+    if status.is_server_error() {
+        return Err((status, Json(message)));
+    }
+    errors.push(ValidationError { code, message });
+    Ok(())
+}
+
+/// Price per share that a trade would fill at right now.
+pub(crate) async fn price_hypothetical(
+    config: &Config,
+    query: &TradeCostQuery,
+) -> Result<SharePrice, (StatusCode, Json<String>)> {
+    if query.quantity <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("Quantity must be at least one share.")),
+        ));
+    }
+
+    let quote = match fetch_price(&query.symbol).await {
+        Ok(quote) => quote_price(config, &query.symbol, &quote)?,
+        Err(e) if e.is_unavailable() => return Err(e.into()),
+        Err(e @ FinnhubError::Decode(_)) => return Err(e.into()),
+        Err(e) => {
+            tracing::error!("Error fetching stock price: {}", e);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(String::from("Error pricing trade")),
+            ));
+        }
+    };
+    Ok(fill_price(
+        config,
+        query.side,
+        &query.symbol,
+        query.quantity,
+        quote,
+    ))
+}
+
+/// What applying a trade needs besides the order itself.
+#[derive(Clone, Copy)]
+pub(crate) struct TradeContext<'a> {
+    pub store: &'a dyn Store,
+    pub config: &'a Config,
+    pub ids: &'a dyn IdGenerator,
+    pub clock: &'a dyn Clock,
+    /// Buys may spend up to the account's cash times this, borrowing the difference.
+    pub buying_power_multiplier: f64,
+    /// Cash set aside for queued buys, which other buys may not spend.
+    pub reserved_cash: i64,
+}
+
+/// Reject an order whose notional, in cents, is too large for the account and holding fields.
+fn check_notional(notional: i64) -> Result<(), (StatusCode, Json<String>)> {
+    if notional > i32::MAX as i64 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("This order is too large.")),
+        ));
+    }
+    Ok(())
+}
+
+/// Log a store operation of a trade that failed and turn it into a 500. `action` completes
+/// "Error ...", e.g. "recording transaction".
+fn trade_failed(action: &'static str) -> impl FnOnce(StoreError) -> (StatusCode, Json<String>) {
+    move |e| {
+        tracing::error!("Error {}: {}", action, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(String::from("Error completing trade")),
+        )
+    }
+}
+
+/// Record a trade's transaction and count it against the account.
+async fn record_trade(
+    store: &dyn Store,
+    account_id: &str,
+    transaction: Transaction,
+) -> Result<(), (StatusCode, Json<String>)> {
+    store
+        .add_transaction(transaction)
+        .await
+        .map_err(trade_failed("recording transaction"))?;
+    store
+        .increment_trades_count(account_id)
+        .await
+        .map_err(trade_failed("counting trade"))?;
+    store
+        .increment_account_version(account_id)
+        .await
+        .map_err(trade_failed("updating account version"))
+}
+
+/// Apply a buy of `quantity` shares at `price` each to an account: charge the cash and fee,
+/// update or open the holding, and record the transaction. The caller owns the store transaction.
+pub(crate) async fn apply_buy(
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    symbol: &str,
+    quantity: i32,
+    price: SharePrice,
+    profile: &FinnhubProfile,
+    note: Option<String>,
+) -> Result<Transaction, (StatusCode, Json<String>)> {
+    let TradeContext {
+        store,
+        config,
+        ids,
+        clock,
+        buying_power_multiplier,
+        reserved_cash,
+    } = *ctx;
+    let total_cost = price.value(quantity as i64);
+    check_notional(total_cost)?;
+
+    // Check if account has enough cash
+    // Update account cash
+    // Update or insert holding
+    // Record transaction
+    // Return transaction
+
+    let mut account = load_trade_account(store, account_id).await?;
+
+    // Fees depend on how many trades the account has already made
+    let fee = config.fee_model.fee(account.trades_count, total_cost);
+    let total_cost = total_cost + fee;
+
+    if account.buying_power(buying_power_multiplier) - reserved_cash < total_cost {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from(
+                "You don't have enough cash to complete this trade.",
+            )),
+        ));
+    }
+
+    // Leveraged buys may borrow; anything else must not take cash below zero
+    let cash = account.cash as i64 - total_cost;
+    account.cash = match buying_power_multiplier > 1.0 {
+        true => cash,
+        false => checked_cash(config, account_id, account.cash as i64, cash),
+    } as i32;
+
+    store
+        .update_account(account_id, account.value as i64, account.cash as i64)
+        .await
+        .map_err(trade_failed("updating account cash"))?;
+    // update holdings
+    let holding = store
+        .get_holding(account_id, symbol)
+        .await
+        .map_err(trade_failed("fetching holding"))?;
+    // A holding left empty by an earlier failure is replaced rather than kept alongside the new one
+    if holding.as_ref().is_some_and(|h| h.quantity <= 0) {
+        store
+            .delete_holding(account_id, symbol)
+            .await
+            .map_err(trade_failed("deleting empty holding"))?;
+    }
+    let holding = holding.unwrap_or_default();
+    if holding.quantity > 0 {
+        let new_quantity = holding.quantity + quantity;
+        let new_price = SharePrice::from_tenths(
+            (holding.purchase_share_price().tenths() * holding.quantity as i64
+                + price.tenths() * quantity as i64)
+                / new_quantity as i64,
+        );
+
+        store
+            .update_holding(account_id, symbol, new_quantity as i64, new_price)
+            .await
+            .map_err(trade_failed("updating holding"))?;
+    } else {
+        // insert holding
+        let mut holding = crate::models::Holding {
+            account_id: account_id.to_string(),
+            stock_symbol: symbol.to_string(),
+            stock_name: profile.display_name(symbol),
+            quantity,
+            asset_type: profile.asset_type(),
+            ..Default::default()
+        };
+        holding.set_purchase_price(price);
+        holding.set_current_price(price);
+        store
+            .add_holding(holding)
+            .await
+            .map_err(trade_failed("adding holding"))?;
+    }
+
+    // Record transaction
+    let transaction = Transaction {
+        id: ids.next_id(),
+        account_id: account_id.to_string(),
+        stock_symbol: symbol.to_string(),
+        transaction_type: String::from("BUY"),
+        quantity,
+        price: price.cents(),
+        price_tenths: price.sub_cent(),
+        timestamp: format_utc(clock.now()),
+        fee: fee as i32,
+        realized_pnl_cents: None,
+        note,
+    };
+    record_trade(store, account_id, transaction.clone()).await?;
+
+    Ok(transaction)
+}
+
+/// Apply a sale of `quantity` shares at `price` each to an account: credit the proceeds
+/// less fees, reduce or close the holding, and record the transaction with its realized P&L. The
+/// caller owns the store transaction.
+pub(crate) async fn apply_sell(
+    ctx: &TradeContext<'_>,
+    account_id: &str,
+    symbol: &str,
+    quantity: i32,
+    price: SharePrice,
+    note: Option<String>,
+) -> Result<Transaction, (StatusCode, Json<String>)> {
+    let TradeContext {
+        store,
+        config,
+        ids,
+        clock,
+        ..
+    } = *ctx;
+    let total_value = price.value(quantity as i64);
+    check_notional(total_value)?;
+
+    // Check if account has enough shares
+    // Update account cash
+    // Update holdings
+    // Record transaction
+    // Return transaction
+
+    let mut account = load_trade_account(store, account_id).await?;
+
+    let holding = store
+        .get_holding(account_id, symbol)
+        .await
+        .map_err(trade_failed("fetching holding"))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(String::from("You cannot sell a stock you do not own.")),
+        ))?;
+    let current_quantity = holding.quantity;
+
+    if current_quantity < quantity {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(String::from("You cannot sell more shares than you own.")),
+        ));
+    }
+
+    let fee = config.fee_model.fee(account.trades_count, total_value);
+    let cash = account.cash as i64 + total_value - fee;
+    account.cash = checked_cash(config, account_id, account.cash as i64, cash) as i32;
+    store
+        .update_account(account_id, account.value as i64, account.cash as i64)
+        .await
+        .map_err(trade_failed("updating account cash"))?;
+
+    let new_quantity = current_quantity - quantity;
+    if new_quantity <= 0 {
+        store
+            .delete_holding(account_id, symbol)
+            .await
+            .map_err(trade_failed("deleting holding"))?;
+    } else {
+        store
+            .update_holding(
+                account_id,
+                symbol,
+                new_quantity as i64,
+                holding.purchase_share_price(),
+            )
+            .await
+            .map_err(trade_failed("updating holding"))?;
+    }
+
+    let transaction = Transaction {
+        id: ids.next_id(),
+        account_id: account_id.to_string(),
+        stock_symbol: symbol.to_string(),
+        transaction_type: String::from("SELL"),
+        quantity,
+        price: price.cents(),
+        price_tenths: price.sub_cent(),
+        timestamp: format_utc(clock.now()),
+        fee: fee as i32,
+        realized_pnl_cents: Some(
+            (total_value - fee - holding.purchase_share_price().value(quantity as i64)) as i32,
+        ),
+        note,
+    };
+    record_trade(store, account_id, transaction.clone()).await?;
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::test_session;
+    use crate::clock::FixedClock;
+    use crate::fees::FeeModel;
+    use crate::finnhub::mock;
+    use crate::ids::SequentialIds;
+    use crate::models::AssetType;
+    use crate::store::MemoryStore;
+
+    const ACCOUNT: &str = "a@example.com";
+
+    /// An account with `cash` cents in a fresh in-memory store, and what trades against it need.
+    struct Fixture {
+        store: Arc<MemoryStore>,
+        config: Config,
+        ids: SequentialIds,
+        clock: FixedClock,
+        buying_power_multiplier: f64,
+    }
+
+    impl Fixture {
+        async fn new(cash: i64) -> Self {
+            Self::with_config(cash, Config::for_tests()).await
+        }
+
+        async fn with_config(cash: i64, config: Config) -> Self {
+            let store = Arc::new(MemoryStore::new());
+            store
+                .add_account(Account::open(ACCOUNT, cash, true))
+                .await
+                .unwrap();
+            Fixture {
+                store,
+                config,
+                ids: SequentialIds::new("txn"),
+                clock: FixedClock(
+                    DateTime::parse_from_rfc3339("2024-03-05T15:00:00Z")
+                        .unwrap()
+                        .with_timezone(&Utc),
+                ),
+                buying_power_multiplier: 1.0,
+            }
+        }
+
+        fn ctx(&self) -> TradeContext<'_> {
+            TradeContext {
+                store: self.store.as_ref(),
+                config: &self.config,
+                ids: &self.ids,
+                clock: &self.clock,
+                buying_power_multiplier: self.buying_power_multiplier,
+                reserved_cash: 0,
+            }
+        }
+
+        async fn buy(
+            &self,
+            symbol: &str,
+            quantity: i32,
+            price: impl Into<SharePrice>,
+        ) -> Transaction {
+            self.buy_profiled(&profile(symbol, "Apple Inc"), quantity, price)
+                .await
+        }
+
+        /// Buy the stock `profile` describes.
+        async fn buy_profiled(
+            &self,
+            profile: &FinnhubProfile,
+            quantity: i32,
+            price: impl Into<SharePrice>,
+        ) -> Transaction {
+            apply_buy(
+                &self.ctx(),
+                ACCOUNT,
+                &profile.ticker,
+                quantity,
+                price.into(),
+                profile,
+                None,
+            )
+            .await
+            .unwrap()
+        }
+
+        async fn sell(
+            &self,
+            symbol: &str,
+            quantity: i32,
+            price: impl Into<SharePrice>,
+        ) -> Transaction {
+            apply_sell(&self.ctx(), ACCOUNT, symbol, quantity, price.into(), None)
+                .await
+                .unwrap()
+        }
+
+        async fn cash(&self) -> i32 {
+            self.store.get_account(ACCOUNT).await.unwrap().unwrap().cash
+        }
+
+        async fn holding(&self, symbol: &str) -> Option<crate::models::Holding> {
+            self.store.get_holding(ACCOUNT, symbol).await.unwrap()
+        }
+    }
+
+    fn profile(symbol: &str, name: &str) -> FinnhubProfile {
+        FinnhubProfile {
+            ticker: symbol.to_string(),
+            name: name.to_string(),
+            finnhub_industry: String::from("Technology"),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn buying_charges_cash_and_opens_a_holding() {
+        let fixture = Fixture::new(100_000).await;
+        let transaction = fixture.buy("AAPL", 3, 15_000).await;
+
+        assert_eq!(transaction.transaction_type, "BUY");
+        assert_eq!(transaction.quantity, 3);
+        assert_eq!(fixture.cash().await, 55_000);
+        let holding = fixture.holding("AAPL").await.unwrap();
+        assert_eq!(holding.quantity, 3);
+        assert_eq!(holding.purchase_price, 15_000);
+        assert_eq!(holding.stock_name, "Apple Inc");
+        let transactions = fixture.store.get_transactions(ACCOUNT).await.unwrap();
+        assert_eq!(transactions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn buying_more_averages_the_purchase_price() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 1, 10_000).await;
+        fixture.buy("AAPL", 3, 20_000).await;
+
+        let holding = fixture.holding("AAPL").await.unwrap();
+        assert_eq!(holding.quantity, 4);
+        assert_eq!(holding.purchase_price, 17_500);
+        assert_eq!(fixture.cash().await, 30_000);
+    }
+
+    #[tokio::test]
+    async fn buys_beyond_the_cash_are_refused_without_changes() {
+        let fixture = Fixture::new(10_000).await;
+        let (status, _) = apply_buy(
+            &fixture.ctx(),
+            ACCOUNT,
+            "AAPL",
+            2,
+            6_000.into(),
+            &profile("AAPL", "Apple Inc"),
+            None,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(fixture.cash().await, 10_000);
+        assert!(fixture.holding("AAPL").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn selling_credits_proceeds_and_reduces_the_holding() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 5, 10_000).await;
+        let transaction = fixture.sell("AAPL", 2, 12_000).await;
+
+        assert_eq!(transaction.transaction_type, "SELL");
+        assert_eq!(fixture.cash().await, 74_000);
+        assert_eq!(fixture.holding("AAPL").await.unwrap().quantity, 3);
+    }
+
+    #[tokio::test]
+    async fn selling_everything_removes_the_holding() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 3, 10_000).await;
+        fixture.sell("AAPL", 3, 10_000).await;
+
+        assert!(fixture.holding("AAPL").await.is_none());
+        assert_eq!(fixture.cash().await, 100_000);
+    }
+
+    #[tokio::test]
+    async fn buying_replaces_a_holding_left_at_zero_shares() {
+        let fixture = Fixture::new(100_000).await;
+        fixture
+            .store
+            .add_holding(crate::models::Holding {
+                account_id: String::from(ACCOUNT),
+                stock_symbol: String::from("AAPL"),
+                quantity: 0,
+                purchase_price: 99_999,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        fixture.buy("AAPL", 2, 10_000).await;
+
+        let holdings = fixture.store.get_holdings(ACCOUNT).await.unwrap();
+        assert_eq!(holdings.len(), 1);
+        assert_eq!(holdings[0].quantity, 2);
+        assert_eq!(holdings[0].purchase_price, 10_000);
+    }
+
+    #[tokio::test]
+    async fn selling_more_than_held_is_refused() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 1, 10_000).await;
+        let (status, _) = apply_sell(&fixture.ctx(), ACCOUNT, "AAPL", 2, 10_000.into(), None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(fixture.holding("AAPL").await.unwrap().quantity, 1);
+    }
+
+    #[test]
+    fn notional_buys_whole_shares() {
+        let config = Config::for_tests();
+        assert_eq!(notional_shares(&config, 10_000, 3_000.into()).unwrap(), 3);
+    }
+
+    #[test]
+    fn notional_buys_whole_lots() {
+        let config = Config {
+            round_lot: Some(100),
+            ..Config::for_tests()
+        };
+        assert_eq!(
+            notional_shares(&config, 1_000_000, 3_000.into()).unwrap(),
+            300
+        );
+    }
+
+    #[test]
+    fn notional_rejects_a_zero_quote() {
+        let config = Config::for_tests();
+        let (status, _) = notional_shares(&config, 10_000, 0.into()).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn notional_rejects_non_positive_amounts() {
+        let config = Config::for_tests();
+        for amount in [0, -10_000] {
+            let (status, _) = notional_shares(&config, amount, 3_000.into()).unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn holdings_take_their_asset_type_from_the_profile() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 1, 10_000).await;
+        let fund = FinnhubProfile {
+            ticker: String::from("SPY"),
+            name: String::from("SPDR S&P 500 ETF Trust"),
+            ..Default::default()
+        };
+        fixture.buy_profiled(&fund, 1, 10_000).await;
+
+        let stock = fixture.holding("AAPL").await.unwrap();
+        assert_eq!(stock.asset_type, AssetType::Stock);
+        let etf = fixture.holding("SPY").await.unwrap();
+        assert_eq!(etf.asset_type, AssetType::Etf);
+    }
+
+    #[tokio::test]
+    async fn crypto_pairs_are_priced_from_crypto_candles_and_bought() {
+        let _finnhub = mock::start().await;
+        let pair = "BINANCE:TESTUSDT";
+        mock::respond(
+            "/crypto/candle",
+            pair,
+            r#"{"c":[250.0,300.0],"o":[240.0,250.0],"t":[1,2],"s":"ok"}"#,
+        );
+        let fixture = Fixture::new(100_000).await;
+
+        let (price, profile) = fetch_buy_quote(&fixture.config, fixture.clock.0, pair)
+            .await
+            .unwrap();
+        assert_eq!(price, SharePrice::from_cents(30_000));
+        assert_eq!(mock::calls("/crypto/candle", pair), 1);
+        assert_eq!(mock::calls("/quote", pair), 0);
+
+        fixture.buy_profiled(&profile, 2, price).await;
+        let holding = fixture.holding(pair).await.unwrap();
+        assert_eq!(holding.asset_type, AssetType::Crypto);
+        assert_eq!(fixture.cash().await, 40_000);
+    }
+
+    #[tokio::test]
+    async fn fees_start_after_the_free_trades() {
+        let config = Config {
+            fee_model: FeeModel {
+                commission: 100,
+                notional_bps: 0,
+                free_trades: 2,
+            },
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(100_000, config).await;
+
+        let fees: Vec<i32> = [
+            fixture.buy("AAPL", 1, 1_000).await,
+            fixture.buy("AAPL", 1, 1_000).await,
+            fixture.buy("AAPL", 1, 1_000).await,
+        ]
+        .iter()
+        .map(|t| t.fee)
+        .collect();
+
+        assert_eq!(fees, [0, 0, 100]);
+        assert_eq!(fixture.cash().await, 100_000 - 3_000 - 100);
+    }
+
+    #[tokio::test]
+    async fn quoted_costs_match_what_the_buy_charges() {
+        let _finnhub = mock::start().await;
+        mock::stock("COSTA", "Cost A", 123.45);
+        let config = Config {
+            fee_model: FeeModel {
+                commission: 100,
+                notional_bps: 25,
+                free_trades: 0,
+            },
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(100_000, config.clone()).await;
+
+        let (_, Json(cost)) = get_trade_cost(
+            State(fixture.store.clone() as Arc<dyn Store>),
+            State(GuestStores::new(0)),
+            State(Arc::new(config)),
+            State(RecentSymbols::new()),
+            test_session(ACCOUNT, Scope::all()).await,
+            Query(TradeCostQuery {
+                symbol: String::from("COSTA"),
+                quantity: 3,
+                side: TradeSide::Buy,
+            }),
+        )
         .await
         .unwrap();
+        let transaction = fixture.buy("COSTA", 3, cost.price).await;
 
-        Ok(Transaction {
-            id: transaction_id,
-            account_id: s,
-            stock_symbol: trade.stock_symbol,
-            transaction_type: String::from("SELL"),
-            quantity: trade.quantity,
-            price: stock_price,
-            timestamp: chrono::Local::now().to_rfc3339(),
-        })
+        assert_eq!(cost.price, 12_345);
+        assert_eq!(transaction.fee as i64, cost.fee);
+        assert_eq!(100_000 - fixture.cash().await as i64, cost.total);
+        assert!(cost.sufficient);
     }
-    .await;
 
-    match result {
-        Ok(transaction) => {
-            session.commit_transaction().await.unwrap();
-            Ok((StatusCode::CREATED, Json(transaction)))
+    #[tokio::test]
+    async fn itemized_fees_sum_to_what_the_buy_charges() {
+        let _finnhub = mock::start().await;
+        mock::stock("FEEQ", "Fee Quote", 80.0);
+        let config = Config {
+            fee_model: FeeModel {
+                commission: 100,
+                notional_bps: 25,
+                free_trades: 1,
+            },
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(100_000, config.clone()).await;
+        let config = Arc::new(config);
+
+        // The first trade is free, the second pays the full fees
+        for expected in [0, 140] {
+            let (_, Json(quote)) = get_fee_quote(
+                State(fixture.store.clone() as Arc<dyn Store>),
+                State(GuestStores::new(0)),
+                State(config.clone()),
+                test_session(ACCOUNT, Scope::all()).await,
+                Query(TradeCostQuery {
+                    symbol: String::from("FEEQ"),
+                    quantity: 2,
+                    side: TradeSide::Buy,
+                }),
+            )
+            .await
+            .unwrap();
+            let fees = quote.fees;
+            let transaction = fixture.buy("FEEQ", 2, quote.price).await;
+
+            assert_eq!(
+                fees.commission + fees.notional_fee - fees.promotion_discount,
+                fees.total
+            );
+            assert_eq!(fees.total, expected);
+            assert_eq!(transaction.fee as i64, fees.total);
         }
-        Err(e) => {
-            session.abort_transaction().await.unwrap();
-            Err(e)
+    }
+
+    #[tokio::test]
+    async fn a_fee_taking_cash_negative_is_counted_and_clamped_per_config() {
+        for (clamp, expected) in [(false, -90), (true, 0)] {
+            let config = Config {
+                fee_model: FeeModel {
+                    commission: 100,
+                    notional_bps: 0,
+                    free_trades: 0,
+                },
+                clamp_negative_cash: clamp,
+                ..Config::for_tests()
+            };
+            let fixture = Fixture::with_config(10_100, config).await;
+            fixture.buy("AAPL", 1, 10_000).await;
+            assert_eq!(fixture.cash().await, 0);
+
+            // Proceeds of 10 cents don't cover the 100 cent commission
+            let events = crate::cash::negative_cash_events();
+            fixture.sell("AAPL", 1, 10).await;
+            assert_eq!(fixture.cash().await, expected);
+            assert!(crate::cash::negative_cash_events() > events);
+
+            // Without positive cash there is no buying power left
+            let (status, _) = apply_buy(
+                &fixture.ctx(),
+                ACCOUNT,
+                "AAPL",
+                1,
+                1.into(),
+                &profile("AAPL", "Apple Inc"),
+                None,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn selling_shares_bought_cheaper_realizes_a_gain() {
+        let fixture = Fixture::new(100_000).await;
+        fixture.buy("AAPL", 2, 10_000).await;
+        fixture.buy("AAPL", 2, 12_000).await;
+
+        let transaction = fixture.sell("AAPL", 3, 15_000).await;
+
+        // Average cost is $110, so each share sold gains $40
+        assert_eq!(transaction.realized_pnl_cents, Some(12_000));
+    }
+
+    #[test]
+    fn order_sizes_are_bounded_at_both_ends() {
+        let config = Config {
+            min_shares: 5,
+            max_shares: 100,
+            ..Config::for_tests()
+        };
+
+        let (status, Json(message)) = check_order_size(&config, 4).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "Orders must be for at least 5 shares.");
+        assert!(check_order_size(&config, 5).is_ok());
+        assert!(check_order_size(&config, 100).is_ok());
+        let (status, Json(message)) = check_order_size(&config, 101).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "Orders must be for at most 100 shares.");
+    }
+
+    #[test]
+    fn round_lots_refuse_odd_lots() {
+        let config = Config {
+            round_lot: Some(100),
+            max_shares: 1_000,
+            ..Config::for_tests()
+        };
+
+        let (status, Json(message)) = check_order_size(&config, 150).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "Orders must be for a multiple of 100 shares.");
+        assert!(check_order_size(&config, 200).is_ok());
+    }
+
+    #[tokio::test]
+    async fn trade_notes_are_stored_with_the_transaction() {
+        let fixture = Fixture::new(100_000).await;
+        apply_buy(
+            &fixture.ctx(),
+            ACCOUNT,
+            "AAPL",
+            1,
+            10_000.into(),
+            &profile("AAPL", "Apple Inc"),
+            Some(String::from("Earnings beat")),
+        )
+        .await
+        .unwrap();
+        fixture.buy("AAPL", 1, 10_000).await;
+
+        let transactions = fixture.store.get_transactions(ACCOUNT).await.unwrap();
+        assert_eq!(transactions[0].note.as_deref(), Some("Earnings beat"));
+        assert_eq!(transactions[1].note, None);
+        assert!(check_note(&Some("x".repeat(MAX_NOTE_LENGTH))).is_ok());
+        assert!(check_note(&Some("x".repeat(MAX_NOTE_LENGTH + 1))).is_err());
+    }
+
+    #[tokio::test]
+    async fn buys_may_not_dip_into_the_cash_reserve() {
+        let fixture = Fixture::new(100_000).await;
+        let settings = AccountSettings {
+            min_cash_reserve_cents: 30_000,
+            ..Default::default()
+        };
+
+        let (status, Json(message)) = check_cash_reserve(
+            &settings,
+            fixture.store.as_ref(),
+            &fixture.config,
+            ACCOUNT,
+            70_001,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            message,
+            "This trade would take your cash below your reserve of $300.00."
+        );
+        check_cash_reserve(
+            &settings,
+            fixture.store.as_ref(),
+            &fixture.config,
+            ACCOUNT,
+            70_000,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn transactions_take_ids_from_the_injected_generator() {
+        let fixture = Fixture::new(100_000).await;
+
+        assert_eq!(fixture.buy("AAPL", 2, 10_000).await.id, "txn-1");
+        assert_eq!(fixture.sell("AAPL", 1, 10_000).await.id, "txn-2");
+    }
+
+    #[tokio::test]
+    async fn transactions_are_stamped_with_the_injected_time() {
+        let fixture = Fixture::new(100_000).await;
+
+        let transaction = fixture.buy("AAPL", 1, 10_000).await;
+
+        assert_eq!(transaction.timestamp, "2024-03-05T15:00:00.000Z");
+        assert_eq!(transaction.timestamp, format_utc(fixture.clock.now()));
+    }
+
+    #[tokio::test]
+    async fn a_blank_profile_name_stores_the_symbol() {
+        let fixture = Fixture::new(100_000).await;
+
+        fixture
+            .buy_profiled(&profile("BLNK", "  "), 1, 10_000)
+            .await;
+
+        let stored = fixture.holding("BLNK").await.unwrap().stock_name;
+        assert_eq!(stored, "BLNK");
+        // A later refresh backfills the real name, but a blank one never replaces it
+        assert_eq!(
+            profile("BLNK", "Blink Inc").rename("BLNK", &stored),
+            Some(String::from("Blink Inc"))
+        );
+        assert_eq!(profile("BLNK", "").rename("BLNK", "Blink Inc"), None);
+    }
+
+    #[tokio::test]
+    async fn buys_of_a_halted_symbol_are_refused() {
+        let _finnhub = mock::start().await;
+        mock::stock("HALTH", "Halted", 50.0);
+        mock::stock("HALTN", "Trading", 50.0);
+        // Updated an hour before and at the fixture's 10:00 Eastern
+        mock::respond(
+            "/quote",
+            "HALTH",
+            r#"{"c":50.0,"d":1.0,"dp":2.0,"pc":49.0,"t":1709647200}"#,
+        );
+        mock::respond(
+            "/quote",
+            "HALTN",
+            r#"{"c":50.0,"d":1.0,"dp":2.0,"pc":49.0,"t":1709650800}"#,
+        );
+        let config = Config {
+            halt_stale_after_secs: Some(600),
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(100_000, config).await;
+        let now = fixture.clock.now();
+
+        let Err((status, _)) = fetch_buy_quote(&fixture.config, now, "HALTH").await else {
+            panic!("a halted symbol was quoted for a buy");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (price, profile) = fetch_buy_quote(&fixture.config, now, "HALTN")
+            .await
+            .unwrap();
+        fixture.buy_profiled(&profile, 1, price).await;
+        assert_eq!(fixture.holding("HALTN").await.unwrap().quantity, 1);
+    }
+
+    #[tokio::test]
+    async fn buys_while_closed_are_queued_and_fill_at_the_open() {
+        let _finnhub = mock::start().await;
+        mock::stock("OPENQ", "Open Queue", 21.5);
+        let config = Config {
+            closed_market_orders: ClosedMarketOrders::Queue,
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(100_000, config).await;
+        // 18:00 Eastern, then 09:30 Eastern the next day
+        let closed = DateTime::parse_from_rfc3339("2024-03-05T23:00:00Z")
+            .unwrap()
+            .to_utc();
+        let open = DateTime::parse_from_rfc3339("2024-03-06T14:30:00Z")
+            .unwrap()
+            .to_utc();
+
+        assert!(must_queue(&fixture.config, closed, "OPENQ").unwrap());
+        assert!(!must_queue(&fixture.config, open, "OPENQ").unwrap());
+
+        // The order job fills it at the quote once the session opens
+        let (quote, profile) = fetch_buy_quote(&fixture.config, open, "OPENQ")
+            .await
+            .unwrap();
+        let price = fill_price(&fixture.config, TradeSide::Buy, "OPENQ", 2, quote);
+        let transaction = fixture.buy_profiled(&profile, 2, price).await;
+        assert_eq!(transaction.price, 2_150);
+        assert_eq!(fixture.cash().await, 95_700);
+    }
+
+    #[test]
+    fn closed_market_trades_are_refused_in_reject_mode() {
+        let config = Config {
+            closed_market_orders: ClosedMarketOrders::Reject,
+            ..Config::for_tests()
+        };
+        let saturday = DateTime::parse_from_rfc3339("2024-03-09T15:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let (status, _) = must_queue(&config, saturday, "AAPL").unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(!must_queue(&config, saturday, "BINANCE:BTCUSDT").unwrap());
+    }
+
+    #[test]
+    fn unaffordable_orders_fail_validation_with_insufficient_funds() {
+        let account = Account::open(ACCOUNT, 10_000, true);
+
+        let error = check_funds(&account, 1.0, 0, 10_001).unwrap();
+        assert_eq!(error.code, ValidationCode::InsufficientFunds);
+        assert_eq!(
+            serde_json::to_value(error.code).unwrap(),
+            "INSUFFICIENT_FUNDS"
+        );
+        assert!(check_funds(&account, 1.0, 0, 10_000).is_none());
+        // Cash reserved for queued buys can't be spent again
+        assert!(check_funds(&account, 1.0, 1, 10_000).is_some());
+    }
+
+    #[tokio::test]
+    async fn the_day_trade_past_the_limit_flags_the_account() {
+        let config = Config {
+            day_trade_limit: 3,
+            ..Config::for_tests()
+        };
+        let mut fixture = Fixture::with_config(100_000, config).await;
+        async fn check(fixture: &Fixture) -> Result<Option<usize>, (StatusCode, Json<String>)> {
+            let today = fixture.clock.now().date_naive();
+            check_day_trade(
+                fixture.store.as_ref(),
+                &fixture.config,
+                ACCOUNT,
+                "AAPL",
+                today,
+            )
+            .await
+        }
+
+        assert_eq!(check(&fixture).await.unwrap(), None);
+        fixture.buy("AAPL", 4, 10_000).await;
+        for nth in 1..=3 {
+            assert_eq!(check(&fixture).await.unwrap(), Some(nth));
+            fixture.sell("AAPL", 1, 10_000).await;
         }
+        assert_eq!(check(&fixture).await.unwrap(), Some(4));
+
+        // Below the equity floor, the flagged account may not make it
+        fixture.config.day_trade_min_equity = Some(1_000_000);
+        let (status, _) = check(&fixture).await.unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn a_2x_multiplier_buys_up_to_twice_the_cash() {
+        let mut fixture = Fixture::new(10_000).await;
+        fixture.buying_power_multiplier = 2.0;
+        let (status, _) = apply_buy(
+            &fixture.ctx(),
+            ACCOUNT,
+            "AAPL",
+            3,
+            7_000.into(),
+            &profile("AAPL", "Apple Inc"),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(fixture.cash().await, 10_000);
+
+        fixture.buy("AAPL", 2, 10_000).await;
+        let account = fixture.store.get_account(ACCOUNT).await.unwrap().unwrap();
+        assert_eq!(account.cash, -10_000);
+        assert_eq!(account.borrowed(), 10_000);
+    }
+
+    #[test]
+    fn multipliers_are_capped_by_the_config() {
+        let config = Config {
+            max_buying_power_multiplier: 2.0,
+            ..Config::for_tests()
+        };
+        let settings = |multiplier| AccountSettings {
+            buying_power_multiplier: multiplier,
+            ..Default::default()
+        };
+
+        assert_eq!(buying_power_multiplier(&config, &settings(1.5)), 1.5);
+        assert_eq!(buying_power_multiplier(&config, &settings(4.0)), 2.0);
+        assert_eq!(buying_power_multiplier(&config, &settings(0.5)), 1.0);
+    }
+
+    #[test]
+    fn a_sell_is_refused_while_a_buy_is_queued_until_it_is_cancelled() {
+        let config = Config {
+            pending_buy_sells: PendingBuySells::Reject,
+            ..Config::for_tests()
+        };
+        let order = |id: &str, side| QueuedOrder {
+            id: id.to_string(),
+            account_id: String::from(ACCOUNT),
+            stock_symbol: String::from("PEND"),
+            side,
+            quantity: 5,
+            reserved: 5_000,
+            note: None,
+            placed_at: String::from("2024-03-05T22:00:00Z"),
+        };
+        let mut orders = vec![order("buy", TradeSide::Buy), order("sell", TradeSide::Sell)];
+
+        let Err((status, _)) = pending_buy_conflict(&config, &orders, ACCOUNT, "pend") else {
+            panic!("sell allowed with a buy queued");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // Cancelling the buy drops it from the queue
+        orders.retain(|o| o.id != "buy");
+        assert!(pending_buy_conflict(&config, &orders, ACCOUNT, "PEND").is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_sell_inside_the_lockup_says_how_long_remains() {
+        let fixture = Fixture::with_config(
+            100_000,
+            Config {
+                sell_lockup_secs: Some(60),
+                ..Config::for_tests()
+            },
+        )
+        .await;
+        fixture.buy("LOCKUP", 1, 1_000).await;
+        let at = |secs| fixture.clock.0 + chrono::Duration::seconds(secs);
+
+        let Err((status, message)) = check_lockup(
+            fixture.store.as_ref(),
+            &fixture.config,
+            ACCOUNT,
+            "lockup",
+            at(20),
+        )
+        .await
+        else {
+            panic!("sell allowed inside the lockup");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(message.0.ends_with("Try again in 40s."));
+
+        assert!(check_lockup(
+            fixture.store.as_ref(),
+            &fixture.config,
+            ACCOUNT,
+            "LOCKUP",
+            at(60)
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_sub_cent_quote_refuses_a_sell_rather_than_filling_at_nothing() {
+        let _finnhub = mock::start().await;
+        mock::respond(
+            "/quote",
+            "SUBCENT",
+            r#"{"c":0.004,"d":0.0,"dp":0.0,"pc":0.004,"t":0}"#,
+        );
+        let query = TradeCostQuery {
+            symbol: String::from("SUBCENT"),
+            quantity: 100,
+            side: TradeSide::Sell,
+        };
+
+        let (status, Json(message)) = price_hypothetical(&Config::for_tests(), &query)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "No quote is available for SUBCENT.");
+    }
+
+    #[tokio::test]
+    async fn trades_for_a_missing_account_or_holding_fail_without_panicking() {
+        let fixture = Fixture::new(100_000).await;
+        let ctx = fixture.ctx();
+        let profile = profile("GHOST", "Ghost Inc");
+
+        let (status, _) = apply_buy(
+            &ctx,
+            "nobody@example.com",
+            "GHOST",
+            1,
+            1_000.into(),
+            &profile,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = apply_sell(&ctx, "nobody@example.com", "GHOST", 1, 1_000.into(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, Json(message)) = apply_sell(&ctx, ACCOUNT, "GHOST", 1, 1_000.into(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(message, "You cannot sell a stock you do not own.");
+    }
+
+    #[tokio::test]
+    async fn guest_buys_never_touch_mongodb() {
+        let _finnhub = mock::start().await;
+        mock::stock("GUESTB", "Guest Buy", 10.0);
+        let shared = Arc::new(MemoryStore::new());
+        // The state's pool can't connect, so any MongoDB read fails the buy
+        let state = AppState::for_tests(shared.clone(), Config::for_tests()).await;
+        let session = test_session("guest-3", Scope::all()).await;
+        session.insert(GUEST_KEY, true).await.unwrap();
+
+        let response = buy_stock(
+            State(state.clone()),
+            session,
+            Json(TradeRequest {
+                stock_symbol: String::from("GUESTB"),
+                quantity: 2,
+                notional: None,
+                note: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let guest = state.guests.get_or_create("guest-3").await;
+        let holding = guest.get_holding("guest-3", "GUESTB").await.unwrap();
+        assert_eq!(holding.unwrap().quantity, 2);
+        assert!(shared.get_accounts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sub_cent_quotes_fill_and_hold_to_a_tenth_of_a_cent() {
+        let _finnhub = mock::start().await;
+        mock::stock("PENNY", "Penny Co", 0.0123);
+        let config = Config {
+            sub_cent_pricing_below: Some(100),
+            ..Config::for_tests()
+        };
+        let fixture = Fixture::with_config(1_000_000, config).await;
+
+        let (quote, profile) = fetch_buy_quote(&fixture.config, fixture.clock.0, "PENNY")
+            .await
+            .unwrap();
+        let price = fill_price(&fixture.config, TradeSide::Buy, "PENNY", 100_000, quote);
+        let transaction = fixture.buy_profiled(&profile, 100_000, price).await;
+
+        // 1.2 cents a share rather than the truncated 1 cent
+        assert_eq!(transaction.price, 1);
+        assert_eq!(transaction.price_tenths, Some(12));
+        assert_eq!(fixture.cash().await, 1_000_000 - 120_000 - transaction.fee);
+        let holding = fixture.holding("PENNY").await.unwrap();
+        assert_eq!(holding.current_price_tenths, Some(12));
+        assert_eq!(holding.total_value, 120_000);
+        assert_eq!(
+            holding.current_share_price().value(holding.quantity as i64),
+            holding.total_value as i64
+        );
     }
 }
@@ -0,0 +1,148 @@
+use crate::models::{Transaction, TransactionSummary};
+use crate::pnl::{parse_timestamp, sort_chronologically, Position};
+use crate::store::{Store, StoreError};
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Periodically archive transactions older than `retention`.
+pub async fn run(store: Arc<dyn Store>, retention: Duration, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match archive_transactions(store.as_ref(), retention).await {
+            Ok(count) => tracing::info!("Archived {} transactions", count),
+            Err(e) => tracing::error!("Error archiving transactions: {}", e),
+        }
+    }
+}
+
+/// Move every account's transactions older than `retention` into the archive, folding them into
+/// per-symbol summaries so realized P&L is unchanged. Returns the number of archived transactions.
+pub async fn archive_transactions(
+    store: &dyn Store,
+    retention: Duration,
+) -> Result<usize, StoreError> {
+    let cutoff = Utc::now() - retention;
+    let mut archived = 0;
+
+    for account in store.get_accounts().await? {
+        let mut transactions = store.get_transactions(&account.id).await?;
+        transactions.retain(|t| parse_timestamp(t).is_some_and(|ts| ts < cutoff));
+        if transactions.is_empty() {
+            continue;
+        }
+        sort_chronologically(&mut transactions);
+
+        let summaries = fold_into_summaries(
+            &account.id,
+            store.get_transaction_summaries(&account.id).await?,
+            &transactions,
+        );
+
+        // Write the archive and summaries before deleting so a failure never loses history
+        store.archive_transactions(&transactions).await?;
+        for summary in summaries {
+            store.upsert_transaction_summary(summary).await?;
+        }
+        let ids: Vec<String> = transactions.iter().map(|t| t.id.clone()).collect();
+        store.delete_transactions(&ids).await?;
+
+        archived += transactions.len();
+    }
+
+    Ok(archived)
+}
+
+/// Apply `transactions` (oldest first) on top of the existing summaries for an account.
+fn fold_into_summaries(
+    account_id: &str,
+    existing: Vec<TransactionSummary>,
+    transactions: &[Transaction],
+) -> Vec<TransactionSummary> {
+    let mut summaries: HashMap<String, TransactionSummary> = existing
+        .into_iter()
+        .map(|s| (s.stock_symbol.clone(), s))
+        .collect();
+
+    for transaction in transactions {
+        let summary = summaries
+            .entry(transaction.stock_symbol.clone())
+            .or_insert_with(|| TransactionSummary {
+                account_id: account_id.to_string(),
+                stock_symbol: transaction.stock_symbol.clone(),
+                ..Default::default()
+            });
+        let mut position = Position::from_summary(summary);
+        position.apply(transaction);
+        summary.quantity = position.quantity;
+        summary.cost_basis = position.cost_basis;
+        summary.realized_pnl = position.realized_pnl;
+        summary.archived_count += 1;
+    }
+
+    summaries.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Account;
+    use crate::pnl::realized_pnl;
+    use crate::store::MemoryStore;
+
+    fn trade(id: &str, kind: &str, quantity: i32, price: i32, days_ago: i64) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            account_id: String::from("a@example.com"),
+            stock_symbol: String::from("AAPL"),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            price_tenths: None,
+            timestamp: (Utc::now() - Duration::days(days_ago)).to_rfc3339(),
+            fee: 0,
+            realized_pnl_cents: None,
+            note: None,
+        }
+    }
+
+    async fn realized(store: &MemoryStore) -> i64 {
+        realized_pnl(
+            &store
+                .get_transaction_summaries("a@example.com")
+                .await
+                .unwrap(),
+            &store.get_transactions("a@example.com").await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn archiving_keeps_realized_pnl() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account::open("a@example.com", 100_000, true))
+            .await
+            .unwrap();
+        for transaction in [
+            trade("1", "BUY", 10, 1_000, 400),
+            trade("2", "SELL", 4, 1_500, 300),
+            trade("3", "BUY", 2, 2_000, 10),
+            trade("4", "SELL", 4, 1_800, 5),
+        ] {
+            store.add_transaction(transaction).await.unwrap();
+        }
+        let before = realized(&store).await;
+
+        let archived = archive_transactions(&store, Duration::days(30))
+            .await
+            .unwrap();
+
+        assert_eq!(archived, 2);
+        let remaining = store.get_transactions("a@example.com").await.unwrap();
+        let mut ids: Vec<&str> = remaining.iter().map(|t| t.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["3", "4"]);
+        assert_eq!(realized(&store).await, before);
+    }
+}
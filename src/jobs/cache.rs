@@ -0,0 +1,14 @@
+use crate::finnhub::sweep_caches;
+
+/// Periodically sweep the Finnhub caches so symbols fetched once don't stay in memory forever.
+/// Caches are capped at `max_entries` each when set.
+pub async fn run(max_entries: Option<usize>, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let removed = sweep_caches(max_entries).await;
+        if removed > 0 {
+            tracing::debug!("Swept {} Finnhub cache entries", removed);
+        }
+    }
+}
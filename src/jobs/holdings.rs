@@ -0,0 +1,29 @@
+use crate::db::DatabasePool;
+use crate::locks::AccountLocks;
+
+/// Periodically purge holdings left at zero or fewer shares from every account.
+pub async fn run(pool: DatabasePool, locks: AccountLocks, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match purge_all(&pool, &locks).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Purged {} empty holdings", count),
+            Err(e) => tracing::error!("Error purging empty holdings: {}", e),
+        }
+    }
+}
+
+/// Purge every account's empty holdings, holding each account's lock so a trade in progress
+/// isn't raced. Returns the number of holdings removed.
+pub async fn purge_all(
+    pool: &DatabasePool,
+    locks: &AccountLocks,
+) -> Result<usize, mongodb::error::Error> {
+    let mut purged = 0;
+    for account in pool.get_accounts().await? {
+        let _lock = locks.lock(&account.id).await;
+        purged += pool.purge_empty_holdings(&account.id).await?.len();
+    }
+    Ok(purged)
+}
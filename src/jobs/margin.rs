@@ -0,0 +1,263 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::finnhub::fetch_price;
+use crate::handlers::trading::{apply_sell, quote_price, TradeContext};
+use crate::ids::{IdGenerator, UuidGenerator};
+use crate::locks::AccountLocks;
+use crate::models::{Holding, MarginCallRecord};
+use crate::money::SharePrice;
+use crate::store::{Store, StoreError};
+use crate::timestamps::format_utc;
+use std::sync::Arc;
+
+/// Note on forced sells, shown in the account's history.
+pub const MARGIN_CALL_NOTE: &str = "Margin call";
+
+/// Periodically sell positions of borrowing accounts that fell below the maintenance margin.
+pub async fn run(
+    store: Arc<dyn Store>,
+    config: Arc<Config>,
+    locks: AccountLocks,
+    interval: std::time::Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match check_margins(
+            store.as_ref(),
+            &config,
+            &locks,
+            &UuidGenerator,
+            &SystemClock,
+        )
+        .await
+        {
+            Ok(0) => {}
+            Ok(closed) => tracing::warn!("Margin calls closed {} positions", closed),
+            Err(e) => tracing::error!("Error checking margins: {}", e),
+        }
+    }
+}
+
+/// Equity in cents that holdings worth `market_value` require at `percent` maintenance margin.
+pub fn maintenance_requirement(market_value: i64, percent: f64) -> i64 {
+    (market_value as f64 * percent / 100.0).round() as i64
+}
+
+/// Check every borrowing account against `MAINTENANCE_MARGIN_PERCENT`, selling positions of those
+/// below it. Returns the number of positions closed.
+pub async fn check_margins(
+    store: &dyn Store,
+    config: &Config,
+    locks: &AccountLocks,
+    ids: &dyn IdGenerator,
+    clock: &dyn Clock,
+) -> Result<usize, StoreError> {
+    let Some(percent) = config.maintenance_margin_percent else {
+        return Ok(0);
+    };
+    let mut closed = 0;
+    for account in store.get_accounts().await? {
+        if account.borrowed() == 0 {
+            continue;
+        }
+        let _lock = locks.lock(&account.id).await;
+        closed += meet_margin(store, config, ids, clock, &account.id, percent).await?;
+    }
+    Ok(closed)
+}
+
+/// Sell an account's positions, largest first, until its equity meets the maintenance
+/// requirement again. Accounts with a holding that can't be quoted are left for the next check.
+/// Returns the number of positions closed.
+async fn meet_margin(
+    store: &dyn Store,
+    config: &Config,
+    ids: &dyn IdGenerator,
+    clock: &dyn Clock,
+    account_id: &str,
+    percent: f64,
+) -> Result<usize, StoreError> {
+    // The account may have traded since it was listed
+    let Some(account) = store.get_account(account_id).await? else {
+        return Ok(0);
+    };
+    if account.borrowed() == 0 {
+        return Ok(0);
+    }
+
+    let mut positions: Vec<(Holding, SharePrice)> = Vec::new();
+    for holding in store.get_holdings(account_id).await? {
+        if holding.quantity <= 0 {
+            continue;
+        }
+        let quote = fetch_price(&holding.stock_symbol).await;
+        match quote.map(|quote| quote_price(config, &holding.stock_symbol, &quote)) {
+            Ok(Ok(price)) => positions.push((holding, price)),
+            Ok(Err(_)) | Err(_) => {
+                tracing::warn!(
+                    "Skipping margin check of {}: no quote for {}",
+                    account_id,
+                    holding.stock_symbol
+                );
+                return Ok(0);
+            }
+        }
+    }
+    positions
+        .sort_by_key(|(holding, price)| std::cmp::Reverse(price.value(holding.quantity as i64)));
+
+    let mut cash = account.cash as i64;
+    let mut market_value: i64 = positions
+        .iter()
+        .map(|(holding, price)| price.value(holding.quantity as i64))
+        .sum();
+    let mut closed = 0;
+    for (holding, price) in positions {
+        let equity = cash + market_value;
+        let requirement = maintenance_requirement(market_value, percent);
+        if equity >= requirement {
+            break;
+        }
+
+        let txn = store.start_transaction().await?;
+        let ctx = TradeContext {
+            store: txn.store(),
+            config,
+            ids,
+            clock,
+            buying_power_multiplier: 1.0,
+            reserved_cash: 0,
+        };
+        let sold = apply_sell(
+            &ctx,
+            account_id,
+            &holding.stock_symbol,
+            holding.quantity,
+            price,
+            Some(String::from(MARGIN_CALL_NOTE)),
+        )
+        .await;
+        let transaction = match sold {
+            Ok(transaction) => transaction,
+            Err((status, message)) => {
+                txn.abort().await?;
+                tracing::error!(
+                    "Error selling {} for margin call on {}: {} {}",
+                    holding.stock_symbol,
+                    account_id,
+                    status,
+                    message.0
+                );
+                break;
+            }
+        };
+        let record = MarginCallRecord {
+            account_id: account_id.to_string(),
+            transaction_id: transaction.id.clone(),
+            stock_symbol: holding.stock_symbol.clone(),
+            quantity: holding.quantity,
+            price: price.cents(),
+            equity,
+            requirement,
+            closed_at: format_utc(clock.now()),
+        };
+        if let Err(e) = txn.store().add_margin_call(record).await {
+            txn.abort().await?;
+            return Err(e);
+        }
+        txn.commit().await?;
+
+        tracing::warn!(
+            "Margin call on {}: sold {} {} (equity {}, requirement {})",
+            account_id,
+            holding.quantity,
+            holding.stock_symbol,
+            equity,
+            requirement
+        );
+        let proceeds = price.value(holding.quantity as i64);
+        cash += proceeds - transaction.fee as i64;
+        market_value -= proceeds;
+        closed += 1;
+    }
+    Ok(closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::finnhub::mock;
+    use crate::ids::SequentialIds;
+    use crate::models::Account;
+    use crate::store::MemoryStore;
+    use chrono::DateTime;
+
+    const ACCOUNT: &str = "a@example.com";
+
+    fn holding(symbol: &str, quantity: i32) -> Holding {
+        Holding {
+            account_id: String::from(ACCOUNT),
+            stock_symbol: symbol.to_string(),
+            quantity,
+            purchase_price: 1_000,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn an_underwater_margin_account_has_its_largest_position_closed() {
+        let _finnhub = mock::start().await;
+        mock::stock("MARGA", "Margin A", 10.0);
+        mock::stock("MARGB", "Margin B", 5.0);
+        let store = MemoryStore::new();
+        store
+            .add_account(Account::open(ACCOUNT, -5_000, true))
+            .await
+            .unwrap();
+        store.add_holding(holding("MARGA", 10)).await.unwrap();
+        store.add_holding(holding("MARGB", 2)).await.unwrap();
+        let config = Config {
+            maintenance_margin_percent: Some(60.0),
+            ..Config::for_tests()
+        };
+        let clock = FixedClock(
+            DateTime::parse_from_rfc3339("2024-03-05T15:00:00Z")
+                .unwrap()
+                .to_utc(),
+        );
+
+        // Equity of $60 against $110 of holdings is short of the $66 required
+        let closed = check_margins(
+            &store,
+            &config,
+            &AccountLocks::new(),
+            &SequentialIds::new("txn"),
+            &clock,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(closed, 1);
+        assert!(store.get_holding(ACCOUNT, "MARGA").await.unwrap().is_none());
+        assert_eq!(
+            store
+                .get_holding(ACCOUNT, "MARGB")
+                .await
+                .unwrap()
+                .unwrap()
+                .quantity,
+            2
+        );
+        let account = store.get_account(ACCOUNT).await.unwrap().unwrap();
+        assert_eq!(account.cash, 5_000);
+        let calls = store.get_margin_calls(ACCOUNT).await.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].transaction_id, "txn-1");
+        assert_eq!((calls[0].equity, calls[0].requirement), (6_000, 6_600));
+        let transactions = store.get_transactions(ACCOUNT).await.unwrap();
+        assert_eq!(transactions[0].transaction_type, "SELL");
+        assert_eq!(transactions[0].note.as_deref(), Some(MARGIN_CALL_NOTE));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod archive;
+pub mod cache;
+pub mod holdings;
+pub mod margin;
+pub mod orders;
+pub mod profiles;
+pub mod reconcile;
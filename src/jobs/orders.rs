@@ -0,0 +1,57 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::db::DatabasePool;
+use crate::handlers::trading::execute_queued_order;
+use crate::ids::UuidGenerator;
+use crate::locks::AccountLocks;
+use crate::market_hours::is_regular_session;
+use std::sync::Arc;
+
+/// Periodically fill queued market-on-open orders once the regular session is open.
+pub async fn run(
+    pool: DatabasePool,
+    config: Arc<Config>,
+    locks: AccountLocks,
+    interval: std::time::Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if !is_regular_session(SystemClock.now()) {
+            continue;
+        }
+        match fill_queued_orders(&pool, &config, &locks).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Filled {} queued orders", count),
+            Err(e) => tracing::error!("Error filling queued orders: {}", e),
+        }
+    }
+}
+
+/// Fill every queued order, oldest first, at the current quote. Orders that can no longer fill,
+/// such as buys the account can't afford anymore, are dropped. Returns the number filled.
+pub async fn fill_queued_orders(
+    pool: &DatabasePool,
+    config: &Config,
+    locks: &AccountLocks,
+) -> Result<usize, mongodb::error::Error> {
+    let mut filled = 0;
+    for order in pool.get_queued_orders(None).await? {
+        let _lock = locks.lock(&order.account_id).await;
+        // Removing the order releases its reserved cash for the fill itself
+        if !pool
+            .delete_queued_order(&order.account_id, &order.id)
+            .await?
+        {
+            continue;
+        }
+        let id = order.id.clone();
+        match execute_queued_order(pool, config, &UuidGenerator, &SystemClock, order).await {
+            Ok(_) => filled += 1,
+            Err((status, message)) => {
+                tracing::warn!("Dropped queued order {}: {} {}", id, status, message.0)
+            }
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,70 @@
+use crate::finnhub::{expiring_profiles, refresh_stock_profile, usage, FREE_TIER_CALLS_PER_MINUTE};
+use std::time::{Duration, Instant};
+
+/// Profiles expiring within this window are refreshed ahead of time.
+pub const REFRESH_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Refresh cached profiles shortly before they expire, at most `per_minute` a minute, so
+/// requests after a mass expiry hit a warm cache instead of all calling Finnhub at once.
+pub async fn run(per_minute: u32) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(60) / per_minute.max(1));
+    loop {
+        ticker.tick().await;
+        refresh_next().await;
+    }
+}
+
+/// Refresh the profile closest to expiry, unless none are expiring or interactive requests
+/// already use most of the Finnhub quota. Returns the refreshed symbol.
+pub async fn refresh_next() -> Option<String> {
+    // Leave headroom for interactive requests
+    if usage().calls_last_minute >= FREE_TIER_CALLS_PER_MINUTE * 3 / 4 {
+        return None;
+    }
+    refresh_soonest(Instant::now()).await
+}
+
+/// Refresh the profile closest to expiry as of `now`, if any are expiring. Returns the refreshed
+/// symbol.
+async fn refresh_soonest(now: Instant) -> Option<String> {
+    let symbol = expiring_profiles(REFRESH_WINDOW, now)
+        .await
+        .into_iter()
+        .next()?;
+    match refresh_stock_profile(&symbol).await {
+        Ok(_) => {
+            tracing::debug!("Refreshed expiring profile for {}", symbol);
+            Some(symbol)
+        }
+        Err(e) => {
+            tracing::warn!("Error refreshing profile for {}: {}", symbol, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finnhub::{fetch_stock_profile, mock, peek_profile, PROFILE_TTL};
+
+    #[tokio::test]
+    async fn an_expiring_profile_is_refreshed_before_it_expires() {
+        // Profiles other tests fetch meanwhile would expire sooner than this one
+        let _finnhub = mock::exclusive().await;
+        mock::stock("AGING", "Old name", 5.0);
+        let before = Instant::now();
+        fetch_stock_profile("AGING").await.unwrap();
+        mock::stock("AGING", "Aging Inc", 5.0);
+
+        // Just before the profile expires, when anything fetched earlier already has
+        let almost_expired = before + PROFILE_TTL;
+        assert_eq!(
+            refresh_soonest(almost_expired).await.as_deref(),
+            Some("AGING")
+        );
+
+        assert_eq!(peek_profile("AGING").await.unwrap().name, "Aging Inc");
+        assert_eq!(mock::calls("/stock/profile2", "AGING"), 2);
+    }
+}
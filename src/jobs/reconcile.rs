@@ -0,0 +1,139 @@
+use crate::clock::{Clock, SystemClock};
+use crate::db::DatabasePool;
+use crate::finnhub::fetch_price;
+use crate::models::{Account, Holding, SnapshotHolding, ValueDrift, ValueSnapshot};
+use crate::money::position_value;
+
+/// Settings for the value reconciliation job.
+#[derive(Clone, Copy, Debug)]
+pub struct ReconcileOptions {
+    /// Largest allowed difference between stored and computed value, in cents.
+    pub threshold: i64,
+    /// Overwrite drifted values with the computed value.
+    pub auto_correct: bool,
+    /// Value quotes under this many cents to a tenth of a cent, as `SUB_CENT_PRICING_BELOW`.
+    pub sub_cent_below: Option<i64>,
+}
+
+/// Periodically reconcile every account's stored value.
+pub async fn run(pool: DatabasePool, options: ReconcileOptions, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match reconcile_accounts(&pool, options).await {
+            Ok(flagged) => tracing::info!("Reconciliation flagged {} accounts", flagged),
+            Err(e) => tracing::error!("Error reconciling accounts: {}", e),
+        }
+    }
+}
+
+/// Recompute each account's value from cash and holdings at current prices, snapshot it, and flag
+/// accounts whose stored value drifted past the threshold. Returns the number of flagged accounts.
+pub async fn reconcile_accounts(
+    pool: &DatabasePool,
+    options: ReconcileOptions,
+) -> Result<usize, mongodb::error::Error> {
+    let mut flagged = 0;
+
+    'accounts: for account in pool.get_accounts().await? {
+        let holdings = pool.get_holdings(&account.id).await?;
+
+        let mut prices = Vec::with_capacity(holdings.len());
+        for holding in &holdings {
+            match fetch_price(&holding.stock_symbol).await {
+                Ok(quote) => prices.push(quote.c),
+                Err(e) => {
+                    tracing::warn!("Skipping reconciliation of {}: {}", account.id, e);
+                    continue 'accounts;
+                }
+            }
+        }
+
+        let computed_value = computed_value(&account, &holdings, &prices, options.sub_cent_below);
+        pool.record_snapshot(ValueSnapshot {
+            account_id: account.id.clone(),
+            date: SystemClock.now().date_naive(),
+            value: computed_value,
+            cash: Some(account.cash as i64),
+            invested: Some(computed_value - account.cash as i64),
+            holdings: holdings.iter().map(SnapshotHolding::from).collect(),
+        })
+        .await?;
+        let Some(drift) = value_drift(account.value as i64, computed_value, options.threshold)
+        else {
+            pool.clear_value_drift(&account.id).await?;
+            continue;
+        };
+
+        tracing::warn!(
+            "Account {} value drifted by {} cents (stored {}, computed {})",
+            account.id,
+            drift,
+            account.value,
+            computed_value
+        );
+        if options.auto_correct {
+            pool.update_account(&account.id, computed_value, account.cash as i64)
+                .await?;
+        }
+        pool.flag_value_drift(ValueDrift {
+            account_id: account.id.clone(),
+            stored_value: account.value as i64,
+            computed_value,
+            drift,
+            detected_at: crate::timestamps::now(),
+            corrected: options.auto_correct,
+        })
+        .await?;
+        flagged += 1;
+    }
+
+    Ok(flagged)
+}
+
+/// Value of an account as cash plus each holding at its price. `prices` are quotes in dollars and
+/// line up with `holdings`; see `position_value` for `sub_cent_below`.
+pub fn computed_value(
+    account: &Account,
+    holdings: &[Holding],
+    prices: &[f64],
+    sub_cent_below: Option<i64>,
+) -> i64 {
+    let invested: i64 = holdings
+        .iter()
+        .zip(prices)
+        .map(|(holding, price)| position_value(*price, holding.quantity as i64, sub_cent_below))
+        .sum();
+    account.cash as i64 + invested
+}
+
+/// How far `computed` is from the `stored` value, in cents, if by more than `threshold`.
+pub fn value_drift(stored: i64, computed: i64, threshold: i64) -> Option<i64> {
+    let drift = computed - stored;
+    (drift.abs() > threshold).then_some(drift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drifted_accounts_are_flagged() {
+        let mut account = Account::open("a@example.com", 50_000, true);
+        let holdings = vec![Holding {
+            stock_symbol: String::from("AAPL"),
+            quantity: 10,
+            ..Default::default()
+        }];
+        let computed = computed_value(&account, &holdings, &[10.0], None);
+        assert_eq!(computed, 60_000);
+
+        account.value = 60_050;
+        assert_eq!(value_drift(account.value as i64, computed, 100), None);
+        account.value = 75_000;
+        assert_eq!(
+            value_drift(account.value as i64, computed, 100),
+            Some(-15_000)
+        );
+    }
+}
@@ -1,11 +1,46 @@
 // src/lib.rs
+pub mod cash;
+pub mod clock;
+pub mod concurrency;
+pub mod config;
+pub mod confirmations;
+pub mod corporate_actions;
+pub mod correlation;
+pub mod day_trades;
 pub mod db;
+pub mod dca;
+pub mod envelope;
+pub mod etag;
+pub mod features;
+pub mod fees;
 pub mod handlers;
+pub mod ids;
+pub mod jobs;
+pub mod liquidity;
+pub mod locks;
+pub mod market_hours;
 pub mod models;
+pub mod money;
+pub mod money_output;
+pub mod oauth;
 
-pub mod finnhub;
 pub mod auth;
+pub mod finnhub;
+pub mod pnl;
+pub mod rebalance;
+pub mod recent;
+pub mod response_cache;
+pub mod sectors;
+pub mod sessions;
+pub mod sim;
+pub mod snapshots;
+pub mod state;
+pub mod stats;
+pub mod store;
+pub mod tax;
+pub mod timestamps;
+pub mod warmup;
 
 // Re-export commonly used items
 pub use db::DatabasePool;
-pub use models::*;
\ No newline at end of file
+pub use models::*;
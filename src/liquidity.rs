@@ -0,0 +1,113 @@
+use crate::models::TradeSide;
+use serde::Serialize;
+
+/// Simulated order book depth. Orders larger than one tranche fill in tranches at progressively
+/// worse prices.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LiquidityModel {
+    /// Shares available at each price level.
+    pub tranche_size: i32,
+    /// How much each further tranche's price worsens, in basis points of the quote.
+    pub slippage_bps: i64,
+}
+
+/// Part of an order filled at a single price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tranche {
+    pub quantity: i32,
+    /// Price per share in cents.
+    pub price: i32,
+}
+
+impl LiquidityModel {
+    /// Split an order of `quantity` shares quoted at `price` cents into tranches. Buys pay more
+    /// and sells receive less for every tranche after the first.
+    pub fn tranches(&self, side: TradeSide, quantity: i32, price: i32) -> Vec<Tranche> {
+        let tranche_size = self.tranche_size.max(1);
+        let mut tranches = Vec::new();
+        let mut remaining = quantity;
+        let mut level = 0;
+        while remaining > 0 {
+            let slippage = price as i64 * self.slippage_bps * level / 10_000;
+            let tranche_price = match side {
+                TradeSide::Buy => price as i64 + slippage,
+                TradeSide::Sell => (price as i64 - slippage).max(1),
+            };
+            let filled = remaining.min(tranche_size);
+            tranches.push(Tranche {
+                quantity: filled,
+                price: tranche_price as i32,
+            });
+            remaining -= filled;
+            level += 1;
+        }
+        tranches
+    }
+
+    /// Volume-weighted price of the tranches, rounded against the trader.
+    pub fn blended_price(side: TradeSide, tranches: &[Tranche]) -> i32 {
+        let quantity: i64 = tranches.iter().map(|t| t.quantity as i64).sum();
+        if quantity == 0 {
+            return 0;
+        }
+        let total: i64 = tranches
+            .iter()
+            .map(|t| t.quantity as i64 * t.price as i64)
+            .sum();
+        let blended = match side {
+            TradeSide::Buy => (total + quantity - 1) / quantity,
+            TradeSide::Sell => total / quantity,
+        };
+        blended as i32
+    }
+
+    /// Fill an order and return its blended price, logging the tranche detail.
+    pub fn fill(&self, side: TradeSide, symbol: &str, quantity: i32, price: i32) -> i32 {
+        if quantity <= self.tranche_size {
+            return price;
+        }
+        let tranches = self.tranches(side, quantity, price);
+        let blended = Self::blended_price(side, &tranches);
+        for (level, tranche) in tranches.iter().enumerate() {
+            tracing::info!(
+                "{:?} {} tranche {}: {} shares at {}",
+                side,
+                symbol,
+                level + 1,
+                tranche.quantity,
+                tranche.price
+            );
+        }
+        tracing::info!(
+            "{:?} {} of {} shares filled at blended price {} (quoted {})",
+            side,
+            symbol,
+            quantity,
+            blended,
+            price
+        );
+        blended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: LiquidityModel = LiquidityModel {
+        tranche_size: 100,
+        slippage_bps: 50,
+    };
+
+    #[test]
+    fn small_orders_fill_at_the_quote() {
+        assert_eq!(MODEL.fill(TradeSide::Buy, "AAPL", 100, 10_000), 10_000);
+    }
+
+    #[test]
+    fn large_orders_fill_worse_than_the_quote() {
+        // Tranches of 100 at 10_000, 100 at 10_050, and 50 at 10_100
+        assert_eq!(MODEL.fill(TradeSide::Buy, "AAPL", 250, 10_000), 10_040);
+        assert_eq!(MODEL.fill(TradeSide::Sell, "AAPL", 250, 10_000), 9_960);
+    }
+}
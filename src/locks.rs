@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OwnedMutexGuard;
+
+/// Per-account async locks serializing multi-step operations such as trades, liquidations, and
+/// rebalances, so two of them never interleave on the same account. Store transactions keep each
+/// write atomic, but not the reads and quotes a handler makes before writing.
+#[derive(Clone, Default)]
+pub struct AccountLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl AccountLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for exclusive access to an account. Access is released when the guard is dropped,
+    /// including when a handler returns early with an error.
+    pub async fn lock(&self, account_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // Drop locks nobody holds or waits on so the map doesn't grow with every account
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(account_id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn a_buy_waits_for_a_reset_in_progress() {
+        let locks = AccountLocks::new();
+        let steps = Arc::new(Mutex::new(Vec::new()));
+
+        let reset = locks.lock("a@example.com").await;
+        let buy = tokio::spawn({
+            let (locks, steps) = (locks.clone(), steps.clone());
+            async move {
+                let _lock = locks.lock("a@example.com").await;
+                steps.lock().unwrap().push("buy");
+            }
+        });
+        steps.lock().unwrap().push("reset cash");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        steps.lock().unwrap().push("reset holdings");
+        drop(reset);
+        buy.await.unwrap();
+
+        assert_eq!(
+            *steps.lock().unwrap(),
+            ["reset cash", "reset holdings", "buy"]
+        );
+    }
+
+    #[tokio::test]
+    async fn locks_are_released_when_a_handler_fails() {
+        let locks = AccountLocks::new();
+        async fn failing(locks: &AccountLocks) -> Result<(), ()> {
+            let _lock = locks.lock("a@example.com").await;
+            Err(())
+        }
+
+        assert!(failing(&locks).await.is_err());
+        let _other = locks.lock("b@example.com").await;
+        tokio::time::timeout(Duration::from_secs(1), locks.lock("a@example.com"))
+            .await
+            .unwrap();
+    }
+}
@@ -1,24 +1,67 @@
-mod auth;
-mod db;
-mod finnhub;
-mod handlers;
-mod models;
-
-use crate::auth::{get_user_data, handle_google_callback, logout, start_google_login};
-use crate::db::DatabasePool;
-use crate::handlers::{
-    accounts::get_account,
-    portfolio::{get_portfolio, get_transaction_history},
-    trading::{buy_stock, sell_stock},
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, ETAG,
+    IF_NONE_MATCH, RETRY_AFTER,
 };
-use axum::http::header::{ACCESS_CONTROL_ALLOW_CREDENTIALS, CONTENT_TYPE, COOKIE};
 use axum::http::HeaderValue;
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
 use reqwest::Method;
 use rusqlite::Connection;
+use std::sync::Arc;
+use stocksim_backend::auth::{
+    self, get_user_data, handle_google_callback, handle_oauth_callback, logout, revalidate_session,
+    start_google_login, start_guest_session, start_login,
+};
+use stocksim_backend::clock::SystemClock;
+use stocksim_backend::concurrency;
+use stocksim_backend::config::{self, Config};
+use stocksim_backend::confirmations::PendingOrders;
+use stocksim_backend::db::DatabasePool;
+use stocksim_backend::envelope::{self, X_ENVELOPE};
+use stocksim_backend::finnhub;
+use stocksim_backend::handlers::{
+    accounts::{export_account, get_account, get_account_extremes, get_composition_history},
+    admin::{
+        apply_corporate_action, bulk_create_accounts, dedupe_account_holdings, get_config,
+        get_finnhub_usage, get_value_drifts, purge_account_empty_holdings,
+        restore_account_snapshot, set_account_feature,
+    },
+    dashboard::get_dashboard,
+    financials::get_financials,
+    holdings::{liquidate_delisted_holding, refresh_holding_profile},
+    leaderboard::{get_leaderboard, set_account_eligibility, set_my_eligibility},
+    metrics::get_metrics,
+    orders::{cancel_queued_order, list_queued_orders},
+    peers::get_peers,
+    pnl::get_pnl_periods,
+    portfolio::{
+        get_correlation, get_dividend_estimate, get_earnings, get_portfolio, get_portfolio_as_of,
+        get_position_history, get_sector_exposure, get_sharpe_ratio, get_transaction_history,
+        rebalance_portfolio, recompute_portfolio,
+    },
+    recent::get_recent,
+    sessions::{list_sessions, revoke_session},
+    settings::{get_settings, update_settings},
+    simulate::simulate_dca,
+    stats::get_my_stats,
+    suggestions::get_suggestions,
+    tax::get_tax_preview,
+    trading::{buy_stock, confirm_buy, get_fee_quote, get_trade_cost, sell_stock, validate_trade},
+};
+use stocksim_backend::ids::UuidGenerator;
+use stocksim_backend::jobs;
+use stocksim_backend::locks::AccountLocks;
+use stocksim_backend::market_hours::ClosedMarketOrders;
+use stocksim_backend::money_output;
+use stocksim_backend::recent::RecentSymbols;
+use stocksim_backend::response_cache::{self, CachedRoute, ResponseCache};
+use stocksim_backend::sessions::{track_activity, SessionRegistry};
+use stocksim_backend::state::AppState;
+use stocksim_backend::store::GuestStores;
+use stocksim_backend::warmup::{self, Warmup};
 use time::Duration;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::{self, TraceLayer};
@@ -40,6 +83,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         };
     }
 
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .compact()
+        .with_max_level(log_level)
+        .init();
+
+    tracing::info!("Log level set to: {}", log_level);
+
+    // Load a .env file if there is one, then check every required variable up front so a
+    // misconfigured deployment fails with the full list of what's missing
+    match config::load_env_file() {
+        Ok(Some(path)) => tracing::info!("Loaded environment from {}", path),
+        Ok(None) => tracing::info!("No .env file found, using the process environment"),
+        Err(e) => {
+            tracing::error!("{}", e);
+            return Err(e.into());
+        }
+    }
+    let config = match Config::from_env() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            tracing::error!("{}", e);
+            return Err(e.into());
+        }
+    };
+
     let db_path = ".";
 
     // Initialize our session store as a SQLite database
@@ -55,67 +125,299 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     // Create session layer with some configuration
-    let session_layer = SessionManagerLayer::new(session_store)
+    let session_layer = SessionManagerLayer::new(session_store.clone())
         .with_secure(false)
         .with_expiry(Expiry::OnInactivity(Duration::days(7)))
         .with_same_site(tower_sessions::cookie::SameSite::Lax)
         .with_http_only(true)
         .with_path("/");
 
-    // Initalize dotenv so we can read .env file
-    dotenv::dotenv().ok();
-
-    let frontend_port =
-        dotenv::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
-    let origin = format!("{}", frontend_port);
-
     // Initialize CORS layer
     let cors = CorsLayer::new()
         .allow_credentials(true)
-        .allow_origin(origin.parse::<HeaderValue>().unwrap())
-        .allow_methods(vec![Method::GET, Method::POST])
-        .allow_headers(vec![ACCESS_CONTROL_ALLOW_CREDENTIALS, CONTENT_TYPE, COOKIE]);
-
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
-        .with_max_level(log_level)
-        .init();
+        .allow_origin(config.frontend_url.parse::<HeaderValue>()?)
+        .allow_methods(vec![
+            Method::GET,
+            Method::POST,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(vec![
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            CONTENT_TYPE,
+            COOKIE,
+            IF_NONE_MATCH,
+            X_ENVELOPE,
+        ])
+        .expose_headers(vec![ETAG, CONTENT_DISPOSITION, RETRY_AFTER]);
 
-    tracing::info!("Log level set to: {}", log_level);
+    // Hand the settings read once above to the modules that need them outside of handlers;
+    // price endpoints return 503 without a Finnhub key
+    finnhub::init(&config);
+    finnhub::set_price_source(config.price_source);
+    auth::init(&config);
+    if let Some(sim) = &config.sim {
+        stocksim_backend::sim::init(sim);
+    }
 
-    let uri = dotenv::var("MONGO_URI").expect("MONGO_URI must be set");
     // Initialize database pool
-    let pool = DatabasePool::new(&uri.to_string()).await.unwrap();
+    let pool = DatabasePool::new(&config.mongo_uri).await?;
+
+    // Archive old transactions once a day if a retention period is configured
+    if let Some(days) = config.transaction_retention_days {
+        tracing::info!("Archiving transactions older than {} days", days);
+        tokio::task::spawn(jobs::archive::run(
+            Arc::new(pool.clone()),
+            chrono::Duration::days(days),
+            tokio::time::Duration::from_secs(60 * 60 * 24),
+        ));
+    }
+
+    // Reconcile stored account values against holdings if an interval is configured
+    if let Some(secs) = config.reconcile_interval_secs {
+        let options = jobs::reconcile::ReconcileOptions {
+            threshold: config.reconcile_drift_threshold,
+            auto_correct: config.reconcile_auto_correct,
+            sub_cent_below: config.sub_cent_pricing_below,
+        };
+        tokio::task::spawn(jobs::reconcile::run(
+            pool.clone(),
+            options,
+            tokio::time::Duration::from_secs(secs),
+        ));
+    }
+
+    // Refresh profiles before they expire so requests don't stampede Finnhub after a mass expiry
+    if let Some(per_minute) = config.profile_refresh_per_minute {
+        tracing::info!("Refreshing up to {} expiring profiles a minute", per_minute);
+        tokio::task::spawn(jobs::profiles::run(per_minute));
+    }
+
+    // Sweep expired Finnhub cache entries every minute, capping each cache if configured
+    tokio::task::spawn(jobs::cache::run(
+        config.finnhub_cache_max_entries,
+        tokio::time::Duration::from_secs(60),
+    ));
+
+    // Fill market-on-open orders once the session opens, if closed-market orders are queued
+    let locks = AccountLocks::new();
+    if config.closed_market_orders == ClosedMarketOrders::Queue {
+        tokio::task::spawn(jobs::orders::run(
+            pool.clone(),
+            config.clone(),
+            locks.clone(),
+            tokio::time::Duration::from_secs(60),
+        ));
+    }
+
+    // Sell positions of borrowing accounts below the maintenance margin if one is configured
+    if let Some(percent) = config.maintenance_margin_percent {
+        tracing::info!("Enforcing a {}% maintenance margin", percent);
+        tokio::task::spawn(jobs::margin::run(
+            Arc::new(pool.clone()),
+            config.clone(),
+            locks.clone(),
+            tokio::time::Duration::from_secs(config.margin_check_secs),
+        ));
+    }
+
+    // Delete holdings left empty by failed trades if a sweep interval is configured
+    if let Some(secs) = config.purge_empty_holdings_secs {
+        tokio::task::spawn(jobs::holdings::run(
+            pool.clone(),
+            locks.clone(),
+            tokio::time::Duration::from_secs(secs),
+        ));
+    }
+
+    // Drop guest accounts once their sessions would have expired
+    let guests = GuestStores::new(config.starting_cash);
+    if config.guest_mode {
+        let guests = guests.clone();
+        tokio::task::spawn(async move {
+            let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(60 * 60));
+            loop {
+                ticker.tick().await;
+                guests.sweep(std::time::Duration::from_secs(60 * 60 * 24 * 7));
+            }
+        });
+    }
+
+    // Track sessions per account so the oldest can be evicted at login
+    let sessions = SessionRegistry::new(config.max_sessions_per_account, Arc::new(session_store));
+
+    // Cache expensive read endpoints for as long as configured
+    let responses = ResponseCache::new(
+        [
+            (CachedRoute::Leaderboard, config.leaderboard_cache_secs),
+            (CachedRoute::Sectors, config.sectors_cache_secs),
+        ]
+        .into_iter()
+        .filter_map(|(route, secs)| Some((route, std::time::Duration::from_secs(secs?)))),
+    );
+
+    // Warm the quote cache for accounts returning after being idle, if configured
+    let recent = RecentSymbols::new();
+    let warmup = config.warm_after_idle_secs.map(|secs| {
+        Warmup::new(
+            Arc::new(pool.clone()),
+            recent.clone(),
+            std::time::Duration::from_secs(secs),
+            config.finnhub_request_budget,
+        )
+    });
 
     // Build application with routes
     let app = Router::new()
         // Account routes
         .route("/account", get(get_account))
+        .route("/account/export", get(export_account))
+        .route("/account/extremes", get(get_account_extremes))
+        .route("/account/composition-history", get(get_composition_history))
+        .route("/dashboard", get(get_dashboard))
+        .route("/suggestions", get(get_suggestions))
         // Trading routes
         .route("/buy", post(buy_stock))
+        .route("/buy/confirm", post(confirm_buy))
         .route("/sell", post(sell_stock))
+        .route("/trade/cost", get(get_trade_cost))
+        .route("/fees/quote", get(get_fee_quote))
+        .route("/trade/validate", post(validate_trade))
+        .route("/tax/preview", get(get_tax_preview))
+        .route("/orders/queued", get(list_queued_orders))
+        .route("/orders/queued/:id", delete(cancel_queued_order))
         .route("/portfolio", get(get_portfolio))
+        .route("/portfolio/asof", get(get_portfolio_as_of))
+        .route("/portfolio/recompute", post(recompute_portfolio))
+        .route("/portfolio/sectors", get(get_sector_exposure))
+        .route("/portfolio/correlation", get(get_correlation))
+        .route("/portfolio/rebalance", post(rebalance_portfolio))
+        .route("/portfolio/dividends/estimate", get(get_dividend_estimate))
+        .route("/portfolio/earnings", get(get_earnings))
+        .route("/portfolio/sharpe", get(get_sharpe_ratio))
         .route("/transactions", get(get_transaction_history))
+        .route(
+            "/holdings/:symbol/refresh-profile",
+            post(refresh_holding_profile),
+        )
+        .route(
+            "/holdings/:symbol/liquidate",
+            post(liquidate_delisted_holding),
+        )
+        .route("/holdings/:symbol/history", get(get_position_history))
+        .route("/stats/me", get(get_my_stats))
+        .route("/pnl/periods", get(get_pnl_periods))
+        .route("/peers/:symbol", get(get_peers))
+        .route("/financials/:symbol", get(get_financials))
+        .route("/recent", get(get_recent))
+        .route("/simulate/dca", post(simulate_dca))
+        // Leaderboard routes
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/account/leaderboard-eligibility", post(set_my_eligibility))
+        // Session management routes
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        // Settings routes
+        .route("/settings", get(get_settings).patch(update_settings))
+        // Admin routes
+        .route("/admin/reconcile", get(get_value_drifts))
+        .route("/admin/config", get(get_config))
+        .route("/admin/finnhub-usage", get(get_finnhub_usage))
+        .route("/admin/corporate-actions", post(apply_corporate_action))
+        .route(
+            "/admin/accounts/:account_id/leaderboard-eligibility",
+            post(set_account_eligibility),
+        )
+        .route(
+            "/admin/accounts/:account_id/features",
+            post(set_account_feature),
+        )
+        .route(
+            "/admin/accounts/:account_id/dedupe-holdings",
+            post(dedupe_account_holdings),
+        )
+        .route("/admin/accounts/bulk", post(bulk_create_accounts))
+        .route(
+            "/admin/accounts/:account_id/purge-empty-holdings",
+            post(purge_account_empty_holdings),
+        )
+        .route(
+            "/admin/accounts/:account_id/restore",
+            post(restore_account_snapshot),
+        )
+        // Operational metrics
+        .route("/metrics", get(get_metrics))
         // Auth routes
         .route("/login", get(start_google_login))
+        .route("/login/:provider", get(start_login))
+        .route("/guest", get(start_guest_session))
         .route("/logout", get(logout))
         .route("/callback", get(handle_google_callback))
+        .route("/callback/:provider", get(handle_oauth_callback))
         .route("/user", get(get_user_data))
-        // Database app state
-        .with_state(pool)
-        // Session, CORS, and tracing layers
-        .layer(session_layer)
-        .layer(cors)
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
-                .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
-        );
+        // Storage and config app state
+        .with_state(AppState {
+            store: Arc::new(pool.clone()),
+            pool,
+            config: config.clone(),
+            guests,
+            sessions: sessions.clone(),
+            confirmations: PendingOrders::new(),
+            ids: Arc::new(UuidGenerator),
+            clock: Arc::new(SystemClock),
+            locks,
+            responses: responses.clone(),
+            recent,
+        })
+        // Money output, envelope, cache invalidation, Finnhub budget and rate limit, session
+        // activity and revalidation, and session layers
+        .layer(middleware::from_fn_with_state(
+            config.money_output,
+            money_output::format_money,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.response_envelope,
+            envelope::wrap_responses,
+        ))
+        .layer(middleware::from_fn_with_state(
+            responses,
+            response_cache::invalidate_on_write,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.finnhub_request_budget,
+            finnhub::attach_budget,
+        ))
+        .layer(middleware::from_fn(finnhub::add_retry_after))
+        .layer(middleware::from_fn_with_state(sessions, track_activity))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            revalidate_session,
+        ));
+    // Cache warm-up reads the session user, so it sits inside the session layer
+    let app = match warmup {
+        Some(warmup) => app.layer(middleware::from_fn_with_state(
+            warmup,
+            warmup::warm_on_return,
+        )),
+        None => app,
+    };
+    let app = app.layer(session_layer);
+
+    // Shed requests beyond the concurrency limit with a 503 instead of queueing them
+    let app = match config.max_concurrent_requests {
+        Some(max) => concurrency::limit(app, max),
+        None => app,
+    };
+
+    // CORS and tracing wrap everything, including shed requests
+    let app = app.layer(cors).layer(
+        TraceLayer::new_for_http()
+            .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+            .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
+    );
 
     // Run server
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    let listener = tokio::net::TcpListener::bind(&config.bind_addr).await?;
 
     tracing::info!("Listening on: {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
@@ -124,4 +426,3 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
-
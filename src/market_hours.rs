@@ -0,0 +1,133 @@
+use crate::finnhub::FinnhubQuote;
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::America::New_York;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Which price values holdings outside regular US market hours.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AfterHoursPricing {
+    /// The latest trade, which may include extended-hours trading.
+    #[default]
+    Latest,
+    /// The regular session's close, so values hold still between sessions.
+    Close,
+}
+
+impl FromStr for AfterHoursPricing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "latest" => Ok(AfterHoursPricing::Latest),
+            "close" => Ok(AfterHoursPricing::Close),
+            _ => Err(format!("Unknown after-hours pricing: {}", s)),
+        }
+    }
+}
+
+/// What happens to stock trades placed outside the regular session. Crypto trades around the
+/// clock regardless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClosedMarketOrders {
+    /// Fill at the latest quote, as during the session.
+    #[default]
+    Allow,
+    /// Refuse the trade.
+    Reject,
+    /// Hold the trade as a market-on-open order, filled once the session opens.
+    Queue,
+}
+
+impl FromStr for ClosedMarketOrders {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(ClosedMarketOrders::Allow),
+            "reject" => Ok(ClosedMarketOrders::Reject),
+            "queue" => Ok(ClosedMarketOrders::Queue),
+            _ => Err(format!("Unknown closed market orders mode: {}", s)),
+        }
+    }
+}
+
+/// Whether US equity markets are in their regular session, 9:30 to 16:00 Eastern on weekdays.
+/// Exchange holidays are not accounted for.
+pub fn is_regular_session(now: DateTime<Utc>) -> bool {
+    let local = now.with_timezone(&New_York);
+    if matches!(local.weekday(), Weekday::Sat | Weekday::Sun) {
+        return false;
+    }
+    let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+    let close = NaiveTime::from_hms_opt(16, 0, 0).unwrap();
+    (open..close).contains(&local.time())
+}
+
+/// Price per share, in dollars, to value a holding at: the quote's previous close outside the
+/// regular session when `pricing` is `Close`, and its latest price otherwise.
+pub fn valuation_price(
+    quote: &FinnhubQuote,
+    pricing: AfterHoursPricing,
+    now: DateTime<Utc>,
+) -> f64 {
+    match pricing {
+        AfterHoursPricing::Close if !is_regular_session(now) && quote.pc > 0.0 => quote.pc,
+        _ => quote.c,
+    }
+}
+
+/// Whether a quote looks halted: during the regular session, its last update is more than
+/// `stale_after` old. Quotes without an update time, such as crypto and historical prices, are
+/// never considered halted.
+pub fn is_halted(quote: &FinnhubQuote, now: DateTime<Utc>, stale_after: chrono::Duration) -> bool {
+    if quote.t <= 0 || !is_regular_session(now) {
+        return false;
+    }
+    match DateTime::from_timestamp(quote.t, 0) {
+        Some(updated) => now - updated > stale_after,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap().to_utc()
+    }
+
+    fn quote(c: f64, pc: f64) -> FinnhubQuote {
+        FinnhubQuote {
+            c,
+            d: c - pc,
+            dp: 0.0,
+            pc,
+            t: 0,
+        }
+    }
+
+    #[test]
+    fn close_pricing_values_after_hours_at_the_close() {
+        let quote = quote(101.5, 100.0);
+        // 18:00 Eastern on a Tuesday
+        let after_hours = at("2024-03-05T23:00:00Z");
+        let during_session = at("2024-03-05T15:00:00Z");
+
+        assert_eq!(
+            valuation_price(&quote, AfterHoursPricing::Close, after_hours),
+            100.0
+        );
+        assert_eq!(
+            valuation_price(&quote, AfterHoursPricing::Close, during_session),
+            101.5
+        );
+        assert_eq!(
+            valuation_price(&quote, AfterHoursPricing::Latest, after_hours),
+            101.5
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use crate::money::SharePrice;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 /// Account represents a user's account.
 /// It has an id, total value, and cash.
@@ -8,6 +10,78 @@ pub struct Account {
     pub value: i32,
     pub cash: i32,
     pub change: i32,
+    /// Incremented whenever a trade changes the account's cash or holdings.
+    #[serde(default)]
+    pub version: i64,
+    /// Number of trades the account has made.
+    #[serde(default)]
+    pub trades_count: i64,
+    /// Whether the account competes on the leaderboard. Accounts from before the flag existed
+    /// are eligible.
+    #[serde(default = "default_true")]
+    pub eligible_for_leaderboard: bool,
+    /// Set once the user has chosen eligibility; afterwards only an admin can change it.
+    #[serde(default)]
+    pub eligibility_locked: bool,
+    /// Set once the account makes more than `DAY_TRADE_LIMIT` day trades in five trading days.
+    #[serde(default)]
+    pub pattern_day_trader: bool,
+    /// When the account was created, as an RFC 3339 timestamp. Unset on older accounts.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the account was last changed, as an RFC 3339 timestamp. Unset on older accounts.
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+impl Account {
+    /// A new account holding only `starting_cash` cents.
+    pub fn open(id: &str, starting_cash: i64, eligible_for_leaderboard: bool) -> Self {
+        Account {
+            id: id.to_string(),
+            value: starting_cash as i32,
+            cash: starting_cash as i32,
+            eligible_for_leaderboard,
+            ..Default::default()
+        }
+    }
+
+    /// Cash borrowed on margin, in cents: however far cash has gone below zero.
+    pub fn borrowed(&self) -> i64 {
+        (-(self.cash as i64)).max(0)
+    }
+
+    /// Most a buy may cost, fees included, with cash leveraged by `multiplier`. Accounts that have
+    /// borrowed have no buying power left.
+    pub fn buying_power(&self, multiplier: f64) -> i64 {
+        (self.cash as f64 * multiplier) as i64
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// One ranked account on the leaderboard.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    /// Account id with the email's local part masked.
+    pub account: String,
+    pub value: i32,
+}
+
+/// Request to grant or revoke a gated feature for an account.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateFeature {
+    pub feature: String,
+    pub enabled: bool,
+}
+
+/// Request to change an account's leaderboard eligibility.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateEligibility {
+    pub eligible: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -15,7 +89,17 @@ pub struct CreateAccount {
     pub value: i32,
     pub cash: i32,
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// Kind of security a holding represents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssetType {
+    Stock,
+    Etf,
+    Crypto,
+    #[default]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Holding {
     pub account_id: String,
     pub stock_symbol: String,
@@ -24,35 +108,543 @@ pub struct Holding {
     pub current_price: i32,
     pub total_value: i32,
     pub purchase_price: i32,
+    /// `current_price` in tenths of a cent, set only when it isn't whole cents. See `SharePrice`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_price_tenths: Option<i64>,
+    /// `purchase_price` in tenths of a cent, set only when it isn't whole cents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purchase_price_tenths: Option<i64>,
+    #[serde(default)]
+    pub asset_type: AssetType,
+    /// Set once the symbol keeps failing to quote. The holding is then valued at
+    /// `current_price`, its last known price.
+    #[serde(default)]
+    pub delisted: bool,
+    /// When the position was opened, as an RFC 3339 timestamp. Unset on older holdings. Stored
+    /// as a string rather than a BSON date, like every other stored timestamp, so the two fields
+    /// compare and sort the same way as transaction timestamps.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// When the position was last changed, as an RFC 3339 timestamp. Unset on older holdings.
+    #[serde(default)]
+    pub updated_at: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Holding {
+    /// The current price, to a tenth of a cent when it was quoted that finely.
+    pub fn current_share_price(&self) -> SharePrice {
+        self.current_price_tenths
+            .map(SharePrice::from_tenths)
+            .unwrap_or(SharePrice::from_cents(self.current_price))
+    }
+
+    /// The average purchase price, to a tenth of a cent when it was filled that finely.
+    pub fn purchase_share_price(&self) -> SharePrice {
+        self.purchase_price_tenths
+            .map(SharePrice::from_tenths)
+            .unwrap_or(SharePrice::from_cents(self.purchase_price))
+    }
+
+    /// Set the current price and the total value it gives the holding.
+    pub fn set_current_price(&mut self, price: SharePrice) {
+        self.current_price = price.cents();
+        self.current_price_tenths = price.sub_cent();
+        self.total_value = price.value(self.quantity as i64) as i32;
+    }
+
+    pub fn set_purchase_price(&mut self, price: SharePrice) {
+        self.purchase_price = price.cents();
+        self.purchase_price_tenths = price.sub_cent();
+    }
+
+    /// Merge several holdings of the same symbol into one: quantities are summed and the purchase
+    /// price becomes their quantity-weighted average. The most recently updated holding supplies
+    /// the name and current price, and the earliest creation time is kept.
+    pub fn merge(mut duplicates: Vec<Holding>) -> Option<Holding> {
+        duplicates.sort_by(|a, b| a.updated_at.cmp(&b.updated_at));
+        let quantity: i64 = duplicates.iter().map(|h| h.quantity as i64).sum();
+        let cost: i64 = duplicates
+            .iter()
+            .map(|h| h.quantity as i64 * h.purchase_share_price().tenths())
+            .sum();
+        let created_at = duplicates.iter().filter_map(|h| h.created_at.clone()).min();
+        let delisted = duplicates.iter().any(|h| h.delisted);
+        let mut merged = duplicates.pop()?;
+        merged.quantity = quantity as i32;
+        if quantity != 0 {
+            merged.set_purchase_price(SharePrice::from_tenths(cost / quantity));
+        }
+        merged.set_current_price(merged.current_share_price());
+        merged.delisted = delisted;
+        merged.created_at = created_at;
+        merged.updated_at = Some(crate::timestamps::now());
+        Some(merged)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HoldingResponse {
     pub stock_symbol: String,
     pub stock_name: String,
     pub quantity: i32,
     pub current_price: i32,
     pub total_value: i32,
-    pub day_change: i32,
-    pub day_change_percent: i32,
+    /// Change since the previous close, in cents. Left out when the holding wasn't quoted for
+    /// this response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_change: Option<i32>,
+    /// Change since the previous close, in hundredths of a percent. Left out with `day_change`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_change_percent: Option<i32>,
     pub purchase_price: i32,
+    /// `current_price` in tenths of a cent, set only when it isn't whole cents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_price_tenths: Option<i64>,
+    /// `purchase_price` in tenths of a cent, set only when it isn't whole cents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purchase_price_tenths: Option<i64>,
     pub stock_logo_url: String,
     pub overall_change: i32,
     pub category: String,
+    pub asset_type: AssetType,
+    pub delisted: bool,
+    /// When the position was opened, if known.
+    pub opened_at: Option<String>,
+    /// Set when the holding was valued at a quote fetched for this response. Other holdings keep
+    /// their last known price.
+    #[serde(default)]
+    pub priced: bool,
+}
+
+impl HoldingResponse {
+    /// The average purchase price, to a tenth of a cent when it was filled that finely.
+    pub fn purchase_share_price(&self) -> SharePrice {
+        self.purchase_price_tenths
+            .map(SharePrice::from_tenths)
+            .unwrap_or(SharePrice::from_cents(self.purchase_price))
+    }
+
+    pub fn current_share_price(&self) -> SharePrice {
+        self.current_price_tenths
+            .map(SharePrice::from_tenths)
+            .unwrap_or(SharePrice::from_cents(self.current_price))
+    }
+
+    /// Value the holding at `price`, setting its current price, total value and overall change.
+    pub fn set_current_price(&mut self, price: SharePrice) {
+        let quantity = self.quantity as i64;
+        self.current_price = price.cents();
+        self.current_price_tenths = price.sub_cent();
+        self.total_value = price.value(quantity) as i32;
+        self.overall_change =
+            (price.value(quantity) - self.purchase_share_price().value(quantity)) as i32;
+    }
+}
+
+/// Order of the holdings in a portfolio response. Ties fall back to symbol order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldingSort {
+    #[default]
+    Symbol,
+    /// Largest position first.
+    Value,
+    /// Largest overall gain first.
+    Gain,
+    /// Largest percent change today first.
+    DayChange,
+}
+
+impl HoldingSort {
+    /// Sort `holdings` in this order.
+    pub fn apply(self, holdings: &mut [HoldingResponse]) {
+        holdings.sort_by(|a, b| a.stock_symbol.cmp(&b.stock_symbol));
+        match self {
+            HoldingSort::Symbol => {}
+            HoldingSort::Value => holdings.sort_by_key(|h| std::cmp::Reverse(h.total_value)),
+            HoldingSort::Gain => holdings.sort_by_key(|h| std::cmp::Reverse(h.overall_change)),
+            HoldingSort::DayChange => {
+                holdings.sort_by_key(|h| std::cmp::Reverse(h.day_change_percent))
+            }
+        }
+    }
+}
+
+/// Query for ordering a portfolio's holdings.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct HoldingSortQuery {
+    #[serde(default)]
+    pub sort: HoldingSort,
+}
+
+/// Pairwise correlations of the daily returns of the portfolio's holdings.
+#[derive(Serialize, Debug)]
+pub struct CorrelationMatrix {
+    /// Symbols in the order of the matrix's rows and columns.
+    pub symbols: Vec<String>,
+    /// `matrix[i][j]` correlates `symbols[i]` with `symbols[j]`. Pairs with too little shared
+    /// history are null.
+    pub matrix: Vec<Vec<Option<f64>>>,
+    /// Holdings left out for lack of price history.
+    pub excluded: Vec<String>,
+    /// Set when the request's Finnhub budget ran out and some holdings were left out.
+    pub truncated: bool,
+}
+
+/// The portfolio's value split by industry.
+#[derive(Serialize, Debug, Clone)]
+pub struct SectorBreakdown {
+    pub sectors: Vec<crate::sectors::SectorExposure>,
+    /// Set when the request's Finnhub budget ran out and only some holdings are included.
+    pub truncated: bool,
+}
+
+/// Dividend income a holding is expected to pay over the next year.
+#[derive(Serialize, Debug)]
+pub struct DividendEstimate {
+    pub stock_symbol: String,
+    pub quantity: i32,
+    /// Dividends per share paid over the trailing year, in cents.
+    pub annual_dividend_per_share: i64,
+    /// Projected annual income at the current share count, in cents.
+    pub annual_income: i64,
+}
+
+/// Projected annual dividend income for the portfolio. Holdings that pay no dividends are left
+/// out.
+#[derive(Serialize, Debug)]
+pub struct DividendIncome {
+    pub holdings: Vec<DividendEstimate>,
+    /// Projected annual income across all holdings, in cents.
+    pub total_annual_income: i64,
+    /// Set when the request's Finnhub budget ran out and only some holdings are included.
+    pub truncated: bool,
+}
+
+/// A scheduled earnings release for a held stock.
+#[derive(Serialize, Debug)]
+pub struct EarningsDate {
+    pub stock_symbol: String,
+    pub date: chrono::NaiveDate,
+    /// `bmo` before market open, `amc` after market close, `dmh` during market hours, or empty
+    /// when unannounced.
+    pub hour: String,
+    /// Consensus earnings per share estimate, in dollars.
+    pub eps_estimate: Option<f64>,
+    /// Consensus revenue estimate, in dollars.
+    pub revenue_estimate: Option<f64>,
+    pub quarter: u32,
+    pub year: i32,
+}
+
+/// Upcoming earnings releases for the portfolio's holdings, earliest first.
+#[derive(Serialize, Debug)]
+pub struct PortfolioEarnings {
+    pub earnings: Vec<EarningsDate>,
+    /// Set when the request's Finnhub budget ran out and only some holdings are included.
+    pub truncated: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Portfolio {
     pub holdings: Vec<HoldingResponse>,
+    /// Set when the request's Finnhub budget ran out and only some holdings are included.
+    pub truncated: bool,
+    /// Holdings that couldn't be quoted, and why.
+    #[serde(default)]
+    pub errors: Vec<PricingError>,
+}
+
+/// A holding that couldn't be quoted while pricing a portfolio.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PricingError {
+    pub stock_symbol: String,
+    pub message: String,
+}
+
+/// Query selecting how a portfolio responds when the price provider is unavailable.
+#[derive(Deserialize, Debug, Default)]
+pub struct PartialQuery {
+    /// Respond with the holdings valued at their last known prices instead of failing.
+    #[serde(default)]
+    pub partial: bool,
+}
+
+/// Request to compare dollar-cost averaging with a lump sum over a past date range.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DcaRequest {
+    pub stock_symbol: String,
+    /// Total to invest, in cents.
+    pub amount: i64,
+    /// How often to invest a share of the amount.
+    #[serde(default)]
+    pub frequency: crate::pnl::Granularity,
+    pub start: chrono::NaiveDate,
+    pub end: chrono::NaiveDate,
+}
+
+/// Symbols to build buying suggestions for, comma-separated.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SuggestionsQuery {
+    pub symbols: String,
+}
+
+/// How many shares of a watched symbol the account's cash covers at the current quote.
+#[derive(Serialize, Debug, Clone)]
+pub struct Suggestion {
+    pub stock_symbol: String,
+    /// Current quote, in cents.
+    pub price: i32,
+    /// Whole shares the account's cash covers at `price`, before fees.
+    pub affordable_quantity: i64,
+    /// Change since the previous close, in cents.
+    pub day_change: i32,
+    /// Change since the previous close, in hundredths of a percent.
+    pub day_change_percent: i32,
+}
+
+/// Affordable quantities for a set of watched symbols and the biggest movers among them.
+#[derive(Serialize, Debug)]
+pub struct Suggestions {
+    /// Reminder that this is arithmetic on cash and quotes, not advice.
+    pub disclaimer: &'static str,
+    /// Cash available to spend, in cents.
+    pub cash: i64,
+    pub suggestions: Vec<Suggestion>,
+    /// Symbols that moved the most today, by absolute percentage.
+    pub movers: Vec<String>,
+    /// Symbols that couldn't be quoted.
+    pub unpriced: Vec<String>,
+    /// Set when the request's Finnhub budget ran out and only some symbols are included.
+    pub truncated: bool,
+}
+
+/// Everything the home screen shows, priced once.
+#[derive(Serialize, Debug)]
+pub struct Dashboard {
+    /// The account, with `change` summed from the priced holdings.
+    pub account: Account,
+    pub holdings: Vec<HoldingResponse>,
+    /// Holdings that moved the most today, by absolute percentage.
+    pub movers: Vec<HoldingResponse>,
+    /// Latest transactions, newest first.
+    pub recent_transactions: Vec<Transaction>,
+    /// Set when the request's Finnhub budget ran out and only some holdings are included.
+    pub truncated: bool,
+}
+
+/// Direction of a trade.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TradeRequest {
+    pub stock_symbol: String,
+    #[serde(default)]
+    pub quantity: i32,
+    /// Dollar amount to buy instead of a share count, e.g. `"250.00"`. Stored in cents.
+    #[serde(default, deserialize_with = "crate::money::deserialize_optional_cents")]
+    pub notional: Option<i64>,
+    /// The user's reason for the trade, kept on the transaction.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Query for pricing a trade without placing it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TradeCostQuery {
+    pub symbol: String,
+    pub quantity: i32,
+    pub side: TradeSide,
+}
+
+/// What a trade would cost or pay out at the current quote. Amounts are in cents.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TradeCost {
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    pub quantity: i32,
+    /// Fill price per share, including liquidity slippage.
+    pub price: i32,
+    pub notional: i64,
+    pub fee: i64,
+    /// Cash leaving the account for a buy, or arriving for a sell.
+    pub total: i64,
+    /// Whether the account has the cash (buys) or shares (sells) for the trade.
+    pub sufficient: bool,
+}
+
+/// Itemized fees a trade would be charged at the current quote.
+#[derive(Serialize, Debug, Clone)]
+pub struct FeeQuote {
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    pub quantity: i32,
+    /// Fill price per share, including liquidity slippage.
+    pub price: i32,
+    pub notional: i64,
+    pub fees: crate::fees::FeeBreakdown,
+}
+
+/// Request to run an order through the pre-trade checks without placing it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValidateTrade {
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    #[serde(default)]
+    pub quantity: i32,
+    /// Dollar amount to buy instead of a share count, as for `/buy`.
+    #[serde(default, deserialize_with = "crate::money::deserialize_optional_cents")]
+    pub notional: Option<i64>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Why an order would be refused.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValidationCode {
+    InvalidQuantity,
+    InvalidNote,
+    FeatureDisabled,
+    UnknownSymbol,
+    MarketClosed,
+    Halted,
+    InsufficientFunds,
+    BelowCashReserve,
+    InsufficientShares,
+    DayTradeRestricted,
+    PendingBuy,
+    LockedUp,
+}
+
+/// A check an order failed, with the message placing it would have responded with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidationError {
+    pub code: ValidationCode,
+    pub message: String,
+}
+
+/// Outcome of the pre-trade checks for an order.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TradeValidation {
+    /// Set when the order passed every check.
+    pub ok: bool,
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    /// Shares the order is for, after converting a notional amount.
+    pub quantity: i32,
+    /// Set when the order would be queued for the open rather than filled now.
+    pub queued: bool,
+    pub errors: Vec<ValidationError>,
+}
+
+/// Query for previewing the taxes on a sale.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaxPreviewQuery {
+    pub symbol: String,
+    pub quantity: i32,
+}
+
+/// Gains taxed under one holding period and the tax estimated on them. Amounts are in cents.
+#[derive(Serialize, Debug)]
+pub struct TermTax {
+    #[serde(flatten)]
+    pub gain: crate::tax::TermGain,
+    /// Tax rate applied, in basis points.
+    pub rate_bps: i64,
+    pub estimated_tax: i64,
+}
+
+/// Estimated short- and long-term gains and taxes on selling shares at the current quote, with
+/// the oldest shares sold first. Amounts are in cents.
+#[derive(Serialize, Debug)]
+pub struct TaxPreview {
+    /// Reminder that this is an estimate at flat rates, not tax advice.
+    pub disclaimer: &'static str,
     pub stock_symbol: String,
     pub quantity: i32,
+    pub price: i32,
+    pub fee: i64,
+    /// Sale value net of fees.
+    pub proceeds: i64,
+    pub short_term: TermTax,
+    pub long_term: TermTax,
+    /// Shares bought before the archived history, whose holding period is unknown. No tax is
+    /// estimated on them.
+    pub unknown_term: crate::tax::TermGain,
+    pub estimated_tax: i64,
+}
+
+/// Returned instead of a transaction when a buy needs confirmation. Sending the token to
+/// `/buy/confirm` before it expires executes the order at the then-current price.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TradeConfirmation {
+    pub confirmation_token: String,
+    pub expires_in_secs: u64,
+    /// The order priced at the current quote.
+    pub summary: TradeCost,
 }
 
+/// Request to execute a buy held for confirmation.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfirmTrade {
+    pub confirmation_token: String,
+}
+
+/// Request to rebalance the portfolio toward target weights.
 #[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RebalanceRequest {
+    /// Target weight per symbol. The account's saved `target_allocations` are used when omitted.
+    #[serde(default)]
+    pub targets: Option<HashMap<String, f64>>,
+    /// Place the proposed trades instead of only returning them.
+    #[serde(default)]
+    pub execute: bool,
+    /// Whether executed trades all commit together or each on its own.
+    #[serde(default)]
+    pub mode: CommitMode,
+}
+
+/// How an operation placing several trades commits them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitMode {
+    /// Every trade commits, or none do.
+    #[default]
+    Atomic,
+    /// Each trade commits on its own, so trades that succeed are kept when others fail.
+    BestEffort,
+}
+
+/// What happened to one trade placed by a multi-trade operation.
+#[derive(Serialize, Debug)]
+pub struct TradeOutcome {
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    pub quantity: i32,
+    /// The recorded transaction, if the trade committed.
+    pub transaction: Option<Transaction>,
+    /// Why the trade failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Trades proposed by a rebalance, and the transactions recorded if they were executed.
+#[derive(Serialize, Debug)]
+pub struct RebalanceResponse {
+    pub trades: Vec<crate::rebalance::RebalanceTrade>,
+    pub executed: bool,
+    /// Transactions that committed.
+    pub transactions: Vec<Transaction>,
+    /// Each executed trade's outcome, in the order placed.
+    pub outcomes: Vec<TradeOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Transaction {
     pub id: String,
     pub account_id: String,
@@ -60,5 +652,478 @@ pub struct Transaction {
     pub transaction_type: String,
     pub quantity: i32,
     pub price: i32,
+    /// `price` in tenths of a cent, set only when the trade filled between whole cents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_tenths: Option<i64>,
     pub timestamp: String,
+    /// Fees charged on the trade, in cents.
+    #[serde(default)]
+    pub fee: i32,
+    /// For sells, proceeds less fees minus the average cost of the shares sold, in cents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realized_pnl_cents: Option<i32>,
+    /// The user's reason for the trade, if they gave one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Aggregate of an account's archived transactions for one symbol. Archived lots are folded into
+/// an average-cost position so realized P&L stays correct after the raw transactions are moved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TransactionSummary {
+    pub account_id: String,
+    pub stock_symbol: String,
+    /// Shares still held from archived buys.
+    pub quantity: i64,
+    /// Cost basis of those shares, in cents.
+    pub cost_basis: i64,
+    /// Realized P&L of archived sells, in cents.
+    pub realized_pnl: i64,
+    pub archived_count: i64,
+}
+
+/// Per-account preferences. Accounts without a settings document, and fields missing from an
+/// older document, fall back to the defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AccountSettings {
+    pub account_id: String,
+    pub currency: String,
+    pub default_order_type: String,
+    /// Overrides the server's fee model for this account when set.
+    pub fee_model: Option<String>,
+    pub email_opt_in: bool,
+    /// Target portfolio weight per symbol, as fractions of the account value. Cash makes up
+    /// the remainder.
+    pub target_allocations: HashMap<String, f64>,
+    /// Cash, in cents, that buys may not dip into.
+    pub min_cash_reserve_cents: i64,
+    /// Gated features granted to this account. Only admins can change these.
+    pub features: HashSet<String>,
+    /// Buys may spend up to cash times this, borrowing the difference. 1.0 trades without
+    /// leverage.
+    pub buying_power_multiplier: f64,
+}
+
+impl Default for AccountSettings {
+    fn default() -> Self {
+        AccountSettings {
+            account_id: String::new(),
+            currency: String::from("USD"),
+            default_order_type: String::from("market"),
+            fee_model: None,
+            email_opt_in: false,
+            target_allocations: HashMap::new(),
+            min_cash_reserve_cents: 0,
+            features: HashSet::new(),
+            buying_power_multiplier: 1.0,
+        }
+    }
+}
+
+/// Partial settings update. Only the provided fields are changed.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UpdateSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_order_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_opt_in: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_allocations: Option<HashMap<String, f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_cash_reserve_cents: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buying_power_multiplier: Option<f64>,
+}
+
+/// A trade placed while the market was closed, waiting to fill at the next open.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedOrder {
+    pub id: String,
+    pub account_id: String,
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    pub quantity: i32,
+    /// Cash set aside for a buy, in cents: its cost and fee at the quote when it was placed.
+    /// Other buys can't spend it while the order waits.
+    pub reserved: i64,
+    pub note: Option<String>,
+    /// When the order was placed, as an RFC 3339 timestamp.
+    pub placed_at: String,
+}
+
+/// An account's total value recorded at the end of pricing, at most one per account and day.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValueSnapshot {
+    pub account_id: String,
+    pub date: chrono::NaiveDate,
+    /// Cash plus holdings, in cents.
+    pub value: i64,
+    /// Cash in cents. Unset on older snapshots, which can't be restored.
+    #[serde(default)]
+    pub cash: Option<i64>,
+    /// Value of the holdings, in cents. Unset on older snapshots.
+    #[serde(default)]
+    pub invested: Option<i64>,
+    /// Holdings at the time of the snapshot.
+    #[serde(default)]
+    pub holdings: Vec<SnapshotHolding>,
+}
+
+/// A holding as captured in a value snapshot, enough to recreate it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotHolding {
+    pub stock_symbol: String,
+    pub stock_name: String,
+    pub quantity: i32,
+    pub current_price: i32,
+    pub purchase_price: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_price_tenths: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purchase_price_tenths: Option<i64>,
+    pub asset_type: AssetType,
+    pub delisted: bool,
+}
+
+impl SnapshotHolding {
+    /// Recreate the holding for an account, stamped as opened and updated at `now`.
+    pub fn into_holding(self, account_id: &str, now: &str) -> Holding {
+        let mut holding = Holding {
+            account_id: account_id.to_string(),
+            total_value: 0,
+            stock_symbol: self.stock_symbol,
+            stock_name: self.stock_name,
+            quantity: self.quantity,
+            current_price: self.current_price,
+            purchase_price: self.purchase_price,
+            current_price_tenths: self.current_price_tenths,
+            purchase_price_tenths: self.purchase_price_tenths,
+            asset_type: self.asset_type,
+            delisted: self.delisted,
+            created_at: Some(now.to_string()),
+            updated_at: Some(now.to_string()),
+        };
+        holding.set_current_price(holding.current_share_price());
+        holding
+    }
+}
+
+impl From<&Holding> for SnapshotHolding {
+    fn from(holding: &Holding) -> Self {
+        SnapshotHolding {
+            stock_symbol: holding.stock_symbol.clone(),
+            stock_name: holding.stock_name.clone(),
+            quantity: holding.quantity,
+            current_price: holding.current_price,
+            purchase_price: holding.purchase_price,
+            current_price_tenths: holding.current_price_tenths,
+            purchase_price_tenths: holding.purchase_price_tenths,
+            asset_type: holding.asset_type,
+            delisted: holding.delisted,
+        }
+    }
+}
+
+impl From<&HoldingResponse> for SnapshotHolding {
+    fn from(holding: &HoldingResponse) -> Self {
+        SnapshotHolding {
+            stock_symbol: holding.stock_symbol.clone(),
+            stock_name: holding.stock_name.clone(),
+            quantity: holding.quantity,
+            current_price: holding.current_price,
+            purchase_price: holding.purchase_price,
+            current_price_tenths: holding.current_price_tenths,
+            purchase_price_tenths: holding.purchase_price_tenths,
+            asset_type: holding.asset_type,
+            delisted: holding.delisted,
+        }
+    }
+}
+
+/// Request to restore an account to the snapshot recorded on a day.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestoreSnapshot {
+    pub date: chrono::NaiveDate,
+}
+
+/// Audit entry for an account restored to a snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotRestoreRecord {
+    pub account_id: String,
+    /// Day of the snapshot restored.
+    pub date: chrono::NaiveDate,
+    pub restored_at: String,
+    /// Admin who restored the account.
+    pub restored_by: String,
+    /// Account value and cash before the restore, in cents.
+    pub previous_value: i64,
+    pub previous_cash: i64,
+    pub value: i64,
+    pub cash: i64,
+    pub holdings: usize,
+}
+
+/// The account's all-time high and low recorded values and its drawdown from the high.
+#[derive(Serialize, Debug)]
+pub struct AccountExtremes {
+    /// Snapshots the extremes were computed from.
+    pub snapshots: usize,
+    /// `None` until the account has been valued at least once.
+    pub extremes: Option<crate::snapshots::Extremes>,
+}
+
+/// The account's value split between cash and holdings on each day it was recorded.
+#[derive(Serialize, Debug)]
+pub struct CompositionHistory {
+    pub points: Vec<crate::snapshots::CompositionPoint>,
+    /// Older snapshots left out because they didn't record cash.
+    pub skipped: usize,
+}
+
+/// The account's annualized Sharpe ratio, from the returns between its recorded values.
+#[derive(Serialize, Debug)]
+pub struct SharpeRatio {
+    /// `None` when it can't be computed, with `reason` saying why.
+    pub sharpe_ratio: Option<f64>,
+    pub reason: Option<String>,
+    /// Daily returns the ratio was computed from.
+    pub returns: usize,
+    /// Annual risk-free rate the returns were measured against, in basis points.
+    pub risk_free_rate_bps: i64,
+}
+
+/// Request to create several accounts at once, such as for a class.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkCreateAccounts {
+    /// Emails of the accounts to create. Each user signs in with the same email.
+    pub emails: Vec<String>,
+}
+
+/// Outcome of a bulk account creation.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BulkCreateSummary {
+    pub created: Vec<String>,
+    /// Emails that already had an account, or were listed more than once.
+    pub skipped: Vec<String>,
+    /// Entries that aren't email addresses.
+    pub invalid: Vec<String>,
+}
+
+/// An account whose stored value differs from its recomputed value by more than the threshold.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ValueDrift {
+    pub account_id: String,
+    pub stored_value: i64,
+    pub computed_value: i64,
+    pub drift: i64,
+    pub detected_at: String,
+    /// Whether the stored value was overwritten with the computed one.
+    pub corrected: bool,
+}
+
+/// A corporate action applied by hand to every account holding the affected symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CorporateAction {
+    /// The symbol was renamed. Holdings and transaction history move to the new symbol.
+    TickerChange { from: String, to: String },
+    /// The company was bought out for cash. Holdings are closed at `cash_per_share` cents.
+    CashMerger {
+        stock_symbol: String,
+        cash_per_share: i32,
+    },
+}
+
+/// Request to apply a corporate action. Re-sending the same `action_id` has no further effect.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CorporateActionRequest {
+    pub action_id: String,
+    #[serde(flatten)]
+    pub action: CorporateAction,
+}
+
+/// Audit entry for an applied corporate action.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CorporateActionRecord {
+    pub action_id: String,
+    pub action: CorporateAction,
+    pub applied_at: String,
+    /// Admin who applied the action.
+    pub applied_by: String,
+    pub holdings_changed: i64,
+    pub transactions_changed: i64,
+}
+
+/// Audit entry for a position sold to meet a margin call.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarginCallRecord {
+    pub account_id: String,
+    /// The forced SELL.
+    pub transaction_id: String,
+    pub stock_symbol: String,
+    pub quantity: i32,
+    /// Price the shares were sold at, in cents.
+    pub price: i32,
+    /// Account equity and the maintenance requirement it fell short of, in cents.
+    pub equity: i64,
+    pub requirement: i64,
+    pub closed_at: String,
+}
+
+/// A position valued at a past day's closing price.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoricalHolding {
+    pub stock_symbol: String,
+    pub quantity: i64,
+    /// Closing price in cents.
+    pub price: i64,
+    pub total_value: i64,
+}
+
+/// The portfolio as it stood at the end of a past day. Amounts are in cents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoricalPortfolio {
+    pub date: chrono::NaiveDate,
+    pub cash: i64,
+    pub holdings: Vec<HistoricalHolding>,
+    /// Cash plus holdings.
+    pub value: i64,
+}
+
+/// A position's size and value at the close of one trading day. Amounts are in cents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionPoint {
+    pub date: chrono::NaiveDate,
+    pub quantity: i64,
+    /// Closing price in cents.
+    pub price: i64,
+    pub value: i64,
+}
+
+/// How a position in one symbol grew and shrank over time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionHistory {
+    pub stock_symbol: String,
+    /// The share count after each day it changed.
+    pub changes: Vec<crate::pnl::PositionChange>,
+    /// The position at each daily close from its first trade through today.
+    pub points: Vec<PositionPoint>,
+}
+
+/// A company Finnhub considers related to another, with its current price.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Peer {
+    pub stock_symbol: String,
+    /// Current price in cents, or `None` if it could not be quoted.
+    pub current_price: Option<i32>,
+    /// Change since the previous close, in hundredths of a percent.
+    pub day_change_percent: Option<i32>,
+}
+
+/// Key metrics of a stock. Each is `None` when the price provider doesn't have it.
+#[derive(Serialize, Debug)]
+pub struct Financials {
+    pub stock_symbol: String,
+    /// Price over trailing twelve-month earnings per share.
+    pub pe_ratio: Option<f64>,
+    pub week_52_high_cents: Option<i64>,
+    pub week_52_low_cents: Option<i64>,
+    /// Market capitalization in millions of dollars.
+    pub market_cap_millions: Option<f64>,
+    pub beta: Option<f64>,
+    /// Indicated annual dividend as a percent of the price.
+    pub dividend_yield_percent: Option<f64>,
+    /// Trailing twelve-month earnings per share, in cents.
+    pub eps_cents: Option<i64>,
+}
+
+/// P&L for one period. Amounts are in cents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PnlPeriod {
+    pub start: chrono::NaiveDate,
+    /// Last day of the period.
+    pub end: chrono::NaiveDate,
+    /// P&L realized by sells during the period.
+    pub realized: i64,
+    /// Unrealized P&L of the positions open at the end of the period, at that day's close, or
+    /// `None` if they could not all be priced.
+    pub unrealized: Option<i64>,
+}
+
+/// P&L grouped by period, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PnlPeriods {
+    pub granularity: crate::pnl::Granularity,
+    pub periods: Vec<PnlPeriod>,
+    /// Set when the request's Finnhub budget ran out before every period was priced.
+    pub truncated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_holdings_merge_into_one_at_the_average_cost() {
+        let aapl = |quantity, purchase_price, updated_at: &str| Holding {
+            account_id: String::from("a@example.com"),
+            stock_symbol: String::from("AAPL"),
+            quantity,
+            purchase_price,
+            current_price: 16_000,
+            created_at: Some(updated_at.to_string()),
+            updated_at: Some(updated_at.to_string()),
+            ..Default::default()
+        };
+
+        let merged = Holding::merge(vec![
+            aapl(3, 20_000, "2024-03-02T15:00:00.000Z"),
+            aapl(1, 10_000, "2024-03-01T15:00:00.000Z"),
+        ])
+        .unwrap();
+
+        assert_eq!(merged.quantity, 4);
+        assert_eq!(merged.purchase_price, 17_500);
+        assert_eq!(merged.total_value, 64_000);
+        assert_eq!(
+            merged.created_at.as_deref(),
+            Some("2024-03-01T15:00:00.000Z")
+        );
+        assert!(Holding::merge(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn snapshot_holdings_restore_the_captured_position() {
+        let holding = Holding {
+            account_id: String::from("a@example.com"),
+            stock_symbol: String::from("AAPL"),
+            stock_name: String::from("Apple Inc"),
+            quantity: 3,
+            purchase_price: 15_000,
+            current_price: 17_000,
+            total_value: 51_000,
+            delisted: true,
+            ..Default::default()
+        };
+
+        let restored = SnapshotHolding::from(&holding)
+            .into_holding("a@example.com", "2024-03-05T15:00:00.000Z");
+
+        assert_eq!(restored.stock_symbol, holding.stock_symbol);
+        assert_eq!(restored.stock_name, holding.stock_name);
+        assert_eq!(restored.quantity, 3);
+        assert_eq!(restored.purchase_price, 15_000);
+        assert_eq!(restored.current_price, 17_000);
+        assert_eq!(restored.total_value, 51_000);
+        assert!(restored.delisted);
+        assert_eq!(
+            restored.created_at.as_deref(),
+            Some("2024-03-05T15:00:00.000Z")
+        );
+    }
 }
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Deserializer};
+
+/// Parse a decimal dollar amount such as `"100"`, `"100.5"` or `"-100.50"` into integer cents.
+/// Amounts with more than two decimal places are rejected rather than rounded.
+pub fn parse_cents(input: &str) -> Result<i64, String> {
+    let input = input.trim();
+    let (negative, digits) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+
+    let (dollars, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if dollars.is_empty() && fraction.is_empty() {
+        return Err(format!("Invalid amount: {:?}", input));
+    }
+    if !dollars
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("Invalid amount: {:?}", input));
+    }
+    if fraction.len() > 2 {
+        return Err(format!(
+            "Amount {:?} has more than two decimal places",
+            input
+        ));
+    }
+
+    let dollars: i64 = if dollars.is_empty() {
+        0
+    } else {
+        dollars
+            .parse()
+            .map_err(|_| format!("Invalid amount: {:?}", input))?
+    };
+    // Pad the fraction to exactly two digits, so "5" means 50 cents
+    let cents: i64 = format!("{:0<2}", fraction).parse().unwrap_or(0);
+
+    let total = dollars
+        .checked_mul(100)
+        .and_then(|d| d.checked_add(cents))
+        .ok_or_else(|| format!("Amount {:?} is too large", input))?;
+    Ok(if negative { -total } else { total })
+}
+
+/// A dollar amount as sent by clients, either a string or a JSON number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DollarAmount {
+    Text(String),
+    Integer(i64),
+    Decimal(f64),
+}
+
+impl DollarAmount {
+    /// Numbers go through their shortest decimal representation, so `100.55` is exactly 10055
+    /// cents and `100.555` is rejected like its string form.
+    fn into_cents(self) -> Result<i64, String> {
+        match self {
+            DollarAmount::Text(s) => parse_cents(&s),
+            DollarAmount::Integer(n) => n
+                .checked_mul(100)
+                .ok_or_else(|| format!("Amount {} is too large", n)),
+            DollarAmount::Decimal(n) => parse_cents(&n.to_string()),
+        }
+    }
+}
+
+/// Deserialize a dollar string or number into integer cents.
+pub fn deserialize_cents<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    DollarAmount::deserialize(deserializer)?
+        .into_cents()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional dollar string or number into integer cents.
+pub fn deserialize_optional_cents<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<DollarAmount>::deserialize(deserializer)?
+        .map(DollarAmount::into_cents)
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// How money in a response is rounded for display. Storage is always exact cents.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// Whole dollars, still expressed in cents.
+    Dollars,
+}
+
+/// Query parameter selecting display rounding, e.g. `?round=dollars`.
+#[derive(Deserialize, Debug, Default)]
+pub struct RoundingQuery {
+    pub round: Option<Rounding>,
+}
+
+/// Round cents to the nearest whole dollar, halves away from zero.
+pub fn round_dollars(cents: i32) -> i32 {
+    let dollars = (cents.abs() + 50) / 100 * 100;
+    if cents < 0 {
+        -dollars
+    } else {
+        dollars
+    }
+}
+
+/// Change as a percentage of the previous value, in hundredths of a percent, or 0 if there was
+/// no previous value.
+pub fn change_percent(change: i32, current: i32) -> i32 {
+    let previous = (current - change) as i64;
+    if previous == 0 {
+        return 0;
+    }
+    (change as i64 * 10_000 / previous) as i32
+}
+
+/// Value of `quantity` shares at `price` cents each, in cents. Computed in i64 since large orders
+/// overflow i32.
+pub fn notional(price: i32, quantity: i32) -> i64 {
+    price as i64 * quantity as i64
+}
+
+/// A price per share, held in tenths of a cent. Prices are whole cents except for quotes under
+/// `SUB_CENT_PRICING_BELOW`, which keep the tenth of a cent through fills, holdings and
+/// valuation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SharePrice(i64);
+
+impl SharePrice {
+    pub fn from_cents(cents: i32) -> Self {
+        SharePrice(cents as i64 * 10)
+    }
+
+    pub fn from_tenths(tenths: i64) -> Self {
+        SharePrice(tenths)
+    }
+
+    /// The price of a quote of `price` dollars: truncated to whole cents, or to the nearest tenth
+    /// of a cent when under `sub_cent_below` cents.
+    pub fn from_quote(price: f64, sub_cent_below: Option<i64>) -> Self {
+        let cents = (price * 100.0) as i64;
+        match sub_cent_below {
+            Some(threshold) if cents < threshold => SharePrice((price * 1000.0).round() as i64),
+            _ => SharePrice(cents * 10),
+        }
+    }
+
+    /// The price truncated to whole cents, as stored alongside the exact price.
+    pub fn cents(self) -> i32 {
+        (self.0 / 10) as i32
+    }
+
+    pub fn tenths(self) -> i64 {
+        self.0
+    }
+
+    /// The price in tenths of a cent if it isn't a whole number of cents. Stored only then, so
+    /// whole-cent prices keep their usual shape.
+    pub fn sub_cent(self) -> Option<i64> {
+        (self.0 % 10 != 0).then_some(self.0)
+    }
+
+    /// Value of `quantity` shares at this price, in cents.
+    pub fn value(self, quantity: i64) -> i64 {
+        self.0 * quantity / 10
+    }
+}
+
+impl From<i32> for SharePrice {
+    fn from(cents: i32) -> Self {
+        SharePrice::from_cents(cents)
+    }
+}
+
+/// Value of `quantity` shares quoted at `price` dollars, in cents. Quotes are normally truncated
+/// to whole cents before multiplying, which for sub-dollar stocks can lose a large share of the
+/// value; quotes under `sub_cent_below` cents are kept to a tenth of a cent instead.
+pub fn position_value(price: f64, quantity: i64, sub_cent_below: Option<i64>) -> i64 {
+    SharePrice::from_quote(price, sub_cent_below).value(quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_whole_dollars() {
+        assert_eq!(parse_cents("100"), Ok(10_000));
+    }
+
+    #[test]
+    fn pads_a_single_decimal_place() {
+        assert_eq!(parse_cents("100.5"), Ok(10_050));
+    }
+
+    #[test]
+    fn rejects_more_than_two_decimal_places() {
+        assert!(parse_cents("100.555").is_err());
+    }
+
+    #[test]
+    fn parses_negative_amounts() {
+        assert_eq!(parse_cents("-100.50"), Ok(-10_050));
+    }
+
+    #[test]
+    fn multiplies_notionals_without_overflowing() {
+        assert_eq!(notional(100_000, 100_000), 10_000_000_000);
+    }
+
+    #[test]
+    fn sub_cent_pricing_values_penny_stocks_more_closely() {
+        // A million shares at $0.0123 are worth $12,300
+        let exact: i64 = 1_230_000;
+        let truncated = position_value(0.0123, 1_000_000, None);
+        let sub_cent = position_value(0.0123, 1_000_000, Some(100));
+
+        assert_eq!(truncated, 1_000_000);
+        assert_eq!(sub_cent, 1_200_000);
+        assert!((exact - sub_cent).abs() < (exact - truncated).abs());
+        // Quotes above the threshold keep whole cents
+        assert_eq!(position_value(12.345, 10, Some(100)), 12_340);
+    }
+}
@@ -0,0 +1,182 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// JSON keys holding amounts of money in cents. Responses carry money under these names only,
+/// and never use them for anything else. Keys ending in `_cents` are left alone, since their
+/// name promises cents.
+const MONEY_FIELDS: &[&str] = &[
+    "amount",
+    "annual_dividend_per_share",
+    "annual_income",
+    "cash",
+    "change",
+    "commission",
+    "computed_value",
+    "cost_basis",
+    "current_price",
+    "day_change",
+    "drawdown",
+    "drift",
+    "fee",
+    "final_value",
+    "gain",
+    "invested",
+    "notional",
+    "notional_fee",
+    "overall_change",
+    "price",
+    "promotion_discount",
+    "purchase_price",
+    "realized",
+    "realized_pnl",
+    "stored_value",
+    "total",
+    "total_annual_income",
+    "total_value",
+    "unrealized",
+    "value",
+];
+
+/// How money is represented in JSON responses. Amounts are computed and stored in cents
+/// regardless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MoneyOutput {
+    /// Integer cents, e.g. `1234`.
+    #[default]
+    Cents,
+    /// Dollars as a number, e.g. `12.34`.
+    Dollars,
+    /// Dollars as a string with two decimal places, e.g. `"12.34"`.
+    String,
+}
+
+impl FromStr for MoneyOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cents" => Ok(MoneyOutput::Cents),
+            "dollars" => Ok(MoneyOutput::Dollars),
+            "string" => Ok(MoneyOutput::String),
+            _ => Err(format!("Unknown money output: {}", s)),
+        }
+    }
+}
+
+impl MoneyOutput {
+    /// Represent an amount of cents.
+    pub fn format(self, cents: i64) -> Value {
+        match self {
+            MoneyOutput::Cents => Value::from(cents),
+            MoneyOutput::Dollars => Value::from(cents as f64 / 100.0),
+            MoneyOutput::String => {
+                let sign = if cents < 0 { "-" } else { "" };
+                let cents = cents.unsigned_abs();
+                Value::from(format!("{}{}.{:02}", sign, cents / 100, cents % 100))
+            }
+        }
+    }
+
+    /// Rewrite every integer under a money key, at any depth, in this representation.
+    pub fn apply(self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    match value.as_i64() {
+                        Some(cents) if MONEY_FIELDS.contains(&key.as_str()) => {
+                            *value = self.format(cents)
+                        }
+                        _ => self.apply(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Middleware rewriting money in successful JSON responses as configured by `MONEY_OUTPUT`.
+/// Downloads and other responses pass through.
+pub async fn format_money(State(output): State<MoneyOutput>, req: Request, next: Next) -> Response {
+    let response = next.run(req).await;
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if output == MoneyOutput::Cents
+        || !response.status().is_success()
+        || !is_json
+        || response.headers().contains_key(CONTENT_DISPOSITION)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Error reading response body for money output: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut data: Value = match serde_json::from_slice(&bytes) {
+        Ok(data) => data,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+
+    output.apply(&mut data);
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(data.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(output: MoneyOutput) -> Value {
+        let mut value = json!({
+            "cash": -1234,
+            "trades_count": 3,
+            "holdings": [{ "stock_symbol": "AAPL", "current_price": 15005, "quantity": 2 }],
+        });
+        output.apply(&mut value);
+        value
+    }
+
+    #[test]
+    fn cents_are_left_as_integers() {
+        assert_eq!(render(MoneyOutput::Cents)["cash"], json!(-1234));
+        assert_eq!(
+            render(MoneyOutput::Cents)["holdings"][0]["current_price"],
+            json!(15005)
+        );
+    }
+
+    #[test]
+    fn dollars_are_numbers() {
+        let value = render(MoneyOutput::Dollars);
+        assert_eq!(value["cash"], json!(-12.34));
+        assert_eq!(value["holdings"][0]["current_price"], json!(150.05));
+        assert_eq!(value["holdings"][0]["quantity"], json!(2));
+    }
+
+    #[test]
+    fn strings_keep_two_decimal_places() {
+        let value = render(MoneyOutput::String);
+        assert_eq!(value["cash"], json!("-12.34"));
+        assert_eq!(value["holdings"][0]["current_price"], json!("150.05"));
+        assert_eq!(value["trades_count"], json!(3));
+    }
+}
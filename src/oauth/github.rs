@@ -0,0 +1,176 @@
+use super::{credentials, OAuthProvider, OAuthTokens};
+use crate::auth::{Scope, SessionUser};
+use async_trait::async_trait;
+use reqwest::header::{ACCEPT, USER_AGENT};
+use reqwest::Client;
+use serde::Deserialize;
+use url::Url;
+
+/// GitHub login, configured with `GITHUB_CLIENT_ID`, `GITHUB_CLIENT_SECRET`, and
+/// `GITHUB_REDIRECT_URI`.
+pub struct GitHub;
+
+/// Profile from GitHub's `/user` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct GitHubUser {
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+/// Address from GitHub's `/user/emails` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct GitHubEmail {
+    pub email: String,
+    pub primary: bool,
+    pub verified: bool,
+}
+
+/// Map a GitHub profile onto the session payload. The account id must be a verified email, so
+/// this uses the primary address if verified, else any verified address, and fails without one.
+pub fn map_user(user: GitHubUser, emails: &[GitHubEmail]) -> Option<SessionUser> {
+    let email = emails
+        .iter()
+        .filter(|e| e.verified)
+        .max_by_key(|e| e.primary)?
+        .email
+        .clone();
+    Some(SessionUser {
+        email,
+        name: user.name.filter(|n| !n.is_empty()).unwrap_or(user.login),
+        picture: user.avatar_url.unwrap_or_default(),
+        provider: String::from("github"),
+        scopes: Scope::all(),
+    })
+}
+
+#[async_trait]
+impl OAuthProvider for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn authorize_url(&self) -> Result<Url, String> {
+        let client_id = &credentials("github")?.client_id;
+        let redirect_uri = &credentials("github")?.redirect_uri;
+
+        let mut url = Url::parse("https://github.com/login/oauth/authorize").unwrap();
+        url.query_pairs_mut()
+            .append_pair("client_id", client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", "read:user user:email");
+        Ok(url)
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<OAuthTokens, String> {
+        let client_id = &credentials("github")?.client_id;
+        let client_secret = &credentials("github")?.client_secret;
+        let redirect_uri = &credentials("github")?.redirect_uri;
+
+        let tokens = Client::new()
+            .post("https://github.com/login/oauth/access_token")
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("code", code),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("redirect_uri", redirect_uri.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<OAuthTokens>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // OAuth app tokens don't expire, so the access token doubles as the revalidation credential
+        Ok(OAuthTokens {
+            refresh_token: Some(tokens.access_token.clone()),
+            ..tokens
+        })
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<String, String> {
+        Ok(refresh_token.to_string())
+    }
+
+    async fn user_info(&self, access_token: &str) -> Result<SessionUser, String> {
+        let client = Client::new();
+        let user = client
+            .get("https://api.github.com/user")
+            .bearer_auth(access_token)
+            .header(USER_AGENT, "stocksim-backend")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<GitHubUser>()
+            .await
+            .map_err(|e| e.to_string())?;
+        let emails = client
+            .get("https://api.github.com/user/emails")
+            .bearer_auth(access_token)
+            .header(USER_AGENT, "stocksim-backend")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<Vec<GitHubEmail>>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        map_user(user, &emails).ok_or_else(|| String::from("No verified email on GitHub account"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: Option<&str>) -> GitHubUser {
+        GitHubUser {
+            login: String::from("octocat"),
+            name: name.map(String::from),
+            avatar_url: Some(String::from("https://avatars.example.com/octocat")),
+        }
+    }
+
+    fn email(email: &str, primary: bool, verified: bool) -> GitHubEmail {
+        GitHubEmail {
+            email: email.to_string(),
+            primary,
+            verified,
+        }
+    }
+
+    #[test]
+    fn maps_the_primary_verified_email() {
+        let emails = [
+            email("other@example.com", false, true),
+            email("octocat@example.com", true, true),
+        ];
+        let mapped = map_user(user(Some("The Octocat")), &emails).unwrap();
+        assert_eq!(mapped.email, "octocat@example.com");
+        assert_eq!(mapped.name, "The Octocat");
+        assert_eq!(mapped.picture, "https://avatars.example.com/octocat");
+        assert_eq!(mapped.provider, "github");
+    }
+
+    #[test]
+    fn falls_back_to_another_verified_email_and_the_login() {
+        let emails = [
+            email("octocat@example.com", true, false),
+            email("other@example.com", false, true),
+        ];
+        let mapped = map_user(user(Some("")), &emails).unwrap();
+        assert_eq!(mapped.email, "other@example.com");
+        assert_eq!(mapped.name, "octocat");
+    }
+
+    #[test]
+    fn refuses_users_without_a_verified_email() {
+        let emails = [email("octocat@example.com", true, false)];
+        assert!(map_user(user(None), &emails).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use super::{credentials, OAuthProvider, OAuthTokens};
+use crate::auth::SessionUser;
+use async_trait::async_trait;
+use reqwest::Client;
+use url::Url;
+
+/// Google login, configured with `GOOGLE_CLIENT_ID`, `GOOGLE_CLIENT_SECRET`, and
+/// `GOOGLE_REDIRECT_URI`.
+pub struct Google;
+
+#[async_trait]
+impl OAuthProvider for Google {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn authorize_url(&self) -> Result<Url, String> {
+        let client_id = &credentials("google")?.client_id;
+        let redirect_uri = &credentials("google")?.redirect_uri;
+
+        let mut url = Url::parse("https://accounts.google.com/o/oauth2/v2/auth").unwrap();
+        url.query_pairs_mut()
+            .append_pair("client_id", client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("scope", "openid email profile")
+            .append_pair("access_type", "offline")
+            // Google only returns a refresh token on consent, which revalidation needs
+            .append_pair("prompt", "consent");
+        Ok(url)
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<OAuthTokens, String> {
+        let client_id = &credentials("google")?.client_id;
+        let client_secret = &credentials("google")?.client_secret;
+        let redirect_uri = &credentials("google")?.redirect_uri;
+
+        Client::new()
+            .post("https://oauth2.googleapis.com/token")
+            .form(&[
+                ("code", code),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("grant_type", "authorization_code"),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<OAuthTokens>()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<String, String> {
+        let client_id = &credentials("google")?.client_id;
+        let client_secret = &credentials("google")?.client_secret;
+
+        let tokens = Client::new()
+            .post("https://oauth2.googleapis.com/token")
+            .form(&[
+                ("refresh_token", refresh_token),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("grant_type", "refresh_token"),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<OAuthTokens>()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(tokens.access_token)
+    }
+
+    async fn user_info(&self, access_token: &str) -> Result<SessionUser, String> {
+        Client::new()
+            .get("https://www.googleapis.com/oauth2/v2/userinfo")
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<SessionUser>()
+            .await
+            .map(|user| SessionUser {
+                provider: String::from("google"),
+                ..user
+            })
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn authorize_url_asks_for_a_refresh_token() {
+        super::super::init(&Config::for_tests());
+        let url = Google.authorize_url().unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&(String::from("access_type"), String::from("offline"))));
+        assert!(query.contains(&(String::from("prompt"), String::from("consent"))));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::auth::SessionUser;
+use crate::config::Config;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::OnceLock;
+use url::Url;
+
+pub mod github;
+pub mod google;
+
+pub use github::GitHub;
+pub use google::Google;
+
+/// Tokens returned by a provider's code exchange.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    /// Credential for fetching fresh user info later, if the provider issues one.
+    pub refresh_token: Option<String>,
+}
+
+/// An OAuth login provider. Every provider maps its user info onto the same session payload,
+/// keyed by the user's verified email so accounts stay the same across providers.
+#[async_trait]
+pub trait OAuthProvider: Send + Sync {
+    /// Name used in the `/login/:provider` and `/callback/:provider` routes.
+    fn name(&self) -> &'static str;
+    /// Where to send the user to log in.
+    fn authorize_url(&self) -> Result<Url, String>;
+    /// Exchange the authorization code from the callback for tokens.
+    async fn exchange_code(&self, code: &str) -> Result<OAuthTokens, String>;
+    /// Trade a refresh token for a new access token.
+    async fn refresh(&self, refresh_token: &str) -> Result<String, String>;
+    /// Fetch the user's profile with an access token.
+    async fn user_info(&self, access_token: &str) -> Result<SessionUser, String>;
+}
+
+/// Look up a provider by its route name.
+pub fn provider(name: &str) -> Option<Box<dyn OAuthProvider>> {
+    match name {
+        "google" => Some(Box::new(Google)),
+        "github" => Some(Box::new(GitHub)),
+        _ => None,
+    }
+}
+
+/// An OAuth app's client credentials and the callback registered for it.
+#[derive(Debug, Clone, Default)]
+pub struct OAuthCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+}
+
+static GOOGLE_CREDENTIALS: OnceLock<OAuthCredentials> = OnceLock::new();
+static GITHUB_CREDENTIALS: OnceLock<OAuthCredentials> = OnceLock::new();
+
+/// Store the providers' credentials from the configuration for all later logins. GitHub login
+/// is unavailable unless it is configured.
+pub fn init(config: &Config) {
+    let _ = GOOGLE_CREDENTIALS.set(config.google_oauth.clone());
+    if let Some(github) = &config.github_oauth {
+        let _ = GITHUB_CREDENTIALS.set(github.clone());
+    }
+}
+
+/// The credentials configured for a provider.
+fn credentials(provider: &str) -> Result<&'static OAuthCredentials, String> {
+    let credentials = match provider {
+        "google" => GOOGLE_CREDENTIALS.get(),
+        "github" => GITHUB_CREDENTIALS.get(),
+        _ => None,
+    };
+    credentials.ok_or_else(|| format!("{} login is not configured", provider))
+}
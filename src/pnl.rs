@@ -0,0 +1,384 @@
+use crate::models::{Transaction, TransactionSummary};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Running average-cost position for a single symbol. All amounts are in cents.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub quantity: i64,
+    pub cost_basis: i64,
+    pub realized_pnl: i64,
+}
+
+impl Position {
+    /// Start from a summary of archived transactions.
+    pub fn from_summary(summary: &TransactionSummary) -> Self {
+        Position {
+            quantity: summary.quantity,
+            cost_basis: summary.cost_basis,
+            realized_pnl: summary.realized_pnl,
+        }
+    }
+
+    /// Apply a BUY or SELL to the position. Buy fees are part of the cost basis, and sells
+    /// realize the proceeds net of fees minus the average cost of the shares sold.
+    pub fn apply(&mut self, transaction: &Transaction) {
+        let quantity = transaction.quantity as i64;
+        let price = transaction.price as i64;
+        let fee = transaction.fee as i64;
+        match transaction.transaction_type.as_str() {
+            "BUY" => {
+                self.quantity += quantity;
+                self.cost_basis += price * quantity + fee;
+            }
+            "SELL" => {
+                let sold_cost = if self.quantity > 0 {
+                    self.cost_basis * quantity.min(self.quantity) / self.quantity
+                } else {
+                    0
+                };
+                self.realized_pnl += price * quantity - fee - sold_cost;
+                self.cost_basis -= sold_cost;
+                self.quantity -= quantity;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parse a transaction timestamp.
+pub fn parse_timestamp(transaction: &Transaction) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(&transaction.timestamp).ok()
+}
+
+/// Sort transactions oldest first.
+pub fn sort_chronologically(transactions: &mut [Transaction]) {
+    transactions.sort_by_key(parse_timestamp);
+}
+
+/// Compute average-cost positions per symbol, starting from archived summaries and replaying the
+/// remaining transactions in order.
+pub fn positions(
+    summaries: &[TransactionSummary],
+    transactions: &[Transaction],
+) -> HashMap<String, Position> {
+    let mut positions: HashMap<String, Position> = summaries
+        .iter()
+        .map(|s| (s.stock_symbol.clone(), Position::from_summary(s)))
+        .collect();
+
+    let mut ordered = transactions.to_vec();
+    sort_chronologically(&mut ordered);
+    for transaction in &ordered {
+        positions
+            .entry(transaction.stock_symbol.clone())
+            .or_default()
+            .apply(transaction);
+    }
+    positions
+}
+
+/// Total realized P&L in cents across all symbols, including archived lots.
+pub fn realized_pnl(summaries: &[TransactionSummary], transactions: &[Transaction]) -> i64 {
+    positions(summaries, transactions)
+        .values()
+        .map(|p| p.realized_pnl)
+        .sum()
+}
+
+/// Cash and share counts reconstructed for the end of a past day.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoricalHoldings {
+    /// Cash in cents.
+    pub cash: i64,
+    /// Shares held per symbol. Closed positions are left out.
+    pub quantities: BTreeMap<String, i64>,
+}
+
+/// Replay transactions made on or before `date` (in UTC) on top of `starting_cash`. A date
+/// before the first transaction yields just the starting cash.
+pub fn holdings_as_of(
+    transactions: &[Transaction],
+    date: NaiveDate,
+    starting_cash: i64,
+) -> HistoricalHoldings {
+    let mut holdings = HistoricalHoldings {
+        cash: starting_cash,
+        quantities: BTreeMap::new(),
+    };
+    for transaction in transactions {
+        let Some(timestamp) = parse_timestamp(transaction) else {
+            continue;
+        };
+        if timestamp.with_timezone(&Utc).date_naive() > date {
+            continue;
+        }
+        let quantity = transaction.quantity as i64;
+        let amount = transaction.price as i64 * quantity;
+        let fee = transaction.fee as i64;
+        let held = holdings
+            .quantities
+            .entry(transaction.stock_symbol.clone())
+            .or_default();
+        match transaction.transaction_type.as_str() {
+            "BUY" => {
+                holdings.cash -= amount + fee;
+                *held += quantity;
+            }
+            "SELL" => {
+                holdings.cash += amount - fee;
+                *held -= quantity;
+            }
+            _ => {}
+        }
+    }
+    holdings.quantities.retain(|_, quantity| *quantity != 0);
+    holdings
+}
+
+/// Shares of one symbol held at the end of a day the position changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionChange {
+    pub date: NaiveDate,
+    pub quantity: i64,
+}
+
+/// Replay the transactions in `symbol`, in order, into the share count held at the end of each
+/// day (in UTC) it changed. Symbols are compared case-insensitively. Days whose trades net out
+/// to no change are left out.
+pub fn position_timeline(transactions: &[Transaction], symbol: &str) -> Vec<PositionChange> {
+    let mut ordered: Vec<(DateTime<FixedOffset>, &Transaction)> = transactions
+        .iter()
+        .filter(|t| t.stock_symbol.eq_ignore_ascii_case(symbol))
+        .filter_map(|t| Some((parse_timestamp(t)?, t)))
+        .collect();
+    ordered.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut timeline: Vec<PositionChange> = Vec::new();
+    let mut quantity = 0;
+    for (timestamp, transaction) in ordered {
+        match transaction.transaction_type.as_str() {
+            "BUY" => quantity += transaction.quantity as i64,
+            "SELL" => quantity -= transaction.quantity as i64,
+            _ => continue,
+        }
+        let date = timestamp.with_timezone(&Utc).date_naive();
+        match timeline.last_mut() {
+            Some(last) if last.date == date => last.quantity = quantity,
+            _ => timeline.push(PositionChange { date, quantity }),
+        }
+    }
+    timeline.dedup_by(|later, earlier| later.quantity == earlier.quantity);
+    if timeline.first().is_some_and(|first| first.quantity == 0) {
+        timeline.remove(0);
+    }
+    timeline
+}
+
+/// Shares held at the end of `date`, given a position's timeline.
+pub fn quantity_on(timeline: &[PositionChange], date: NaiveDate) -> i64 {
+    timeline
+        .iter()
+        .take_while(|change| change.date <= date)
+        .last()
+        .map_or(0, |change| change.quantity)
+}
+
+/// Length of the periods P&L is grouped into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    #[default]
+    Month,
+    Year,
+}
+
+impl Granularity {
+    /// First day of the period containing `date`.
+    pub fn period_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            Granularity::Month => date.with_day(1).unwrap(),
+            Granularity::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap(),
+        }
+    }
+
+    /// First day of the period after the one starting on `start`.
+    pub fn next_start(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => start + chrono::Duration::days(1),
+            Granularity::Week => start + chrono::Duration::days(7),
+            Granularity::Month => match start.month() {
+                12 => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1).unwrap(),
+                month => NaiveDate::from_ymd_opt(start.year(), month + 1, 1).unwrap(),
+            },
+            Granularity::Year => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1).unwrap(),
+        }
+    }
+}
+
+/// P&L realized in one period and the positions still open at its end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodPositions {
+    pub start: NaiveDate,
+    /// Last day of the period.
+    pub end: NaiveDate,
+    /// P&L realized by sells in the period, in cents.
+    pub realized_pnl: i64,
+    /// Positions open at the end of the period, by symbol.
+    pub open: BTreeMap<String, Position>,
+}
+
+/// Group realized P&L into consecutive periods from the first transaction through the period
+/// containing `until`, with zeros for periods without sells. Archived lots seed the positions but
+/// their P&L, realized before the first remaining transaction, is not attributed to any period.
+pub fn pnl_by_period(
+    summaries: &[TransactionSummary],
+    transactions: &[Transaction],
+    granularity: Granularity,
+    until: NaiveDate,
+) -> Vec<PeriodPositions> {
+    let mut ordered: Vec<(NaiveDate, &Transaction)> = transactions
+        .iter()
+        .filter_map(|t| Some((parse_timestamp(t)?.with_timezone(&Utc).date_naive(), t)))
+        .collect();
+    ordered.sort_by_key(|(_, t)| parse_timestamp(t));
+    let Some((first, _)) = ordered.first() else {
+        return Vec::new();
+    };
+
+    let mut positions: BTreeMap<String, Position> = summaries
+        .iter()
+        .map(|s| (s.stock_symbol.clone(), Position::from_summary(s)))
+        .collect();
+    let mut pending = ordered.iter().peekable();
+    let mut periods = Vec::new();
+    let mut start = granularity.period_start(*first);
+    while start <= until {
+        let next = granularity.next_start(start);
+        let mut realized_pnl = 0;
+        while let Some((_, transaction)) = pending.next_if(|(date, _)| *date < next) {
+            let position = positions
+                .entry(transaction.stock_symbol.clone())
+                .or_default();
+            let before = position.realized_pnl;
+            position.apply(transaction);
+            realized_pnl += position.realized_pnl - before;
+        }
+        periods.push(PeriodPositions {
+            start,
+            end: next - chrono::Duration::days(1),
+            realized_pnl,
+            open: positions
+                .iter()
+                .filter(|(_, p)| p.quantity > 0)
+                .map(|(symbol, p)| (symbol.clone(), *p))
+                .collect(),
+        });
+        start = next;
+    }
+    periods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(kind: &str, symbol: &str, quantity: i32, price: i32, timestamp: &str) -> Transaction {
+        Transaction {
+            stock_symbol: symbol.to_string(),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            timestamp: timestamp.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn holdings_as_of_replays_only_earlier_days() {
+        let transactions = [
+            trade("BUY", "AAPL", 10, 10_000, "2024-01-02T15:00:00Z"),
+            trade("BUY", "MSFT", 5, 20_000, "2024-01-03T15:00:00Z"),
+            trade("SELL", "AAPL", 4, 11_000, "2024-01-03T23:30:00-05:00"),
+            trade("SELL", "MSFT", 5, 21_000, "2024-01-05T15:00:00Z"),
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+
+        let holdings = holdings_as_of(&transactions, date, 1_000_000);
+
+        // The AAPL sell was on the 4th in UTC
+        assert_eq!(holdings.cash, 1_000_000 - 100_000 - 100_000);
+        assert_eq!(
+            holdings.quantities,
+            BTreeMap::from([(String::from("AAPL"), 10), (String::from("MSFT"), 5)])
+        );
+    }
+
+    #[test]
+    fn holdings_before_the_first_trade_are_the_starting_cash() {
+        let transactions = [trade("BUY", "AAPL", 10, 10_000, "2024-01-02T15:00:00Z")];
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let holdings = holdings_as_of(&transactions, date, 1_000_000);
+
+        assert_eq!(holdings.cash, 1_000_000);
+        assert!(holdings.quantities.is_empty());
+    }
+
+    #[test]
+    fn realized_pnl_is_bucketed_by_month_with_empty_months_at_zero() {
+        let transactions = [
+            trade("BUY", "AAPL", 10, 10_000, "2024-01-15T15:00:00Z"),
+            trade("SELL", "AAPL", 4, 12_000, "2024-02-10T15:00:00Z"),
+        ];
+        let until = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+
+        let periods = pnl_by_period(&[], &transactions, Granularity::Month, until);
+
+        let buckets: Vec<(NaiveDate, NaiveDate, i64)> = periods
+            .iter()
+            .map(|p| (p.start, p.end, p.realized_pnl))
+            .collect();
+        let day = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        assert_eq!(
+            buckets,
+            [
+                (day(1, 1), day(1, 31), 0),
+                (day(2, 1), day(2, 29), 8_000),
+                (day(3, 1), day(3, 31), 0),
+            ]
+        );
+        assert_eq!(periods[0].open["AAPL"].quantity, 10);
+        assert_eq!(periods[2].open["AAPL"].quantity, 6);
+    }
+
+    #[test]
+    fn a_buy_add_sell_sequence_replays_into_a_quantity_timeline() {
+        let transactions = [
+            trade("SELL", "aapl", 12, 11_000, "2024-01-09T15:00:00Z"),
+            trade("BUY", "AAPL", 10, 10_000, "2024-01-02T15:00:00Z"),
+            trade("BUY", "MSFT", 5, 20_000, "2024-01-03T15:00:00Z"),
+            trade("BUY", "AAPL", 5, 10_500, "2024-01-05T15:00:00Z"),
+            // Bought and sold back the same day, so the day nets out
+            trade("BUY", "AAPL", 2, 10_500, "2024-01-08T15:00:00Z"),
+            trade("SELL", "AAPL", 2, 10_600, "2024-01-08T16:00:00Z"),
+        ];
+        let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+
+        let timeline = position_timeline(&transactions, "AAPL");
+
+        let changes: Vec<(NaiveDate, i64)> =
+            timeline.iter().map(|c| (c.date, c.quantity)).collect();
+        assert_eq!(changes, [(day(2), 10), (day(5), 15), (day(9), 3)]);
+        assert_eq!(quantity_on(&timeline, day(1)), 0);
+        assert_eq!(quantity_on(&timeline, day(8)), 15);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::models::TradeSide;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A position priced at the current quote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricedPosition {
+    pub stock_symbol: String,
+    pub quantity: i32,
+    /// Price per share in cents.
+    pub price: i32,
+}
+
+/// A whole-share trade moving a position toward its target weight.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RebalanceTrade {
+    pub stock_symbol: String,
+    pub side: TradeSide,
+    pub quantity: i32,
+    /// Estimated price per share in cents.
+    pub price: i32,
+}
+
+/// Check that every weight is between 0 and 1 and that they sum to at most 1.
+pub fn validate_targets(targets: &HashMap<String, f64>) -> Result<(), String> {
+    if targets.values().any(|w| !(0.0..=1.0).contains(w)) {
+        return Err(String::from("Target weights must be between 0 and 1."));
+    }
+    if targets.values().sum::<f64>() > 1.0 + 1e-9 {
+        return Err(String::from(
+            "Target weights must not add up to more than 1.",
+        ));
+    }
+    Ok(())
+}
+
+/// Whole-share trades moving `positions` toward `targets`, weights of the account value
+/// (cash plus positions). Held symbols missing from `targets` are sold off, and target symbols
+/// not yet held need an entry in `positions` with a zero quantity for their price. Sells come
+/// first so their proceeds fund the buys; each group is sorted by symbol.
+pub fn plan(
+    positions: &[PricedPosition],
+    cash: i64,
+    targets: &HashMap<String, f64>,
+) -> Vec<RebalanceTrade> {
+    let total_value = cash
+        + positions
+            .iter()
+            .map(|p| p.quantity as i64 * p.price as i64)
+            .sum::<i64>();
+
+    let mut sells = BTreeMap::new();
+    let mut buys = BTreeMap::new();
+    for position in positions.iter().filter(|p| p.price > 0) {
+        let weight = targets.get(&position.stock_symbol).copied().unwrap_or(0.0);
+        let target_value = (total_value as f64 * weight) as i64;
+        let current_value = position.quantity as i64 * position.price as i64;
+        // Truncate toward zero so a rebalance never overshoots its target
+        let shares = ((target_value - current_value) / position.price as i64) as i32;
+        let trade = |side, quantity| RebalanceTrade {
+            stock_symbol: position.stock_symbol.clone(),
+            side,
+            quantity,
+            price: position.price,
+        };
+        if shares < 0 {
+            sells.insert(&position.stock_symbol, trade(TradeSide::Sell, -shares));
+        } else if shares > 0 {
+            buys.insert(&position.stock_symbol, trade(TradeSide::Buy, shares));
+        }
+    }
+
+    sells.into_values().chain(buys.into_values()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(symbol: &str, quantity: i32, price: i32) -> PricedPosition {
+        PricedPosition {
+            stock_symbol: symbol.to_string(),
+            quantity,
+            price,
+        }
+    }
+
+    fn trade(symbol: &str, side: TradeSide, quantity: i32, price: i32) -> RebalanceTrade {
+        RebalanceTrade {
+            stock_symbol: symbol.to_string(),
+            side,
+            quantity,
+            price,
+        }
+    }
+
+    #[test]
+    fn plans_sells_then_buys_toward_the_targets() {
+        let positions = [
+            position("AAPL", 10, 10_000),
+            position("GOOG", 5, 20_000),
+            position("MSFT", 0, 5_000),
+        ];
+        let targets = HashMap::from([(String::from("AAPL"), 0.25), (String::from("MSFT"), 0.5)]);
+
+        assert_eq!(
+            plan(&positions, 0, &targets),
+            [
+                trade("AAPL", TradeSide::Sell, 5, 10_000),
+                trade("GOOG", TradeSide::Sell, 5, 20_000),
+                trade("MSFT", TradeSide::Buy, 20, 5_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions_on_target_are_left_alone() {
+        let positions = [position("AAPL", 5, 10_000)];
+        let targets = HashMap::from([(String::from("AAPL"), 0.5)]);
+        assert!(plan(&positions, 50_000, &targets).is_empty());
+    }
+
+    #[test]
+    fn rejects_weights_out_of_range_or_over_one() {
+        let over = HashMap::from([(String::from("AAPL"), 0.6), (String::from("MSFT"), 0.6)]);
+        assert!(validate_targets(&over).is_err());
+        let negative = HashMap::from([(String::from("AAPL"), -0.1)]);
+        assert!(validate_targets(&negative).is_err());
+        let valid = HashMap::from([(String::from("AAPL"), 0.4), (String::from("MSFT"), 0.6)]);
+        assert!(validate_targets(&valid).is_ok());
+    }
+}
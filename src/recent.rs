@@ -0,0 +1,69 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Symbols remembered per account.
+pub const RECENT_SYMBOLS: usize = 10;
+
+/// Symbols each account has looked up, most recent first and capped at `RECENT_SYMBOLS`. Kept in
+/// memory, so the lists are lost on restart.
+#[derive(Clone, Default)]
+pub struct RecentSymbols {
+    viewed: Arc<Mutex<HashMap<String, VecDeque<String>>>>,
+}
+
+impl RecentSymbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that an account looked up a symbol, moving it to the front if already listed.
+    pub fn view(&self, account_id: &str, symbol: &str) {
+        let symbol = symbol.trim().to_uppercase();
+        if symbol.is_empty() {
+            return;
+        }
+        let mut viewed = self.viewed.lock().unwrap();
+        let symbols = viewed.entry(account_id.to_string()).or_default();
+        symbols.retain(|s| *s != symbol);
+        symbols.push_front(symbol);
+        symbols.truncate(RECENT_SYMBOLS);
+    }
+
+    /// The symbols an account looked up, most recent first.
+    pub fn list(&self, account_id: &str) -> Vec<String> {
+        let viewed = self.viewed.lock().unwrap();
+        viewed
+            .get(account_id)
+            .map(|symbols| symbols.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_are_listed_most_recent_first() {
+        let recent = RecentSymbols::new();
+        for symbol in ["aapl", "MSFT", "TSLA", "AAPL"] {
+            recent.view("a@example.com", symbol);
+        }
+
+        assert_eq!(recent.list("a@example.com"), ["AAPL", "TSLA", "MSFT"]);
+        assert!(recent.list("b@example.com").is_empty());
+    }
+
+    #[test]
+    fn lists_are_capped() {
+        let recent = RecentSymbols::new();
+        for n in 0..=RECENT_SYMBOLS {
+            recent.view("a@example.com", &format!("SYM{}", n));
+        }
+
+        let listed = recent.list("a@example.com");
+        assert_eq!(listed.len(), RECENT_SYMBOLS);
+        assert_eq!(listed[0], format!("SYM{}", RECENT_SYMBOLS));
+        assert!(!listed.contains(&String::from("SYM0")));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::auth::SessionUser;
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_sessions::Session;
+
+/// Expensive read endpoints whose responses may be cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CachedRoute {
+    /// `GET /leaderboard`, shared by every account.
+    Leaderboard,
+    /// `GET /portfolio/sectors`, per account.
+    Sectors,
+}
+
+type Entry = (Arc<dyn Any + Send + Sync>, Instant);
+
+/// In-memory cache of computed responses, keyed by route and account. Only routes given a TTL are
+/// cached. Entries for an account are dropped whenever one of its write requests succeeds.
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    ttls: Arc<HashMap<CachedRoute, Duration>>,
+    entries: Arc<Mutex<HashMap<(CachedRoute, String), Entry>>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttls: impl IntoIterator<Item = (CachedRoute, Duration)>) -> Self {
+        Self {
+            ttls: Arc::new(ttls.into_iter().collect()),
+            entries: Arc::default(),
+        }
+    }
+
+    /// The cached response for `route` and `key` if it is still fresh. Use an empty key for
+    /// responses shared by every account.
+    pub fn get<T: Clone + 'static>(&self, route: CachedRoute, key: &str) -> Option<T> {
+        let ttl = self.ttls.get(&route)?;
+        let entries = self.entries.lock().unwrap();
+        let (value, stored_at) = entries.get(&(route, key.to_string()))?;
+        if stored_at.elapsed() >= *ttl {
+            return None;
+        }
+        value.downcast_ref::<T>().cloned()
+    }
+
+    /// Cache a response if `route` is configured for caching.
+    pub fn put<T: Send + Sync + 'static>(&self, route: CachedRoute, key: &str, value: T) {
+        let Some(ttl) = self.ttls.get(&route) else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(route, _), (_, stored_at)| {
+            self.ttls
+                .get(route)
+                .is_some_and(|ttl| stored_at.elapsed() < *ttl)
+        });
+        if !ttl.is_zero() {
+            entries.insert((route, key.to_string()), (Arc::new(value), Instant::now()));
+        }
+    }
+
+    /// Drop every cached response for an account.
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().retain(|(_, k), _| k != key);
+    }
+
+    /// Drop every cached response for a route.
+    pub fn invalidate_route(&self, route: CachedRoute) {
+        self.entries.lock().unwrap().retain(|(r, _), _| *r != route);
+    }
+}
+
+/// Middleware dropping the session account's cached responses after any successful request
+/// that isn't a read.
+pub async fn invalidate_on_write(
+    State(cache): State<ResponseCache>,
+    session: Session,
+    req: Request,
+    next: Next,
+) -> Response {
+    let write = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let response = next.run(req).await;
+    if write && response.status().is_success() {
+        if let Ok(Some(info)) = session.get::<SessionUser>("SESSION").await {
+            cache.invalidate(&info.email);
+        }
+    }
+    response
+}
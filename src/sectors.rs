@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Sectors below this share of the holdings' value are folded into "Other".
+pub const OTHER_THRESHOLD_PERCENT: f64 = 2.0;
+
+/// Bucket for holdings whose profile has no industry.
+pub const UNKNOWN_SECTOR: &str = "Unknown";
+/// Bucket collecting sectors below the threshold.
+pub const OTHER_SECTOR: &str = "Other";
+
+/// Share of the holdings' value in one sector.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SectorExposure {
+    pub sector: String,
+    /// Value in cents.
+    pub value: i64,
+    /// Percent of the total holdings value.
+    pub percent: f64,
+}
+
+/// Total `(sector, value)` pairs per sector, largest first, with sectors under
+/// `threshold_percent` of the total folded into a trailing "Other" bucket. Empty sectors count
+/// as "Unknown".
+pub fn aggregate(values: Vec<(String, i64)>, threshold_percent: f64) -> Vec<SectorExposure> {
+    let mut totals: HashMap<String, i64> = HashMap::new();
+    for (sector, value) in values {
+        let sector = match sector.is_empty() {
+            true => String::from(UNKNOWN_SECTOR),
+            false => sector,
+        };
+        *totals.entry(sector).or_default() += value;
+    }
+
+    let total: i64 = totals.values().sum();
+    let percent = |value: i64| match total {
+        0 => 0.0,
+        total => value as f64 / total as f64 * 100.0,
+    };
+
+    let mut other = 0;
+    let mut sectors: Vec<SectorExposure> = Vec::new();
+    for (sector, value) in totals {
+        if percent(value) < threshold_percent {
+            other += value;
+        } else {
+            sectors.push(SectorExposure {
+                sector,
+                value,
+                percent: percent(value),
+            });
+        }
+    }
+    sectors.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.sector.cmp(&b.sector)));
+    if other > 0 {
+        sectors.push(SectorExposure {
+            sector: String::from(OTHER_SECTOR),
+            value: other,
+            percent: percent(other),
+        });
+    }
+    sectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sectors_are_totalled_with_slivers_in_other() {
+        let values = vec![
+            (String::from("Technology"), 50_000),
+            (String::from("Banking"), 30_000),
+            (String::from("Technology"), 10_000),
+            (String::new(), 8_000),
+            (String::from("Airlines"), 1_000),
+            (String::from("Utilities"), 1_000),
+        ];
+
+        let sectors = aggregate(values, OTHER_THRESHOLD_PERCENT);
+
+        let buckets: Vec<(&str, i64)> = sectors
+            .iter()
+            .map(|s| (s.sector.as_str(), s.value))
+            .collect();
+        assert_eq!(
+            buckets,
+            [
+                ("Technology", 60_000),
+                ("Banking", 30_000),
+                (UNKNOWN_SECTOR, 8_000),
+                (OTHER_SECTOR, 2_000),
+            ]
+        );
+        assert_eq!(sectors[0].percent, 60.0);
+        assert_eq!(sectors[3].percent, 2.0);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::auth::SessionUser;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tower_sessions::session::Id;
+use tower_sessions::{Session, SessionStore};
+
+/// Session key holding when the session was created, as a Unix timestamp.
+pub const CREATED_AT_KEY: &str = "CREATED_AT";
+
+/// A logged-in session as listed by `GET /sessions`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ActiveSession {
+    /// Opaque handle for revoking the session. The session id itself is never exposed since it
+    /// is the cookie value.
+    pub id: String,
+    #[serde(skip)]
+    session_id: Id,
+    pub created_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+    /// `User-Agent` of the browser that logged in, if it sent one.
+    pub user_agent: Option<String>,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+/// Tracks which sessions belong to each account so they can be listed and revoked, and so the
+/// oldest can be evicted once an account exceeds `MAX_SESSIONS_PER_ACCOUNT`. The session store
+/// cannot be queried by account, so the index lives in memory and only covers sessions created
+/// since startup.
+#[derive(Clone)]
+pub struct SessionRegistry {
+    max_per_account: Option<usize>,
+    store: Arc<dyn SessionStore>,
+    active: Arc<Mutex<HashMap<String, Vec<ActiveSession>>>>,
+}
+
+impl SessionRegistry {
+    /// Create an empty registry deleting evicted sessions from `store`. No limit is enforced
+    /// when `max_per_account` is `None`.
+    pub fn new(max_per_account: Option<usize>, store: Arc<dyn SessionStore>) -> Self {
+        Self {
+            max_per_account,
+            store,
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record a new session for an account, deleting its oldest sessions if it now has more
+    /// than the limit. Returns the number of evicted sessions.
+    pub async fn register(
+        &self,
+        account_id: &str,
+        id: Id,
+        created_at: DateTime<Utc>,
+        user_agent: Option<String>,
+    ) -> usize {
+        let evicted = {
+            let mut active = self.active.lock().unwrap();
+            let sessions = active.entry(account_id.to_string()).or_default();
+            sessions.retain(|existing| existing.session_id != id);
+            sessions.push(ActiveSession {
+                id: uuid::Uuid::new_v4().to_string(),
+                session_id: id,
+                created_at,
+                last_active_at: created_at,
+                user_agent,
+                current: false,
+            });
+            sessions.sort_by_key(|session| session.created_at);
+            let excess = match self.max_per_account {
+                Some(max) => sessions.len().saturating_sub(max),
+                None => 0,
+            };
+            sessions.drain(..excess).collect::<Vec<_>>()
+        };
+
+        for session in &evicted {
+            tracing::info!(
+                "Evicting session created at {} for {}: session limit reached",
+                session.created_at,
+                account_id
+            );
+            if let Err(e) = self.store.delete(&session.session_id).await {
+                tracing::error!("Error deleting evicted session: {}", e);
+            }
+        }
+        evicted.len()
+    }
+
+    /// Note activity on a session.
+    pub fn touch(&self, account_id: &str, id: Id) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(session) = active
+            .get_mut(account_id)
+            .and_then(|sessions| sessions.iter_mut().find(|s| s.session_id == id))
+        {
+            session.last_active_at = Utc::now();
+        }
+    }
+
+    /// An account's sessions, oldest first, with `current` set on the one with id `current`.
+    pub fn list(&self, account_id: &str, current: Option<Id>) -> Vec<ActiveSession> {
+        let active = self.active.lock().unwrap();
+        let mut sessions = active.get(account_id).cloned().unwrap_or_default();
+        for session in &mut sessions {
+            session.current = Some(session.session_id) == current;
+        }
+        sessions
+    }
+
+    /// Log out one of an account's sessions by the handle from `list`. Returns false if the
+    /// account has no such session.
+    pub async fn revoke(&self, account_id: &str, handle: &str) -> bool {
+        let revoked = {
+            let mut active = self.active.lock().unwrap();
+            let Some(sessions) = active.get_mut(account_id) else {
+                return false;
+            };
+            let Some(index) = sessions.iter().position(|s| s.id == handle) else {
+                return false;
+            };
+            sessions.remove(index)
+        };
+        if let Err(e) = self.store.delete(&revoked.session_id).await {
+            tracing::error!("Error deleting revoked session: {}", e);
+        }
+        true
+    }
+
+    /// Forget a session, e.g. on logout.
+    pub fn unregister(&self, account_id: &str, id: Id) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(sessions) = active.get_mut(account_id) {
+            sessions.retain(|existing| existing.session_id != id);
+            if sessions.is_empty() {
+                active.remove(account_id);
+            }
+        }
+    }
+}
+
+/// Middleware recording when each logged-in session was last used.
+pub async fn track_activity(
+    State(registry): State<SessionRegistry>,
+    session: Session,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let (Ok(Some(info)), Some(id)) = (session.get::<SessionUser>("SESSION").await, session.id())
+    {
+        registry.touch(&info.email, id);
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_sessions::session::Record;
+    use tower_sessions::MemoryStore;
+
+    /// A registry over a session store holding sessions 1 to `count`.
+    async fn registry(
+        max_per_account: Option<usize>,
+        count: i128,
+    ) -> (SessionRegistry, MemoryStore) {
+        let store = MemoryStore::default();
+        for id in 1..=count {
+            store
+                .save(&Record {
+                    id: Id(id),
+                    data: HashMap::new(),
+                    expiry_date: time::OffsetDateTime::now_utc() + time::Duration::hours(1),
+                })
+                .await
+                .unwrap();
+        }
+        let registry = SessionRegistry::new(max_per_account, Arc::new(store.clone()));
+        (registry, store)
+    }
+
+    fn created(minute: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn logging_in_past_the_limit_evicts_the_oldest_session() {
+        let (registry, store) = registry(Some(2), 3).await;
+        assert_eq!(
+            registry
+                .register("a@example.com", Id(1), created(0), None)
+                .await,
+            0
+        );
+        assert_eq!(
+            registry
+                .register("a@example.com", Id(2), created(1), None)
+                .await,
+            0
+        );
+        assert_eq!(
+            registry
+                .register("a@example.com", Id(3), created(2), None)
+                .await,
+            1
+        );
+
+        let listed: Vec<Id> = registry
+            .list("a@example.com", None)
+            .iter()
+            .map(|s| s.session_id)
+            .collect();
+        assert_eq!(listed, [Id(2), Id(3)]);
+        assert!(store.load(&Id(1)).await.unwrap().is_none());
+        assert!(store.load(&Id(2)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn both_sessions_are_listed_and_one_can_be_revoked() {
+        let (registry, store) = registry(None, 2).await;
+        registry
+            .register(
+                "a@example.com",
+                Id(1),
+                created(0),
+                Some(String::from("Firefox")),
+            )
+            .await;
+        registry
+            .register("a@example.com", Id(2), created(1), None)
+            .await;
+
+        let listed = registry.list("a@example.com", Some(Id(2)));
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].user_agent.as_deref(), Some("Firefox"));
+        assert!(!listed[0].current);
+        assert!(listed[1].current);
+
+        assert!(!registry.revoke("b@example.com", &listed[0].id).await);
+        assert!(registry.revoke("a@example.com", &listed[0].id).await);
+        let remaining: Vec<Id> = registry
+            .list("a@example.com", None)
+            .iter()
+            .map(|s| s.session_id)
+            .collect();
+        assert_eq!(remaining, [Id(2)]);
+        assert!(store.load(&Id(1)).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Settings for backtesting against a simulated clock.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SimConfig {
+    /// Date the simulation starts on.
+    pub start_date: NaiveDate,
+    /// Simulated seconds that pass per real second.
+    pub speed: f64,
+}
+
+/// Clock that starts at `start` when the server boots and runs `speed` times faster than real time.
+struct SimClock {
+    start: DateTime<Utc>,
+    speed: f64,
+    started: Instant,
+}
+
+static CLOCK: OnceLock<SimClock> = OnceLock::new();
+
+/// Start the simulation clock. Once started, timestamps use simulated time and prices come from
+/// historical candles for the simulated date.
+pub fn init(config: &SimConfig) {
+    let start = config.start_date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let _ = CLOCK.set(SimClock {
+        start,
+        speed: config.speed,
+        started: Instant::now(),
+    });
+    tracing::info!(
+        "Simulation mode starting at {} ({}x speed)",
+        config.start_date,
+        config.speed
+    );
+}
+
+/// The simulated current time, or `None` outside simulation mode.
+pub fn now() -> Option<DateTime<Utc>> {
+    let clock = CLOCK.get()?;
+    let elapsed = clock.started.elapsed().as_secs_f64() * clock.speed;
+    Some(clock.start + chrono::Duration::milliseconds((elapsed * 1000.0) as i64))
+}
@@ -0,0 +1,239 @@
+use crate::models::ValueSnapshot;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::fmt;
+
+/// An account's value on a day, in cents.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ValuePoint {
+    pub date: NaiveDate,
+    pub value: i64,
+}
+
+/// An account's value on a day split between cash and holdings, in cents. `cash` and `invested`
+/// sum to `value`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompositionPoint {
+    pub date: NaiveDate,
+    pub cash: i64,
+    pub invested: i64,
+    pub value: i64,
+}
+
+/// Split each snapshot's value between cash and holdings, oldest first. Snapshots recorded
+/// before cash was captured can't be split and are left out. Snapshots with cash but not the
+/// invested amount are taken to have invested the rest of their value.
+pub fn composition(snapshots: &[ValueSnapshot]) -> Vec<CompositionPoint> {
+    let mut points: Vec<CompositionPoint> = snapshots
+        .iter()
+        .filter_map(|s| {
+            let cash = s.cash?;
+            let invested = s.invested.unwrap_or(s.value - cash);
+            Some(CompositionPoint {
+                date: s.date,
+                cash,
+                invested,
+                value: cash + invested,
+            })
+        })
+        .collect();
+    points.sort_by_key(|p| p.date);
+    points
+}
+
+/// Highest and lowest recorded values and how far the latest value is below the high.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Extremes {
+    pub high: ValuePoint,
+    pub low: ValuePoint,
+    pub current: ValuePoint,
+    /// `high` minus `current`, in cents. Zero at a new high.
+    pub drawdown: i64,
+    /// Drawdown as a percentage of the high.
+    pub drawdown_percent: f64,
+}
+
+/// Find the extremes of a snapshot series in any order. A single snapshot is its own high, low,
+/// and current value; an empty series has no extremes. Ties keep the earliest date.
+pub fn extremes(snapshots: &[ValueSnapshot]) -> Option<Extremes> {
+    let mut points: Vec<ValuePoint> = snapshots
+        .iter()
+        .map(|s| ValuePoint {
+            date: s.date,
+            value: s.value,
+        })
+        .collect();
+    points.sort_by_key(|p| p.date);
+
+    let current = *points.last()?;
+    let mut high = points[0];
+    let mut low = points[0];
+    for point in &points[1..] {
+        if point.value > high.value {
+            high = *point;
+        }
+        if point.value < low.value {
+            low = *point;
+        }
+    }
+
+    let drawdown = high.value - current.value;
+    let drawdown_percent = match high.value {
+        v if v > 0 => drawdown as f64 / v as f64 * 100.0,
+        _ => 0.0,
+    };
+    Some(Extremes {
+        high,
+        low,
+        current,
+        drawdown,
+        drawdown_percent,
+    })
+}
+
+/// Trading days in a year, used to annualize daily returns.
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// Returns from each recorded value to the next, oldest first, as fractions. Days without a
+/// snapshot are skipped, so a return can span several days. Returns from a value of zero or less
+/// are undefined and left out.
+pub fn daily_returns(snapshots: &[ValueSnapshot]) -> Vec<f64> {
+    let mut values: Vec<(NaiveDate, i64)> = snapshots.iter().map(|s| (s.date, s.value)).collect();
+    values.sort_by_key(|(date, _)| *date);
+    values
+        .windows(2)
+        .filter(|pair| pair[0].1 > 0)
+        .map(|pair| (pair[1].1 - pair[0].1) as f64 / pair[0].1 as f64)
+        .collect()
+}
+
+/// Why a Sharpe ratio couldn't be computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharpeUnavailable {
+    /// Fewer than two returns, so their volatility is unknown. Holds how many there were.
+    TooFewReturns(usize),
+    /// Every return was the same, so the ratio is undefined.
+    NoVolatility,
+}
+
+impl fmt::Display for SharpeUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharpeUnavailable::TooFewReturns(n) => write!(
+                f,
+                "At least 2 daily returns are needed, and the account has {}",
+                n
+            ),
+            SharpeUnavailable::NoVolatility => write!(f, "The account's value has not varied"),
+        }
+    }
+}
+
+/// Annualized Sharpe ratio of a series of daily returns, given an annual risk-free rate as a
+/// fraction. The mean excess return over the daily risk-free rate is divided by the sample
+/// standard deviation of the excess returns, then scaled by the square root of
+/// `TRADING_DAYS_PER_YEAR`.
+pub fn sharpe_ratio(returns: &[f64], risk_free_rate: f64) -> Result<f64, SharpeUnavailable> {
+    let n = returns.len();
+    if n < 2 {
+        return Err(SharpeUnavailable::TooFewReturns(n));
+    }
+    let daily_risk_free = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    let excess: Vec<f64> = returns.iter().map(|r| r - daily_risk_free).collect();
+    let mean = excess.iter().sum::<f64>() / n as f64;
+    let variance = excess.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let deviation = variance.sqrt();
+    if deviation < f64::EPSILON {
+        return Err(SharpeUnavailable::NoVolatility);
+    }
+    Ok(mean / deviation * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(date: &str, value: i64) -> ValueSnapshot {
+        ValueSnapshot {
+            account_id: String::from("a@example.com"),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            value,
+            cash: None,
+            invested: None,
+            holdings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn extremes_find_the_peak_and_the_drawdown_from_it() {
+        let snapshots = [
+            snapshot("2024-03-04", 120_000),
+            snapshot("2024-03-01", 100_000),
+            snapshot("2024-03-05", 90_000),
+            snapshot("2024-03-06", 105_000),
+        ];
+
+        let extremes = extremes(&snapshots).unwrap();
+
+        assert_eq!(
+            extremes.high,
+            ValuePoint {
+                date: snapshots[0].date,
+                value: 120_000
+            }
+        );
+        assert_eq!(
+            extremes.low,
+            ValuePoint {
+                date: snapshots[2].date,
+                value: 90_000
+            }
+        );
+        assert_eq!(extremes.current.value, 105_000);
+        assert_eq!(extremes.drawdown, 15_000);
+        assert_eq!(extremes.drawdown_percent, 12.5);
+    }
+
+    #[test]
+    fn one_snapshot_is_its_own_peak_and_none_has_no_extremes() {
+        let extremes = extremes(&[snapshot("2024-03-01", 100_000)]).unwrap();
+        assert_eq!(extremes.high, extremes.low);
+        assert_eq!(extremes.drawdown, 0);
+        assert_eq!(super::extremes(&[]), None);
+    }
+
+    #[test]
+    fn composition_at_each_date_sums_to_the_total_value() {
+        let mut split = snapshot("2024-03-02", 100_000);
+        split.cash = Some(40_000);
+        split.invested = Some(60_000);
+        let mut cash_only = snapshot("2024-03-01", 100_000);
+        cash_only.cash = Some(25_000);
+        let old = snapshot("2024-02-29", 90_000);
+
+        let points = composition(&[split, old, cash_only]);
+
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].cash, points[0].invested), (25_000, 75_000));
+        assert_eq!((points[1].cash, points[1].invested), (40_000, 60_000));
+        assert!(points.iter().all(|p| p.cash + p.invested == p.value));
+        assert!(points[0].date < points[1].date);
+    }
+
+    #[test]
+    fn sharpe_ratio_of_a_known_series() {
+        // Mean 0.5% over a sample deviation of sqrt(0.0005 / 3), annualized over 252 days
+        let returns = [0.01, -0.01, 0.02, 0.0];
+        let sharpe = sharpe_ratio(&returns, 0.0).unwrap();
+        assert!((sharpe - 37.8_f64.sqrt()).abs() < 1e-9);
+
+        assert_eq!(
+            sharpe_ratio(&returns[..1], 0.0),
+            Err(SharpeUnavailable::TooFewReturns(1))
+        );
+        assert_eq!(
+            sharpe_ratio(&[0.01, 0.01], 0.02),
+            Err(SharpeUnavailable::NoVolatility)
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::confirmations::PendingOrders;
+use crate::db::DatabasePool;
+use crate::ids::IdGenerator;
+use crate::locks::AccountLocks;
+use crate::recent::RecentSymbols;
+use crate::response_cache::ResponseCache;
+use crate::sessions::SessionRegistry;
+use crate::store::{GuestStores, Store};
+use axum::extract::FromRef;
+use std::sync::Arc;
+
+/// Shared application state. Handlers extract the parts they need, e.g. `State<Arc<dyn Store>>`.
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    /// Account, holding, and transaction storage.
+    pub store: Arc<dyn Store>,
+    /// MongoDB collections outside the `Store` trait, such as settings.
+    pub pool: DatabasePool,
+    pub config: Arc<Config>,
+    pub guests: GuestStores,
+    /// Active sessions per account, for enforcing the session limit.
+    pub sessions: SessionRegistry,
+    /// Large buys awaiting confirmation.
+    pub confirmations: PendingOrders,
+    /// Generates transaction ids.
+    pub ids: Arc<dyn IdGenerator>,
+    /// Current time for trade timestamps.
+    pub clock: Arc<dyn Clock>,
+    /// Serializes multi-step operations per account.
+    pub locks: AccountLocks,
+    /// Computed responses of expensive read endpoints.
+    pub responses: ResponseCache,
+    /// Symbols each account recently looked up.
+    pub recent: RecentSymbols,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State for driving handlers in tests, storing in `store`. The MongoDB pool can't connect,
+    /// ids count up from `txn-1`, and no sessions or responses are shared with other tests.
+    pub(crate) async fn for_tests(store: Arc<dyn Store>, config: Config) -> Self {
+        let guests = GuestStores::new(config.starting_cash);
+        AppState {
+            store,
+            pool: DatabasePool::unreachable().await,
+            sessions: SessionRegistry::new(
+                config.max_sessions_per_account,
+                Arc::new(tower_sessions::MemoryStore::default()),
+            ),
+            config: Arc::new(config),
+            guests,
+            confirmations: PendingOrders::new(),
+            ids: Arc::new(crate::ids::SequentialIds::new("txn")),
+            clock: Arc::new(crate::clock::SystemClock),
+            locks: AccountLocks::new(),
+            responses: ResponseCache::new([]),
+            recent: RecentSymbols::new(),
+        }
+    }
+}
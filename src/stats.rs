@@ -0,0 +1,122 @@
+use crate::models::Transaction;
+use crate::pnl::{parse_timestamp, sort_chronologically, Position};
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Summary of an account's trading activity.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct TradeStats {
+    pub total_trades: usize,
+    pub closed_positions: usize,
+    /// Share of closed positions that made money, or `None` if nothing has been closed.
+    pub win_rate: Option<f64>,
+    /// Average days from opening to closing a position, or `None` if nothing has been closed.
+    pub average_holding_period_days: Option<f64>,
+    /// Symbol with the most trades; ties go to the alphabetically first symbol.
+    pub most_traded_symbol: Option<String>,
+}
+
+/// A position that was opened and later sold down to zero.
+struct ClosedPosition {
+    realized_pnl: i64,
+    held_secs: i64,
+}
+
+/// Compute trading statistics from an account's transactions, in any order.
+pub fn trade_stats(transactions: &[Transaction]) -> TradeStats {
+    let mut ordered = transactions.to_vec();
+    sort_chronologically(&mut ordered);
+
+    let mut positions: HashMap<&str, (Position, i64, Option<DateTime<FixedOffset>>)> =
+        HashMap::new();
+    let mut trade_counts: HashMap<&str, usize> = HashMap::new();
+    let mut closed = Vec::new();
+
+    for transaction in &ordered {
+        *trade_counts.entry(&transaction.stock_symbol).or_default() += 1;
+
+        let (position, realized_at_open, opened_at) =
+            positions.entry(&transaction.stock_symbol).or_default();
+        if position.quantity <= 0 {
+            *realized_at_open = position.realized_pnl;
+            *opened_at = parse_timestamp(transaction);
+        }
+        position.apply(transaction);
+
+        if transaction.transaction_type == "SELL" && position.quantity <= 0 {
+            let held_secs = match (*opened_at, parse_timestamp(transaction)) {
+                (Some(opened), Some(closed)) => (closed - opened).num_seconds(),
+                _ => 0,
+            };
+            closed.push(ClosedPosition {
+                realized_pnl: position.realized_pnl - *realized_at_open,
+                held_secs,
+            });
+        }
+    }
+
+    let most_traded_symbol = trade_counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(symbol, _)| symbol.to_string());
+
+    let (win_rate, average_holding_period_days) = if closed.is_empty() {
+        (None, None)
+    } else {
+        let wins = closed.iter().filter(|c| c.realized_pnl > 0).count();
+        let held_secs: i64 = closed.iter().map(|c| c.held_secs).sum();
+        (
+            Some(wins as f64 / closed.len() as f64),
+            Some(held_secs as f64 / closed.len() as f64 / 86_400.0),
+        )
+    };
+
+    TradeStats {
+        total_trades: transactions.len(),
+        closed_positions: closed.len(),
+        win_rate,
+        average_holding_period_days,
+        most_traded_symbol,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, kind: &str, quantity: i32, price: i32, day: u32) -> Transaction {
+        Transaction {
+            stock_symbol: symbol.to_string(),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            timestamp: format!("2024-03-{:02}T15:00:00Z", day),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn win_rate_counts_profitable_closed_positions() {
+        let stats = trade_stats(&[
+            trade("AAPL", "BUY", 10, 1_000, 1),
+            trade("AAPL", "SELL", 10, 1_200, 3),
+            trade("MSFT", "BUY", 5, 2_000, 2),
+            trade("MSFT", "SELL", 5, 1_500, 6),
+            trade("TSLA", "BUY", 1, 3_000, 4),
+        ]);
+
+        assert_eq!(stats.total_trades, 5);
+        assert_eq!(stats.closed_positions, 2);
+        assert_eq!(stats.win_rate, Some(0.5));
+        assert_eq!(stats.average_holding_period_days, Some(3.0));
+        assert_eq!(stats.most_traded_symbol.as_deref(), Some("AAPL"));
+    }
+
+    #[test]
+    fn nothing_closed_has_no_win_rate() {
+        let stats = trade_stats(&[trade("AAPL", "BUY", 1, 1_000, 1)]);
+        assert_eq!(stats.win_rate, None);
+        assert_eq!(stats.average_holding_period_days, None);
+    }
+}
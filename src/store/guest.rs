@@ -0,0 +1,78 @@
+use super::{MemoryStore, Store};
+use crate::models::Account;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A guest's store and when it was last used.
+type GuestEntry = (Arc<MemoryStore>, Instant);
+
+/// In-memory stores for guest sessions, keyed by guest account id. A guest's store is dropped
+/// once it has been idle longer than the session expiry, so the account resets with the session.
+#[derive(Clone)]
+pub struct GuestStores {
+    starting_cash: i64,
+    stores: Arc<Mutex<HashMap<String, GuestEntry>>>,
+}
+
+impl GuestStores {
+    /// Create an empty registry whose guest accounts start with `starting_cash` cents.
+    pub fn new(starting_cash: i64) -> Self {
+        Self {
+            starting_cash,
+            stores: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Get the store for a guest, creating it with a fresh account on first use.
+    pub async fn get_or_create(&self, guest_id: &str) -> Arc<MemoryStore> {
+        let existing = {
+            let mut stores = self.stores.lock().unwrap();
+            stores.get_mut(guest_id).map(|(store, last_used)| {
+                *last_used = Instant::now();
+                store.clone()
+            })
+        };
+        if let Some(store) = existing {
+            return store;
+        }
+
+        let store = Arc::new(MemoryStore::new());
+        // The memory store cannot fail
+        let _ = store
+            .add_account(Account {
+                id: guest_id.to_string(),
+                value: self.starting_cash as i32,
+                cash: self.starting_cash as i32,
+                change: 0,
+                version: 0,
+                trades_count: 0,
+                // Guest accounts are throwaway and never ranked
+                eligible_for_leaderboard: false,
+                eligibility_locked: true,
+                pattern_day_trader: false,
+                created_at: None,
+                updated_at: None,
+            })
+            .await;
+        self.stores
+            .lock()
+            .unwrap()
+            .insert(guest_id.to_string(), (store.clone(), Instant::now()));
+        store
+    }
+
+    /// Drop a guest's store.
+    pub fn remove(&self, guest_id: &str) {
+        self.stores.lock().unwrap().remove(guest_id);
+    }
+
+    /// Drop every store idle for longer than `max_idle`.
+    pub fn sweep(&self, max_idle: Duration) {
+        let now = Instant::now();
+        self.stores
+            .lock()
+            .unwrap()
+            .retain(|_, (_, last_used)| now.duration_since(*last_used) < max_idle);
+    }
+}
@@ -0,0 +1,608 @@
+use super::{Store, StoreError, StoreTransaction};
+use crate::models::{
+    Account, AccountSettings, CorporateActionRecord, Holding, MarginCallRecord, QueuedOrder,
+    Transaction, TransactionSummary, UpdateSettings, ValueSnapshot,
+};
+use crate::money::SharePrice;
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+/// A `Store` kept entirely in memory. Used for guest accounts, which never touch MongoDB, and
+/// for exercising handlers without a database.
+#[derive(Default)]
+pub struct MemoryStore {
+    accounts: Mutex<Vec<Account>>,
+    holdings: Mutex<Vec<Holding>>,
+    transactions: Mutex<Vec<Transaction>>,
+    archived_transactions: Mutex<Vec<Transaction>>,
+    transaction_summaries: Mutex<Vec<TransactionSummary>>,
+    value_snapshots: Mutex<Vec<ValueSnapshot>>,
+    corporate_actions: Mutex<Vec<CorporateActionRecord>>,
+    margin_calls: Mutex<Vec<MarginCallRecord>>,
+    settings: Mutex<Vec<AccountSettings>>,
+}
+
+/// Everything a `MemoryStore` holds, saved when a transaction starts.
+pub struct Contents {
+    accounts: Vec<Account>,
+    holdings: Vec<Holding>,
+    transactions: Vec<Transaction>,
+    archived_transactions: Vec<Transaction>,
+    transaction_summaries: Vec<TransactionSummary>,
+    value_snapshots: Vec<ValueSnapshot>,
+    corporate_actions: Vec<CorporateActionRecord>,
+    margin_calls: Vec<MarginCallRecord>,
+    settings: Vec<AccountSettings>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy everything the store holds.
+    fn contents(&self) -> Contents {
+        Contents {
+            accounts: self.accounts.lock().unwrap().clone(),
+            holdings: self.holdings.lock().unwrap().clone(),
+            transactions: self.transactions.lock().unwrap().clone(),
+            archived_transactions: self.archived_transactions.lock().unwrap().clone(),
+            transaction_summaries: self.transaction_summaries.lock().unwrap().clone(),
+            value_snapshots: self.value_snapshots.lock().unwrap().clone(),
+            corporate_actions: self.corporate_actions.lock().unwrap().clone(),
+            margin_calls: self.margin_calls.lock().unwrap().clone(),
+            settings: self.settings.lock().unwrap().clone(),
+        }
+    }
+
+    /// Put back contents saved by `contents`, discarding every write since.
+    pub(super) fn restore(&self, contents: Contents) {
+        *self.accounts.lock().unwrap() = contents.accounts;
+        *self.holdings.lock().unwrap() = contents.holdings;
+        *self.transactions.lock().unwrap() = contents.transactions;
+        *self.archived_transactions.lock().unwrap() = contents.archived_transactions;
+        *self.transaction_summaries.lock().unwrap() = contents.transaction_summaries;
+        *self.value_snapshots.lock().unwrap() = contents.value_snapshots;
+        *self.corporate_actions.lock().unwrap() = contents.corporate_actions;
+        *self.margin_calls.lock().unwrap() = contents.margin_calls;
+        *self.settings.lock().unwrap() = contents.settings;
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn add_account(&self, mut account: Account) -> Result<(), StoreError> {
+        let now = crate::timestamps::now();
+        account.created_at = Some(now.clone());
+        account.updated_at = Some(now);
+        self.accounts.lock().unwrap().push(account);
+        Ok(())
+    }
+    async fn get_account(&self, account_id: &str) -> Result<Option<Account>, StoreError> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts.iter().find(|a| a.id == account_id).cloned())
+    }
+    async fn get_accounts(&self) -> Result<Vec<Account>, StoreError> {
+        Ok(self.accounts.lock().unwrap().clone())
+    }
+    async fn update_account(
+        &self,
+        account_id: &str,
+        new_value: i64,
+        new_cash: i64,
+    ) -> Result<(), StoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.iter_mut().find(|a| a.id == account_id) {
+            account.value = new_value as i32;
+            account.cash = new_cash as i32;
+            account.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+    async fn increment_account_version(&self, account_id: &str) -> Result<(), StoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.iter_mut().find(|a| a.id == account_id) {
+            account.version += 1;
+            account.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+    async fn increment_trades_count(&self, account_id: &str) -> Result<(), StoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.iter_mut().find(|a| a.id == account_id) {
+            account.trades_count += 1;
+        }
+        Ok(())
+    }
+    async fn set_leaderboard_eligibility(
+        &self,
+        account_id: &str,
+        eligible: bool,
+    ) -> Result<(), StoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.iter_mut().find(|a| a.id == account_id) {
+            account.eligible_for_leaderboard = eligible;
+            account.eligibility_locked = true;
+            account.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+
+    async fn flag_pattern_day_trader(&self, account_id: &str) -> Result<(), StoreError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.iter_mut().find(|a| a.id == account_id) {
+            account.pattern_day_trader = true;
+            account.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+
+    async fn add_holding(&self, mut holding: Holding) -> Result<(), StoreError> {
+        let now = crate::timestamps::now();
+        holding.created_at = Some(now.clone());
+        holding.updated_at = Some(now);
+        self.holdings.lock().unwrap().push(holding);
+        Ok(())
+    }
+    async fn get_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+    ) -> Result<Option<Holding>, StoreError> {
+        let holdings = self.holdings.lock().unwrap();
+        Ok(holdings
+            .iter()
+            .find(|h| h.account_id == account_id && h.stock_symbol == stock_symbol)
+            .cloned())
+    }
+    async fn get_holdings(&self, account_id: &str) -> Result<Vec<Holding>, StoreError> {
+        let holdings = self.holdings.lock().unwrap();
+        Ok(holdings
+            .iter()
+            .filter(|h| h.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+    async fn update_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        quantity: i64,
+        purchase_price: SharePrice,
+    ) -> Result<(), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        if let Some(holding) = holdings
+            .iter_mut()
+            .find(|h| h.account_id == account_id && h.stock_symbol == stock_symbol)
+        {
+            holding.quantity = quantity as i32;
+            holding.set_purchase_price(purchase_price);
+            holding.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+    async fn update_holding_name(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        stock_name: &str,
+    ) -> Result<(), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        if let Some(holding) = holdings
+            .iter_mut()
+            .find(|h| h.account_id == account_id && h.stock_symbol == stock_symbol)
+        {
+            holding.stock_name = stock_name.to_string();
+            holding.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+    async fn mark_holding_delisted(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        last_price: i64,
+    ) -> Result<(), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        if let Some(holding) = holdings
+            .iter_mut()
+            .find(|h| h.account_id == account_id && h.stock_symbol == stock_symbol)
+        {
+            holding.delisted = true;
+            holding.current_price = last_price as i32;
+            holding.updated_at = Some(crate::timestamps::now());
+        }
+        Ok(())
+    }
+    async fn update_holdings_prices(
+        &self,
+        account_id: &str,
+        prices: &[(String, SharePrice)],
+    ) -> Result<(), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        for (stock_symbol, price) in prices {
+            if let Some(holding) = holdings
+                .iter_mut()
+                .find(|h| h.account_id == account_id && &h.stock_symbol == stock_symbol)
+            {
+                holding.set_current_price(*price);
+                holding.updated_at = Some(crate::timestamps::now());
+            }
+        }
+        Ok(())
+    }
+    async fn delete_holding(&self, account_id: &str, stock_symbol: &str) -> Result<(), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        holdings.retain(|h| !(h.account_id == account_id && h.stock_symbol == stock_symbol));
+        Ok(())
+    }
+    async fn get_holdings_by_symbol(&self, stock_symbol: &str) -> Result<Vec<Holding>, StoreError> {
+        let holdings = self.holdings.lock().unwrap();
+        Ok(holdings
+            .iter()
+            .filter(|h| h.stock_symbol == stock_symbol)
+            .cloned()
+            .collect())
+    }
+    async fn rename_symbol(&self, from: &str, to: &str) -> Result<(u64, u64), StoreError> {
+        let mut holdings = self.holdings.lock().unwrap();
+        let (renamed, mut kept): (Vec<Holding>, Vec<Holding>) =
+            holdings.drain(..).partition(|h| h.stock_symbol == from);
+        let holdings_changed = renamed.len() as u64;
+        for holding in renamed {
+            let renamed = Holding {
+                stock_symbol: to.to_string(),
+                ..holding
+            };
+            match kept
+                .iter()
+                .position(|h| h.account_id == renamed.account_id && h.stock_symbol == to)
+            {
+                Some(index) => {
+                    let existing = kept.remove(index);
+                    let stock_name = existing.stock_name.clone();
+                    if let Some(mut merged) = Holding::merge(vec![existing, renamed]) {
+                        merged.stock_name = stock_name;
+                        kept.push(merged);
+                    }
+                }
+                None => kept.push(renamed),
+            }
+        }
+        *holdings = kept;
+        drop(holdings);
+
+        let mut summaries = self.transaction_summaries.lock().unwrap();
+        let (renamed, mut kept): (Vec<TransactionSummary>, Vec<TransactionSummary>) =
+            summaries.drain(..).partition(|s| s.stock_symbol == from);
+        for summary in renamed {
+            match kept
+                .iter_mut()
+                .find(|s| s.account_id == summary.account_id && s.stock_symbol == to)
+            {
+                Some(existing) => {
+                    existing.quantity += summary.quantity;
+                    existing.cost_basis += summary.cost_basis;
+                    existing.realized_pnl += summary.realized_pnl;
+                    existing.archived_count += summary.archived_count;
+                }
+                None => kept.push(TransactionSummary {
+                    stock_symbol: to.to_string(),
+                    ..summary
+                }),
+            }
+        }
+        *summaries = kept;
+        drop(summaries);
+
+        let mut transactions_changed = 0;
+        for transactions in [&self.transactions, &self.archived_transactions] {
+            for transaction in transactions
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .filter(|t| t.stock_symbol == from)
+            {
+                transaction.stock_symbol = to.to_string();
+                transactions_changed += 1;
+            }
+        }
+        Ok((holdings_changed, transactions_changed))
+    }
+
+    async fn add_transaction(&self, transaction: Transaction) -> Result<(), StoreError> {
+        self.transactions.lock().unwrap().push(transaction);
+        Ok(())
+    }
+    async fn get_transactions(&self, account_id: &str) -> Result<Vec<Transaction>, StoreError> {
+        let transactions = self.transactions.lock().unwrap();
+        Ok(transactions
+            .iter()
+            .filter(|t| t.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+    async fn delete_transactions(&self, ids: &[String]) -> Result<(), StoreError> {
+        let mut transactions = self.transactions.lock().unwrap();
+        transactions.retain(|t| !ids.contains(&t.id));
+        Ok(())
+    }
+    async fn archive_transactions(&self, transactions: &[Transaction]) -> Result<(), StoreError> {
+        let mut archived = self.archived_transactions.lock().unwrap();
+        archived.extend_from_slice(transactions);
+        Ok(())
+    }
+    async fn get_transaction_summaries(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<TransactionSummary>, StoreError> {
+        let summaries = self.transaction_summaries.lock().unwrap();
+        Ok(summaries
+            .iter()
+            .filter(|s| s.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+    async fn upsert_transaction_summary(
+        &self,
+        summary: TransactionSummary,
+    ) -> Result<(), StoreError> {
+        let mut summaries = self.transaction_summaries.lock().unwrap();
+        summaries.retain(|s| {
+            !(s.account_id == summary.account_id && s.stock_symbol == summary.stock_symbol)
+        });
+        summaries.push(summary);
+        Ok(())
+    }
+
+    async fn record_snapshot(&self, snapshot: ValueSnapshot) -> Result<(), StoreError> {
+        let mut snapshots = self.value_snapshots.lock().unwrap();
+        snapshots.retain(|s| !(s.account_id == snapshot.account_id && s.date == snapshot.date));
+        snapshots.push(snapshot);
+        Ok(())
+    }
+    async fn get_snapshots(&self, account_id: &str) -> Result<Vec<ValueSnapshot>, StoreError> {
+        let snapshots = self.value_snapshots.lock().unwrap();
+        let mut snapshots: Vec<ValueSnapshot> = snapshots
+            .iter()
+            .filter(|s| s.account_id == account_id)
+            .cloned()
+            .collect();
+        snapshots.sort_by_key(|s| s.date);
+        Ok(snapshots)
+    }
+
+    async fn get_corporate_action(
+        &self,
+        action_id: &str,
+    ) -> Result<Option<CorporateActionRecord>, StoreError> {
+        let actions = self.corporate_actions.lock().unwrap();
+        Ok(actions.iter().find(|a| a.action_id == action_id).cloned())
+    }
+    async fn add_corporate_action(&self, record: CorporateActionRecord) -> Result<(), StoreError> {
+        self.corporate_actions.lock().unwrap().push(record);
+        Ok(())
+    }
+
+    async fn get_margin_calls(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<MarginCallRecord>, StoreError> {
+        let calls = self.margin_calls.lock().unwrap();
+        Ok(calls
+            .iter()
+            .filter(|c| c.account_id == account_id)
+            .cloned()
+            .collect())
+    }
+    async fn add_margin_call(&self, record: MarginCallRecord) -> Result<(), StoreError> {
+        self.margin_calls.lock().unwrap().push(record);
+        Ok(())
+    }
+
+    async fn get_settings(&self, account_id: &str) -> Result<AccountSettings, StoreError> {
+        let settings = self.settings.lock().unwrap();
+        Ok(settings
+            .iter()
+            .find(|s| s.account_id == account_id)
+            .cloned()
+            .unwrap_or_else(|| AccountSettings {
+                account_id: account_id.to_string(),
+                ..Default::default()
+            }))
+    }
+    async fn update_settings(
+        &self,
+        account_id: &str,
+        update: &UpdateSettings,
+    ) -> Result<AccountSettings, StoreError> {
+        let mut settings = self.settings.lock().unwrap();
+        let index = match settings.iter().position(|s| s.account_id == account_id) {
+            Some(index) => index,
+            None => {
+                settings.push(AccountSettings {
+                    account_id: account_id.to_string(),
+                    ..Default::default()
+                });
+                settings.len() - 1
+            }
+        };
+        let current = &mut settings[index];
+        if let Some(currency) = &update.currency {
+            current.currency = currency.clone();
+        }
+        if let Some(order_type) = &update.default_order_type {
+            current.default_order_type = order_type.clone();
+        }
+        if let Some(fee_model) = &update.fee_model {
+            current.fee_model = Some(fee_model.clone());
+        }
+        if let Some(opt_in) = update.email_opt_in {
+            current.email_opt_in = opt_in;
+        }
+        if let Some(allocations) = &update.target_allocations {
+            current.target_allocations = allocations.clone();
+        }
+        if let Some(reserve) = update.min_cash_reserve_cents {
+            current.min_cash_reserve_cents = reserve;
+        }
+        if let Some(multiplier) = update.buying_power_multiplier {
+            current.buying_power_multiplier = multiplier;
+        }
+        Ok(current.clone())
+    }
+
+    async fn get_queued_orders(&self, _account_id: &str) -> Result<Vec<QueuedOrder>, StoreError> {
+        // Guests can't queue orders, since the order job only sees MongoDB
+        Ok(Vec::new())
+    }
+
+    async fn start_transaction(&self) -> Result<StoreTransaction<'_>, StoreError> {
+        Ok(StoreTransaction::Memory(self, self.contents()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn aborting_a_transaction_undoes_its_writes() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account::open("a@example.com", 100_000, false))
+            .await
+            .unwrap();
+
+        let txn = store.start_transaction().await.unwrap();
+        txn.store()
+            .update_account("a@example.com", 50_000, 50_000)
+            .await
+            .unwrap();
+        txn.store()
+            .add_holding(Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("AAPL"),
+                quantity: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        txn.abort().await.unwrap();
+
+        let account = store.get_account("a@example.com").await.unwrap().unwrap();
+        assert_eq!(account.cash, 100_000);
+        assert!(store
+            .get_holdings("a@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn updating_a_holding_bumps_updated_at_only() {
+        let store = MemoryStore::new();
+        store
+            .add_holding(Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("AAPL"),
+                quantity: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let opened = store
+            .get_holding("a@example.com", "AAPL")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(opened.created_at, opened.updated_at);
+
+        // Stored timestamps have millisecond precision
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        store
+            .update_holding("a@example.com", "AAPL", 15, SharePrice::from_cents(10_000))
+            .await
+            .unwrap();
+
+        let updated = store
+            .get_holding("a@example.com", "AAPL")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.created_at, opened.created_at);
+        assert!(updated.updated_at > opened.updated_at);
+    }
+
+    #[tokio::test]
+    async fn committing_a_transaction_keeps_its_writes() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account::open("a@example.com", 100_000, false))
+            .await
+            .unwrap();
+
+        let txn = store.start_transaction().await.unwrap();
+        txn.store()
+            .update_account("a@example.com", 50_000, 50_000)
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        let account = store.get_account("a@example.com").await.unwrap().unwrap();
+        assert_eq!(account.cash, 50_000);
+    }
+
+    #[tokio::test]
+    async fn repricing_updates_each_listed_holding_of_the_account() {
+        let store = MemoryStore::new();
+        for (account_id, symbol) in [
+            ("a@example.com", "AAPL"),
+            ("a@example.com", "MSFT"),
+            ("a@example.com", "NVDA"),
+            ("b@example.com", "AAPL"),
+        ] {
+            store
+                .add_holding(Holding {
+                    account_id: account_id.to_string(),
+                    stock_symbol: symbol.to_string(),
+                    quantity: 3,
+                    current_price: 1_000,
+                    total_value: 3_000,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        store
+            .update_holdings_prices(
+                "a@example.com",
+                &[
+                    (String::from("AAPL"), SharePrice::from_cents(1_500)),
+                    (String::from("MSFT"), SharePrice::from_cents(900)),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let priced = |holdings: Vec<Holding>| -> Vec<(String, i32, i32)> {
+            holdings
+                .into_iter()
+                .map(|h| (h.stock_symbol, h.current_price, h.total_value))
+                .collect()
+        };
+        assert_eq!(
+            priced(store.get_holdings("a@example.com").await.unwrap()),
+            [
+                (String::from("AAPL"), 1_500, 4_500),
+                (String::from("MSFT"), 900, 2_700),
+                (String::from("NVDA"), 1_000, 3_000),
+            ]
+        );
+        assert_eq!(
+            priced(store.get_holdings("b@example.com").await.unwrap()),
+            [(String::from("AAPL"), 1_000, 3_000)]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::auth::GUEST_KEY;
+use crate::db::DatabasePool;
+use crate::models::{
+    Account, AccountSettings, CorporateActionRecord, Holding, MarginCallRecord, QueuedOrder,
+    Transaction, TransactionSummary, UpdateSettings, ValueSnapshot,
+};
+use crate::money::SharePrice;
+use async_trait::async_trait;
+use std::fmt;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+pub mod guest;
+pub mod memory;
+pub mod mongo;
+
+pub use guest::GuestStores;
+pub use memory::MemoryStore;
+
+/// Error returned by a `Store` backend.
+#[derive(Debug, Clone)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+/// A unit of work started with `Store::start_transaction`. Writes made through `store()` take
+/// effect together on `commit` and are all undone by `abort`.
+pub enum StoreTransaction<'a> {
+    /// A MongoDB transaction, with a pool whose operations run in its session.
+    Mongo(DatabasePool),
+    /// A memory store and its contents when the transaction started, put back on abort. Callers
+    /// hold the account's lock, so no other writes are lost by restoring them.
+    Memory(&'a MemoryStore, memory::Contents),
+}
+
+impl StoreTransaction<'_> {
+    /// The store whose reads and writes are part of the transaction.
+    pub fn store(&self) -> &dyn Store {
+        match self {
+            StoreTransaction::Mongo(pool) => pool,
+            StoreTransaction::Memory(store, _) => *store,
+        }
+    }
+
+    pub async fn commit(self) -> Result<(), StoreError> {
+        match self {
+            StoreTransaction::Mongo(pool) => Ok(pool.commit_transaction().await?),
+            StoreTransaction::Memory(..) => Ok(()),
+        }
+    }
+
+    pub async fn abort(self) -> Result<(), StoreError> {
+        match self {
+            StoreTransaction::Mongo(pool) => Ok(pool.abort_transaction().await?),
+            StoreTransaction::Memory(store, contents) => {
+                store.restore(contents);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Storage for accounts, holdings, and transactions. Handlers work against this trait so they run
+/// the same on MongoDB (`DatabasePool`) and in memory (`MemoryStore`, used for guests).
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn add_account(&self, account: Account) -> Result<(), StoreError>;
+    async fn get_account(&self, account_id: &str) -> Result<Option<Account>, StoreError>;
+    async fn get_accounts(&self) -> Result<Vec<Account>, StoreError>;
+    async fn update_account(
+        &self,
+        account_id: &str,
+        new_value: i64,
+        new_cash: i64,
+    ) -> Result<(), StoreError>;
+    /// Mark the account's cash or holdings as changed, invalidating cached responses.
+    async fn increment_account_version(&self, account_id: &str) -> Result<(), StoreError>;
+    async fn increment_trades_count(&self, account_id: &str) -> Result<(), StoreError>;
+    /// Set whether the account is ranked on the leaderboard and lock the choice.
+    async fn set_leaderboard_eligibility(
+        &self,
+        account_id: &str,
+        eligible: bool,
+    ) -> Result<(), StoreError>;
+    async fn flag_pattern_day_trader(&self, account_id: &str) -> Result<(), StoreError>;
+
+    async fn add_holding(&self, holding: Holding) -> Result<(), StoreError>;
+    async fn get_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+    ) -> Result<Option<Holding>, StoreError>;
+    async fn get_holdings(&self, account_id: &str) -> Result<Vec<Holding>, StoreError>;
+    async fn update_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        quantity: i64,
+        purchase_price: SharePrice,
+    ) -> Result<(), StoreError>;
+    async fn update_holding_name(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        stock_name: &str,
+    ) -> Result<(), StoreError>;
+    /// Flag a holding as delisted and record the last price it was quoted at, in cents.
+    async fn mark_holding_delisted(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        last_price: i64,
+    ) -> Result<(), StoreError>;
+    /// Store freshly quoted prices on an account's holdings, updating their total values.
+    async fn update_holdings_prices(
+        &self,
+        account_id: &str,
+        prices: &[(String, SharePrice)],
+    ) -> Result<(), StoreError>;
+    async fn delete_holding(&self, account_id: &str, stock_symbol: &str) -> Result<(), StoreError>;
+    /// Every account's holding of a symbol.
+    async fn get_holdings_by_symbol(&self, stock_symbol: &str) -> Result<Vec<Holding>, StoreError>;
+    /// Move every holding, transaction, and archived record from one symbol to another, merging
+    /// into holdings and summaries of `to` an account already has. Returns the number of holdings
+    /// and transactions changed.
+    async fn rename_symbol(&self, from: &str, to: &str) -> Result<(u64, u64), StoreError>;
+
+    async fn add_transaction(&self, transaction: Transaction) -> Result<(), StoreError>;
+    async fn get_transactions(&self, account_id: &str) -> Result<Vec<Transaction>, StoreError>;
+    async fn delete_transactions(&self, ids: &[String]) -> Result<(), StoreError>;
+    async fn archive_transactions(&self, transactions: &[Transaction]) -> Result<(), StoreError>;
+    async fn get_transaction_summaries(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<TransactionSummary>, StoreError>;
+    async fn upsert_transaction_summary(
+        &self,
+        summary: TransactionSummary,
+    ) -> Result<(), StoreError>;
+
+    /// Record an account's value for a day, replacing any earlier snapshot from the same day.
+    async fn record_snapshot(&self, snapshot: ValueSnapshot) -> Result<(), StoreError>;
+    /// Get an account's snapshots, oldest first.
+    async fn get_snapshots(&self, account_id: &str) -> Result<Vec<ValueSnapshot>, StoreError>;
+
+    /// The audit entry of an applied corporate action.
+    async fn get_corporate_action(
+        &self,
+        action_id: &str,
+    ) -> Result<Option<CorporateActionRecord>, StoreError>;
+    async fn add_corporate_action(&self, record: CorporateActionRecord) -> Result<(), StoreError>;
+
+    /// The audit entries of an account's positions sold to meet margin calls.
+    async fn get_margin_calls(&self, account_id: &str)
+        -> Result<Vec<MarginCallRecord>, StoreError>;
+    async fn add_margin_call(&self, record: MarginCallRecord) -> Result<(), StoreError>;
+
+    /// Get an account's settings, or the defaults if none have been saved.
+    async fn get_settings(&self, account_id: &str) -> Result<AccountSettings, StoreError>;
+    /// Merge the provided fields into an account's settings and return the result.
+    async fn update_settings(
+        &self,
+        account_id: &str,
+        update: &UpdateSettings,
+    ) -> Result<AccountSettings, StoreError>;
+
+    /// Get an account's market-on-open orders, oldest first.
+    async fn get_queued_orders(&self, account_id: &str) -> Result<Vec<QueuedOrder>, StoreError>;
+
+    /// Start a transaction grouping the writes of a multi-step operation. Only writes made
+    /// through the transaction's `store()` are part of it.
+    async fn start_transaction(&self) -> Result<StoreTransaction<'_>, StoreError>;
+}
+
+/// Pick the store for an authenticated session: guests get their in-memory store, everyone
+/// else MongoDB.
+pub async fn resolve_store(
+    session: &Session,
+    account_id: &str,
+    store: &Arc<dyn Store>,
+    guests: &GuestStores,
+) -> Arc<dyn Store> {
+    match session.get::<bool>(GUEST_KEY).await {
+        Ok(Some(true)) => guests.get_or_create(account_id).await,
+        _ => store.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{test_session, Scope};
+    use crate::clock::SystemClock;
+    use crate::config::Config;
+    use crate::finnhub::FinnhubProfile;
+    use crate::handlers::trading::{apply_buy, TradeContext};
+    use crate::ids::SequentialIds;
+
+    #[tokio::test]
+    async fn guest_trades_stay_in_the_guest_store() {
+        let shared = Arc::new(MemoryStore::new());
+        let guests = GuestStores::new(100_000);
+        let session = test_session("guest-1", Scope::all()).await;
+        session.insert(GUEST_KEY, true).await.unwrap();
+
+        let store = resolve_store(
+            &session,
+            "guest-1",
+            &(shared.clone() as Arc<dyn Store>),
+            &guests,
+        )
+        .await;
+        let ctx = TradeContext {
+            store: store.as_ref(),
+            config: &Config::for_tests(),
+            ids: &SequentialIds::new("txn"),
+            clock: &SystemClock,
+            buying_power_multiplier: 1.0,
+            reserved_cash: 0,
+        };
+        let profile = FinnhubProfile {
+            ticker: String::from("AAPL"),
+            name: String::from("Apple Inc"),
+            ..Default::default()
+        };
+        apply_buy(&ctx, "guest-1", "AAPL", 2, 10_000.into(), &profile, None)
+            .await
+            .unwrap();
+
+        let guest = guests.get_or_create("guest-1").await;
+        assert_eq!(guest.get_holdings("guest-1").await.unwrap().len(), 1);
+        assert_eq!(
+            guest.get_account("guest-1").await.unwrap().unwrap().cash,
+            80_000
+        );
+        assert!(shared.get_accounts().await.unwrap().is_empty());
+        assert!(shared.get_holdings("guest-1").await.unwrap().is_empty());
+        assert!(shared.get_transactions("guest-1").await.unwrap().is_empty());
+    }
+}
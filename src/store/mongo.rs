@@ -0,0 +1,174 @@
+use super::{Store, StoreError, StoreTransaction};
+use crate::db::DatabasePool;
+use crate::models::{
+    Account, AccountSettings, CorporateActionRecord, Holding, MarginCallRecord, QueuedOrder,
+    Transaction, TransactionSummary, UpdateSettings, ValueSnapshot,
+};
+use crate::money::SharePrice;
+use async_trait::async_trait;
+
+#[async_trait]
+impl Store for DatabasePool {
+    async fn add_account(&self, account: Account) -> Result<(), StoreError> {
+        Ok(DatabasePool::add_account(self, account).await?)
+    }
+    async fn get_account(&self, account_id: &str) -> Result<Option<Account>, StoreError> {
+        Ok(DatabasePool::get_account(self, account_id).await?)
+    }
+    async fn get_accounts(&self) -> Result<Vec<Account>, StoreError> {
+        Ok(DatabasePool::get_accounts(self).await?)
+    }
+    async fn update_account(
+        &self,
+        account_id: &str,
+        new_value: i64,
+        new_cash: i64,
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::update_account(self, account_id, new_value, new_cash).await?)
+    }
+    async fn increment_account_version(&self, account_id: &str) -> Result<(), StoreError> {
+        Ok(DatabasePool::increment_account_version(self, account_id).await?)
+    }
+    async fn increment_trades_count(&self, account_id: &str) -> Result<(), StoreError> {
+        Ok(DatabasePool::increment_trades_count(self, account_id).await?)
+    }
+    async fn set_leaderboard_eligibility(
+        &self,
+        account_id: &str,
+        eligible: bool,
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::set_leaderboard_eligibility(self, account_id, eligible).await?)
+    }
+    async fn flag_pattern_day_trader(&self, account_id: &str) -> Result<(), StoreError> {
+        Ok(DatabasePool::flag_pattern_day_trader(self, account_id).await?)
+    }
+
+    async fn add_holding(&self, holding: Holding) -> Result<(), StoreError> {
+        Ok(DatabasePool::add_holding(self, holding).await?)
+    }
+    async fn get_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+    ) -> Result<Option<Holding>, StoreError> {
+        Ok(DatabasePool::get_holding(self, account_id, stock_symbol).await?)
+    }
+    async fn get_holdings(&self, account_id: &str) -> Result<Vec<Holding>, StoreError> {
+        Ok(DatabasePool::get_holdings(self, account_id).await?)
+    }
+    async fn update_holding(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        quantity: i64,
+        purchase_price: SharePrice,
+    ) -> Result<(), StoreError> {
+        Ok(
+            DatabasePool::update_holding(self, account_id, stock_symbol, quantity, purchase_price)
+                .await?,
+        )
+    }
+    async fn update_holding_name(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        stock_name: &str,
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::update_holding_name(self, account_id, stock_symbol, stock_name).await?)
+    }
+    async fn mark_holding_delisted(
+        &self,
+        account_id: &str,
+        stock_symbol: &str,
+        last_price: i64,
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::mark_holding_delisted(self, account_id, stock_symbol, last_price).await?)
+    }
+    async fn update_holdings_prices(
+        &self,
+        account_id: &str,
+        prices: &[(String, SharePrice)],
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::update_holdings_prices(self, account_id, prices).await?)
+    }
+    async fn delete_holding(&self, account_id: &str, stock_symbol: &str) -> Result<(), StoreError> {
+        Ok(DatabasePool::delete_holding(self, account_id, stock_symbol).await?)
+    }
+    async fn get_holdings_by_symbol(&self, stock_symbol: &str) -> Result<Vec<Holding>, StoreError> {
+        Ok(DatabasePool::get_holdings_by_symbol(self, stock_symbol).await?)
+    }
+    async fn rename_symbol(&self, from: &str, to: &str) -> Result<(u64, u64), StoreError> {
+        Ok(DatabasePool::rename_symbol(self, from, to).await?)
+    }
+
+    async fn add_transaction(&self, transaction: Transaction) -> Result<(), StoreError> {
+        Ok(DatabasePool::add_transaction(self, transaction).await?)
+    }
+    async fn get_transactions(&self, account_id: &str) -> Result<Vec<Transaction>, StoreError> {
+        Ok(DatabasePool::get_transactions(self, account_id).await?)
+    }
+    async fn delete_transactions(&self, ids: &[String]) -> Result<(), StoreError> {
+        Ok(DatabasePool::delete_transactions(self, ids).await?)
+    }
+    async fn archive_transactions(&self, transactions: &[Transaction]) -> Result<(), StoreError> {
+        Ok(DatabasePool::archive_transactions(self, transactions).await?)
+    }
+    async fn get_transaction_summaries(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<TransactionSummary>, StoreError> {
+        Ok(DatabasePool::get_transaction_summaries(self, account_id).await?)
+    }
+    async fn upsert_transaction_summary(
+        &self,
+        summary: TransactionSummary,
+    ) -> Result<(), StoreError> {
+        Ok(DatabasePool::upsert_transaction_summary(self, summary).await?)
+    }
+
+    async fn record_snapshot(&self, snapshot: ValueSnapshot) -> Result<(), StoreError> {
+        Ok(DatabasePool::record_snapshot(self, snapshot).await?)
+    }
+    async fn get_snapshots(&self, account_id: &str) -> Result<Vec<ValueSnapshot>, StoreError> {
+        Ok(DatabasePool::get_snapshots(self, account_id).await?)
+    }
+
+    async fn get_corporate_action(
+        &self,
+        action_id: &str,
+    ) -> Result<Option<CorporateActionRecord>, StoreError> {
+        Ok(DatabasePool::get_corporate_action(self, action_id).await?)
+    }
+    async fn add_corporate_action(&self, record: CorporateActionRecord) -> Result<(), StoreError> {
+        Ok(DatabasePool::add_corporate_action(self, record).await?)
+    }
+
+    async fn get_margin_calls(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<MarginCallRecord>, StoreError> {
+        Ok(DatabasePool::get_margin_calls(self, account_id).await?)
+    }
+    async fn add_margin_call(&self, record: MarginCallRecord) -> Result<(), StoreError> {
+        Ok(DatabasePool::add_margin_call(self, record).await?)
+    }
+
+    async fn get_settings(&self, account_id: &str) -> Result<AccountSettings, StoreError> {
+        Ok(DatabasePool::get_settings(self, account_id).await?)
+    }
+    async fn update_settings(
+        &self,
+        account_id: &str,
+        update: &UpdateSettings,
+    ) -> Result<AccountSettings, StoreError> {
+        Ok(DatabasePool::update_settings(self, account_id, update).await?)
+    }
+
+    async fn get_queued_orders(&self, account_id: &str) -> Result<Vec<QueuedOrder>, StoreError> {
+        Ok(DatabasePool::get_queued_orders(self, Some(account_id)).await?)
+    }
+
+    async fn start_transaction(&self) -> Result<StoreTransaction<'_>, StoreError> {
+        Ok(StoreTransaction::Mongo(self.begin_transaction().await?))
+    }
+}
@@ -0,0 +1,201 @@
+use crate::models::{Transaction, TransactionSummary};
+use crate::pnl::parse_timestamp;
+use chrono::{Months, NaiveDate, Utc};
+use serde::Serialize;
+
+/// Shares bought together. Amounts are in cents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lot {
+    /// Day the shares were bought, in UTC. Shares from archived transactions have no date.
+    pub acquired: Option<NaiveDate>,
+    pub quantity: i64,
+    /// Cost of the lot's remaining shares, fees included.
+    pub cost_basis: i64,
+}
+
+/// How a gain is taxed, by how long the shares were held.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Term {
+    /// Held a year or less.
+    Short,
+    /// Held more than a year.
+    Long,
+    /// Bought before the archived history, so the holding period is unknown.
+    Unknown,
+}
+
+/// Classify shares bought on `acquired` and sold on `sold`. Shares held past the anniversary of
+/// their purchase are long-term.
+pub fn term(acquired: Option<NaiveDate>, sold: NaiveDate) -> Term {
+    match acquired.and_then(|date| date.checked_add_months(Months::new(12))) {
+        Some(anniversary) if sold > anniversary => Term::Long,
+        Some(_) => Term::Short,
+        None => Term::Unknown,
+    }
+}
+
+/// Rebuild the open lots in `symbol`, oldest first, by replaying its transactions with sells
+/// consuming the oldest shares first. Shares still held from archived buys form an undated lot
+/// ahead of the rest.
+pub fn open_lots(
+    summary: Option<&TransactionSummary>,
+    transactions: &[Transaction],
+    symbol: &str,
+) -> Vec<Lot> {
+    let mut lots: Vec<Lot> = summary
+        .filter(|s| s.quantity > 0)
+        .map(|s| Lot {
+            acquired: None,
+            quantity: s.quantity,
+            cost_basis: s.cost_basis,
+        })
+        .into_iter()
+        .collect();
+
+    let mut ordered: Vec<_> = transactions
+        .iter()
+        .filter(|t| t.stock_symbol.eq_ignore_ascii_case(symbol))
+        .filter_map(|t| Some((parse_timestamp(t)?, t)))
+        .collect();
+    ordered.sort_by_key(|(timestamp, _)| *timestamp);
+
+    for (timestamp, transaction) in ordered {
+        let quantity = transaction.quantity as i64;
+        match transaction.transaction_type.as_str() {
+            "BUY" => lots.push(Lot {
+                acquired: Some(timestamp.with_timezone(&Utc).date_naive()),
+                quantity,
+                cost_basis: transaction.price as i64 * quantity + transaction.fee as i64,
+            }),
+            "SELL" => {
+                let mut remaining = quantity;
+                for lot in lots.iter_mut() {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let taken = remaining.min(lot.quantity);
+                    lot.cost_basis -= lot.cost_basis * taken / lot.quantity.max(1);
+                    lot.quantity -= taken;
+                    remaining -= taken;
+                }
+                lots.retain(|lot| lot.quantity > 0);
+            }
+            _ => {}
+        }
+    }
+    lots
+}
+
+/// Shares of a sale taxed under one term. Amounts are in cents.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TermGain {
+    pub quantity: i64,
+    pub cost_basis: i64,
+    /// The shares' part of the sale's proceeds, net of fees.
+    pub proceeds: i64,
+    pub gain: i64,
+}
+
+/// A sale's gain split by holding period.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaleSplit {
+    pub short_term: TermGain,
+    pub long_term: TermGain,
+    pub unknown_term: TermGain,
+}
+
+/// Split the sale of `quantity` shares for `proceeds` cents on `sold` across `lots`, oldest
+/// first. Proceeds are shared between the lots in proportion to the shares taken from each.
+/// Shares beyond what the lots hold are ignored.
+pub fn split_sale(lots: &[Lot], quantity: i64, proceeds: i64, sold: NaiveDate) -> SaleSplit {
+    let mut split = SaleSplit::default();
+    let mut remaining = quantity;
+    for lot in lots {
+        if remaining == 0 {
+            break;
+        }
+        let taken = remaining.min(lot.quantity);
+        let cost_basis = lot.cost_basis * taken / lot.quantity.max(1);
+        let share = proceeds * taken / quantity.max(1);
+        let bucket = match term(lot.acquired, sold) {
+            Term::Short => &mut split.short_term,
+            Term::Long => &mut split.long_term,
+            Term::Unknown => &mut split.unknown_term,
+        };
+        bucket.quantity += taken;
+        bucket.cost_basis += cost_basis;
+        bucket.proceeds += share;
+        bucket.gain += share - cost_basis;
+        remaining -= taken;
+    }
+    split
+}
+
+/// Tax on a gain of `gain` cents at `rate_bps` basis points. Losses owe nothing.
+pub fn estimated_tax(gain: i64, rate_bps: i64) -> i64 {
+    gain.max(0) * rate_bps / 10_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(kind: &str, quantity: i32, price: i32, timestamp: &str) -> Transaction {
+        Transaction {
+            stock_symbol: String::from("AAPL"),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            timestamp: timestamp.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn a_sale_across_old_and_new_lots_splits_into_long_and_short_term() {
+        let transactions = [
+            trade("BUY", 10, 10_000, "2022-01-10T15:00:00Z"),
+            trade("SELL", 4, 12_000, "2023-01-03T15:00:00Z"),
+            trade("BUY", 10, 15_000, "2024-01-10T15:00:00Z"),
+        ];
+        let lots = open_lots(None, &transactions, "AAPL");
+        assert_eq!(lots.len(), 2);
+        assert_eq!((lots[0].quantity, lots[0].cost_basis), (6, 60_000));
+
+        let split = split_sale(&lots, 10, 200_000, day("2024-03-05"));
+
+        assert_eq!(
+            split.long_term,
+            TermGain {
+                quantity: 6,
+                cost_basis: 60_000,
+                proceeds: 120_000,
+                gain: 60_000
+            }
+        );
+        assert_eq!(
+            split.short_term,
+            TermGain {
+                quantity: 4,
+                cost_basis: 60_000,
+                proceeds: 80_000,
+                gain: 20_000
+            }
+        );
+        assert_eq!(split.unknown_term, TermGain::default());
+        assert_eq!(estimated_tax(split.short_term.gain, 2_400), 4_800);
+    }
+
+    #[test]
+    fn shares_are_long_term_only_after_their_anniversary() {
+        let bought = Some(day("2023-03-05"));
+        assert_eq!(term(bought, day("2024-03-05")), Term::Short);
+        assert_eq!(term(bought, day("2024-03-06")), Term::Long);
+        assert_eq!(term(None, day("2024-03-06")), Term::Unknown);
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+
+/// Format a time as an RFC 3339 UTC timestamp, the format all stored timestamps use.
+pub fn format_utc(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The current time as a stored timestamp. Independent of the server's local timezone, and
+/// simulated time in simulation mode.
+pub fn now() -> String {
+    format_utc(crate::sim::now().unwrap_or_else(Utc::now))
+}
+
+/// Parse an IANA timezone name such as `America/New_York`.
+pub fn parse_tz(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>()
+        .map_err(|_| format!("Unknown timezone: {}", name))
+}
+
+/// Re-express a stored timestamp in `tz`, or in UTC if no zone is given. Older timestamps
+/// written with the server's local offset are normalized the same way. Unparseable timestamps
+/// are returned unchanged.
+pub fn localize(timestamp: &str, tz: Option<Tz>) -> String {
+    let Ok(parsed) = DateTime::parse_from_rfc3339(timestamp) else {
+        return timestamp.to_string();
+    };
+    match tz {
+        Some(tz) => parsed
+            .with_timezone(&tz)
+            .to_rfc3339_opts(SecondsFormat::Millis, false),
+        None => format_utc(parsed.with_timezone(&Utc)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    #[test]
+    fn the_same_instant_is_stored_the_same_in_any_zone() {
+        let new_york = FixedOffset::west_opt(5 * 3600).unwrap();
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let in_new_york = new_york.with_ymd_and_hms(2024, 3, 5, 10, 0, 0).unwrap();
+        let in_tokyo = tokyo.with_ymd_and_hms(2024, 3, 6, 0, 0, 0).unwrap();
+
+        let stored = format_utc(in_new_york.with_timezone(&Utc));
+        assert_eq!(stored, format_utc(in_tokyo.with_timezone(&Utc)));
+        assert_eq!(stored, "2024-03-05T15:00:00.000Z");
+    }
+
+    #[test]
+    fn local_offsets_are_normalized_or_localized() {
+        assert_eq!(
+            localize("2024-03-05T10:00:00-05:00", None),
+            "2024-03-05T15:00:00.000Z"
+        );
+        assert_eq!(
+            localize(
+                "2024-03-05T15:00:00Z",
+                Some(parse_tz("Asia/Tokyo").unwrap())
+            ),
+            "2024-03-06T00:00:00.000+09:00"
+        );
+        assert_eq!(localize("yesterday", None), "yesterday");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::auth::SessionUser;
+use crate::finnhub::fetch_price;
+use crate::recent::RecentSymbols;
+use crate::store::Store;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::future::join_all;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_sessions::Session;
+
+/// Warms the quote cache for an account's holdings and recently viewed symbols when it returns
+/// after being idle, so the requests that follow read prices from the cache.
+#[derive(Clone)]
+pub struct Warmup {
+    store: Arc<dyn Store>,
+    recent: RecentSymbols,
+    idle_after: Duration,
+    /// Most symbols quoted per warm-up, like a request's Finnhub budget.
+    max_symbols: usize,
+    last_seen: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Warmup {
+    pub fn new(
+        store: Arc<dyn Store>,
+        recent: RecentSymbols,
+        idle_after: Duration,
+        max_symbols: usize,
+    ) -> Self {
+        Self {
+            store,
+            recent,
+            idle_after,
+            max_symbols,
+            last_seen: Arc::default(),
+        }
+    }
+
+    /// Note a request from an account. Returns true if it is the first since startup or since
+    /// the account went idle.
+    fn returning(&self, account_id: &str) -> bool {
+        let mut last_seen = self.last_seen.lock().unwrap();
+        last_seen.retain(|_, seen| seen.elapsed() < self.idle_after);
+        last_seen
+            .insert(account_id.to_string(), Instant::now())
+            .is_none()
+    }
+
+    /// Quote an account's held and recently viewed symbols concurrently, filling the quote cache.
+    /// Stops early while Finnhub is rate limiting, since `fetch_price` fails fast then.
+    pub async fn warm(&self, account_id: &str) {
+        let mut symbols: BTreeSet<String> = self.recent.list(account_id).into_iter().collect();
+        match self.store.get_holdings(account_id).await {
+            Ok(holdings) => symbols.extend(
+                holdings
+                    .into_iter()
+                    .filter(|h| !h.delisted)
+                    .map(|h| h.stock_symbol),
+            ),
+            Err(e) => tracing::warn!("Failed to fetch holdings to warm for {}: {}", account_id, e),
+        }
+
+        let symbols: Vec<String> = symbols.into_iter().take(self.max_symbols).collect();
+        tracing::debug!("Warming {} quotes for {}", symbols.len(), account_id);
+        join_all(symbols.iter().map(|symbol| fetch_price(symbol))).await;
+    }
+}
+
+/// Middleware starting a background warm-up on an account's first request after being idle. The
+/// request itself doesn't wait for it.
+pub async fn warm_on_return(
+    State(warmup): State<Warmup>,
+    session: Session,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Ok(Some(info)) = session.get::<SessionUser>("SESSION").await {
+        if warmup.returning(&info.email) {
+            let warmup = warmup.clone();
+            tokio::spawn(async move { warmup.warm(&info.email).await });
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finnhub::mock;
+    use crate::models::Holding;
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn warming_quotes_held_and_viewed_symbols() {
+        let _finnhub = mock::exclusive().await;
+        for symbol in ["WARMH", "WARMV", "WARMX"] {
+            mock::stock(symbol, symbol, 10.0);
+        }
+        let store = Arc::new(MemoryStore::new());
+        store
+            .add_holding(Holding {
+                account_id: String::from("a@example.com"),
+                stock_symbol: String::from("WARMH"),
+                quantity: 1,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let recent = RecentSymbols::new();
+        recent.view("a@example.com", "WARMV");
+        recent.view("a@example.com", "WARMH");
+        let warmup = Warmup::new(store, recent, Duration::from_secs(60), 10);
+
+        assert!(warmup.returning("a@example.com"));
+        assert!(!warmup.returning("a@example.com"));
+        warmup.warm("a@example.com").await;
+
+        for symbol in ["WARMH", "WARMV"] {
+            assert_eq!(mock::calls("/quote", symbol), 1);
+            // Later lookups are served from the cache
+            fetch_price(symbol).await.unwrap();
+            assert_eq!(mock::calls("/quote", symbol), 1);
+        }
+        assert_eq!(mock::calls("/quote", "WARMX"), 0);
+    }
+}